@@ -0,0 +1,500 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+// Generates the element ID constants and tables in consts.rs from the EBML
+// schemas, see `generate`. schema/ebml_matroska.xml is the Matroska schema
+// as published with RFC 9559 and schema/ebml.xml holds the EBML header and
+// global elements of RFC 8794, which the Matroska one leaves out. Elements
+// of the later file replace ones with the same ID in the earlier, the way
+// ebml_matroska.xml narrows EBMLMaxIDLength.
+
+const SCHEMAS: [&str; 2] = ["schema/ebml.xml", "schema/ebml_matroska.xml"];
+
+// Masters are named with a `Node` suffix in this crate, except these
+const NAMES: [(&str, &str); 4] = [
+    ("EBML", "EBMLHeaderNode"),
+    ("SignatureSlot", "SignatureSlot"),
+    ("SignatureElements", "SignatureElements"),
+    ("SignatureElementList", "SignatureElementList"),
+];
+
+// The attributes RFC 8794 section 11.1.6 defines for `<element>`
+const ATTRIBUTES: [&str; 14] = [
+    "name", "path", "id", "minOccurs", "maxOccurs", "range", "length", "default", "type",
+    "unknownsizeallowed", "recursive", "recurring", "minver", "maxver",
+];
+
+// An XML tag with its attributes, child tags and text
+struct Tag {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Tag>,
+    text: String,
+}
+
+impl Tag {
+    fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+struct Schema {
+    name: String,
+    path: String,
+    id: u64,
+    kind: String,
+    range: Option<String>,
+    length: Option<String>,
+    default: Option<String>,
+    min_occurs: u64,
+    max_occurs: Option<u64>,
+    min_version: u64,
+    max_version: Option<u64>,
+    recursive: bool,
+    recurring: bool,
+    unknown_size_allowed: bool,
+    enums: Vec<f64>,
+    definition: Option<String>,
+    webm: bool,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let mut elements: Vec<Schema> = Vec::new();
+    for path in SCHEMAS.iter() {
+        println!("cargo:rerun-if-changed={}", path);
+        let xml = fs::read_to_string(path).expect("can't read the schema");
+        for mut element in schema(&parse(&xml)) {
+            match elements.iter_mut().find(|e| e.id == element.id) {
+                // overrides only restate constraints, not the definition
+                Some(known) => {
+                    element.definition = element.definition.or(known.definition.take());
+                    *known = element;
+                },
+                None => elements.push(element),
+            }
+        }
+    }
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("schema.rs");
+    fs::write(out, generate(&elements)).unwrap();
+}
+
+// The root tag of a document. Declarations, comments and DOCTYPEs are
+// skipped and CDATA sections read as text.
+fn parse(xml: &str) -> Tag {
+    let mut stack = vec![Tag { name: String::new(), attrs: Vec::new(), children: Vec::new(), text: String::new() }];
+    let mut rest = xml;
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                stack.last_mut().unwrap().text.push_str(&unescape(rest));
+                break;
+            },
+        };
+        stack.last_mut().unwrap().text.push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+        let skip = |rest: &str, end: &str| {
+            let len = rest.find(end).unwrap_or_else(|| panic!("unterminated {}", end));
+            len + end.len()
+        };
+        if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")..];
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let len = cdata.find("]]>").expect("unterminated CDATA");
+            stack.last_mut().unwrap().text.push_str(&cdata[..len]);
+            rest = &cdata[len + 3..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip(rest, ">")..];
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let len = close.find('>').expect("unterminated tag");
+            let tag = stack.pop().unwrap();
+            assert_eq!(tag.name, close[..len].trim(), "mismatched closing tag");
+            stack.last_mut().expect("closing tag without an opening one").children.push(tag);
+            rest = &close[len + 1..];
+        } else {
+            let (tag, closed, len) = open_tag(&rest[1..]);
+            rest = &rest[len + 1..];
+            if closed {
+                stack.last_mut().unwrap().children.push(tag);
+            } else {
+                stack.push(tag);
+            }
+        }
+    }
+    assert_eq!(stack.len(), 1, "unclosed tag");
+    stack.pop().unwrap().children.pop().expect("no root tag")
+}
+
+// A tag after its `<`, whether it closes itself and how long it is
+fn open_tag(text: &str) -> (Tag, bool, usize) {
+    let name_len = text.find(|c: char| c.is_whitespace() || c == '/' || c == '>').expect("unterminated tag");
+    let mut tag = Tag { name: text[..name_len].to_string(), attrs: Vec::new(), children: Vec::new(), text: String::new() };
+    let mut pos = name_len;
+    loop {
+        let rest = &text[pos..];
+        let trimmed = rest.trim_start();
+        pos += rest.len() - trimmed.len();
+        if trimmed.starts_with("/>") {
+            return (tag, true, pos + 2);
+        }
+        if trimmed.starts_with('>') {
+            return (tag, false, pos + 1);
+        }
+        let eq = trimmed.find('=').expect("attribute without a value");
+        let key = trimmed[..eq].trim().to_string();
+        let value = trimmed[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'').expect("unquoted attribute");
+        let len = value[1..].find(quote).expect("unterminated attribute");
+        tag.attrs.push((key, unescape(&value[1..len + 1])));
+        pos += trimmed.len() - value.len() + len + 2;
+    }
+}
+
+// Text with its entity and character references decoded
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let len = rest[amp..].find(';').expect("unterminated entity");
+        let entity = &rest[amp + 1..amp + len];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => entity.strip_prefix('#').expect("unknown entity").parse(),
+                };
+                code.ok().and_then(char::from_u32).expect("bad character reference")
+            },
+        };
+        out.push(c);
+        rest = &rest[amp + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The `<element>` definitions of an `<EBMLSchema>`
+fn schema(root: &Tag) -> Vec<Schema> {
+    assert_eq!(root.name, "EBMLSchema", "not an EBML schema");
+    root.children.iter().map(|tag| {
+        assert_eq!(tag.name, "element", "unknown tag in the schema");
+        for (key, _) in &tag.attrs {
+            assert!(ATTRIBUTES.contains(&key.as_str()), "unknown attribute {}", key);
+        }
+        let get = |key: &str| tag.attr(key).map(str::to_string);
+        let flag = |key: &str| tag.attr(key) == Some("1");
+        let integer = |key: &str| tag.attr(key).map(|n| n.parse().expect("bad integer"));
+        let id = get("id").expect("element without an id");
+        let mut element = Schema {
+            name: get("name").expect("element without a name"),
+            path: get("path").expect("element without a path"),
+            id: u64::from_str_radix(id.trim_start_matches("0x"), 16).expect("bad id"),
+            kind: get("type").expect("element without a type"),
+            range: get("range"),
+            length: get("length"),
+            default: get("default"),
+            min_occurs: integer("minOccurs").unwrap_or(0),
+            max_occurs: integer("maxOccurs"),
+            min_version: integer("minver").unwrap_or(1),
+            max_version: integer("maxver"),
+            recursive: flag("recursive"),
+            recurring: flag("recurring"),
+            unknown_size_allowed: flag("unknownsizeallowed"),
+            enums: Vec::new(),
+            definition: None,
+            webm: false,
+        };
+        for child in &tag.children {
+            match child.name.as_str() {
+                "documentation" if child.attr("purpose") == Some("definition") => {
+                    element.definition = Some(citations(child.text.trim()));
+                },
+                "documentation" | "implementation_note" => (),
+                "restriction" => {
+                    for value in child.children.iter().filter_map(|e| e.attr("value")) {
+                        element.enums.extend(number(value));
+                    }
+                },
+                "extension" if child.attr("type") == Some("webmproject.org") => {
+                    element.webm = child.attr("webm") == Some("1");
+                },
+                "extension" => (),
+                other => panic!("unknown tag {} in {}", other, element.name),
+            }
+        }
+        element
+    }).collect()
+}
+
+// Documentation with the spec's citations like `[@!RFC9562]` written as
+// plain text, which rustdoc would take for links
+fn citations(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[@") {
+        let len = rest[start..].find(']').expect("unterminated citation");
+        out.push_str(&rest[..start]);
+        out.push_str(rest[start + 2..start + len].trim_start_matches(['!', '?']));
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Decimal or hexadecimal integers and floats like `0x1.f4p+12`
+fn number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => {
+            let (mantissa, exponent) = match hex.find('p') {
+                Some(p) => (&hex[..p], hex[p + 1..].parse::<i32>().ok()?),
+                None => (hex, 0),
+            };
+            let (whole, fraction) = match mantissa.find('.') {
+                Some(dot) => (&mantissa[..dot], &mantissa[dot + 1..]),
+                None => (mantissa, ""),
+            };
+            let mut value = u64::from_str_radix(whole, 16).ok()? as f64;
+            for (i, digit) in fraction.chars().enumerate() {
+                value += digit.to_digit(16)? as f64 / 16f64.powi(i as i32 + 1);
+            }
+            value * 2f64.powi(exponent)
+        },
+        None => text.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// The bounds a `range` or `length` attribute sets, e.g. `not 0`, `>= 4`,
+// `1-8`, or several joined with commas like `>= -0xB4p+0, <= 0xB4p+0`.
+// `not 0` can only be expressed for unsigned integers.
+fn conditions(text: &str, unsigned: bool, integral: bool) -> Option<(f64, f64)> {
+    let (mut min, mut max) = (if unsigned { 0.0 } else { f64::MIN }, f64::MAX);
+    for condition in text.split(',').map(str::trim) {
+        if condition == "not 0" {
+            if !unsigned {
+                return None;
+            }
+            min = min.max(1.0);
+        } else if let Some(value) = condition.strip_prefix(">=") {
+            min = min.max(number(value)?);
+        } else if let Some(value) = condition.strip_prefix("<=") {
+            max = max.min(number(value)?);
+        } else if let Some(value) = condition.strip_prefix('>') {
+            min = min.max(next(number(value)?, integral, 1.0));
+        } else if let Some(value) = condition.strip_prefix('<') {
+            max = max.min(next(number(value)?, integral, -1.0));
+        } else if let Some(dash) = condition.get(1..).and_then(|rest| rest.find('-')) {
+            min = min.max(number(&condition[..dash + 1])?);
+            max = max.min(number(&condition[dash + 2..])?);
+        } else {
+            let value = number(condition)?;
+            min = min.max(value);
+            max = max.min(value);
+        }
+    }
+    Some((min, max))
+}
+
+// The closest value above or below an excluded bound
+fn next(value: f64, integral: bool, direction: f64) -> f64 {
+    if integral {
+        value + direction
+    } else if value == 0.0 {
+        f64::MIN_POSITIVE * direction
+    } else if (value > 0.0) == (direction > 0.0) {
+        f64::from_bits(value.to_bits() + 1)
+    } else {
+        f64::from_bits(value.to_bits() - 1)
+    }
+}
+
+// The allowed values of a number, from its `range` or else the values of
+// its enumeration
+fn bounds(element: &Schema) -> Option<(f64, f64)> {
+    let (unsigned, integral) = match element.kind.as_str() {
+        "uinteger" => (true, true),
+        "integer" => (false, true),
+        "float" => (false, false),
+        _ => return None,
+    };
+    match &element.range {
+        Some(range) => conditions(range, unsigned, integral),
+        None if element.enums.is_empty() => None,
+        None => {
+            let min = element.enums.iter().cloned().fold(f64::MAX, f64::min);
+            let max = element.enums.iter().cloned().fold(f64::MIN, f64::max);
+            Some((min, max))
+        },
+    }
+}
+
+fn float(value: f64) -> String {
+    if value == f64::MAX {
+        "f64::MAX".to_string()
+    } else if value == f64::MIN {
+        "f64::MIN".to_string()
+    } else if value == f64::MIN_POSITIVE {
+        "f64::MIN_POSITIVE".to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+// The `length` attribute as a range of byte counts
+fn length(element: &Schema) -> String {
+    match element.length.as_deref().map(|length| conditions(length, true, true).expect("bad length")) {
+        Some((min, max)) if max == f64::MAX => format!("Some({}..=u64::MAX)", min as u64),
+        Some((min, max)) => format!("Some({}..={})", min as u64, max as u64),
+        None => "None".to_string(),
+    }
+}
+
+fn kind(element: &Schema) -> &'static str {
+    match element.kind.as_str() {
+        "master" => "Master",
+        "uinteger" => "UInt",
+        "integer" => "SInt",
+        "float" => "Float",
+        "string" => "String",
+        "utf-8" => "UTF8",
+        "date" => "Date",
+        "binary" => "Binary",
+        other => panic!("unknown element type {}", other),
+    }
+}
+
+// The name of an element in this crate
+fn name(element: &Schema) -> String {
+    match NAMES.iter().find(|(name, _)| *name == element.name) {
+        Some((_, name)) => name.to_string(),
+        None if element.kind == "master" => format!("{}Node", element.name),
+        None => element.name.clone(),
+    }
+}
+
+fn constant(element: &Schema) -> String {
+    let name: String = name(element).chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    format!("ID_{}", name.to_uppercase())
+}
+
+// The masters an element may appear in, per its path: `None` for global
+// elements, whose path holds a `(min-max\)` part before their name, and the
+// element itself as well for recursive ones marked with `+`.
+fn parents(element: &Schema, elements: &[Schema]) -> Option<Vec<String>> {
+    let path = element.path.as_str();
+    let global = path.rfind("\\)").is_some_and(|end| !path[end + 2..].contains('\\'));
+    if global {
+        return None;
+    }
+    let path = match path.rfind("\\)") {
+        Some(end) => &path[end + 2..],
+        None => path,
+    };
+    let parts: Vec<&str> = path.split('\\').filter(|part| !part.is_empty()).collect();
+    let mut parents = Vec::new();
+    if parts.len() > 1 {
+        let parent = parts[parts.len() - 2].trim_start_matches('+');
+        let parent = elements.iter().find(|e| e.name == parent).expect("unknown parent");
+        parents.push(constant(parent));
+    }
+    if element.recursive || parts.last().unwrap().starts_with('+') {
+        parents.push(constant(element));
+    }
+    Some(parents)
+}
+
+fn generate(elements: &[Schema]) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated by build.rs from {}", SCHEMAS.join(" and ")).unwrap();
+    for element in elements {
+        writeln!(out).unwrap();
+        if let Some(definition) = &element.definition {
+            writeln!(out, "#[doc = {:?}]", definition).unwrap();
+        }
+        writeln!(out, "pub const {}: u64 = {:#x};", constant(element), element.id).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "pub static NODE_INFOS: [NodeInfo<'static>; {}] = [", elements.len()).unwrap();
+    for element in elements {
+        let parents = match parents(element, elements) {
+            Some(parents) => format!("Some(&[{}])", parents.join(", ")),
+            None => "None".to_string(),
+        };
+        writeln!(
+            out,
+            "    NodeInfo {{ id: {}, name: {:?}, parents: {}, kind: ElementKind::{}, \
+             min_occurs: {}, max_occurs: {:?}, default: {:?}, length: {}, \
+             min_version: {}, max_version: {:?}, recursive: {}, recurring: {}, \
+             unknown_size_allowed: {}, webm: {}, definition: {:?} }},",
+            constant(element), name(element), parents, kind(element),
+            element.min_occurs, element.max_occurs, element.default, length(element),
+            element.min_version, element.max_version, element.recursive, element.recurring,
+            element.unknown_size_allowed, element.webm, element.definition,
+        ).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    // children every instance of a master needs: those it must hold at
+    // least one of that have no default to fall back on, in each master
+    // they may appear in but themselves
+    let mut mandatory: Vec<(String, Vec<String>)> = Vec::new();
+    for element in elements {
+        if element.min_occurs == 0 || element.default.is_some() {
+            continue;
+        }
+        let id = constant(element);
+        for parent in parents(element, elements).unwrap_or_default() {
+            if parent == id {
+                continue;
+            }
+            match mandatory.iter_mut().find(|(master, _)| *master == parent) {
+                Some((_, children)) => children.push(id.clone()),
+                None => mandatory.push((parent, vec![id.clone()])),
+            }
+        }
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "pub const MANDATORY_CHILDREN: [(u64, &[u64]); {}] = [", mandatory.len()).unwrap();
+    for (parent, children) in &mandatory {
+        writeln!(out, "    ({}, &[{}]),", parent, children.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    let ranges: Vec<(String, (f64, f64))> = elements.iter()
+        .filter_map(|element| bounds(element).map(|bounds| (constant(element), bounds)))
+        .collect();
+    writeln!(out).unwrap();
+    writeln!(out, "pub const VALUE_RANGES: [(u64, RangeInclusive<f64>); {}] = [", ranges.len()).unwrap();
+    for (id, (min, max)) in &ranges {
+        writeln!(out, "    ({}, {}..={}),", id, float(*min), float(*max)).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    writeln!(out).unwrap();
+    writeln!(out, "pub(crate) fn element_kind(id: u64) -> ElementKind {{").unwrap();
+    writeln!(out, "    match id {{").unwrap();
+    for element in elements {
+        writeln!(out, "        {:#x} => ElementKind::{},", element.id, kind(element)).unwrap();
+    }
+    writeln!(out, "        _ => ElementKind::Unknown,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
@@ -0,0 +1,135 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, GenericArgument, Lit, PathArguments, Type};
+
+// `#[derive(FromEbml)]` for structs whose fields are children of a master,
+// see `libwebm_rs::view`. Each field names the ID of its child:
+//
+//     #[derive(FromEbml)]
+//     struct Track {
+//         #[ebml(id = ID_TRACKNUMBER)]
+//         number: u64,
+//         #[ebml(id = ID_LANGUAGE, default = "eng")]
+//         language: String,
+//         #[ebml(id = ID_NAME)]
+//         name: Option<String>,
+//         #[ebml(id = ID_CONTENTENCODINGNODE)]
+//         encodings: Vec<Encoding>,
+//         #[ebml(skip)]
+//         note: String,
+//     }
+//
+// Plain fields need the child unless given a `default`, either an
+// expression or none for `Default::default()`. `Option` fields are None
+// without it and `Vec` fields, other than `Vec<u8>` for binary data, collect
+// every child with the ID. Skipped fields are always their default.
+#[proc_macro_derive(FromEbml, attributes(ebml))]
+pub fn derive_from_ebml(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct Attribute {
+    id: Option<Expr>,
+    // Some(None) for a bare `default`
+    default: Option<Option<Expr>>,
+    skip: bool,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "FromEbml needs named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "FromEbml can only be derived for structs")),
+    };
+
+    let mut values = Vec::new();
+    for field in fields {
+        let name = field.ident.as_ref().unwrap();
+        let attribute = attribute(field)?;
+        let value = if attribute.skip {
+            quote!(::std::default::Default::default())
+        } else {
+            let id = attribute.id.ok_or_else(|| {
+                syn::Error::new_spanned(field, "missing #[ebml(id = ...)] or #[ebml(skip)]")
+            })?;
+            match (wrapper(&field.ty), attribute.default) {
+                (Some("Option"), Some(_)) | (Some("Vec"), Some(_)) => {
+                    return Err(syn::Error::new_spanned(field, "Option and Vec fields can't have a default"));
+                },
+                (Some("Option"), None) => quote!(::libwebm_rs::view::optional(node, #id)?),
+                (Some("Vec"), None) => quote!(::libwebm_rs::view::repeated(node, #id)?),
+                (_, None) => quote!(::libwebm_rs::view::required(node, #id)?),
+                (_, Some(default)) => {
+                    let default = match default {
+                        Some(Expr::Lit(lit)) if matches!(lit.lit, Lit::Str(_)) => {
+                            quote!(::std::string::String::from(#lit))
+                        },
+                        Some(default) => quote!(#default),
+                        None => quote!(::std::default::Default::default()),
+                    };
+                    quote!(::libwebm_rs::view::optional(node, #id)?.unwrap_or_else(|| #default))
+                },
+            }
+        };
+        values.push(quote!(#name: #value));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::libwebm_rs::view::FromEbml for #name #type_generics #where_clause {
+            fn from_node(node: &::libwebm_rs::ebml::Node) -> ::std::result::Result<Self, ::libwebm_rs::error::Error> {
+                ::std::result::Result::Ok(#name { #(#values),* })
+            }
+        }
+    })
+}
+
+fn attribute(field: &syn::Field) -> syn::Result<Attribute> {
+    let mut attribute = Attribute { id: None, default: None, skip: false };
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("ebml")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                attribute.id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                attribute.default = Some(match meta.input.peek(syn::Token![=]) {
+                    true => Some(meta.value()?.parse()?),
+                    false => None,
+                });
+            } else if meta.path.is_ident("skip") {
+                attribute.skip = true;
+            } else {
+                return Err(meta.error("expected `id`, `default` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attribute)
+}
+
+// "Option" or "Vec" for those types, but not for `Vec<u8>`, which is binary
+// data rather than repeated children
+fn wrapper(ty: &Type) -> Option<&'static str> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    let argument = match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) => arguments.args.first()?,
+        _ => return None,
+    };
+    if segment.ident == "Option" {
+        Some("Option")
+    } else if segment.ident == "Vec" {
+        match argument {
+            GenericArgument::Type(Type::Path(inner)) if inner.path.is_ident("u8") => None,
+            _ => Some("Vec"),
+        }
+    } else {
+        None
+    }
+}
@@ -0,0 +1,45 @@
+// Print the element tree of a file, with cluster timestamps and sizes.
+//
+//     cargo run --example dump -- sample/big-buck-bunny_trailer.webm
+use std::env;
+use std::fs::File;
+use libwebm_rs::consts::*;
+use libwebm_rs::ebml::{ElementKind, Node, WebmFile};
+use libwebm_rs::format::{ByteSize, Timestamp};
+
+fn dump(node: &Node, depth: usize, scale: u64) {
+    let element = node.get_element();
+    let name = get_node_info(element.id).map(|info| info.name).unwrap_or("Unknown");
+    let size = if element.is_unknown_size() {
+        "unknown size".to_string()
+    } else {
+        ByteSize(element.size).to_string()
+    };
+    let value = match (element.id, &element.kind) {
+        (ID_TIMESTAMP, _) => format!(" {}", Timestamp(element.data.into_uint().saturating_mul(scale))),
+        (ID_DURATION, _) => format!(" {}", Timestamp((element.data.into_float() * scale as f64) as u64)),
+        (_, ElementKind::UInt) => format!(" {}", element.data.into_uint()),
+        (_, ElementKind::String) | (_, ElementKind::UTF8) => format!(" {:?}", element.data.into_string()),
+        _ => String::new(),
+    };
+    println!("{}{} @{} ({}){}", "  ".repeat(depth), name, element.offset, size, value);
+
+    // blocks are too numerous to be useful
+    for child in node.get_children().iter().filter(|n| n.get_element().id != ID_SIMPLEBLOCK) {
+        dump(child, depth + 1, scale);
+    }
+}
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./sample/big-buck-bunny_trailer.webm".to_string());
+    let document = WebmFile::open(File::open(path).unwrap()).unwrap();
+    let scale = document.root.get_info_nodes()
+        .first()
+        .map(|info| info.get_timestamp_scale())
+        .unwrap_or(1_000_000);
+
+    dump(document.header.as_node(), 0, scale);
+    dump(document.root.as_node(), 0, scale);
+}
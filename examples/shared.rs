@@ -0,0 +1,37 @@
+// Parse a file once and serve its Clusters from several worker threads, as
+// a server handing byte ranges to many clients would.
+//
+//     cargo run --example shared -- sample/big-buck-bunny_trailer.webm
+use std::env;
+use std::fs;
+use std::thread;
+use libwebm_rs::shared::SharedWebm;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./sample/big-buck-bunny_trailer.webm".to_string());
+    let shared = SharedWebm::new(fs::read(path).unwrap()).unwrap();
+
+    let workers: Vec<_> = shared.document().root.get_clusters()
+        .into_iter()
+        .enumerate()
+        .map(|(i, cluster)| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let scale = shared.document().root.get_info_nodes()[0].get_timestamp_scale();
+                let bytes = shared.element_bytes(&cluster.get_element()).unwrap_or_default();
+                println!(
+                    "cluster {}: timestamp {} ns, {} bytes",
+                    i,
+                    cluster.get_timestamp() * scale,
+                    bytes.len(),
+                );
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+#ifndef LIBWEBM_RS_H
+#define LIBWEBM_RS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WEBM_OK 0
+#define WEBM_END 1
+#define WEBM_ERROR -1
+
+typedef struct WebmHandle WebmHandle;
+
+typedef struct {
+    uint64_t number;
+    uint64_t uid;
+    uint64_t track_type;
+    /* owned by the handle, valid until webm_close */
+    const char *codec_id;
+    uint64_t pixel_width;
+    uint64_t pixel_height;
+    double sampling_frequency;
+    uint64_t channels;
+} WebmTrackInfo;
+
+typedef struct {
+    uint64_t track;
+    /* in TimestampScale units, see webm_timestamp_scale */
+    uint64_t timestamp;
+    int keyframe;
+    /* owned by the handle, valid until the next webm_next_packet call */
+    const uint8_t *data;
+    size_t size;
+} WebmPacket;
+
+/* Returns NULL if the file can't be opened or parsed */
+WebmHandle *webm_open(const char *path);
+void webm_close(WebmHandle *handle);
+
+size_t webm_track_count(const WebmHandle *handle);
+int webm_track_info(const WebmHandle *handle, size_t index, WebmTrackInfo *out);
+
+/* Returns WEBM_OK with the next frame, WEBM_END once all are read */
+int webm_next_packet(WebmHandle *handle, WebmPacket *out);
+
+uint64_t webm_timestamp_scale(const WebmHandle *handle);
+/* In TimestampScale units, negative if the file doesn't say */
+double webm_duration(const WebmHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::consts::*;
+use crate::ebml::{ClusterNode, Node};
+use crate::temporal::{signalled_layer, TemporalLayers};
+use crate::vint;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lacing {
+    None,
+    Xiph,
+    FixedSize,
+    EBML,
+}
+
+// A parsed SimpleBlock or Block payload
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub track: u64,
+    // timestamp relative to the enclosing cluster
+    pub timecode: i16,
+    pub flags: u8,
+    pub frames: Vec<Vec<u8>>,
+}
+
+impl Block {
+    pub fn parse(data: &[u8]) -> Option<Block> {
+        let (track, track_len) = vint::parse(data)?;
+        let header = data.get(track_len..track_len + 3)?;
+        let timecode = i16::from_be_bytes([header[0], header[1]]);
+        let flags = header[2];
+
+        let payload = &data[track_len + 3..];
+        let frames = match lacing_from_flags(flags) {
+            Lacing::None => vec![payload.to_vec()],
+            lacing => parse_laced_frames(lacing, payload)?,
+        };
+
+        Some(Block {
+            track,
+            timecode,
+            flags,
+            frames,
+        })
+    }
+
+    // Only valid for SimpleBlocks, Blocks signal this via ReferenceBlock
+    pub fn is_keyframe(&self) -> bool {
+        self.flags & 0x80 == 0x80
+    }
+
+    pub fn is_invisible(&self) -> bool {
+        self.flags & 0x08 == 0x08
+    }
+
+    pub fn is_discardable(&self) -> bool {
+        self.flags & 0x01 == 0x01
+    }
+
+    pub fn lacing(&self) -> Lacing {
+        lacing_from_flags(self.flags)
+    }
+
+    // Absolute timestamp (in TimestampScale units) given the cluster timestamp
+    pub fn timestamp(&self, cluster_timestamp: u64) -> u64 {
+        (cluster_timestamp as i64).saturating_add(self.timecode as i64).max(0) as u64
+    }
+}
+
+// A single frame from a SimpleBlock or BlockGroup, in file order. Fields
+// get added as more of what blocks carry is exposed, so build one with
+// `Packet::new` and set the rest on it.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Packet {
+    pub track: u64,
+    // absolute timestamp in TimestampScale units
+    pub timestamp: u64,
+    pub duration: Option<u64>,
+    pub keyframe: bool,
+    // nanoseconds of decoded audio to drop from the end, from a BlockGroup
+    pub discard_padding: Option<i64>,
+    // decode but don't present, set on SeekPreRoll packets after a seek
+    pub preroll: bool,
+    // false for frames split out of a VP9 superframe that are only
+    // decoded, never shown, see `Packets::split_superframes`
+    pub show_frame: bool,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    // A shown frame with no duration or padding
+    pub fn new(track: u64, timestamp: u64, keyframe: bool, data: Vec<u8>) -> Packet {
+        Packet {
+            track,
+            timestamp,
+            duration: None,
+            keyframe,
+            discard_padding: None,
+            preroll: false,
+            show_frame: true,
+            data,
+        }
+    }
+}
+
+// Iterates the frames of every block in a list of clusters. Laced blocks
+// yield one packet per frame, all sharing the block's timestamp.
+pub struct Packets {
+    clusters: VecDeque<ClusterNode>,
+    children: VecDeque<Node>,
+    cluster_timestamp: u64,
+    pending: VecDeque<Packet>,
+    // VP9 tracks whose superframes are split, see `split_superframes`
+    superframe_tracks: HashSet<u64>,
+    // the highest temporal layer kept on each track and the layers so far,
+    // see `max_temporal_layer`
+    temporal_layers: HashMap<u64, (u8, TemporalLayers)>,
+    layer_add_id: Option<u64>,
+}
+
+impl Packets {
+    pub fn new(clusters: Vec<ClusterNode>) -> Packets {
+        Packets {
+            clusters: clusters.into(),
+            children: VecDeque::new(),
+            cluster_timestamp: 0,
+            pending: VecDeque::new(),
+            superframe_tracks: HashSet::new(),
+            temporal_layers: HashMap::new(),
+            layer_add_id: None,
+        }
+    }
+
+    // Split the VP9 superframes on these tracks into their frames, each
+    // its own packet with the block's timestamp. Only the first carries
+    // the block's keyframe flag, and frames that are never shown, like
+    // alt-ref frames, have `show_frame` cleared. Payloads on other tracks
+    // could look like superframes by chance, so only pass VP9 tracks.
+    pub fn split_superframes<I: IntoIterator<Item = u64>>(mut self, tracks: I) -> Self {
+        self.superframe_tracks.extend(tracks);
+        self
+    }
+
+    // Drop the blocks of a VP9 track above a temporal layer, e.g. 0 for
+    // just the base layer of an SVC stream, for a lower frame rate preview
+    // without decoding. See `temporal` for how layers are found.
+    pub fn max_temporal_layer(mut self, track: u64, layer: u8) -> Self {
+        self.temporal_layers.insert(track, (layer, TemporalLayers::new()));
+        self
+    }
+
+    // Take each block's temporal layer from the first byte of its
+    // BlockAdditional with this BlockAddID, where there is one
+    pub fn temporal_layer_addition(mut self, add_id: u64) -> Self {
+        self.layer_add_id = Some(add_id);
+        self
+    }
+
+    // Whether a block's packets are above the temporal layer kept for
+    // their track
+    fn above_max_layer(&mut self, node: &Node, packets: &[Packet]) -> bool {
+        let packet = match packets.first() {
+            Some(packet) => packet,
+            None => return false,
+        };
+        let (max, layers) = match self.temporal_layers.get_mut(&packet.track) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let signalled = self.layer_add_id.and_then(|id| signalled_layer(node, id));
+        layers.block_layer(&packet.data, signalled) > *max
+    }
+}
+
+impl Iterator for Packets {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        loop {
+            if let Some(packet) = self.pending.pop_front() {
+                return Some(packet);
+            }
+
+            match self.children.pop_front() {
+                Some(node) => {
+                    let packets = block_packets(&node, self.cluster_timestamp);
+                    if self.above_max_layer(&node, &packets) {
+                        continue;
+                    }
+                    for packet in packets {
+                        if self.superframe_tracks.contains(&packet.track) {
+                            self.pending.extend(split_superframe(packet));
+                        } else {
+                            self.pending.push_back(packet);
+                        }
+                    }
+                },
+                None => {
+                    let cluster = self.clusters.pop_front()?;
+                    self.cluster_timestamp = cluster.get_timestamp();
+                    self.children = cluster.get_children().into();
+                },
+            }
+        }
+    }
+}
+
+// The Block of a SimpleBlock or BlockGroup, with what a BlockGroup says
+// about it. A BlockGroup holds a keyframe when it has no ReferenceBlock.
+pub(crate) struct BlockEntry {
+    pub block: Block,
+    pub keyframe: bool,
+    pub duration: Option<u64>,
+    pub discard_padding: Option<i64>,
+}
+
+// None for other elements and for Blocks that can't be parsed
+pub(crate) fn block_of(node: &Node) -> Option<BlockEntry> {
+    let element = node.element();
+    match element.id {
+        ID_SIMPLEBLOCK => {
+            let block = Block::parse(element.data.as_bytes())?;
+            Some(BlockEntry { keyframe: block.is_keyframe(), block, duration: None, discard_padding: None })
+        },
+        ID_BLOCKGROUPNODE => {
+            let child = |id| node.children().iter().find(|n| n.element().id == id).map(Node::element);
+            Some(BlockEntry {
+                block: Block::parse(child(ID_BLOCK)?.data.as_bytes())?,
+                keyframe: child(ID_REFERENCEBLOCK).is_none(),
+                duration: child(ID_BLOCKDURATION).map(|e| e.data.into_uint()),
+                discard_padding: child(ID_DISCARDPADDING).map(|e| e.data.into_int()),
+            })
+        },
+        _ => None,
+    }
+}
+
+// The packets of a SimpleBlock or BlockGroup in a Cluster with the given
+// timestamp, one per frame. Other elements have none.
+pub(crate) fn block_packets(node: &Node, cluster_timestamp: u64) -> Vec<Packet> {
+    let BlockEntry { block, keyframe, duration, discard_padding } = match block_of(node) {
+        Some(entry) => entry,
+        None => return Vec::new(),
+    };
+
+    let timestamp = block.timestamp(cluster_timestamp);
+    let track = block.track;
+    block.frames.into_iter()
+        .map(|data| Packet {
+            track,
+            timestamp,
+            duration,
+            keyframe,
+            discard_padding,
+            preroll: false,
+            show_frame: true,
+            data,
+        })
+        .collect()
+}
+
+// The frames of a VP9 superframe, which ends with an index of their sizes
+// framed by a marker byte on both sides. None if `data` isn't one.
+pub fn superframe_frames(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let marker = *data.last()?;
+    if marker & 0xe0 != 0xc0 {
+        return None;
+    }
+    let frames = (marker & 0x07) as usize + 1;
+    let size_len = ((marker >> 3) & 0x03) as usize + 1;
+    let index_len = 2 + size_len * frames;
+    let index_start = data.len().checked_sub(index_len)?;
+    if data[index_start] != marker {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(frames);
+    let mut offset = 0;
+    for size in data[index_start + 1..data.len() - 1].chunks(size_len) {
+        let size = size.iter().rev().fold(0, |size, &byte| size << 8 | byte as usize);
+        let end = offset + size;
+        if end > index_start {
+            return None;
+        }
+        result.push(&data[offset..end]);
+        offset = end;
+    }
+    Some(result)
+}
+
+// The show_frame flag of a VP9 frame's uncompressed header, true for a
+// show_existing_frame as that shows an earlier one
+pub fn vp9_show_frame(frame: &[u8]) -> bool {
+    let byte = match frame.first() {
+        Some(byte) => *byte,
+        None => return false,
+    };
+    // frame_marker, then the profile's low and high bits and, for profile
+    // 3, a reserved bit
+    let profile = (byte >> 5 & 1) | (byte >> 3 & 2);
+    let bit = if profile == 3 { 5 } else { 4 };
+    let show_existing_frame = byte >> (7 - bit) & 1 == 1;
+    // otherwise frame_type comes first, then show_frame
+    show_existing_frame || byte >> (7 - bit - 2) & 1 == 1
+}
+
+fn split_superframe(packet: Packet) -> Vec<Packet> {
+    let frames = match superframe_frames(&packet.data) {
+        Some(frames) => frames,
+        None => return vec![packet],
+    };
+    frames.iter()
+        .enumerate()
+        .map(|(i, frame)| Packet {
+            keyframe: packet.keyframe && i == 0,
+            show_frame: vp9_show_frame(frame),
+            data: frame.to_vec(),
+            ..packet.clone()
+        })
+        .collect()
+}
+
+// Iterates the same packets as `Packets` backwards: clusters last to first,
+// and within each cluster latest timestamp first. Packets with the same
+// timestamp, like the frames of a laced block, come in reverse file order.
+pub struct PacketsRev {
+    clusters: Vec<ClusterNode>,
+    pending: Vec<Packet>,
+}
+
+impl PacketsRev {
+    pub fn new(clusters: Vec<ClusterNode>) -> PacketsRev {
+        PacketsRev {
+            clusters,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Iterator for PacketsRev {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        while self.pending.is_empty() {
+            self.pending = cluster_packets_rev(self.clusters.pop()?);
+        }
+        self.pending.pop()
+    }
+}
+
+// A cluster's packets in reverse presentation order, stored back to front
+// so they can be popped off the end
+pub(crate) fn cluster_packets_rev(cluster: ClusterNode) -> Vec<Packet> {
+    let mut packets: Vec<Packet> = Packets::new(vec![cluster]).collect();
+    packets.sort_by_key(|packet| packet.timestamp);
+    packets
+}
+
+fn lacing_from_flags(flags: u8) -> Lacing {
+    match flags & 0x06 {
+        0x02 => Lacing::Xiph,
+        0x04 => Lacing::FixedSize,
+        0x06 => Lacing::EBML,
+        _    => Lacing::None,
+    }
+}
+
+fn parse_laced_frames(lacing: Lacing, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let count = *data.first()? as usize + 1;
+    // a single frame has no lace sizes, whatever the lacing
+    if count == 1 {
+        return Some(vec![data[1..].to_vec()]);
+    }
+    let mut pos = 1;
+    let mut sizes: Vec<usize> = Vec::with_capacity(count);
+
+    match lacing {
+        Lacing::Xiph => {
+            for _ in 0..count - 1 {
+                let mut size = 0;
+                loop {
+                    let b = *data.get(pos)?;
+                    pos += 1;
+                    size += b as usize;
+                    if b != 0xff {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        },
+        Lacing::EBML => {
+            let (first, len) = vint::parse(data.get(pos..)?)?;
+            pos += len;
+            sizes.push(first as usize);
+            let mut prev = first as i64;
+            for _ in 1..count - 1 {
+                let (raw, len) = vint::parse(data.get(pos..)?)?;
+                pos += len;
+                // sizes after the first are stored as signed differences
+                let bias = (1i64 << (7 * len - 1)) - 1;
+                prev = prev.checked_add(raw as i64 - bias).filter(|size| *size >= 0)?;
+                sizes.push(prev as usize);
+            }
+        },
+        Lacing::FixedSize => {
+            let remaining = data.len() - pos;
+            if !remaining.is_multiple_of(count) {
+                return None;
+            }
+            sizes = vec![remaining / count; count - 1];
+        },
+        Lacing::None => unreachable!(),
+    }
+
+    // the last frame takes whatever is left
+    let used = sizes.iter().try_fold(pos, |total, size| total.checked_add(*size))?;
+    sizes.push(data.len().checked_sub(used)?);
+
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        frames.push(data.get(pos..pos + size)?.to_vec());
+        pos += size;
+    }
+    Some(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ebml::WebmFile;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    #[test]
+    fn test_parse_simple_block() {
+        let block = Block::parse(&[0x81, 0x00, 0x10, 0x80, 0xaa, 0xbb]).unwrap();
+        assert_eq!(block.track, 1);
+        assert_eq!(block.timecode, 16);
+        assert!(block.is_keyframe());
+        assert_eq!(block.lacing(), Lacing::None);
+        assert_eq!(block.frames, vec![vec![0xaa, 0xbb]]);
+        assert_eq!(block.timestamp(100), 116);
+    }
+
+    #[test]
+    fn test_parse_negative_timecode() {
+        let block = Block::parse(&[0x82, 0xff, 0xfe, 0x00, 0x01]).unwrap();
+        assert_eq!(block.track, 2);
+        assert_eq!(block.timecode, -2);
+        assert!(!block.is_keyframe());
+        assert_eq!(block.timestamp(1), 0);
+    }
+
+    #[test]
+    fn test_parse_xiph_lacing() {
+        let mut data = vec![0x81, 0x00, 0x00, 0x02, 0x02, 0xff, 0x01, 0x02];
+        data.extend(vec![1; 256]);
+        data.extend(vec![2; 2]);
+        data.extend(vec![3; 3]);
+        let block = Block::parse(&data).unwrap();
+        assert_eq!(block.lacing(), Lacing::Xiph);
+        assert_eq!(block.frames, vec![vec![1; 256], vec![2; 2], vec![3; 3]]);
+    }
+
+    #[test]
+    fn test_parse_ebml_lacing() {
+        // sizes 3, 4 (+1 as 0xc0 in a 1-byte signed vint), then the rest
+        let data = [0x81, 0x00, 0x00, 0x06, 0x02, 0x83, 0xc0, 1, 1, 1, 2, 2, 2, 2, 3];
+        let block = Block::parse(&data).unwrap();
+        assert_eq!(block.lacing(), Lacing::EBML);
+        assert_eq!(block.frames, vec![vec![1; 3], vec![2; 4], vec![3]]);
+
+        // one frame, so no sizes at all
+        let block = Block::parse(&[0x81, 0x00, 0x00, 0x06, 0x00, 0x83, 1, 2]).unwrap();
+        assert_eq!(block.frames, vec![vec![0x83, 1, 2]]);
+    }
+
+    #[test]
+    fn test_parse_fixed_lacing() {
+        let data = [0x81, 0x00, 0x00, 0x04, 0x01, 1, 1, 2, 2];
+        let block = Block::parse(&data).unwrap();
+        assert_eq!(block.frames, vec![vec![1, 1], vec![2, 2]]);
+        assert!(Block::parse(&[0x81, 0x00, 0x00, 0x04, 0x01, 1, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        assert!(Block::parse(&[0x81, 0x00]).is_none());
+        assert!(Block::parse(&[0x81, 0x00, 0x00, 0x02, 0x01, 0xff]).is_none());
+    }
+
+    #[test]
+    fn test_superframes() {
+        // a hidden alt-ref frame followed by a shown inter frame
+        let mut superframe = vec![0x84, 1, 2, 3, 0x86, 4, 5];
+        superframe.extend([0xc1, 4, 3, 0xc1]);
+        assert_eq!(superframe_frames(&superframe), Some(vec![&[0x84, 1, 2, 3][..], &[0x86, 4, 5][..]]));
+        assert_eq!(superframe_frames(&[0x86, 4, 5]), None);
+        // sizes past the index
+        assert_eq!(superframe_frames(&[0x84, 0xc1, 4, 3, 0xc1]), None);
+
+        assert!(!vp9_show_frame(&[0x84]));
+        assert!(vp9_show_frame(&[0x86]));
+        // show_existing_frame, and a shown keyframe in profile 3
+        assert!(vp9_show_frame(&[0x88]));
+        assert!(vp9_show_frame(&[0xb1]));
+
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .cluster(0)
+            .frame(1, 0, true, &superframe)
+            .frame(2, 0, true, &superframe)
+            .frame(1, 33, false, &[0x86, 6])
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.packets().count(), 3);
+        let packets: Vec<Packet> = document.packets().split_superframes([1]).collect();
+        let summary: Vec<(u64, u64, bool, bool, usize)> = packets.iter()
+            .map(|p| (p.track, p.timestamp, p.keyframe, p.show_frame, p.data.len()))
+            .collect();
+        assert_eq!(summary, vec![
+            (1, 0, true, false, 4),
+            (1, 0, false, true, 3),
+            (2, 0, true, true, superframe.len()),
+            (1, 33, false, true, 2),
+        ]);
+    }
+}
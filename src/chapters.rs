@@ -0,0 +1,345 @@
+use crate::consts::*;
+use crate::ebml::{ChaptersNode, EditionEntryNode, Element, Node, WebmFile};
+use crate::format::parse_timestamp;
+use crate::index::Index;
+use crate::linking::SegmentUid;
+
+// A titled start time, in nanoseconds like ChapterTimeStart
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterEntry {
+    pub title: String,
+    pub start: u64,
+}
+
+// A single edition with one chapter per entry, in start time order
+pub fn chapters_from_entries(entries: &[ChapterEntry], language: &str) -> ChaptersNode {
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|entry| entry.start);
+
+    let mut edition = vec![Node::from(Element::uint(ID_EDITIONUID, uid()))];
+    for entry in entries {
+        let display = Element::master(ID_CHAPTERDISPLAYNODE, vec![
+            Element::string(ID_CHAPSTRING, &entry.title).into(),
+            Element::string(ID_CHAPLANGUAGE, language).into(),
+        ]);
+        edition.push(Element::master(ID_CHAPTERATOMNODE, vec![
+            Element::uint(ID_CHAPTERUID, uid()).into(),
+            Element::uint(ID_CHAPTERTIMESTART, entry.start).into(),
+            display,
+        ]));
+    }
+
+    let edition = Element::master(ID_EDITIONENTRYNODE, edition);
+    ChaptersNode::from(Element::master(ID_CHAPTERSNODE, vec![edition]))
+}
+
+// Quick navigation for long recordings: a chapter at each of the given
+// start times, named "Chapter 1", "Chapter 2" and so on
+pub fn chapters_at(start_times: &[u64]) -> ChaptersNode {
+    let mut times = start_times.to_vec();
+    times.sort_unstable();
+    times.dedup();
+
+    let entries: Vec<ChapterEntry> = times.into_iter()
+        .enumerate()
+        .map(|(i, start)| ChapterEntry { title: format!("Chapter {}", i + 1), start })
+        .collect();
+    chapters_from_entries(&entries, "eng")
+}
+
+// Read chapter entries from either a CUE sheet (TRACK, TITLE and INDEX 01
+// lines, with MM:SS:FF times at 75 frames a second) or a plain list of
+// `HH:MM:SS[.nnn] Title` lines as found in video descriptions. Blank
+// lines are skipped. None if a line can't be understood.
+pub fn parse_cue_sheet(text: &str) -> Option<Vec<ChapterEntry>> {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let is_cue_sheet = lines.iter().any(|line| line.starts_with("TRACK "));
+    if !is_cue_sheet {
+        return lines.iter()
+            .map(|line| {
+                let (time, title) = line.split_once(char::is_whitespace)?;
+                Some(ChapterEntry { title: title.trim().to_string(), start: parse_timestamp(time)? })
+            })
+            .collect();
+    }
+
+    let mut entries = Vec::new();
+    let mut title = None;
+    let mut in_track = false;
+    for line in lines {
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match command {
+            "TRACK" => {
+                in_track = true;
+                title = None;
+            },
+            // a TITLE before the first TRACK names the whole disc
+            "TITLE" if in_track => title = Some(rest.trim().trim_matches('"').to_string()),
+            "INDEX" if in_track => {
+                let (number, time) = rest.trim().split_once(char::is_whitespace)?;
+                if number == "01" {
+                    let title = title.clone().unwrap_or_else(|| format!("Track {}", entries.len() + 1));
+                    entries.push(ChapterEntry { title, start: parse_cue_time(time.trim())? });
+                }
+            },
+            _ => (),
+        }
+    }
+    Some(entries)
+}
+
+// MM:SS:FF, where there are 75 frames in a second
+fn parse_cue_time(time: &str) -> Option<u64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= 75 {
+        return None;
+    }
+    let seconds = minutes.checked_mul(60)?.checked_add(seconds)?;
+    seconds.checked_mul(1_000_000_000)?.checked_add(frames * 1_000_000_000 / 75)
+}
+
+// A chapter roughly every `interval` nanoseconds. Each chapter starts on
+// the first video keyframe at least `interval` after the previous one, so
+// players can seek straight to it. Files without video use exact multiples
+// of the interval up to the duration.
+pub fn chapters_every(document: &WebmFile, interval: u64) -> ChaptersNode {
+    let interval = interval.max(1);
+    let index = Index::build(document);
+    let scale = index.timestamp_scale;
+    let video = index.tracks.iter().find(|t| t.track_type == 1).map(|t| t.number);
+
+    let mut times = Vec::new();
+    match video {
+        Some(track) => {
+            for keyframe in index.keyframes.iter().filter(|k| k.track == track) {
+                let time = keyframe.timestamp.saturating_mul(scale);
+                if times.last().is_none_or(|&last: &u64| time >= last.saturating_add(interval)) {
+                    times.push(time);
+                }
+            }
+        },
+        None => {
+            let duration = document.root.get_info_nodes()
+                .first()
+                .and_then(|info| info.get_duration())
+                .map(|ticks| (ticks * scale as f64) as u64)
+                .unwrap_or(0);
+            times.push(0);
+            while let Some(next) = times.last().and_then(|t| t.checked_add(interval)) {
+                if next >= duration {
+                    break;
+                }
+                times.push(next);
+            }
+        },
+    }
+    chapters_at(&times)
+}
+
+// One step of an ordered edition's playback: the range of a source
+// segment a chapter plays and where that starts on the virtual timeline,
+// all in nanoseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelinePart {
+    pub chapter_uid: u64,
+    // None for the document's own segment, otherwise the linked segment to
+    // play from and the edition to use there, if one is named
+    pub segment: Option<SegmentUid>,
+    pub segment_edition: Option<u64>,
+    pub start: u64,
+    pub end: u64,
+    pub timeline_start: u64,
+}
+
+// The playback timeline of an ordered edition: each enabled top level
+// chapter in turn, from its ChapterTimeStart to its ChapterTimeEnd. Linked
+// segments can be found by their SegmentUUID, e.g. among the files passed
+// to `linking::resolve_timeline`. None if the edition isn't ordered or a
+// chapter has no end, or ends before it starts.
+pub fn ordered_timeline(document: &WebmFile, edition: &EditionEntryNode) -> Option<Vec<TimelinePart>> {
+    if !edition.is_ordered() {
+        return None;
+    }
+    let own_uid = document.root.get_info_nodes().first().and_then(|info| info.get_segment_uid());
+
+    let mut parts = Vec::new();
+    let mut timeline_start = 0u64;
+    for atom in edition.get_chapter_atoms().iter().filter(|atom| atom.is_enabled()) {
+        let start = atom.get_start_time();
+        let end = atom.get_end_time().filter(|&end| end >= start)?;
+        let segment = atom.get_segment_uid().filter(|&uid| Some(uid) != own_uid);
+        parts.push(TimelinePart {
+            chapter_uid: atom.get_uid(),
+            segment,
+            segment_edition: segment.and(atom.get_segment_edition_uid()),
+            start,
+            end,
+            timeline_start,
+        });
+        timeline_start = timeline_start.saturating_add(end - start);
+    }
+    Some(parts)
+}
+
+// Replace the document's Chapters, or add them before the first Cluster,
+// ready for `WebmFile::write_to`
+pub fn set_chapters(document: &mut WebmFile, chapters: ChaptersNode) {
+    let root = document.root.as_node_mut();
+    let children = root.get_children();
+    let existing = children.iter().position(|n| n.get_element().id == ID_CHAPTERSNODE);
+    if let Some(index) = existing {
+        root.remove_child(index);
+    }
+    let index = existing.unwrap_or_else(|| {
+        children.iter()
+            .position(|n| n.get_element().id == ID_CLUSTERNODE)
+            .unwrap_or(children.len())
+    });
+    root.insert_child(index, chapters.as_node().clone());
+}
+
+// ChapterUID and EditionUID must be unique and non-zero
+fn uid() -> u64 {
+    let random = SegmentUid::generate();
+    let mut half = [0u8; 8];
+    half.copy_from_slice(&random.as_bytes()[..8]);
+    u64::from_be_bytes(half).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn start_times(chapters: &ChaptersNode) -> Vec<u64> {
+        chapters.get_edition_entries()[0]
+            .get_chapter_atoms()
+            .iter()
+            .map(|atom| atom.get_start_time())
+            .collect()
+    }
+
+    #[test]
+    fn test_chapters_at() {
+        let chapters = chapters_at(&[60_000_000_000, 0, 0]);
+        assert_eq!(start_times(&chapters), vec![0, 60_000_000_000]);
+
+        let atom = &chapters.get_edition_entries()[0].get_chapter_atoms()[1];
+        assert_ne!(atom.get_uid(), 0);
+        assert_eq!(atom.get_displays()[0].get_string(), "Chapter 2");
+        assert_eq!(atom.get_displays()[0].get_languages(), vec!["eng".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_cue_sheet() {
+        let sheet = "PERFORMER \"Someone\"\n\
+            TITLE \"Album\"\n\
+            FILE \"album.wav\" WAVE\n\
+            \x20 TRACK 01 AUDIO\n\
+            \x20   TITLE \"Intro\"\n\
+            \x20   INDEX 01 00:00:00\n\
+            \x20 TRACK 02 AUDIO\n\
+            \x20   TITLE \"Second Song\"\n\
+            \x20   INDEX 00 03:58:00\n\
+            \x20   INDEX 01 04:00:37\n";
+        let entries = parse_cue_sheet(sheet).unwrap();
+        assert_eq!(entries, vec![
+            ChapterEntry { title: "Intro".to_string(), start: 0 },
+            ChapterEntry { title: "Second Song".to_string(), start: 240_493_333_333 },
+        ]);
+        assert!(parse_cue_sheet("TRACK 01 AUDIO\nINDEX 01 00:00:99").is_none());
+
+        let list = "00:00:00 Opening\n\n00:12:30.5 The middle bit\n";
+        let entries = parse_cue_sheet(list).unwrap();
+        assert_eq!(entries[1], ChapterEntry { title: "The middle bit".to_string(), start: 750_500_000_000 });
+        assert!(parse_cue_sheet("soon Opening").is_none());
+
+        let chapters = chapters_from_entries(&entries, "und");
+        let atoms = chapters.get_edition_entries()[0].get_chapter_atoms();
+        assert_eq!(atoms[0].get_displays()[0].get_string(), "Opening");
+        assert_eq!(atoms[1].get_start_time(), 750_500_000_000);
+    }
+
+    #[test]
+    fn test_chapters_every() {
+        // keyframes every 400ms for 4 seconds
+        let mut builder = WebmBuilder::new().video_track(1, "V_VP9", 64, 64);
+        for second in 0..4 {
+            builder = builder.cluster(second * 1000);
+            for i in 0..10 {
+                builder = builder.frame(1, i * 100, i % 4 == 0, &[0; 4]);
+            }
+        }
+        let document = WebmFile::from_bytes(&builder.build()).unwrap();
+        let chapters = chapters_every(&document, 1_000_000_000);
+        let times: Vec<u64> = start_times(&chapters).iter().map(|t| t / 1_000_000).collect();
+        assert_eq!(times, vec![0, 1000, 2000, 3000]);
+
+        let audio = WebmBuilder::new().duration(2500.0).audio_track(1, "A_OPUS", 48000.0, 2).build();
+        let document = WebmFile::from_bytes(&audio).unwrap();
+        let times = start_times(&chapters_every(&document, 1_000_000_000));
+        assert_eq!(times, vec![0, 1_000_000_000, 2_000_000_000]);
+    }
+
+    #[test]
+    fn test_ordered_timeline() {
+        let linked = SegmentUid::generate();
+        let atom = |uid: u64, start: u64, end: u64, extra: Vec<Node>| {
+            let mut children = vec![
+                Element::uint(ID_CHAPTERUID, uid).into(),
+                Element::uint(ID_CHAPTERTIMESTART, start).into(),
+                Element::uint(ID_CHAPTERTIMEEND, end).into(),
+            ];
+            children.extend(extra);
+            Element::master(ID_CHAPTERATOMNODE, children)
+        };
+        let edition = |ordered: u64, atoms: Vec<Node>| {
+            let mut children = vec![Node::from(Element::uint(ID_EDITIONFLAGORDERED, ordered))];
+            children.extend(atoms);
+            EditionEntryNode::from(Element::master(ID_EDITIONENTRYNODE, children))
+        };
+        let document = WebmFile::from_bytes(&WebmBuilder::new().build()).unwrap();
+
+        let ordered = edition(1, vec![
+            atom(1, 10_000, 20_000, vec![]),
+            atom(2, 0, 5_000, vec![Element::uint(ID_CHAPTERFLAGENABLED, 0).into()]),
+            atom(3, 0, 5_000, vec![
+                Element::binary(ID_CHAPTERSEGMENTUUID, linked.as_bytes()).into(),
+                Element::uint(ID_CHAPTERSEGMENTEDITIONUID, 7).into(),
+            ]),
+            atom(4, 30_000, 40_000, vec![]),
+        ]);
+        let timeline = ordered_timeline(&document, &ordered).unwrap();
+        let parts: Vec<(u64, u64, u64, u64)> = timeline.iter()
+            .map(|part| (part.chapter_uid, part.start, part.end, part.timeline_start))
+            .collect();
+        assert_eq!(parts, vec![(1, 10_000, 20_000, 0), (3, 0, 5_000, 10_000), (4, 30_000, 40_000, 15_000)]);
+        assert_eq!(timeline[0].segment, None);
+        assert_eq!((timeline[1].segment, timeline[1].segment_edition), (Some(linked), Some(7)));
+
+        assert!(ordered_timeline(&document, &edition(0, vec![atom(1, 0, 10, vec![])])).is_none());
+        assert!(ordered_timeline(&document, &edition(1, vec![atom(1, 10, 0, vec![])])).is_none());
+    }
+
+    #[test]
+    fn test_set_chapters() {
+        let data = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).frame(1, 0, true, &[0; 4]).build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        set_chapters(&mut document, chapters_at(&[0]));
+        set_chapters(&mut document, chapters_at(&[0, 5]));
+
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        let saved = WebmFile::from_bytes(out.get_ref()).unwrap();
+        let chapters = saved.root.get_chapters();
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(start_times(&chapters[0]), vec![0, 5]);
+        assert_eq!(chapters[0], document.root.get_chapters()[0]);
+
+        let ids: Vec<u64> = saved.root.get_children().iter().map(|n| n.get_element().id).collect();
+        let position = |id| ids.iter().position(|&i| i == id).unwrap();
+        assert!(position(ID_CHAPTERSNODE) < position(ID_CLUSTERNODE));
+    }
+}
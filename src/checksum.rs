@@ -0,0 +1,411 @@
+use std::io::{Read, Seek, SeekFrom, Write, Error as IOError};
+#[cfg(feature = "sha256")]
+use sha2::{Digest, Sha256};
+use crate::consts::ID_CRC32;
+use crate::ebml::{Element, Node, WebmFile};
+use crate::error::invalid_input;
+
+// Per frame checksums, so an archive can check that its media survived
+// intact even when the container around it was rewritten (remuxed, tags
+// edited, Cues added). Only frame payloads are hashed, never headers.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    // CRC-32 as used by EBML and zlib, always available
+    #[default]
+    Crc32,
+    #[cfg(feature = "xxhash")]
+    Xxh3,
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Crc32 => "crc32",
+            #[cfg(feature = "xxhash")]
+            Algorithm::Xxh3 => "xxh3",
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+
+    // Big-endian for the integer checksums
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Crc32 => crc32(data).to_be_bytes().to_vec(),
+            #[cfg(feature = "xxhash")]
+            Algorithm::Xxh3 => xxhash_rust::xxh3::xxh3_64(data).to_be_bytes().to_vec(),
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameDigest {
+    // in TimestampScale units
+    pub timestamp: u64,
+    pub size: usize,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackDigests {
+    pub track: u64,
+    // in file order
+    pub frames: Vec<FrameDigest>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumReport {
+    pub algorithm: Algorithm,
+    // in order of first appearance
+    pub tracks: Vec<TrackDigests>,
+}
+
+impl ChecksumReport {
+    pub fn new(document: &WebmFile, algorithm: Algorithm) -> ChecksumReport {
+        let mut tracks: Vec<TrackDigests> = Vec::new();
+        for packet in document.packets() {
+            let frame = FrameDigest {
+                timestamp: packet.timestamp,
+                size: packet.data.len(),
+                digest: algorithm.digest(&packet.data),
+            };
+            match tracks.iter_mut().find(|t| t.track == packet.track) {
+                Some(track) => track.frames.push(frame),
+                None => tracks.push(TrackDigests { track: packet.track, frames: vec![frame] }),
+            }
+        }
+        ChecksumReport { algorithm, tracks }
+    }
+
+    // Frames that differ between two reports, as (track, frame index)
+    // pairs. Frames missing from either side count as differing.
+    pub fn mismatches(&self, other: &ChecksumReport) -> Vec<(u64, usize)> {
+        let mut mismatches = Vec::new();
+        let mut tracks: Vec<u64> = Vec::new();
+        for track in self.tracks.iter().chain(&other.tracks) {
+            if !tracks.contains(&track.track) {
+                tracks.push(track.track);
+            }
+        }
+        for track in tracks {
+            let ours = self.frames(track);
+            let theirs = other.frames(track);
+            for i in 0..ours.len().max(theirs.len()) {
+                if ours.get(i) != theirs.get(i) {
+                    mismatches.push((track, i));
+                }
+            }
+        }
+        mismatches
+    }
+
+    // One tab separated line per frame: track, timestamp, size and hex
+    // digest, grouped by track after a `#` line naming the algorithm
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<(), IOError> {
+        writeln!(out, "#algorithm\t{}", self.algorithm.name())?;
+        for track in &self.tracks {
+            for frame in &track.frames {
+                let hex: String = frame.digest.iter().map(|b| format!("{:02x}", b)).collect();
+                writeln!(out, "{}\t{}\t{}\t{}", track.track, frame.timestamp, frame.size, hex)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn frames(&self, track: u64) -> &[FrameDigest] {
+        self.tracks.iter()
+            .find(|t| t.track == track)
+            .map(|t| &t.frames[..])
+            .unwrap_or(&[])
+    }
+}
+
+// A digest fed piece by piece, giving the same result as
+// `Algorithm::digest` over all the pieces at once
+pub struct Hasher {
+    state: State,
+}
+
+enum State {
+    Crc32(u32),
+    #[cfg(feature = "xxhash")]
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    #[cfg(feature = "sha256")]
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Hasher {
+        let state = match algorithm {
+            Algorithm::Crc32 => State::Crc32(0),
+            #[cfg(feature = "xxhash")]
+            Algorithm::Xxh3 => State::Xxh3(Box::default()),
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => State::Sha256(Sha256::new()),
+        };
+        Hasher { state }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            State::Crc32(crc) => *crc = crc32_update(*crc, data),
+            #[cfg(feature = "xxhash")]
+            State::Xxh3(xxh3) => xxh3.update(data),
+            #[cfg(feature = "sha256")]
+            State::Sha256(sha256) => sha256.update(data),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        match self.state {
+            State::Crc32(crc) => crc.to_be_bytes().to_vec(),
+            #[cfg(feature = "xxhash")]
+            State::Xxh3(xxh3) => xxh3.digest().to_be_bytes().to_vec(),
+            #[cfg(feature = "sha256")]
+            State::Sha256(sha256) => sha256.finalize().to_vec(),
+        }
+    }
+}
+
+// Hashes a whole file while it's being parsed, so ingestion doesn't need a
+// second pass for a content hash:
+//
+//     let mut input = HashingReader::new(file, Algorithm::Sha256);
+//     let document = WebmReader::new(&mut input).parse()?;
+//     let hash = input.finish()?;
+//
+// Bytes are hashed in file order however the parser moves around. Reading
+// them again after seeking back doesn't count twice, and anything the parser
+// seeks past, like skipped elements, is read into the hash when it next
+// reads further on. `finish` hashes whatever the parser never got to.
+pub struct HashingReader<R: Read + Seek> {
+    inner: R,
+    hasher: Hasher,
+    // where the parser is, where `inner` is and how much has been hashed
+    position: u64,
+    inner_position: u64,
+    hashed: u64,
+}
+
+impl<R: Read + Seek> HashingReader<R> {
+    // `inner` is hashed from where it is now
+    pub fn new(mut inner: R, algorithm: Algorithm) -> HashingReader<R> {
+        let start = inner.stream_position().unwrap_or(0);
+        HashingReader {
+            inner,
+            hasher: Hasher::new(algorithm),
+            position: start,
+            inner_position: start,
+            hashed: start,
+        }
+    }
+
+    // Bytes hashed so far, less any the stream started after
+    pub fn hashed(&self) -> u64 {
+        self.hashed
+    }
+
+    // Hash the rest of the stream and return the digest
+    pub fn finish(mut self) -> Result<Vec<u8>, IOError> {
+        self.catch_up(u64::MAX)?;
+        Ok(self.hasher.finish())
+    }
+
+    // Hash from where hashing stopped up to `offset`, or the end of the
+    // stream if that comes first
+    fn catch_up(&mut self, offset: u64) -> Result<(), IOError> {
+        if self.hashed >= offset {
+            return Ok(());
+        }
+        self.move_inner(self.hashed)?;
+        let mut buf = vec![0; 64 * 1024];
+        while self.hashed < offset {
+            let len = (offset - self.hashed).min(buf.len() as u64) as usize;
+            let read = self.inner.read(&mut buf[..len])?;
+            if read == 0 {
+                break;
+            }
+            self.hasher.update(&buf[..read]);
+            self.hashed += read as u64;
+            self.inner_position += read as u64;
+        }
+        Ok(())
+    }
+
+    fn move_inner(&mut self, offset: u64) -> Result<(), IOError> {
+        if self.inner_position != offset {
+            self.inner_position = self.inner.seek(SeekFrom::Start(offset))?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IOError> {
+        self.catch_up(self.position)?;
+        self.move_inner(self.position)?;
+        let read = self.inner.read(buf)?;
+        let end = self.position + read as u64;
+        if self.hashed >= self.position && self.hashed < end {
+            self.hasher.update(&buf[(self.hashed - self.position) as usize..read]);
+            self.hashed = end;
+        }
+        self.position = end;
+        self.inner_position = end;
+        Ok(read)
+    }
+}
+
+// Seeking is lazy, `inner` only moves once there's something to read
+impl<R: Read + Seek> Seek for HashingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, IOError> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta)
+                .ok_or_else(|| invalid_input("seek before the start"))?,
+            SeekFrom::End(_) => {
+                self.inner_position = self.inner.seek(pos)?;
+                self.inner_position
+            },
+        };
+        Ok(self.position)
+    }
+}
+
+// Recompute a CRC-32 at the start of a master after editing its children
+pub(crate) fn update_crc32(node: &mut Node) {
+    if node.children().first().is_none_or(|child| child.element().id != ID_CRC32) {
+        return;
+    }
+    let data: Vec<u8> = node.children()[1..].iter().flat_map(Node::to_bytes).collect();
+    node.remove_child(0);
+    node.insert_child(0, Element::binary(ID_CRC32, &crc32(&data).to_le_bytes()).into());
+}
+
+// Reflected CRC-32 with polynomial 0xedb88320
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// Continue a CRC-32 from its value over the data before `data`
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::consts::*;
+    use crate::ebml::WebmReader;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn builder() -> WebmBuilder {
+        WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, b"123456789")
+            .frame(2, 0, true, &[1])
+            .frame(1, 40, false, &[2, 3])
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_hasher() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let algorithms = [
+            Algorithm::Crc32,
+            #[cfg(feature = "xxhash")]
+            Algorithm::Xxh3,
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256,
+        ];
+        for &algorithm in algorithms.iter() {
+            let mut hasher = Hasher::new(algorithm);
+            for piece in data.chunks(77) {
+                hasher.update(piece);
+            }
+            assert_eq!(hasher.finish(), algorithm.digest(&data));
+        }
+    }
+
+    #[test]
+    fn test_hashing_reader() {
+        let sample = std::fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let expected = Algorithm::Crc32.digest(&sample);
+
+        let mut input = HashingReader::new(Cursor::new(&sample), Algorithm::Crc32);
+        let document = WebmReader::new(&mut input).parse().unwrap();
+        assert_eq!(input.hashed(), sample.len() as u64);
+        assert_eq!(input.finish().unwrap(), expected);
+        assert_eq!(document.packets().count(), 2308);
+
+        // Clusters are seeked past and hashed on the next read
+        let mut input = HashingReader::new(Cursor::new(&sample), Algorithm::Crc32);
+        let document = WebmReader::new(&mut input).skip_elements(vec![ID_CLUSTERNODE]).parse().unwrap();
+        assert!(document.root.get_clusters().is_empty());
+        assert_eq!(input.finish().unwrap(), expected);
+
+        // only the header, the rest is hashed by `finish`
+        let mut input = HashingReader::new(Cursor::new(&sample), Algorithm::Crc32);
+        WebmReader::new(&mut input).parse_header().unwrap();
+        assert!(input.hashed() < sample.len() as u64);
+        assert_eq!(input.finish().unwrap(), expected);
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxh3() {
+        assert_eq!(Algorithm::Xxh3.digest(b""), 0x2d06_8005_38d3_94c2u64.to_be_bytes().to_vec());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_sha256() {
+        let digest = Algorithm::Sha256.digest(b"abc");
+        assert_eq!(&digest[..4], &[0xba, 0x78, 0x16, 0xbf]);
+        assert_eq!(digest.len(), 32);
+    }
+
+    #[test]
+    fn test_checksum_report() {
+        let document = WebmFile::from_bytes(&builder().build()).unwrap();
+        let report = ChecksumReport::new(&document, Algorithm::Crc32);
+        let tracks: Vec<(u64, usize)> = report.tracks.iter().map(|t| (t.track, t.frames.len())).collect();
+        assert_eq!(tracks, vec![(1, 2), (2, 1)]);
+        assert_eq!(report.tracks[0].frames[0].digest, vec![0xcb, 0xf4, 0x39, 0x26]);
+
+        let mut out = Vec::new();
+        report.write_to(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().next(), Some("#algorithm\tcrc32"));
+        assert_eq!(text.lines().nth(1), Some("1\t0\t9\tcbf43926"));
+        assert_eq!(text.lines().count(), 4);
+
+        // the same frames in a different container layout still match
+        let remuxed = builder().without_cues().unknown_size_clusters().build();
+        let other = ChecksumReport::new(&WebmFile::from_bytes(&remuxed).unwrap(), Algorithm::Crc32);
+        assert!(report.mismatches(&other).is_empty());
+
+        let changed = builder().frame(2, 60, true, &[4]).build();
+        let other = ChecksumReport::new(&WebmFile::from_bytes(&changed).unwrap(), Algorithm::Crc32);
+        assert_eq!(report.mismatches(&other), vec![(2, 1)]);
+    }
+}
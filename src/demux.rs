@@ -0,0 +1,264 @@
+use crate::block::{Packet, Packets};
+use crate::consts::*;
+use crate::ebml::{Element, Node, ProjectionNode, WebmFile, TrackEntryNode};
+
+// Everything a decoder needs to be set up for a track
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecParameters {
+    pub codec_id: String,
+    pub codec_private: Option<Vec<u8>>,
+    // both in nanoseconds
+    pub codec_delay: u64,
+    pub seek_preroll: u64,
+    pub default_duration: Option<u64>,
+    pub pixel_width: Option<u64>,
+    pub pixel_height: Option<u64>,
+    // how to turn or map the picture for display
+    pub projection: Option<Projection>,
+    pub sampling_frequency: Option<f64>,
+    pub channels: Option<u64>,
+    pub bit_depth: Option<u64>,
+}
+
+// The Projection of a video track, with the pose in degrees
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Projection {
+    // 0 rectangular, 1 equirectangular, 2 cubemap, 3 mesh
+    pub projection_type: u64,
+    pub private: Option<Vec<u8>>,
+    pub pose_yaw: f64,
+    pub pose_pitch: f64,
+    pub pose_roll: f64,
+}
+
+impl Projection {
+    // Flat video shown turned `degrees` counter-clockwise, so -90 for a
+    // recording that needs a quarter turn clockwise to stand upright
+    pub fn rotation(degrees: f64) -> Projection {
+        let roll = degrees.rem_euclid(360.0);
+        Projection {
+            pose_roll: if roll > 180.0 { roll - 360.0 } else { roll },
+            ..Projection::default()
+        }
+    }
+
+    // Whether the type is known and the pose is in the ranges the spec
+    // allows
+    pub fn is_valid(&self) -> bool {
+        self.projection_type <= 3
+            && (-180.0..=180.0).contains(&self.pose_yaw)
+            && (-90.0..=90.0).contains(&self.pose_pitch)
+            && (-180.0..=180.0).contains(&self.pose_roll)
+    }
+
+    pub fn to_node(&self) -> Node {
+        let mut children = vec![Node::from(Element::uint(ID_PROJECTIONTYPE, self.projection_type))];
+        if let Some(private) = &self.private {
+            children.push(Element::binary(ID_PROJECTIONPRIVATE, private).into());
+        }
+        children.extend([
+            Node::from(Element::float(ID_PROJECTIONPOSEYAW, self.pose_yaw)),
+            Element::float(ID_PROJECTIONPOSEPITCH, self.pose_pitch).into(),
+            Element::float(ID_PROJECTIONPOSEROLL, self.pose_roll).into(),
+        ]);
+        Element::master(ID_PROJECTIONNODE, children)
+    }
+}
+
+impl From<&ProjectionNode> for Projection {
+    fn from(projection: &ProjectionNode) -> Projection {
+        Projection {
+            projection_type: projection.get_type(),
+            private: projection.get_private(),
+            pose_yaw: projection.get_pose_yaw(),
+            pose_pitch: projection.get_pose_pitch(),
+            pose_roll: projection.get_pose_roll(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub number: u64,
+    pub uid: u64,
+    pub track_type: u64,
+    pub language: Option<String>,
+    pub codec: CodecParameters,
+}
+
+// A minimal container-agnostic demuxer interface, close to what audio and
+// video players expect from a format reader. Timestamps are in the units
+// given by `timestamp_scale` (nanoseconds per tick).
+pub trait Demuxer {
+    fn tracks(&self) -> &[Track];
+
+    fn timestamp_scale(&self) -> u64;
+
+    fn next_packet(&mut self) -> Option<Packet>;
+
+    // Move to the start of the cluster containing `timestamp`, returning the
+    // timestamp packets will resume from. Tracks with a SeekPreRoll start
+    // that much earlier, and their packets before `timestamp` come back with
+    // `preroll` set.
+    fn seek(&mut self, timestamp: u64) -> Option<u64>;
+}
+
+pub struct WebmDemuxer {
+    document: WebmFile,
+    tracks: Vec<Track>,
+    packets: Packets,
+    // the last seek target, until which pre-roll packets are flagged
+    seek_target: Option<u64>,
+}
+
+impl WebmDemuxer {
+    pub fn new(document: WebmFile) -> WebmDemuxer {
+        let tracks = document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .map(|entry| Track::from(&entry))
+            .collect();
+        let packets = document.packets();
+
+        WebmDemuxer {
+            document,
+            tracks,
+            packets,
+            seek_target: None,
+        }
+    }
+
+    pub fn get_document(&self) -> &WebmFile {
+        &self.document
+    }
+}
+
+impl Demuxer for WebmDemuxer {
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn timestamp_scale(&self) -> u64 {
+        self.document.root.get_info_nodes()
+            .first()
+            .map(|info| info.get_timestamp_scale())
+            .unwrap_or(1_000_000)
+    }
+
+    fn next_packet(&mut self) -> Option<Packet> {
+        let mut packet = self.packets.next()?;
+        if let Some(target) = self.seek_target {
+            packet.preroll = packet.timestamp < target && self.tracks.iter()
+                .any(|t| t.number == packet.track && t.codec.seek_preroll > 0);
+        }
+        Some(packet)
+    }
+
+    fn seek(&mut self, timestamp: u64) -> Option<u64> {
+        // SeekPreRoll is in nanoseconds, round it up to whole ticks
+        let scale = self.timestamp_scale().max(1);
+        let preroll = self.tracks.iter().map(|t| t.codec.seek_preroll).max().unwrap_or(0);
+        let from = timestamp.saturating_sub(preroll.div_ceil(scale));
+
+        let clusters = self.document.root.get_clusters();
+        let position = clusters.iter().rposition(|c| c.get_timestamp() <= from)?;
+        let start = clusters[position].get_timestamp();
+
+        self.packets = Packets::new(clusters[position..].to_vec());
+        self.seek_target = Some(timestamp);
+        Some(start)
+    }
+}
+
+impl From<&TrackEntryNode> for Track {
+    fn from(entry: &TrackEntryNode) -> Track {
+        let video = entry.get_video_settings();
+        let audio = entry.get_audio_settings();
+        Track {
+            number: entry.get_track_number(),
+            uid: entry.get_track_uid(),
+            track_type: entry.get_track_type(),
+            language: entry.get_language(),
+            codec: CodecParameters {
+                codec_id: entry.get_codec_id(),
+                codec_private: entry.get_codec_private(),
+                codec_delay: entry.get_codec_delay().unwrap_or(0),
+                seek_preroll: entry.get_seek_preroll(),
+                default_duration: entry.get_default_duration(),
+                pixel_width: video.as_ref().map(|v| v.get_pixel_width()),
+                pixel_height: video.as_ref().map(|v| v.get_pixel_height()),
+                projection: video.as_ref().and_then(|v| v.get_projection()).map(|p| Projection::from(&p)),
+                sampling_frequency: audio.as_ref().map(|a| a.get_sampling_frequency()),
+                channels: audio.as_ref().map(|a| a.get_num_channels()),
+                bit_depth: audio.as_ref().and_then(|a| a.get_bit_depth()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn demuxer() -> WebmDemuxer {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        WebmDemuxer::new(WebmFile::open(f).unwrap())
+    }
+
+    #[test]
+    fn test_tracks() {
+        let demuxer = demuxer();
+        let tracks = demuxer.tracks();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].codec.pixel_width, Some(640));
+        assert_eq!(tracks[1].codec.codec_id, "A_VORBIS");
+        assert_eq!(tracks[1].codec.sampling_frequency, Some(44100.0));
+        assert!(tracks[1].codec.codec_private.is_some());
+    }
+
+    #[test]
+    fn test_packets_and_seek() {
+        let mut demuxer = demuxer();
+        let first = demuxer.next_packet().unwrap();
+        let mut count = 1;
+        while demuxer.next_packet().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 2308);
+
+        // there is only one cluster, so seeking anywhere rewinds to it
+        assert_eq!(demuxer.seek(20_000), Some(0));
+        assert_eq!(demuxer.next_packet().unwrap().data, first.data);
+    }
+
+    #[test]
+    fn test_seek_preroll() {
+        // 80ms of pre-roll, with a cluster every 100ms
+        let mut builder = WebmBuilder::new()
+            .audio_track(1, "A_OPUS", 48000.0, 2)
+            .seek_preroll(80_000_000);
+        for cluster in 0..5 {
+            builder = builder.cluster(cluster * 100);
+            for i in 0..5 {
+                builder = builder.frame(1, i * 20, true, &[0xf8]);
+            }
+        }
+        let mut demuxer = WebmDemuxer::new(WebmFile::from_bytes(&builder.build()).unwrap());
+        assert!(demuxer.next_packet().is_some_and(|p| !p.preroll));
+
+        // 80ms before 310ms is in the cluster at 200ms
+        assert_eq!(demuxer.seek(310), Some(200));
+        let mut packets = Vec::new();
+        while let Some(packet) = demuxer.next_packet() {
+            packets.push((packet.timestamp, packet.preroll));
+        }
+        assert_eq!(&packets[..7], &[
+            (200, true), (220, true), (240, true), (260, true), (280, true), (300, true), (320, false),
+        ]);
+
+        // no pre-roll needed this far into a cluster
+        assert_eq!(demuxer.seek(390), Some(300));
+    }
+}
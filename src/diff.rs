@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Error as FmtError};
+use crate::consts::*;
+use crate::ebml::{Element, ElementKind, Node, WebmFile};
+
+// Elements that are expected to differ between two muxes of the same
+// content and are skipped by default
+pub const DEFAULT_IGNORED: [u64; 2] = [ID_DATEUTC, ID_SEGMENTUUID];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffOptions {
+    pub ignored: Vec<u64>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions {
+            ignored: DEFAULT_IGNORED.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed { old: String, new: String },
+    // a master element whose children differ
+    Children,
+}
+
+// One node in the tree of differences. Paths name each element with its
+// occurrence among siblings of the same ID, e.g.
+// `SegmentNode/ClusterNode[0]/Timestamp[0]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub id: u64,
+    pub change: Change,
+    pub children: Vec<Difference>,
+}
+
+impl Difference {
+    fn write_indented(&self, f: &mut Formatter<'_>, depth: usize) -> Result<(), FmtError> {
+        let indent = "  ".repeat(depth);
+        match &self.change {
+            Change::Added(value) => writeln!(f, "{}+ {} = {}", indent, self.path, value)?,
+            Change::Removed(value) => writeln!(f, "{}- {} = {}", indent, self.path, value)?,
+            Change::Changed { old, new } => {
+                writeln!(f, "{}~ {}: {} -> {}", indent, self.path, old, new)?
+            },
+            Change::Children => writeln!(f, "{}~ {}", indent, self.path)?,
+        }
+        for child in &self.children {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        self.write_indented(f, 0)
+    }
+}
+
+// Compare two documents element by element, ignoring DateUTC and SegmentUUID
+pub fn diff(a: &WebmFile, b: &WebmFile) -> Vec<Difference> {
+    diff_with(a, b, &DiffOptions::default())
+}
+
+pub fn diff_with(a: &WebmFile, b: &WebmFile, options: &DiffOptions) -> Vec<Difference> {
+    let roots = [
+        (a.header.as_node(), b.header.as_node()),
+        (a.root.as_node(), b.root.as_node()),
+    ];
+    roots.iter()
+        .filter_map(|(a, b)| diff_nodes(a, b, name(a.get_element().id), options))
+        .collect()
+}
+
+fn diff_nodes(a: &Node, b: &Node, path: String, options: &DiffOptions) -> Option<Difference> {
+    let (ea, eb) = (a.get_element(), b.get_element());
+    if ea.kind != ElementKind::Master {
+        let (old, new) = (ea.data.into_vec(), eb.data.into_vec());
+        if old == new {
+            return None;
+        }
+        let change = Change::Changed { old: value(&ea), new: value(&eb) };
+        return Some(Difference { path, id: ea.id, change, children: Vec::new() });
+    }
+
+    let (ca, cb) = (a.get_children(), b.get_children());
+    let mut children = Vec::new();
+
+    // pair up the nth occurrence of each ID on both sides, in the order
+    // they first appear
+    let mut keys = occurrences(&ca);
+    let left_keys: HashSet<(u64, usize)> = keys.iter().cloned().collect();
+    keys.extend(occurrences(&cb).into_iter().filter(|key| !left_keys.contains(key)));
+
+    for (id, occurrence) in keys {
+        if options.ignored.contains(&id) {
+            continue;
+        }
+        let left = ca.iter().filter(|n| n.get_element().id == id).nth(occurrence);
+        let right = cb.iter().filter(|n| n.get_element().id == id).nth(occurrence);
+        let child_path = format!("{}/{}[{}]", path, name(id), occurrence);
+        let difference = match (left, right) {
+            (Some(l), Some(r)) => diff_nodes(l, r, child_path, options),
+            (Some(l), None) => Some(leaf(child_path, Change::Removed(value(&l.get_element())), id)),
+            (None, Some(r)) => Some(leaf(child_path, Change::Added(value(&r.get_element())), id)),
+            (None, None) => None,
+        };
+        children.extend(difference);
+    }
+
+    if children.is_empty() {
+        None
+    } else {
+        Some(Difference { path, id: ea.id, change: Change::Children, children })
+    }
+}
+
+// (ID, nth occurrence of that ID) for each node in order
+fn occurrences(nodes: &[Node]) -> Vec<(u64, usize)> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    nodes.iter()
+        .map(|node| {
+            let id = node.get_element().id;
+            let count = seen.entry(id).or_insert(0);
+            *count += 1;
+            (id, *count - 1)
+        })
+        .collect()
+}
+
+fn leaf(path: String, change: Change, id: u64) -> Difference {
+    Difference { path, id, change, children: Vec::new() }
+}
+
+fn name(id: u64) -> String {
+    element_name(id)
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:#x}", id))
+}
+
+// Human readable value of an element for reports
+fn value(element: &Element) -> String {
+    let data = &element.data;
+    match element.kind {
+        ElementKind::UInt => data.into_uint().to_string(),
+        ElementKind::SInt | ElementKind::Date => data.into_int().to_string(),
+        ElementKind::Float => data.into_float().to_string(),
+        ElementKind::String | ElementKind::UTF8 => format!("{:?}", data.into_string()),
+        ElementKind::Master => "{..}".to_string(),
+        ElementKind::Binary | ElementKind::Unknown => {
+            let bytes = data.into_vec();
+            if bytes.len() <= 16 {
+                bytes.iter().map(|b| format!("{:02x}", b)).collect()
+            } else {
+                format!("<{} bytes>", bytes.len())
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(children: &[u8]) -> WebmFile {
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0x80 | children.len() as u8]);
+        data.extend(children);
+        WebmFile::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn test_identical() {
+        let info = [0x15, 0x49, 0xa9, 0x66, 0x84, 0x4d, 0x80, 0x81, b'a'];
+        assert!(diff(&document(&info), &document(&info)).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        // MuxingApp "a" and DateUTC 1
+        let a = [
+            0x15, 0x49, 0xa9, 0x66, 0x8f,
+            0x4d, 0x80, 0x81, b'a',
+            0x44, 0x61, 0x88, 0, 0, 0, 0, 0, 0, 0, 0x01,
+        ];
+        // MuxingApp "b", DateUTC 2 and a Title
+        let b = [
+            0x15, 0x49, 0xa9, 0x66, 0x93,
+            0x4d, 0x80, 0x81, b'b',
+            0x44, 0x61, 0x88, 0, 0, 0, 0, 0, 0, 0, 0x02,
+            0x7b, 0xa9, 0x81, b't',
+        ];
+        let differences = diff(&document(&a), &document(&b));
+        assert_eq!(differences.len(), 1);
+
+        let info = &differences[0].children[0];
+        assert_eq!(info.path, "SegmentNode/InfoNode[0]");
+        assert_eq!(info.children, vec![
+            Difference {
+                path: "SegmentNode/InfoNode[0]/MuxingApp[0]".to_string(),
+                id: ID_MUXINGAPP,
+                change: Change::Changed { old: "\"a\"".to_string(), new: "\"b\"".to_string() },
+                children: Vec::new(),
+            },
+            Difference {
+                path: "SegmentNode/InfoNode[0]/Title[0]".to_string(),
+                id: ID_TITLE,
+                change: Change::Added("\"t\"".to_string()),
+                children: Vec::new(),
+            },
+        ]);
+
+        // DateUTC shows up once it isn't ignored
+        let options = DiffOptions { ignored: Vec::new() };
+        let differences = diff_with(&document(&a), &document(&b), &options);
+        assert_eq!(differences[0].children[0].children.len(), 3);
+    }
+}
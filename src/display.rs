@@ -0,0 +1,268 @@
+use std::io::{Read, Seek, Write, Error as IOError};
+use std::ops::Range;
+use crate::checksum::update_crc32;
+use crate::consts::*;
+use crate::demux::Projection;
+use crate::ebml::{Element, Node, WebmReader};
+use crate::error::{invalid_data, invalid_input};
+use crate::inplace::replace_in_place;
+use crate::remux::Remuxer;
+
+// Display fixes for video tracks, like anamorphic video that plays back
+// squeezed or phone recordings stored on their side, touching nothing but
+// Tracks. `set_display_in_place` overwrites Tracks where it is, which works
+// when the edit doesn't grow it or Voids after it leave room. Otherwise
+// `remux_display` writes a new file with the Clusters copied as they are.
+//
+//     let edit = DisplayEdit::aspect_ratio(16, 9);
+//     match set_display_in_place(&mut file, 1, &edit) {
+//         Err(e) if e.kind() == ErrorKind::InvalidInput => remux_display(&mut file, out, 1, &edit)?,
+//         result => result.map(|_| ())?,
+//     }
+
+// New values for the Video elements of a track, None to leave one as it is
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DisplayEdit {
+    pub display_width: Option<u64>,
+    pub display_height: Option<u64>,
+    pub display_unit: Option<u64>,
+    pub aspect_ratio_type: Option<u64>,
+    // replaces a Projection the track has
+    pub projection: Option<Projection>,
+}
+
+impl DisplayEdit {
+    // Display at this aspect ratio, e.g. 16:9, whatever the pixel size
+    pub fn aspect_ratio(width: u64, height: u64) -> DisplayEdit {
+        DisplayEdit {
+            display_width: Some(width),
+            display_height: Some(height),
+            // display aspect ratio
+            display_unit: Some(3),
+            ..DisplayEdit::default()
+        }
+    }
+
+    // Turn the picture, see `Projection::rotation`
+    pub fn rotation(degrees: f64) -> DisplayEdit {
+        DisplayEdit { projection: Some(Projection::rotation(degrees)), ..DisplayEdit::default() }
+    }
+
+    fn values(&self) -> [(u64, Option<u64>); 4] {
+        [
+            (ID_DISPLAYWIDTH, self.display_width),
+            (ID_DISPLAYHEIGHT, self.display_height),
+            (ID_DISPLAYUNIT, self.display_unit),
+            (ID_ASPECTRATIOTYPE, self.aspect_ratio_type),
+        ]
+    }
+}
+
+// Apply an edit to the video track with this TrackNumber in a Tracks
+// node, keeping sizes and a leading CRC-32 up to date. False if there's no
+// such track or it has no Video element.
+pub fn edit_display(tracks: &mut Node, track: u64, edit: &DisplayEdit) -> bool {
+    let entry = tracks.children().iter().position(|entry| {
+        entry.element().id == ID_TRACKENTRYNODE
+            && entry.children().iter().any(|child| {
+                child.element().id == ID_TRACKNUMBER && child.element().data.into_uint() == track
+            })
+    });
+    let video = entry.and_then(|entry| {
+        tracks.children()[entry].children().iter().position(|child| child.element().id == ID_VIDEONODE)
+    });
+    let (entry, video) = match (entry, video) {
+        (Some(entry), Some(video)) => (entry, video),
+        _ => return false,
+    };
+
+    tracks.edit_child(entry, |entry| {
+        entry.edit_child(video, |video| {
+            for (id, value) in edit.values().iter() {
+                let value = match value {
+                    Some(value) => *value,
+                    None => continue,
+                };
+                set_child(video, Element::uint(*id, value).into());
+            }
+            if let Some(projection) = &edit.projection {
+                set_child(video, projection.to_node());
+            }
+        });
+    });
+    update_crc32(tracks);
+    true
+}
+
+// Overwrite the Tracks element of a file with an edited one, returning the
+// bytes rewritten. An InvalidInput error, leaving the file as it was, if
+// the edited Tracks doesn't fit, the track isn't there or the projection
+// is out of range.
+pub fn set_display_in_place<F>(mut f: F, track: u64, edit: &DisplayEdit) -> Result<Range<u64>, IOError>
+where
+    F: Read + Write + Seek,
+{
+    check_projection(edit)?;
+    let document = WebmReader::new(&mut f)
+        .skip_elements([ID_CLUSTERNODE, ID_CUESNODE])
+        .parse()
+        .map_err(|_| invalid_data("could not parse document"))?;
+    let tracks = document.root.get_tracks().into_iter().next()
+        .ok_or_else(|| invalid_input("no Tracks"))?;
+    let mut edited = tracks.as_node().clone();
+    if !edit_display(&mut edited, track, edit) {
+        return Err(invalid_input("no such video track"));
+    }
+    replace_in_place(f, &tracks.get_element(), document.root.get_element().end_offset(), &edited.to_bytes())
+}
+
+// Copy `input` to `output` with an edited Tracks, see `Remuxer`
+pub fn remux_display<R, W>(mut input: R, output: W, track: u64, edit: &DisplayEdit) -> Result<(), IOError>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    check_projection(edit)?;
+    let mut document = WebmReader::new(&mut input)
+        .skip_elements([ID_SIMPLEBLOCK, ID_BLOCKGROUPNODE])
+        .parse()
+        .map_err(|_| invalid_data("could not parse document"))?;
+    let root = document.root.as_node_mut();
+    let tracks = root.children().iter()
+        .position(|child| child.element().id == ID_TRACKSNODE)
+        .ok_or_else(|| invalid_input("no Tracks"))?;
+    let mut found = false;
+    root.edit_child(tracks, |tracks| found = edit_display(tracks, track, edit));
+    if !found {
+        return Err(invalid_input("no such video track"));
+    }
+    Remuxer::new(&document).write(input, output)
+}
+
+// Replace the first child with the same ID, or append
+fn set_child(node: &mut Node, child: Node) {
+    let id = child.element().id;
+    match node.children().iter().position(|existing| existing.element().id == id) {
+        Some(index) => {
+            node.remove_child(index);
+            node.insert_child(index, child);
+        },
+        None => node.insert_child(node.children().len(), child),
+    }
+}
+
+fn check_projection(edit: &DisplayEdit) -> Result<(), IOError> {
+    match &edit.projection {
+        Some(projection) if !projection.is_valid() => Err(invalid_input("projection out of range")),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind};
+    use crate::checksum::crc32;
+    use crate::ebml::{VideoNode, WebmFile};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn video(data: &[u8]) -> VideoNode {
+        let document = WebmFile::from_bytes(data).unwrap();
+        document.root.get_tracks()[0].get_track_entries()[0].get_video_settings().unwrap()
+    }
+
+    fn builder() -> WebmBuilder {
+        WebmBuilder::new()
+            .video_track(1, "V_VP9", 720, 576)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, &[1; 32])
+            .frame(2, 0, true, &[2; 8])
+    }
+
+    #[test]
+    fn test_set_display_in_place() {
+        // no room after Tracks to grow into
+        let data = builder().build();
+        let mut file = Cursor::new(data.clone());
+        let edit = DisplayEdit::aspect_ratio(16, 9);
+        let error = set_display_in_place(&mut file, 1, &edit).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(file.get_ref(), &data);
+        assert!(set_display_in_place(&mut file, 2, &edit).is_err());
+
+        // a Void after Tracks makes room
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        root.insert_child(tracks + 1, Element::binary(ID_VOID, &[0; 16]).into());
+        let mut padded = Cursor::new(Vec::new());
+        document.write_to(&mut padded).unwrap();
+        let padded = padded.into_inner();
+
+        let mut file = Cursor::new(padded.clone());
+        set_display_in_place(&mut file, 1, &edit).unwrap();
+        let edited = file.into_inner();
+        assert_eq!(edited.len(), padded.len());
+        let video = video(&edited);
+        assert_eq!((video.get_display_width(), video.get_display_height(), video.get_display_unit()), (Some(16), Some(9), Some(3)));
+        assert_eq!(video.get_pixel_width(), 720);
+        let before = WebmFile::from_bytes(&padded).unwrap();
+        let after = WebmFile::from_bytes(&edited).unwrap();
+        assert_eq!(after.root.get_clusters()[0].get_element().offset, before.root.get_clusters()[0].get_element().offset);
+
+        // changing values again fits where they are
+        let mut file = Cursor::new(edited);
+        let edit = DisplayEdit { display_width: Some(4), display_height: Some(3), ..DisplayEdit::default() };
+        set_display_in_place(&mut file, 1, &edit).unwrap();
+        let video = self::video(file.get_ref());
+        assert_eq!((video.get_display_width(), video.get_display_height(), video.get_display_unit()), (Some(4), Some(3), Some(3)));
+    }
+
+    #[test]
+    fn test_remux_display() {
+        let data = builder().build();
+        let mut out = Vec::new();
+        let edit = DisplayEdit { display_width: Some(1024), aspect_ratio_type: Some(2), ..DisplayEdit::default() };
+        remux_display(Cursor::new(&data), &mut out, 1, &edit).unwrap();
+        let video = video(&out);
+        assert_eq!((video.get_display_width(), video.get_aspect_ratio_type()), (Some(1024), Some(2)));
+        assert_eq!(video.get_display_height(), None);
+        let packets = |data: &[u8]| WebmFile::from_bytes(data).unwrap().packets().collect::<Vec<_>>();
+        assert_eq!(packets(&out), packets(&data));
+
+        // a CRC-32 on Tracks is kept valid
+        let mut tracks = Element::master(ID_TRACKSNODE, WebmFile::from_bytes(&data).unwrap().root.get_tracks()[0].get_children());
+        tracks.insert_child(0, Element::binary(ID_CRC32, &[0; 4]).into());
+        assert!(edit_display(&mut tracks, 1, &edit));
+        let rest: Vec<u8> = tracks.children()[1..].iter().flat_map(Node::to_bytes).collect();
+        assert_eq!(tracks.children()[0].element().data.as_bytes(), &crc32(&rest).to_le_bytes()[..]);
+    }
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(Projection::rotation(90.0).pose_roll, 90.0);
+        assert_eq!(Projection::rotation(270.0).pose_roll, -90.0);
+        assert_eq!(Projection::rotation(-180.0).pose_roll, 180.0);
+
+        let data = builder().build();
+        let mut out = Vec::new();
+        remux_display(Cursor::new(&data), &mut out, 1, &DisplayEdit::rotation(-90.0)).unwrap();
+        let projection = video(&out).get_projection().unwrap();
+        assert_eq!((projection.get_type(), projection.get_pose_roll()), (0, -90.0));
+
+        // a second edit replaces the Projection, in place as it's the same size
+        let mut file = Cursor::new(out);
+        let edit = DisplayEdit {
+            projection: Some(Projection { projection_type: 1, ..Projection::rotation(180.0) }),
+            ..DisplayEdit::default()
+        };
+        set_display_in_place(&mut file, 1, &edit).unwrap();
+        let video = video(file.get_ref());
+        let projection = video.get_projection().unwrap();
+        assert_eq!((projection.get_type(), projection.get_pose_roll()), (1, 180.0));
+        assert_eq!(video.get_children().iter().filter(|child| child.element().id == ID_PROJECTIONNODE).count(), 1);
+
+        let edit = DisplayEdit { projection: Some(Projection { pose_pitch: 120.0, ..Projection::default() }), ..edit };
+        assert_eq!(set_display_in_place(&mut file, 1, &edit).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
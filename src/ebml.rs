@@ -1,12 +1,29 @@
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Error as IOError};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Take, Write, Error as IOError, ErrorKind};
 use std::fmt::{Debug, Formatter, Error as FmtError};
+use std::hash::{Hash, Hasher};
+use std::ops::{Range, RangeInclusive};
+use std::time::Instant;
 use crate::consts::*;
+use crate::format;
+use crate::block::{Packets, PacketsRev};
+use crate::demux::Track;
+use crate::media_info::{MediaInfo, Probe};
+use crate::encryption::DrmInfo;
+use crate::linking::SegmentUid;
+use crate::profile::ParseProfile;
+use crate::error::{DataError, Error, Warning};
+use crate::vint;
+
+pub mod encode;
+pub mod parser;
 
 // Generate a node type from some base node
 macro_rules! node_type {
     ($name:ident, $base:ty) => {
-        #[derive(Debug, Clone)]
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
         pub struct $name($base);
 
         impl $name {
@@ -19,6 +36,22 @@ macro_rules! node_type {
             pub fn get_children(&self) -> Vec<Node> {
                 self.0.children.clone()
             }
+
+            // The untyped node, for generic tree walks
+            #[allow(dead_code)]
+            pub fn as_node(&self) -> &Node {
+                &self.0
+            }
+
+            pub fn as_node_mut(&mut self) -> &mut Node {
+                &mut self.0
+            }
+        }
+
+        impl From<Node> for $name {
+            fn from(node: Node) -> $name {
+                $name(node)
+            }
         }
     };
 }
@@ -52,41 +85,77 @@ macro_rules! find_node {
     };
     // Find and convert to a given node type
     ($list:expr, $nty:ident, $id:expr) => {
-        match find_node!($list, $id) {
-            Some(n) => Some($nty(n)),
-            None => None,
-        }
+        find_node!($list, $id).map($nty)
     };
 }
 
 // Return the data from a node in list
 macro_rules! find_node_data {
     ($list:expr, $id:expr) => {
-        match find_node!($list, $id) {
-            Some(n) => Some(n.element.data),
-            None => None
-        }
+        find_node!($list, $id).map(|n| n.element.data)
     };
 }
 
 // Return a node's data and call into to convert type. Wrap result in Option
 macro_rules! find_node_data_opt {
     ($list:expr, $id:expr) => {
-        match find_node_data!($list, $id) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!($list, $id).map(|d| d.into())
+    };
+}
+
+// Return a node's data and convert, falling back to the spec default. Getters
+// of elements the spec gives a default use this, so a file leaving them out
+// reads the same as one writing them, rather than panicking.
+macro_rules! find_node_data_or {
+    ($list:expr, $id:expr, $default:expr) => {
+        find_node_data!($list, $id).map(|d| d.into()).unwrap_or($default)
     };
 }
 
-// Return a node's data, unwrap, and convert
+// Return a node's data and convert. A missing mandatory element reads as
+// the type's zero value so malformed input can't cause a panic.
 macro_rules! find_node_data_mand {
     ($list:expr, $id:expr) => {
-        find_node_data!($list, $id).unwrap().into()
+        find_node_data!($list, $id).map(|d| d.into()).unwrap_or_default()
     };
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Borrow the data of a node's first child with the ID, without copying
+// the children as the macros above do
+macro_rules! borrow_node_data {
+    ($node:expr, $id:expr) => {
+        $node.children.iter()
+            .find(|node| node.element.id == $id)
+            .map(|node| &node.element.data)
+    };
+}
+
+// Masters nested deeper than this are rejected. Real files stay in single
+// digits, the limit only stops crafted input from exhausting the stack.
+pub const MAX_DEPTH: usize = 64;
+
+// Longest IDs and sizes WebM allows, whatever the EBML header declares
+pub const MAX_ID_LENGTH: usize = 4;
+pub const MAX_SIZE_LENGTH: usize = 8;
+
+// Newest EBMLReadVersion and DocTypeReadVersion the parser understands
+pub const EBML_READ_VERSION: u64 = 1;
+pub const DOC_TYPE_READ_VERSION: u64 = 4;
+
+// Strings longer than this (titles, comments) rarely repeat, so
+// `WebmReader::string_pool` leaves them out
+const MAX_POOLED_STRING: u64 = 64;
+
+// DocTypes accepted by `WebmFile::check_compatibility_in`. WebM is a subset
+// of Matroska, so Matroska mode accepts both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DocTypeMode {
+    #[default]
+    WebM,
+    Matroska,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ElementKind {
     Unknown,
     Master,
@@ -99,11 +168,80 @@ pub enum ElementKind {
     Binary,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct ElementData(Vec<u8>);
 
+// An element's data decoded according to its kind in the spec, so callers
+// don't have to know which `into_*` applies. Elements of unknown kind are
+// Binary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue<'a> {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    // nanoseconds since 2001-01-01T00:00:00 UTC
+    Date(i64),
+    Binary(&'a [u8]),
+    Master,
+}
+
+// What to do with elements whose ID isn't in the WebM spec. Their size is
+// always honoured, so novel elements never throw off the offsets of the
+// elements after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownElements {
+    // leave them out of the tree without reading their payload
+    Skip,
+    // keep them in the tree with their raw payload
+    #[default]
+    Keep,
+    // fail with Error::UnknownElement
+    Reject,
+}
+
+// What to do with String and UTF-8 elements that aren't valid UTF-8, which
+// turn up in real WritingApp and Title fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidStrings {
+    // keep them, recording a Warning; getters replace the bad bytes
+    #[default]
+    Replace,
+    // fail with Error::InvalidElement
+    Reject,
+}
+
+// What to do with Float elements that aren't 0, 4 or 8 bytes long
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidFloats {
+    // fail with Error::InvalidElement
+    #[default]
+    Reject,
+    // keep them, recording a Warning; getters read them as NaN
+    Keep,
+}
+
 pub struct WebmReader<T: Read + Seek> {
-    reader: T,
+    reader: BufReader<T>,
+    // Segment element once the header has been read
+    segment: Option<Element>,
+    unknown_elements: UnknownElements,
+    // EBMLMaxIDLength and EBMLMaxSizeLength of the current document
+    max_id_length: usize,
+    max_size_length: usize,
+    // most bytes of Cluster payloads to hold on to, see `memory_budget`
+    memory_budget: Option<u64>,
+    // binary payloads larger than this are left in the source
+    max_binary_size: Option<u64>,
+    invalid_strings: InvalidStrings,
+    invalid_floats: InvalidFloats,
+    warnings: Vec<Warning>,
+    profile: Option<ParseProfile>,
+    strings: Option<StringPool>,
+    skipped_ids: HashSet<u64>,
+    kept_ids: Option<HashSet<u64>>,
+    header_scan: u64,
+    skipped_prefix: u64,
 }
 
 #[derive(Debug)]
@@ -115,38 +253,191 @@ pub struct WebmFile {
 pub struct NodeInfo<'a> {
     pub id: u64,
     pub name: &'a str,
+    // the masters it may appear in, empty for top level elements and None
+    // for global ones like Void that can go anywhere
+    pub parents: Option<&'a [u64]>,
+    pub kind: ElementKind,
+    // how often it may appear in each parent, None for no limit
+    pub min_occurs: u64,
+    pub max_occurs: Option<u64>,
+    // the value a missing element has, as written in the schema
+    pub default: Option<&'a str>,
+    // the sizes its data may have, in bytes
+    pub length: Option<RangeInclusive<u64>>,
+    // the first and last DocTypeVersion it's part of. A max_version of 0
+    // marks elements that were never in a published version.
+    pub min_version: u64,
+    pub max_version: Option<u64>,
+    // whether it may hold itself, like ChapterAtom
+    pub recursive: bool,
+    // whether a Top-Level Element may appear again in the Segment, e.g.
+    // after an update, with the later copy replacing the earlier
+    pub recurring: bool,
+    pub unknown_size_allowed: bool,
+    // whether WebM keeps it
+    pub webm: bool,
+    // the schema's definition of it
+    pub definition: Option<&'a str>,
 }
 
-#[derive(Clone)]
+// Nodes compare and hash structurally, see `Element`
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Node {
     element: Element,
     children: Vec<Node>,
 }
 
 impl Node {
-    #[allow(dead_code)]
-    fn get_element(&self) -> Element {
+    pub fn get_element(&self) -> Element {
         self.element.clone()
     }
 
-    #[allow(dead_code)]
-    fn get_children(&self) -> Vec<Node> {
+    pub fn get_children(&self) -> Vec<Node> {
         self.children.clone()
     }
+
+    // Borrowed versions of the two above
+    pub fn element(&self) -> &Element {
+        &self.element
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    // Editing operations keep this node's size and header size in step with
+    // its content. Edit nested nodes through `edit_child` so every ancestor
+    // is updated too. Offsets still refer to the parsed stream.
+    pub fn insert_child(&mut self, index: usize, child: Node) {
+        let index = index.min(self.children.len());
+        self.children.insert(index, child);
+        self.update_size();
+    }
+
+    pub fn remove_child(&mut self, index: usize) -> Option<Node> {
+        if index >= self.children.len() {
+            return None;
+        }
+        let child = self.children.remove(index);
+        self.update_size();
+        Some(child)
+    }
+
+    pub fn set_children(&mut self, children: Vec<Node>) {
+        self.children = children;
+        self.update_size();
+    }
+
+    // Replace the payload of a non-master element. Masters are encoded from
+    // their children, so this returns false for them.
+    pub fn replace_data(&mut self, data: Vec<u8>) -> bool {
+        if self.element.kind == ElementKind::Master {
+            return false;
+        }
+        self.element.data = ElementData(data);
+        self.update_size();
+        true
+    }
+
+    // Run `edit` on a child and then update this node's size. Returns false
+    // if there is no such child.
+    pub fn edit_child(&mut self, index: usize, edit: impl FnOnce(&mut Node)) -> bool {
+        match self.children.get_mut(index) {
+            Some(child) => edit(child),
+            None => return false,
+        }
+        self.update_size();
+        true
+    }
+
+    // Bulk edits, e.g. moving many blocks between Clusters, without a size
+    // update per change. Sizes are stale until `update_sizes`.
+    pub(crate) fn children_mut(&mut self) -> &mut Vec<Node> {
+        &mut self.children
+    }
+
+    // Recompute sizes here and in every master below after `children_mut`
+    pub(crate) fn update_sizes(&mut self) {
+        if self.element.kind != ElementKind::Master {
+            return;
+        }
+        for child in &mut self.children {
+            child.update_sizes();
+        }
+        self.update_size();
+    }
+
+    // This node and everything under it, see `Elements`. Paths start at
+    // this node.
+    pub fn iter(&self) -> Elements<'_> {
+        Elements::new(&[self])
+    }
+
+    fn update_size(&mut self) {
+        let size = self.content_len();
+        self.element.size = size;
+        self.element.header_size = header_size(self.element.id, size);
+    }
+
+    // Encode the node and its children. Sizes are recomputed from the
+    // content, so a parsed node with an unknown size gets a known one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        out
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        if self.element.kind != ElementKind::Master {
+            encode::write_element(out, self.element.id, &self.element.data.0);
+            return;
+        }
+        let mut children = Vec::new();
+        for child in &self.children {
+            child.write_to(&mut children);
+        }
+        encode::write_element(out, self.element.id, &children);
+    }
+
+    fn encoded_len(&self) -> u64 {
+        let size = self.content_len();
+        header_size(self.element.id, size) + size
+    }
+
+    // Bytes of binary data in the subtree
+    fn payload_len(&self) -> u64 {
+        match self.element.kind {
+            ElementKind::Master => self.children.iter().map(Node::payload_len).sum(),
+            ElementKind::Binary => self.element.data.0.len() as u64,
+            _ => 0,
+        }
+    }
+
+    fn drop_payload(&mut self) {
+        if self.element.kind == ElementKind::Binary {
+            self.element.data = ElementData(Vec::new());
+        }
+        self.children.iter_mut().for_each(Node::drop_payload);
+    }
+
+    fn content_len(&self) -> u64 {
+        if self.element.kind == ElementKind::Master {
+            self.children.iter().map(Node::encoded_len).sum()
+        } else {
+            self.element.data.0.len() as u64
+        }
+    }
 }
 
 impl Debug for Node {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
-        let name = match get_node_info(self.element.id) {
-            Some(info) => info.name,
-            None => "Node",
-        };
+        let name = element_name(self.element.id).unwrap_or("Node");
 
         let mut dbg = f.debug_struct(name);
         dbg.field("element", &self.element);
 
         // ignore children if empty
-        if self.children.len() > 0 {
+        if !self.children.is_empty() {
             dbg.field("children", &self.children);
         }
 
@@ -154,6 +445,97 @@ impl Debug for Node {
     }
 }
 
+// The IDs from the top of the tree down to an element, shown as names from
+// NODE_INFOS, e.g. `SegmentNode/InfoNode/TimestampScale`. IDs without a
+// name are shown in hex.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ElementPath(pub Vec<u64>);
+
+impl ElementPath {
+    pub fn ids(&self) -> &[u64] {
+        &self.0
+    }
+
+    // Zero for top level elements like the EBML header and the Segment
+    pub fn depth(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+}
+
+impl std::fmt::Display for ElementPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        for (i, id) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            match element_name(*id) {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "{:#x}", id)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// What a walk over elements does after visiting one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkControl {
+    Continue,
+    // go on with the element's next sibling, not its children
+    SkipChildren,
+    Stop,
+}
+
+// Depth first iteration over a tree in document order, parents before
+// their children, see `WebmFile::iter` and `Node::iter`
+pub struct Elements<'a> {
+    // nodes still to visit with their depth, next one last
+    stack: Vec<(usize, &'a Node)>,
+    path: Vec<u64>,
+    // children of the last element returned, on top of the stack
+    pushed: usize,
+}
+
+impl<'a> Elements<'a> {
+    fn new(roots: &[&'a Node]) -> Elements<'a> {
+        Elements {
+            stack: roots.iter().rev().map(|node| (0, *node)).collect(),
+            path: Vec::new(),
+            pushed: 0,
+        }
+    }
+
+    // Don't descend into the element last returned by `next`
+    pub fn skip_children(&mut self) {
+        self.stack.truncate(self.stack.len() - self.pushed);
+        self.pushed = 0;
+    }
+
+    // Call `visit` on each element in turn, as directed by what it returns
+    pub fn walk(mut self, mut visit: impl FnMut(&ElementPath, &Element) -> WalkControl) {
+        while let Some((path, element)) = self.next() {
+            match visit(&path, element) {
+                WalkControl::Continue => (),
+                WalkControl::SkipChildren => self.skip_children(),
+                WalkControl::Stop => return,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (ElementPath, &'a Element);
+
+    fn next(&mut self) -> Option<(ElementPath, &'a Element)> {
+        let (depth, node) = self.stack.pop()?;
+        self.path.truncate(depth);
+        self.path.push(node.element.id);
+        self.stack.extend(node.children.iter().rev().map(|child| (depth + 1, child)));
+        self.pushed = node.children.len();
+        Some((ElementPath(self.path.clone()), &node.element))
+    }
+}
+
 // bit of a hack, but seems to work well enough
 node_type!(EBMLHeaderNode, Node);
 node_type!(SegmentNode, Node);
@@ -163,6 +545,7 @@ node_type!(InfoNode, Node);
 node_type!(ClusterNode, Node);
 node_type!(BlockGroupNode, Node);
 node_type!(SlicesNode, Node);
+node_type!(TimeSliceNode, Node);
 node_type!(TracksNode, Node);
 node_type!(TrackEntryNode, Node);
 node_type!(VideoNode, Node);
@@ -179,10 +562,16 @@ node_type!(ChaptersNode, Node);
 node_type!(EditionEntryNode, Node);
 node_type!(ChapterAtomNode, Node);
 node_type!(ChapterDisplayNode, Node);
+node_type!(ChapProcessNode, Node);
+node_type!(ChapProcessCommandNode, Node);
 node_type!(TagsNode, Node);
 node_type!(TagNode, Node);
 node_type!(TargetsNode, Node);
 node_type!(SimpleTagNode, Node);
+node_type!(SignatureSlotNode, Node);
+node_type!(AttachmentsNode, Node);
+node_type!(AttachedFileNode, Node);
+node_type!(SignatureElementListNode, Node);
 
 #[derive(Clone)]
 pub struct Element {
@@ -190,395 +579,1269 @@ pub struct Element {
     pub size: u64,
     pub kind: ElementKind,
     pub data: ElementData,
+    // absolute position of the element ID and the length of ID + size
+    pub offset: u64,
+    pub header_size: u64,
 }
 
-impl<T: Read + Seek> WebmReader<T> {
-    pub fn new(r: T) -> WebmReader<T> {
-        WebmReader {
-            reader: r,
-        }
+// Elements are equal when they hold the same ID, kind and data. Where
+// they sit in the stream and how their header was encoded (offset, header
+// length, unknown sizes) are ignored, so the same content parsed from two
+// files compares equal.
+impl PartialEq for Element {
+    fn eq(&self, other: &Element) -> bool {
+        self.id == other.id && self.kind == other.kind && self.data == other.data
     }
+}
 
-    pub fn parse(&mut self) -> Result<WebmFile, ()> {
-        // check magic number
-        match self.check_magic_number() {
-            Ok(v) => {
-                if !v {
-                    panic!("incorrect magic number")
-                }
-            },
-            Err(e) => panic!("{}", e),
-        }
-        
-        // seek back to beginning
-        self.reader.seek(SeekFrom::Start(0)).unwrap();
+impl Eq for Element {}
 
-        // parse master element
-        let header = EBMLHeaderNode(self.build_node_tree());
-        // parse segments
-        let root = SegmentNode(self.build_node_tree());
-        Ok(WebmFile {
-            header: header,
-            root: root,
-        })
+impl Hash for Element {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.kind.hash(state);
+        self.data.hash(state);
     }
+}
 
-    fn build_node_tree(&mut self) -> Node {
-        // parse next element
-        let elem = self.parse_element();
-        let mut children: Vec<Node> = Vec::new();
-        
-        // if elem is a master, build child node tree
-        if elem.kind == ElementKind::Master {
-            let start = self.reader.seek(SeekFrom::Current(0)).unwrap();
-            let mut offset = start;
+impl Element {
+    // Absolute position of the first byte after the element header
+    pub fn data_offset(&self) -> u64 {
+        self.offset + self.header_size
+    }
 
-            while offset < start + elem.size {
-                children.push(self.build_node_tree());
-                offset = self.reader.seek(SeekFrom::Current(0)).unwrap();
-            }    
-        }
+    // Absolute position just past the element, u64::MAX if the size is unknown
+    pub fn end_offset(&self) -> u64 {
+        self.data_offset().saturating_add(self.size)
+    }
 
-        Node {
-            element: elem,
-            children: children,
-        }
+    pub fn is_unknown_size(&self) -> bool {
+        self.size == UNKNOWN_SIZE
     }
 
-    fn parse_element(&mut self) -> Element {
-        // get the ID size
-        let id_size = count_leading_zeros(read_bytes(&mut self.reader, 1)[0]) + 1;
-        // seek back one byte
-        self.reader.seek(SeekFrom::Current(-1)).unwrap();
+    // Decoded from the payload on every call, and strings are allocated
+    // each time, so keep the result rather than calling this in a loop
+    pub fn decode_value(&self) -> ElementValue<'_> {
+        match self.kind {
+            ElementKind::Master => ElementValue::Master,
+            ElementKind::UInt => ElementValue::UInt(self.data.into_uint()),
+            ElementKind::SInt => ElementValue::Int(self.data.into_int()),
+            ElementKind::Float => ElementValue::Float(self.data.into_float()),
+            ElementKind::String | ElementKind::UTF8 => ElementValue::Str(self.data.into_string()),
+            ElementKind::Date => ElementValue::Date(self.data.into_int()),
+            ElementKind::Binary | ElementKind::Unknown => ElementValue::Binary(&self.data.0),
+        }
+    }
 
-        // read ID
-        let id = bytes_to_uint(&read_bytes(&mut self.reader, id_size as usize));
-        // read next vint
-        let size = read_vint(&mut self.reader);
-
-        // Match all IDs to a given element type
-        let kind = match id {
-            0xe7 | 0xab | 0xcc |
-            0xd7 | 0x83 | 0xb9 |
-            0x88 | 0x9c | 0x9a |
-            0xb0 | 0xba | 0x9f |
-            0xb3 | 0xf1 | 0xf7 |
-            0xa7 |
-            0x4286 | 0x42f7 | 0x42f2 |
-            0x42f3 | 0x4287 | 0x4285 |
-            0x53ac | 0x73c5 | 0x55aa |
-            0x56aa | 0x56bb | 0x53b8 |
-            0x53c0 | 0x5378 |
-            0x2ad7b1 | 0x23e383         => ElementKind::UInt,
-
-            0xfb |
-            0x75a2                      => ElementKind::SInt,
-
-            0xb5 |
-            0x4489 |
-            0x23314f                    => ElementKind::Float,
-
-            0x4461                      => ElementKind::Date,
-
-            0x86 |
-            0x4282 |
-            0x22b59c                    => ElementKind::String,
-
-            0x9b |
-            0x4d80 | 0x5741 | 0x536e |
-            0x258688                    => ElementKind::UTF8,
-
-            0xa3 | 0xa1 |
-            0xec | 0xbf |
-            0x53ab | 0x63a2 | 0x73a4    => ElementKind::Binary,
-
-            0xa0 | 0x8e | 0xe8 |
-            0xae | 0xe0 | 0xe1 |
-            0xbb | 0xb7 |
-            0x4dbb |
-            0x1a45dfa3 | 0x18538067 |
-            0x114d9b74 | 0x1549a966 |
-            0x1f43b675 | 0x1654ae6b |
-            0x1c53bb6b                  => ElementKind::Master,
-
-            // Failsafe, we can check for these in testing
-            _                           => ElementKind::Unknown,
-        };
+    // A Date element as ISO 8601, see `format::format_date`
+    pub fn date_string(&self) -> Option<String> {
+        Some(self.data.as_iso8601()).filter(|_| self.kind == ElementKind::Date)
+    }
 
-        // assign the element data
-        // if master, ignore data
-        let data = if kind == ElementKind::Master {
-            ElementData(Vec::new())
-        } else {
-            ElementData(read_bytes(&mut self.reader, size as usize))
-        };
+    // Whether the payload was dropped to stay within a memory budget or
+    // left in the source for being too large
+    pub fn is_dropped(&self) -> bool {
+        self.kind == ElementKind::Binary && self.size > 0 && self.data.0.is_empty()
+    }
 
+    // A window onto the payload in `source`, the stream the element was
+    // parsed from, see `WebmReader::payload_reader`
+    pub fn payload_reader<R: Read + Seek>(&self, mut source: R) -> Result<Take<R>, IOError> {
+        source.seek(SeekFrom::Start(self.data_offset()))?;
+        Ok(source.take(self.size))
+    }
 
-        Element {
-            id: id,
-            size: size,
-            kind: kind,
-            data: data,
-        }
+    // Build elements in memory, e.g. for writing or test fixtures. The kind
+    // comes from the ID where it's known, and the offset is left at zero.
+    pub fn uint(id: u64, value: u64) -> Element {
+        Element::new(id, ElementKind::UInt, encode::uint_bytes(value))
     }
 
-    fn check_magic_number(&mut self) -> Result<bool, IOError> {
-        let mut buf: [u8; 4] = [0; 4];
-        match self.reader.read(&mut buf) {
-            Ok(size) => {
-                if size != 4 {
-                    Ok(false)
-                } else if buf != MAGIC_NUMBER {
-                    Ok(false)
-                } else {
-                    Ok(true)
-                }
-            },
-            Err(e) => Err(e),
-        }
+    pub fn int(id: u64, value: i64) -> Element {
+        Element::new(id, ElementKind::SInt, encode::int_bytes(value))
     }
-}
 
-impl WebmFile {
-    pub fn open(file: File) -> WebmFile {
-        WebmReader::new(file).parse().unwrap()
+    pub fn float(id: u64, value: f64) -> Element {
+        Element::new(id, ElementKind::Float, encode::float_bytes(value))
     }
-}
 
-impl EBMLHeaderNode {
-    pub fn get_version(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x4286).unwrap().into()
+    pub fn string(id: u64, value: &str) -> Element {
+        Element::new(id, ElementKind::UTF8, value.as_bytes().to_vec())
     }
 
-    pub fn get_read_version(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x42f7).unwrap().into()
+    // Nanoseconds since 2001-01-01T00:00:00 UTC
+    pub fn date(id: u64, nanoseconds: i64) -> Element {
+        Element::new(id, ElementKind::Date, nanoseconds.to_be_bytes().to_vec())
     }
 
-    pub fn get_max_id_length(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x42f2).unwrap().into()
+    // A Date from ISO 8601, see `format::parse_date`
+    pub fn date_from_iso8601(id: u64, value: &str) -> Option<Element> {
+        format::parse_date(value).map(|nanoseconds| Element::date(id, nanoseconds))
     }
 
-    pub fn get_max_size_length(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x42f3).unwrap().into()
+    pub fn binary(id: u64, data: &[u8]) -> Element {
+        Element::new(id, ElementKind::Binary, data.to_vec())
     }
 
-    pub fn get_doc_type(&self) -> String {
-        find_node_data!(self.get_children(), 0x4282).unwrap().into()
+    // Masters are returned as a Node since they hold their children
+    pub fn master(id: u64, children: Vec<Node>) -> Node {
+        let size = children.iter().map(Node::encoded_len).sum();
+        let element = Element {
+            id,
+            size,
+            kind: ElementKind::Master,
+            data: ElementData(Vec::new()),
+            offset: 0,
+            header_size: header_size(id, size),
+        };
+        Node { element, children }
     }
 
-    pub fn get_doc_type_version(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x4287).unwrap().into()
+    fn new(id: u64, default_kind: ElementKind, data: Vec<u8>) -> Element {
+        let kind = match element_kind(id) {
+            ElementKind::Unknown => default_kind,
+            kind => kind,
+        };
+        let size = data.len() as u64;
+        Element {
+            id,
+            size,
+            kind,
+            data: ElementData(data),
+            offset: 0,
+            header_size: header_size(id, size),
+        }
     }
+}
 
-    pub fn get_doc_type_read_version(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x4285).unwrap().into()
+impl From<Element> for Node {
+    fn from(element: Element) -> Node {
+        Node { element, children: Vec::new() }
     }
 }
 
-impl SegmentNode {
-    pub fn get_seek_head_nodes(&self) -> Vec<SeekHeadNode> {
-        filter_nodes!(self.get_children(), SeekHeadNode, 0x114d9b74)
+impl<T: Read + Seek> WebmReader<T> {
+    pub fn new(r: T) -> WebmReader<T> {
+        WebmReader {
+            reader: BufReader::new(r),
+            segment: None,
+            unknown_elements: UnknownElements::default(),
+            max_id_length: MAX_ID_LENGTH,
+            max_size_length: MAX_SIZE_LENGTH,
+            memory_budget: None,
+            max_binary_size: None,
+            invalid_strings: InvalidStrings::default(),
+            invalid_floats: InvalidFloats::default(),
+            warnings: Vec::new(),
+            profile: None,
+            strings: None,
+            skipped_ids: HashSet::new(),
+            kept_ids: None,
+            header_scan: 0,
+            skipped_prefix: 0,
+        }
     }
 
-    pub fn get_info_nodes(&self) -> Vec<InfoNode> {
-        filter_nodes!(self.get_children(), InfoNode, 0x1549a966)
+    pub fn unknown_elements(mut self, policy: UnknownElements) -> Self {
+        self.unknown_elements = policy;
+        self
     }
 
-    pub fn get_clusters(&self) -> Vec<ClusterNode> {
-        filter_nodes!(self.get_children(), ClusterNode, 0x1F43B675)
+    // Step over elements with these IDs without reading their payloads,
+    // leaving them out of the tree, e.g. SimpleBlock and BlockGroup for a
+    // fast scan of Cluster timestamps. Masters of unknown size can't be
+    // stepped over, so their children are read and then dropped.
+    pub fn skip_elements<I: IntoIterator<Item = u64>>(mut self, ids: I) -> Self {
+        self.skipped_ids.extend(ids);
+        self
     }
 
-    pub fn get_tracks(&self) -> Vec<TracksNode> {
-        filter_nodes!(self.get_children(), TracksNode, 0x1654ae6b)
+    // Only keep Segment elements with these IDs, along with everything
+    // inside them and the masters leading to them, e.g. Title and CodecID
+    // for a pruned tree with just those. Other payloads aren't read. The
+    // EBML header is always kept.
+    pub fn keep_elements<I: IntoIterator<Item = u64>>(mut self, ids: I) -> Self {
+        self.kept_ids.get_or_insert_with(HashSet::new).extend(ids);
+        self
     }
 
-    pub fn get_cues(&self) -> Vec<CuesNode> {
-        filter_nodes!(self.get_children(), CuesNode, 0x1c53bb6b)
+    // Look for the EBML header up to `bytes` into the stream instead of
+    // requiring it at the start, for dumps with junk or the tail of an HTTP
+    // response in front. Offsets stay those of the whole stream.
+    pub fn scan_for_header(mut self, bytes: u64) -> Self {
+        self.header_scan = bytes;
+        self
     }
 
-    pub fn get_chapters(&self) -> Vec<ChaptersNode> {
-        filter_nodes!(self.get_children(), ChaptersNode, 0x1043a770)
+    // How many bytes came before the EBML header, see `scan_for_header`
+    pub fn skipped_prefix(&self) -> u64 {
+        self.skipped_prefix
     }
 
-    pub fn get_tags(&self) -> Vec<TagsNode> {
-        filter_nodes!(self.get_children(), TagsNode, 0x1254c367)
+    pub fn invalid_strings(mut self, policy: InvalidStrings) -> Self {
+        self.invalid_strings = policy;
+        self
     }
-}
 
-impl SeekHeadNode {
-    pub fn get_seek_nodes(&self) -> Vec<SeekNode> {
-        filter_nodes!(self.get_children(), SeekNode, 0x4dbb)
+    pub fn invalid_floats(mut self, policy: InvalidFloats) -> Self {
+        self.invalid_floats = policy;
+        self
     }
-}
 
-impl SeekNode {
-    pub fn get_seek_id(&self) -> Vec<u8> {
-        find_node_data!(self.get_children(), 0x53ab).unwrap().into()
+    // Everything worked around so far, in the order it was read
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
     }
 
-    pub fn get_seek_position(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x53ac).unwrap().into()
+    // Record the time and bytes spent on each type of top level element,
+    // see `ParseProfile`
+    pub fn profiling(mut self) -> Self {
+        self.profile = Some(ParseProfile::default());
+        self
     }
-}
 
-impl InfoNode {
-    pub fn get_timestamp_scale(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x2ad7b1).unwrap().into()
+    pub fn profile(&self) -> Option<&ParseProfile> {
+        self.profile.as_ref()
     }
 
-    pub fn get_duration(&self) -> Option<f64> {
-        match find_node_data!(self.get_children(), 0x4489) {
-            Some(d) => Some(d.into_float()),
-            None => None,
-        }
+    // Decode short String and UTF-8 values into a `StringPool` as they're
+    // read, so the languages, codec IDs and tag names repeated all over a
+    // file are allocated once, see `TrackEntryNode::codec_id_in`
+    pub fn string_pool(mut self) -> Self {
+        self.strings = Some(StringPool::new());
+        self
     }
 
-    pub fn get_date_created(&self) -> Option<i64> {
-        match find_node_data!(self.get_children(), 0x4461) {
-            Some(d) => Some(d.into_int()),
-            None => None,
-        }
+    pub fn strings(&self) -> Option<&StringPool> {
+        self.strings.as_ref()
     }
 
-    pub fn get_muxing_app(&self) -> String {
-        find_node_data!(self.get_children(), 0x4d80).unwrap().into()
+    // Hold on to at most `bytes` of Cluster payloads (blocks and other
+    // binary data) while parsing. Past that the oldest Clusters have their
+    // payloads dropped, always keeping the latest one. Their structure,
+    // sizes and offsets stay, so `Element::is_dropped` can tell what to
+    // read again from the source when it's needed.
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
     }
 
-    pub fn get_writing_app(&self) -> String {
-        find_node_data!(self.get_children(), 0x5741).unwrap().into()
+    // Leave the payloads of binary elements over `bytes` long in the source,
+    // e.g. attachments of hundreds of MB. They read as dropped, see
+    // `Element::is_dropped`, and can be streamed with `payload_reader`.
+    // Blocks are always read, `memory_budget` is what limits those.
+    pub fn max_binary_size(mut self, bytes: u64) -> Self {
+        self.max_binary_size = Some(bytes);
+        self
     }
-}
 
-impl ClusterNode {
-    pub fn get_timestamp(&self) -> u64 {
-        find_node_data!(self.get_children(), 0xe7).unwrap().into()
+    pub fn parse(&mut self) -> Result<WebmFile, Error> {
+        let header = self.parse_header()?;
+        self.parse_segment(header)
     }
 
-    pub fn get_prev_size(&self) -> Option<u64> {
-        match find_node_data!(self.get_children(), 0xab) {
-            Some(d) => Some(d.into_uint()),
-            None => None,
+    // Parse the rest of the Segment starting at a position previously
+    // returned by `position()`. The returned root only holds the elements
+    // from that offset onwards.
+    pub fn parse_from(&mut self, offset: u64) -> Result<WebmFile, Error> {
+        let header = self.parse_header()?;
+        let segment = self.segment.as_ref().ok_or(Error::MissingSegment)?;
+        if offset < segment.data_offset() || offset > segment.end_offset() {
+            return Err(Error::InvalidOffset(offset));
         }
-    }
 
-    pub fn get_simple_blocks(&self) -> Vec<Node> {
-        filter_nodes!(self.get_children(), 0xa3)
-    }
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.parse_segment(header)
+    }
+
+    // Read the EBML header and the Segment element header, leaving the
+    // reader at the first child of the Segment
+    pub fn parse_header(&mut self) -> Result<EBMLHeaderNode, Error> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        if self.header_scan > 0 {
+            let mut prefix = Vec::new();
+            (&mut self.reader).take(self.header_scan.saturating_add(4)).read_to_end(&mut prefix)?;
+            // without a magic number the usual error comes from the start
+            self.skipped_prefix = prefix.windows(4)
+                .position(|bytes| bytes == MAGIC_NUMBER)
+                .unwrap_or(0) as u64;
+            self.seek_to(self.skipped_prefix)?;
+        }
+        self.read_header()
+    }
+
+    // Parse every document in the stream. Naive stream dumps often hold
+    // several EBML documents back to back, or several Segments after one
+    // header. Void elements between them are skipped and anything else
+    // after the last Segment is ignored.
+    pub fn parse_all(&mut self) -> Result<Vec<WebmFile>, Error> {
+        let mut documents = vec![self.parse()?];
+        loop {
+            while self.peek_element_id()? == Some(ID_VOID) {
+                // a truncated Void is just more trailing garbage
+                if self.parse_element().is_err() {
+                    return Ok(documents);
+                }
+            }
 
-    pub fn get_block_groups(&self) -> Vec<BlockGroupNode> {
-        filter_nodes!(self.get_children(), BlockGroupNode, 0xa0)
+            let header = match (self.peek_element_id()?, documents.last()) {
+                (Some(ID_EBMLHEADERNODE), _) => self.read_header()?,
+                // another Segment sharing the previous header
+                (Some(ID_SEGMENTNODE), Some(previous)) if self.element_header_available()? => {
+                    let header = previous.header.clone();
+                    self.segment = Some(self.parse_element()?);
+                    header
+                },
+                _ => break,
+            };
+            documents.push(self.parse_segment(header)?);
+        }
+        Ok(documents)
+    }
+
+    // Visit every element in the stream in document order without building
+    // a tree, as directed by `visit`. Masters skipped with SkipChildren are
+    // stepped over without reading them, and Stop returns straight away, so
+    // e.g. finding the Tracks only reads the start of the file. Unknown
+    // elements are visited with their data whatever `unknown_elements` says.
+    pub fn walk(&mut self, mut visit: impl FnMut(&ElementPath, &Element) -> WalkControl) -> Result<(), Error> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut path = Vec::new();
+        while self.element_header_available()? {
+            if self.walk_element(&mut path, &mut visit, u64::MAX)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Visit the element at the current position and maybe its children,
+    // true if the walk was stopped. `limit` is where its parent's children
+    // end, see `children_end`.
+    fn walk_element(
+        &mut self,
+        path: &mut Vec<u64>,
+        visit: &mut impl FnMut(&ElementPath, &Element) -> WalkControl,
+        limit: u64,
+    ) -> Result<bool, Error> {
+        let element = self.parse_element()?;
+        if path.len() > MAX_DEPTH {
+            return Err(Error::TooDeep { offset: element.offset });
+        }
+        path.push(element.id);
+        let control = visit(&ElementPath(path.clone()), &element);
+        match control {
+            WalkControl::Stop => return Ok(true),
+            _ if element.kind != ElementKind::Master => (),
+            WalkControl::SkipChildren if element.is_unknown_size() => {
+                let limit = self.stream_len()?;
+                self.seek_to(element.offset)?;
+                let end = self.complete_element_end(limit)?.unwrap_or(limit);
+                self.seek_to(end)?;
+            },
+            WalkControl::SkipChildren => {
+                let end = element.end_offset().min(self.stream_len()?);
+                self.seek_to(end)?;
+            },
+            WalkControl::Continue => {
+                let limit = children_end(&element, limit);
+                while self.has_next_child(&element, limit)? && self.element_header_available()? {
+                    if self.walk_element(path, visit, limit)? {
+                        return Ok(true);
+                    }
+                }
+            },
+        }
+        path.pop();
+        Ok(false)
     }
-}
 
-impl BlockGroupNode {
-    pub fn get_block_duration(&self) -> Option<u64> {
-        match find_node_data!(self.get_children(), 0x9b) {
-            Some(d) => Some(d.into_uint()),
-            None => None,
+    // Read the EBML header at the current position and the Segment element
+    // header, leaving the reader at the first child of the Segment
+    fn read_header(&mut self) -> Result<EBMLHeaderNode, Error> {
+        // check magic number
+        let start = self.position()?;
+        if !self.check_magic_number()? {
+            return Err(Error::InvalidMagic);
         }
-    }
 
-    pub fn get_reference_blocks(&self) -> Vec<i64> {
-        filter_nodes_raw!(self.get_children(), 0xfb)
-            .map(|node| node.element.data.into_int())
-            .collect()
+        // seek back to the start of the header
+        self.seek_to(start)?;
+        let timer = self.profile.as_ref().map(|_| Instant::now());
+
+        // parse master element, the header itself is read with the WebM limits
+        self.max_id_length = MAX_ID_LENGTH;
+        self.max_size_length = MAX_SIZE_LENGTH;
+        let header = EBMLHeaderNode(self.build_node_tree()?);
+        self.record(ID_EBMLHEADERNODE, start, timer)?;
+        self.max_id_length = (header.get_max_id_length() as usize).clamp(1, MAX_ID_LENGTH);
+        self.max_size_length = (header.get_max_size_length() as usize).clamp(1, MAX_SIZE_LENGTH);
+        if self.peek_element_id()? != Some(ID_SEGMENTNODE) || !self.element_header_available()? {
+            return Err(Error::MissingSegment);
+        }
+        // read the segment header, its children are read on demand
+        let segment_start = self.position()?;
+        let timer = self.profile.as_ref().map(|_| Instant::now());
+        self.segment = Some(self.parse_element()?);
+        self.record(ID_SEGMENTNODE, segment_start, timer)?;
+        Ok(header)
+    }
+
+    // Note an element read from `start` up to the current position, if
+    // profiling
+    fn record(&mut self, id: u64, start: u64, timer: Option<Instant>) -> Result<(), Error> {
+        let bytes = self.position()? - start;
+        if let (Some(profile), Some(timer)) = (self.profile.as_mut(), timer) {
+            let time = timer.elapsed();
+            profile.record(id, bytes, time);
+            profile.total += time;
+        }
+        Ok(())
     }
 
-    pub fn get_discard_padding(&self) -> Option<i64> {
-        match find_node_data!(self.get_children(), 0x75a2) {
-            Some(d) => Some(d.into_int()),
-            None => None,
+    // Parse the next top level element in the Segment, None once the end of
+    // the Segment is reached
+    pub fn next_segment_child(&mut self) -> Result<Option<Node>, Error> {
+        let segment = match self.segment.clone() {
+            Some(segment) => segment,
+            None => return Ok(None),
+        };
+        let limit = children_end(&segment, u64::MAX);
+        loop {
+            // a truncated Segment ends where the data does
+            if !self.has_next_child(&segment, limit)? || !self.element_header_available()? {
+                return Ok(None);
+            }
+            let start = self.position()?;
+            let timer = self.profile.as_ref().map(|_| Instant::now());
+            let kept = self.kept(ID_SEGMENTNODE);
+            let node = self.build_node_tree_at(0, kept, limit)?;
+            self.record(node.element.id, start, timer)?;
+            if !self.skips(&node.element) && self.retains(&node, kept) {
+                return Ok(Some(node));
+            }
         }
     }
 
-    pub fn get_slices(&self) -> Option<SlicesNode> {
-        find_node!(self.get_children(), SlicesNode, 0x8e)
-    }
-}
+    // Codecs, dimensions and duration from just the EBML header, Info and
+    // Tracks. Reading stops once both are found or at the first Cluster, so
+    // this is quick however large the file is.
+    pub fn probe(&mut self) -> Result<Probe, Error> {
+        let header = self.parse_header()?;
+        let mut info = None;
+        let mut tracks = None;
+        while info.is_none() || tracks.is_none() {
+            if self.peek_element_id()? == Some(ID_CLUSTERNODE) {
+                break;
+            }
+            match self.next_segment_child()? {
+                Some(node) if node.element.id == ID_INFONODE => info = Some(InfoNode(node)),
+                Some(node) if node.element.id == ID_TRACKSNODE => tracks = Some(TracksNode(node)),
+                Some(_) => (),
+                None => break,
+            }
+        }
 
-impl TracksNode {
-    pub fn get_track_entries(&self) -> Vec<TrackEntryNode> {
-        filter_nodes!(self.get_children(), TrackEntryNode, 0xae)
+        let segment = self.segment.clone().ok_or(Error::MissingSegment)?;
+        let tracks = tracks.ok_or(Error::MissingElement { id: ID_TRACKSNODE, offset: segment.data_offset() })?;
+        let duration_ms = info.and_then(|info| {
+            let scale = info.get_timestamp_scale();
+            info.get_duration().map(|ticks| (ticks * scale as f64 / 1e6).round() as u64)
+        });
+        Ok(Probe {
+            doc_type: header.get_doc_type(),
+            duration_ms,
+            tracks: tracks.get_track_entries().iter().map(Track::from).collect(),
+        })
     }
-}
 
-impl TrackEntryNode {
-    pub fn get_track_number(&self) -> u64 {
-        find_node_data!(self.get_children(), 0xd7).unwrap().into()
+    // Current byte offset, which is always on an element boundary between
+    // calls. Save this to resume with `parse_from` later.
+    pub fn position(&mut self) -> Result<u64, Error> {
+        Ok(self.reader.stream_position()?)
+    }
+
+    fn parse_segment(&mut self, header: EBMLHeaderNode) -> Result<WebmFile, Error> {
+        let mut children: Vec<Node> = Vec::new();
+        // Clusters still holding their payloads, oldest first, with sizes
+        let mut retained = VecDeque::new();
+        let mut retained_bytes = 0;
+        while let Some(node) = self.next_segment_child()? {
+            if let (Some(budget), ID_CLUSTERNODE) = (self.memory_budget, node.element.id) {
+                let bytes = node.payload_len();
+                retained.push_back((children.len(), bytes));
+                retained_bytes += bytes;
+                while retained_bytes > budget && retained.len() > 1 {
+                    let (index, bytes) = retained.pop_front().unwrap();
+                    children[index].drop_payload();
+                    retained_bytes -= bytes;
+                }
+            }
+            children.push(node);
+        }
+
+        let root = SegmentNode(Node {
+            element: self.segment.clone().ok_or(Error::MissingSegment)?,
+            children,
+        });
+        Ok(WebmFile {
+            header,
+            root,
+        })
+    }
+
+    // Length of the underlying stream, which may have grown since the last call
+    pub(crate) fn stream_len(&mut self) -> Result<u64, Error> {
+        let position = self.position()?;
+        let len = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(position))?;
+        Ok(len)
+    }
+
+    pub(crate) fn seek_to(&mut self, offset: u64) -> Result<(), Error> {
+        let current = self.position()?;
+        // seek_relative keeps the read buffer when we're moving within it
+        self.reader.seek_relative(offset as i64 - current as i64)?;
+        Ok(())
+    }
+
+    // Access the underlying stream, e.g. to append data to an in-memory buffer
+    pub fn get_mut(&mut self) -> &mut T {
+        self.reader.get_mut()
+    }
+
+    // The payload of an element from this stream, read from the source as
+    // it's consumed, for those left out by `max_binary_size` or
+    // `memory_budget`
+    pub fn payload_reader(&mut self, element: &Element) -> Result<impl Read + '_, Error> {
+        self.seek_to(element.data_offset())?;
+        Ok((&mut self.reader).take(element.size))
+    }
+
+    // Up to `len` raw bytes from `offset`, fewer at the end of the stream
+    pub(crate) fn read_raw_at(&mut self, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+        self.seek_to(offset)?;
+        let mut buf = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub(crate) fn segment_element(&self) -> Option<&Element> {
+        self.segment.as_ref()
+    }
+
+    // Work out where the element at the current position ends without
+    // reading its payload. None if it doesn't fit within the first `limit`
+    // bytes of the stream, or if it has an unknown size and could still
+    // grow. The reader position is left unchanged.
+    pub(crate) fn complete_element_end(&mut self, limit: u64) -> Result<Option<u64>, Error> {
+        let start = self.position()?;
+        let end = self.scan_element_end(limit, 0);
+        self.seek_to(start)?;
+        end
+    }
+
+    fn scan_element_end(&mut self, limit: u64, depth: usize) -> Result<Option<u64>, Error> {
+        let offset = self.position()?;
+        if depth > MAX_DEPTH {
+            return Err(Error::TooDeep { offset });
+        }
+        let id = match vint::read_raw(&mut self.reader) {
+            Some((id, _)) => id,
+            None => return Ok(None),
+        };
+        let size = match vint::read_size(&mut self.reader) {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+        let data_start = self.position()?;
+        if data_start > limit {
+            return Ok(None);
+        }
+        if size != UNKNOWN_SIZE {
+            return Ok(data_start.checked_add(size).filter(|end| *end <= limit));
+        }
+
+        // unknown sizes end where the first non-child element starts
+        loop {
+            let position = self.position()?;
+            match self.peek_element_id()? {
+                Some(child) if !ends_unknown_size(id, child) => {
+                    match self.scan_element_end(limit, depth + 1)? {
+                        Some(end) => self.seek_to(end)?,
+                        None => return Ok(None),
+                    }
+                },
+                Some(_) => return Ok(Some(position)),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    // Read the next element ID without consuming it, None at the end of the
+    // stream
+    pub(crate) fn peek_element_id(&mut self) -> Result<Option<u64>, Error> {
+        let position = self.position()?;
+        let id = vint::read_id(&mut self.reader);
+        self.seek_to(position)?;
+        Ok(id)
+    }
+
+    // Whether a complete element ID and size can be read at the current
+    // position. The reader position is left unchanged.
+    pub(crate) fn element_header_available(&mut self) -> Result<bool, Error> {
+        let start = self.position()?;
+        let available = vint::read_raw(&mut self.reader).is_some()
+            && vint::read_size(&mut self.reader).is_some();
+        self.seek_to(start)?;
+        Ok(available)
+    }
+
+    // Whether the master element being read has more children at the
+    // current position, given where they end, see `children_end`
+    fn has_next_child(&mut self, parent: &Element, limit: u64) -> Result<bool, Error> {
+        if self.position()? >= limit {
+            return Ok(false);
+        }
+        if !parent.is_unknown_size() {
+            return Ok(true);
+        }
+        Ok(match self.peek_element_id()? {
+            Some(id) => !ends_unknown_size(parent.id, id),
+            None => false,
+        })
+    }
+
+    pub(crate) fn build_node_tree(&mut self) -> Result<Node, Error> {
+        self.build_node_tree_at(0, true, u64::MAX)
+    }
+
+    // `kept` is whether an ancestor is kept whole by `keep_elements`, and
+    // `limit` where the parent's children end
+    fn build_node_tree_at(&mut self, depth: usize, kept: bool, limit: u64) -> Result<Node, Error> {
+        // parse next element
+        let elem = self.parse_element_in(kept)?;
+        let kept = kept || self.kept(elem.id);
+        if depth > MAX_DEPTH {
+            return Err(Error::TooDeep { offset: elem.offset });
+        }
+        let mut children: Vec<Node> = Vec::new();
+
+        // if elem is a master, build child node tree
+        if elem.kind == ElementKind::Master {
+            let limit = children_end(&elem, limit);
+            while self.has_next_child(&elem, limit)? {
+                let child = self.build_node_tree_at(depth + 1, kept, limit)?;
+                if !self.skips(&child.element) && self.retains(&child, kept) {
+                    children.push(child);
+                }
+            }
+        }
+
+        Ok(Node {
+            element: elem,
+            children,
+        })
+    }
+
+    // Whether elements of this kind are only read to step over them
+    fn skips(&self, element: &Element) -> bool {
+        self.skips_id(element.id, &element.kind)
+    }
+
+    // Whether `keep_elements` keeps this ID, always so without a list
+    fn kept(&self, id: u64) -> bool {
+        self.kept_ids.as_ref().is_none_or(|ids| ids.contains(&id))
+    }
+
+    // Whether a parsed node stays in the tree under `keep_elements`: kept
+    // itself or by an ancestor, or holding something that is
+    fn retains(&self, node: &Node, kept_ancestor: bool) -> bool {
+        kept_ancestor || self.kept(node.element.id) || !node.children.is_empty()
+    }
+
+    fn skips_id(&self, id: u64, kind: &ElementKind) -> bool {
+        (*kind == ElementKind::Unknown && self.unknown_elements == UnknownElements::Skip)
+            || self.skipped_ids.contains(&id)
+    }
+
+    // Read a vint from an element header, telling a stream that ends early
+    // apart from a first byte with no length marker
+    fn read_header_vint(&mut self, offset: u64) -> Result<(u64, usize), Error> {
+        let start = self.position()?;
+        if let Some(vint) = vint::read_raw(&mut self.reader) {
+            return Ok(vint);
+        }
+        self.seek_to(start)?;
+        let mut first = [0u8; 1];
+        match self.reader.read(&mut first)? {
+            1 if first[0] == 0 => Err(Error::InvalidVint { offset: start }),
+            _ => Err(Error::UnexpectedEof { offset }),
+        }
+    }
+
+    pub(crate) fn parse_element(&mut self) -> Result<Element, Error> {
+        self.parse_element_in(true)
+    }
+
+    // Parse an element, only reading the payload of one left out by
+    // `keep_elements` if `kept` says an ancestor keeps it
+    fn parse_element_in(&mut self, kept: bool) -> Result<Element, Error> {
+        let offset = self.position()?;
+        // read ID, keeping the length marker
+        let (id, id_len) = self.read_header_vint(offset)?;
+        if id_len > self.max_id_length {
+            return Err(Error::InvalidVint { offset });
+        }
+        // read next vint
+        let (raw, len) = self.read_header_vint(offset)?;
+        if len > self.max_size_length {
+            return Err(Error::InvalidVint { offset: offset + id_len as u64 });
+        }
+        let size = vint::size(raw, len);
+        let header_size = self.position()? - offset;
+        let kind = element_kind(id);
+        if check_size(id, &kind, size, offset, self.invalid_floats)? {
+            self.warnings.push(Warning::InvalidFloatLength { id, offset, len: size });
+        }
+        if kind == ElementKind::Unknown && self.unknown_elements == UnknownElements::Reject {
+            return Err(Error::UnknownElement { id, offset });
+        }
+
+        // assign the element data
+        // if master, ignore data
+        let pruned = !kept && kind != ElementKind::Master && !self.kept(id);
+        let oversized = kind == ElementKind::Binary
+            && id != ID_SIMPLEBLOCK
+            && id != ID_BLOCK
+            && self.max_binary_size.is_some_and(|max| size > max);
+        let skipped = (self.skips_id(id, &kind) || pruned || oversized) && size != UNKNOWN_SIZE;
+        let data = if kind == ElementKind::Master && !skipped {
+            ElementData(Vec::new())
+        } else if skipped {
+            let len = self.stream_len()?;
+            let end = self.position()?.checked_add(size)
+                .filter(|end| *end <= len)
+                .ok_or(Error::UnexpectedEof { offset })?;
+            self.seek_to(end)?;
+            ElementData(Vec::new())
+        } else {
+            ElementData(read_bytes(&mut self.reader, size).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => Error::UnexpectedEof { offset },
+                _ => Error::Io(e),
+            })?)
+        };
+
+        let element = Element {
+            id,
+            size,
+            kind,
+            data,
+            offset,
+            header_size,
+        };
+        check_string(&element, self.invalid_strings, &mut self.warnings)?;
+        let is_string = element.kind == ElementKind::String || element.kind == ElementKind::UTF8;
+        if let Some(strings) = self.strings.as_mut().filter(|_| is_string && size <= MAX_POOLED_STRING) {
+            strings.get(&element.data);
+        }
+        Ok(element)
+    }
+
+    fn check_magic_number(&mut self) -> Result<bool, IOError> {
+        let mut buf: [u8; 4] = [0; 4];
+        match self.reader.read(&mut buf) {
+            Ok(size) => Ok(size == 4 && buf == MAGIC_NUMBER),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl WebmFile {
+    // Parse a whole document from a file or anything else that can be read
+    // and seeked, e.g. a Cursor over a buffer or an mmap adapter
+    pub fn open<R: Read + Seek>(reader: R) -> Result<WebmFile, Error> {
+        WebmReader::new(reader).parse()
+    }
+
+    // Parse a document that is already in memory, e.g. an ArrayBuffer
+    // handed over from JavaScript
+    pub fn from_bytes(data: &[u8]) -> Result<WebmFile, Error> {
+        WebmReader::new(Cursor::new(data)).parse()
+    }
+
+    // Check up front that the document is WebM that this crate can read,
+    // rather than finding out from getters returning defaults later on
+    pub fn check_compatibility(&self) -> Result<(), Error> {
+        self.check_compatibility_in(DocTypeMode::WebM)
+    }
+
+    pub fn check_compatibility_in(&self, mode: DocTypeMode) -> Result<(), Error> {
+        let doc_type = self.header.get_doc_type();
+        let accepted = match mode {
+            DocTypeMode::WebM => doc_type == "webm",
+            DocTypeMode::Matroska => doc_type == "webm" || doc_type == "matroska",
+        };
+        if !accepted {
+            return Err(Error::UnsupportedDocType(doc_type));
+        }
+
+        let ebml_read_version = self.header.get_read_version();
+        if ebml_read_version > EBML_READ_VERSION {
+            return Err(Error::UnsupportedVersion { doc_type: "EBML".to_string(), read_version: ebml_read_version });
+        }
+        let read_version = self.header.get_doc_type_read_version();
+        if read_version > DOC_TYPE_READ_VERSION {
+            return Err(Error::UnsupportedVersion { doc_type, read_version });
+        }
+        Ok(())
+    }
+
+    // Iterate every frame in the Segment's clusters in file order
+    pub fn packets(&self) -> Packets {
+        Packets::new(self.root.get_clusters())
+    }
+
+    // Every element, the EBML header's and then the Segment's, in document
+    // order with its path
+    pub fn iter(&self) -> Elements<'_> {
+        Elements::new(&[&self.header.0, &self.root.0])
+    }
+
+    // Read the elements in `range` of the source again and put them in
+    // place of the ones parsed from there before, e.g. after an edit from
+    // the `inplace` module. The range has to cover whole elements inside
+    // one master, and what's there now has to fill it exactly, as edits
+    // that keep everything else where it was do. Nothing outside the range
+    // is read.
+    pub fn reparse_range<T: Read + Seek>(&mut self, r: T, range: Range<u64>) -> Result<(), Error> {
+        let mut reader = WebmReader::new(r);
+        reader.parse_header()?;
+        splice_range(&mut self.root.0, &mut reader, &range)
+    }
+
+    // `iter` as directed by `visit`, see `WalkControl`
+    pub fn walk(&self, visit: impl FnMut(&ElementPath, &Element) -> WalkControl) {
+        self.iter().walk(visit)
+    }
+
+    // The same frames backwards, latest first, e.g. for rewinding
+    pub fn packets_rev(&self) -> PacketsRev {
+        PacketsRev::new(self.root.get_clusters())
+    }
+
+    // Summary of the container and its tracks
+    pub fn media_info(&self) -> MediaInfo {
+        MediaInfo::new(self)
+    }
+
+    // Key IDs and encryption schemes of encrypted tracks, if there are any
+    pub fn drm_info(&self) -> Option<DrmInfo> {
+        DrmInfo::new(self)
+    }
+
+    // Write the header and Segment back out from the in-memory tree, with
+    // every size recomputed from the content. The Segment size is written
+    // as an 8 byte placeholder and patched once its children are out, so
+    // they are encoded one at a time. Positions stored in the file (SeekHead,
+    // Cues) and CRC-32s are written as they are and go stale if an edit
+    // moved or changed what they point at.
+    pub fn write_to<W: Write + Seek>(&self, mut w: W) -> Result<(), IOError> {
+        w.write_all(&self.header.as_node().to_bytes())?;
+
+        let mut segment_header = Vec::new();
+        encode::write_element_id(&mut segment_header, ID_SEGMENTNODE);
+        let size_offset = w.stream_position()? + segment_header.len() as u64;
+        vint::write_unknown_size(&mut segment_header);
+        w.write_all(&segment_header)?;
+
+        let mut size = 0;
+        for child in &self.root.0.children {
+            let bytes = child.to_bytes();
+            size += bytes.len() as u64;
+            w.write_all(&bytes)?;
+        }
+
+        let mut patched = Vec::new();
+        if vint::write_with_length(&mut patched, size, 8).is_some() {
+            let end = w.stream_position()?;
+            w.seek(SeekFrom::Start(size_offset))?;
+            w.write_all(&patched)?;
+            w.seek(SeekFrom::Start(end))?;
+        }
+        Ok(())
+    }
+}
+
+// Parsed documents are never mutated through a shared reference, so they
+// must stay Send + Sync for `SharedWebm`. This fails to compile if that
+// ever changes.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn check<T: Send + Sync>() {}
+    check::<WebmFile>();
+    check::<Node>();
+    check::<Element>();
+    check::<ElementData>();
+    check::<SegmentNode>();
+}
+
+impl EBMLHeaderNode {
+    pub fn get_version(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x4286, 1)
+    }
+
+    pub fn get_read_version(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x42f7, 1)
+    }
+
+    pub fn get_max_id_length(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x42f2, 4)
+    }
+
+    pub fn get_max_size_length(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x42f3, 8)
+    }
+
+    pub fn get_doc_type(&self) -> String {
+        find_node_data_mand!(self.get_children(), 0x4282)
+    }
+
+    // Borrowed versions of the getters, for hot paths that shouldn't
+    // allocate. Strings stop at the first zero byte or invalid UTF-8.
+    pub fn doc_type(&self) -> &str {
+        borrow_node_data!(self.0, 0x4282).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    pub fn get_doc_type_version(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x4287, 1)
+    }
+
+    pub fn get_doc_type_read_version(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x4285, 1)
+    }
+}
+
+impl SegmentNode {
+    pub fn get_seek_head_nodes(&self) -> Vec<SeekHeadNode> {
+        filter_nodes!(self.get_children(), SeekHeadNode, 0x114d9b74)
+    }
+
+    pub fn get_info_nodes(&self) -> Vec<InfoNode> {
+        filter_nodes!(self.get_children(), InfoNode, 0x1549a966)
+    }
+
+    pub fn get_clusters(&self) -> Vec<ClusterNode> {
+        filter_nodes!(self.get_children(), ClusterNode, 0x1F43B675)
+    }
+
+    pub fn get_tracks(&self) -> Vec<TracksNode> {
+        filter_nodes!(self.get_children(), TracksNode, 0x1654ae6b)
+    }
+
+    pub fn get_cues(&self) -> Vec<CuesNode> {
+        filter_nodes!(self.get_children(), CuesNode, 0x1c53bb6b)
+    }
+
+    pub fn get_chapters(&self) -> Vec<ChaptersNode> {
+        filter_nodes!(self.get_children(), ChaptersNode, 0x1043a770)
+    }
+
+    pub fn get_tags(&self) -> Vec<TagsNode> {
+        filter_nodes!(self.get_children(), TagsNode, 0x1254c367)
+    }
+
+    pub fn get_signature_slots(&self) -> Vec<SignatureSlotNode> {
+        filter_nodes!(self.get_children(), SignatureSlotNode, 0x1b538667)
+    }
+
+    // Matroska only, WebM doesn't allow attachments
+    pub fn get_attachments(&self) -> Vec<AttachmentsNode> {
+        filter_nodes!(self.get_children(), AttachmentsNode, 0x1941a469)
+    }
+}
+
+impl AttachmentsNode {
+    pub fn get_attached_files(&self) -> Vec<AttachedFileNode> {
+        filter_nodes!(self.get_children(), AttachedFileNode, 0x61a7)
+    }
+}
+
+impl AttachedFileNode {
+    pub fn get_description(&self) -> Option<String> {
+        find_node_data_opt!(self.get_children(), 0x467e)
+    }
+
+    pub fn get_name(&self) -> String {
+        find_node_data_mand!(self.get_children(), 0x466e)
+    }
+
+    pub fn get_media_type(&self) -> String {
+        find_node_data_mand!(self.get_children(), 0x4660)
+    }
+
+    pub fn get_data(&self) -> Vec<u8> {
+        find_node_data_mand!(self.get_children(), 0x465c)
+    }
+
+    pub fn get_uid(&self) -> u64 {
+        find_node_data_mand!(self.get_children(), 0x46ae)
+    }
+}
+
+impl SeekHeadNode {
+    pub fn get_seek_nodes(&self) -> Vec<SeekNode> {
+        filter_nodes!(self.get_children(), SeekNode, 0x4dbb)
+    }
+}
+
+impl SeekNode {
+    pub fn get_seek_id(&self) -> Vec<u8> {
+        find_node_data_mand!(self.get_children(), 0x53ab)
+    }
+
+    pub fn get_seek_position(&self) -> u64 {
+        find_node_data_mand!(self.get_children(), 0x53ac)
+    }
+}
+
+impl InfoNode {
+    pub fn get_timestamp_scale(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x2ad7b1, 1_000_000)
+    }
+
+    pub fn get_duration(&self) -> Option<f64> {
+        find_node_data!(self.get_children(), 0x4489).map(|d| d.into_float())
+    }
+
+    pub fn get_date_created(&self) -> Option<i64> {
+        find_node_data!(self.get_children(), 0x4461).map(|d| d.into_int())
+    }
+
+    pub fn get_muxing_app(&self) -> String {
+        find_node_data_mand!(self.get_children(), 0x4d80)
+    }
+
+    pub fn get_writing_app(&self) -> String {
+        find_node_data_mand!(self.get_children(), 0x5741)
+    }
+
+    pub fn get_title(&self) -> Option<String> {
+        find_node_data_opt!(self.get_children(), 0x7ba9)
+    }
+
+    pub fn muxing_app(&self) -> &str {
+        borrow_node_data!(self.0, 0x4d80).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    pub fn writing_app(&self) -> &str {
+        borrow_node_data!(self.0, 0x5741).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        borrow_node_data!(self.0, 0x7ba9).map(ElementData::as_str)
+    }
+
+    pub fn get_segment_uid(&self) -> Option<SegmentUid> {
+        find_node_data!(self.get_children(), 0x73a4)
+            .and_then(|d| SegmentUid::from_slice(&d.into_vec()))
+    }
+
+    // UIDs shared by all segments of the same family
+    pub fn get_segment_families(&self) -> Vec<SegmentUid> {
+        filter_nodes_raw!(self.get_children(), 0x4444)
+            .filter_map(|node| SegmentUid::from_slice(&node.element.data.into_vec()))
+            .collect()
+    }
+
+    pub fn get_prev_uid(&self) -> Option<SegmentUid> {
+        find_node_data!(self.get_children(), 0x3cb923)
+            .and_then(|d| SegmentUid::from_slice(&d.into_vec()))
+    }
+
+    pub fn get_next_uid(&self) -> Option<SegmentUid> {
+        find_node_data!(self.get_children(), 0x3eb923)
+            .and_then(|d| SegmentUid::from_slice(&d.into_vec()))
+    }
+
+    pub fn get_segment_filename(&self) -> Option<String> {
+        find_node_data_opt!(self.get_children(), 0x7384)
+    }
+
+    pub fn get_prev_filename(&self) -> Option<String> {
+        find_node_data_opt!(self.get_children(), 0x3c83ab)
+    }
+
+    pub fn get_next_filename(&self) -> Option<String> {
+        find_node_data_opt!(self.get_children(), 0x3e83ab)
+    }
+}
+
+impl ClusterNode {
+    pub fn get_timestamp(&self) -> u64 {
+        find_node_data_mand!(self.get_children(), 0xe7)
+    }
+
+    pub fn get_prev_size(&self) -> Option<u64> {
+        find_node_data!(self.get_children(), 0xab).map(|d| d.into_uint())
+    }
+
+    pub fn get_simple_blocks(&self) -> Vec<Node> {
+        filter_nodes!(self.get_children(), 0xa3)
+    }
+
+    pub fn get_block_groups(&self) -> Vec<BlockGroupNode> {
+        filter_nodes!(self.get_children(), BlockGroupNode, 0xa0)
+    }
+}
+
+impl BlockGroupNode {
+    pub fn get_block_duration(&self) -> Option<u64> {
+        find_node_data!(self.get_children(), 0x9b).map(|d| d.into_uint())
+    }
+
+    pub fn get_reference_blocks(&self) -> Vec<i64> {
+        filter_nodes_raw!(self.get_children(), 0xfb)
+            .map(|node| node.element.data.into_int())
+            .collect()
+    }
+
+    pub fn get_discard_padding(&self) -> Option<i64> {
+        find_node_data!(self.get_children(), 0x75a2).map(|d| d.into_int())
+    }
+
+    pub fn get_slices(&self) -> Option<SlicesNode> {
+        find_node!(self.get_children(), SlicesNode, 0x8e)
+    }
+}
+
+// Slices are from early Matroska and no longer written, but old files use
+// them to describe the frames laced in a Block
+impl SlicesNode {
+    pub fn get_time_slices(&self) -> Vec<TimeSliceNode> {
+        filter_nodes!(self.get_children(), TimeSliceNode, 0xe8)
+    }
+}
+
+impl TimeSliceNode {
+    // Which frame of the Block's lace this describes
+    pub fn get_lace_number(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0xcc, 0)
+    }
+
+    pub fn get_frame_number(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0xcd, 0)
+    }
+
+    pub fn get_block_addition_id(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0xcb, 0)
+    }
+
+    // In the track's timestamp units
+    pub fn get_delay(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0xce, 0)
+    }
+
+    pub fn get_slice_duration(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0xcf, 0)
+    }
+}
+
+impl TracksNode {
+    pub fn get_track_entries(&self) -> Vec<TrackEntryNode> {
+        filter_nodes!(self.get_children(), TrackEntryNode, 0xae)
+    }
+}
+
+impl TrackEntryNode {
+    pub fn get_track_number(&self) -> u64 {
+        find_node_data_mand!(self.get_children(), 0xd7)
     }
 
     pub fn get_track_uid(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x73c5).unwrap().into()
+        find_node_data_mand!(self.get_children(), 0x73c5)
     }
 
     pub fn get_track_type(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x83).unwrap().into()
+        find_node_data_mand!(self.get_children(), 0x83)
     }
 
     pub fn is_enabled(&self) -> bool {
-        find_node_data!(self.get_children(), 0xb9).unwrap().into()
+        find_node_data_or!(self.get_children(), 0xb9, true)
     }
 
     pub fn is_default(&self) -> bool {
-        find_node_data!(self.get_children(), 0x88).unwrap().into()
+        find_node_data_or!(self.get_children(), 0x88, true)
     }
 
     pub fn is_forced(&self) -> bool {
-        find_node_data!(self.get_children(), 0x55aa).unwrap().into()
+        find_node_data_or!(self.get_children(), 0x55aa, false)
     }
 
     pub fn is_laced(&self) -> bool {
-        find_node_data!(self.get_children(), 0x9c).unwrap().into()
+        find_node_data_or!(self.get_children(), 0x9c, true)
     }
 
     pub fn get_default_duration(&self) -> Option<u64> {
-        match find_node_data!(self.get_children(), 0x23e383) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x23e383).map(|d| d.into())
     }
 
     pub fn get_name(&self) -> Option<String> {
-        match find_node_data!(self.get_children(), 0x536e) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x536e).map(|d| d.into())
     }
 
     pub fn get_language(&self) -> Option<String> {
-        match find_node_data!(self.get_children(), 0x22b59c) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x22b59c).map(|d| d.into())
     }
 
     pub fn get_codec_id(&self) -> String {
-        find_node_data!(self.get_children(), 0x86).unwrap().into()
+        find_node_data_mand!(self.get_children(), 0x86)
     }
 
     pub fn get_codec_private(&self) -> Option<Vec<u8>> {
-        match find_node_data!(self.get_children(), 0x63a2) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x63a2).map(|d| d.into())
     }
 
     pub fn get_codec_name(&self) -> Option<String> {
-        match find_node_data!(self.get_children(), 0x258688) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x258688).map(|d| d.into())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        borrow_node_data!(self.0, 0x536e).map(ElementData::as_str)
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        borrow_node_data!(self.0, 0x22b59c).map(ElementData::as_str)
+    }
+
+    pub fn codec_id(&self) -> &str {
+        borrow_node_data!(self.0, 0x86).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    pub fn codec_private(&self) -> Option<&[u8]> {
+        borrow_node_data!(self.0, 0x63a2).map(ElementData::as_bytes)
+    }
+
+    pub fn codec_name(&self) -> Option<&str> {
+        borrow_node_data!(self.0, 0x258688).map(ElementData::as_str)
+    }
+
+    // Shared copies out of `strings`, e.g. the one filled by
+    // `WebmReader::string_pool`, instead of a new String per call
+    pub fn codec_id_in(&self, strings: &StringPool) -> Arc<str> {
+        borrow_node_data!(self.0, 0x86).map_or_else(|| Arc::from(""), |data| strings.lookup(data))
+    }
+
+    pub fn language_in(&self, strings: &StringPool) -> Option<Arc<str>> {
+        borrow_node_data!(self.0, 0x22b59c).map(|data| strings.lookup(data))
     }
 
     pub fn get_codec_delay(&self) -> Option<u64> {
-        match find_node_data!(self.get_children(), 0x56aa) {
-            Some(d) => Some(d.into()),
-            None => None,
-        }
+        find_node_data!(self.get_children(), 0x56aa).map(|d| d.into())
     }
 
     pub fn get_seek_preroll(&self) -> u64 {
-        find_node_data!(self.get_children(), 0x56bb).unwrap().into()
+        find_node_data_or!(self.get_children(), 0x56bb, 0)
     }
 
     pub fn get_video_settings(&self) -> Option<VideoNode> {
@@ -592,11 +1855,26 @@ impl TrackEntryNode {
     pub fn get_encoding_settings(&self) -> Option<ContentEncodingsNode> {
         find_node!(self.get_children(), ContentEncodingsNode, 0x6d80)
     }
+
+    // Whether frames are AES encrypted as described by the WebM encryption
+    // spec, in which case each frame starts with a signal byte
+    pub fn is_encrypted(&self) -> bool {
+        self.get_encoding_settings()
+            .map(|settings| settings.get_encodings())
+            .unwrap_or_default()
+            .iter()
+            .any(|encoding| {
+                encoding.get_type() == 1
+                    && find_node!(encoding.get_children(), ContentEncryptionNode, 0x5035)
+                        .map(|encryption| encryption.get_algorithm_type() == 5)
+                        .unwrap_or(false)
+            })
+    }
 }
 
 impl VideoNode {
     pub fn get_interlacing_flag(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x9a)
+        find_node_data_or!(self.get_children(), 0x9a, 0)
     }
 
     pub fn get_stereo_mode(&self) -> Option<u64> {
@@ -646,6 +1924,10 @@ impl VideoNode {
     pub fn get_aspect_ratio_type(&self) -> Option<u64> {
         find_node_data_opt!(self.get_children(), 0x54b3)
     }
+
+    pub fn get_projection(&self) -> Option<ProjectionNode> {
+        find_node!(self.get_children(), ProjectionNode, 0x7670)
+    }
 }
 
 impl ProjectionNode {
@@ -672,7 +1954,7 @@ impl ProjectionNode {
 
 impl AudioNode {
     pub fn get_sampling_frequency(&self) -> f64 {
-        find_node_data_mand!(self.get_children(), 0xb5)
+        find_node_data_or!(self.get_children(), 0xb5, 8000.0)
     }
 
     pub fn get_output_sampling_frequency(&self) -> Option<f64> {
@@ -680,7 +1962,7 @@ impl AudioNode {
     }
 
     pub fn get_num_channels(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x9f)
+        find_node_data_or!(self.get_children(), 0x9f, 1)
     }
 
     pub fn get_bit_depth(&self) -> Option<u64> {
@@ -696,31 +1978,35 @@ impl ContentEncodingsNode {
 
 impl ContentEncodingNode {
     pub fn get_order(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x5031)
+        find_node_data_or!(self.get_children(), 0x5031, 0)
     }
 
     pub fn get_scope(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x5032)
+        find_node_data_or!(self.get_children(), 0x5032, 1)
     }
 
     pub fn get_type(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x5033)
+        find_node_data_or!(self.get_children(), 0x5033, 0)
     }
 
-    pub fn get_encryption_node(&self) -> ContentEncryptionNode {
-        find_node!(self.get_children(), ContentEncryptionNode, 0x5035).unwrap()
+    pub fn get_encryption_node(&self) -> Option<ContentEncryptionNode> {
+        find_node!(self.get_children(), ContentEncryptionNode, 0x5035)
     }
 }
 
 impl ContentEncryptionNode {
     pub fn get_algorithm_type(&self) -> u64 {
-        find_node_data_mand!(self.get_children(), 0x47e1)
+        find_node_data_or!(self.get_children(), 0x47e1, 0)
     }
 
     pub fn get_key_id(&self) -> Option<Vec<u8>> {
         find_node_data_opt!(self.get_children(), 0x47e2)
     }
 
+    pub fn key_id(&self) -> Option<&[u8]> {
+        borrow_node_data!(self.0, 0x47e2).map(ElementData::as_bytes)
+    }
+
     pub fn get_aes_settings(&self) -> Option<ContentEncAESSettingsNode> {
         find_node!(self.get_children(), ContentEncAESSettingsNode, 0x47e7)
     }
@@ -769,9 +2055,27 @@ impl ChaptersNode {
 }
 
 impl EditionEntryNode {
+    pub fn get_uid(&self) -> Option<u64> {
+        find_node_data_opt!(self.get_children(), 0x45bc)
+    }
+
     pub fn get_chapter_atoms(&self) -> Vec<ChapterAtomNode> {
         filter_nodes!(self.get_children(), ChapterAtomNode, 0xb6)
     }
+
+    pub fn is_hidden(&self) -> bool {
+        find_node_data_or!(self.get_children(), 0x45bd, false)
+    }
+
+    pub fn is_default(&self) -> bool {
+        find_node_data_or!(self.get_children(), 0x45db, false)
+    }
+
+    // Ordered editions are played chapter by chapter instead of straight
+    // through, see `chapters::ordered_timeline`
+    pub fn is_ordered(&self) -> bool {
+        find_node_data_or!(self.get_children(), 0x45dd, false)
+    }
 }
 
 impl ChapterAtomNode {
@@ -779,6 +2083,29 @@ impl ChapterAtomNode {
         find_node_data_mand!(self.get_children(), 0x73c4)
     }
 
+    // Nested chapters
+    pub fn get_chapter_atoms(&self) -> Vec<ChapterAtomNode> {
+        filter_nodes!(self.get_children(), ChapterAtomNode, 0xb6)
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        find_node_data_or!(self.get_children(), 0x98, false)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        find_node_data_or!(self.get_children(), 0x4598, true)
+    }
+
+    // The linked segment an ordered chapter plays from
+    pub fn get_segment_uid(&self) -> Option<SegmentUid> {
+        find_node_data!(self.get_children(), 0x6e67)
+            .and_then(|d| SegmentUid::from_slice(&d.into_vec()))
+    }
+
+    pub fn get_segment_edition_uid(&self) -> Option<u64> {
+        find_node_data_opt!(self.get_children(), 0x6ebc)
+    }
+
     pub fn get_string_uid(&self) -> Option<String> {
         find_node_data_opt!(self.get_children(), 0x5654)
     }
@@ -787,9 +2114,44 @@ impl ChapterAtomNode {
         find_node_data_mand!(self.get_children(), 0x91)
     }
 
+    pub fn get_end_time(&self) -> Option<u64> {
+        find_node_data_opt!(self.get_children(), 0x92)
+    }
+
     pub fn get_displays(&self) -> Vec<ChapterDisplayNode> {
         filter_nodes!(self.get_children(), ChapterDisplayNode, 0x80)
     }
+
+    pub fn get_processes(&self) -> Vec<ChapProcessNode> {
+        filter_nodes!(self.get_children(), ChapProcessNode, 0x6944)
+    }
+}
+
+impl ChapProcessNode {
+    // 0 for Matroska Script, 1 for DVD menu commands
+    pub fn get_codec_id(&self) -> u64 {
+        find_node_data_or!(self.get_children(), 0x6955, 0)
+    }
+
+    // e.g. the DVD level and content, for DVD menus
+    pub fn get_private(&self) -> Option<Vec<u8>> {
+        find_node_data_opt!(self.get_children(), 0x450d)
+    }
+
+    pub fn get_commands(&self) -> Vec<ChapProcessCommandNode> {
+        filter_nodes!(self.get_children(), ChapProcessCommandNode, 0x6911)
+    }
+}
+
+impl ChapProcessCommandNode {
+    // 0 while the chapter plays, 1 before it starts and 2 after it ends
+    pub fn get_time(&self) -> u64 {
+        find_node_data_mand!(self.get_children(), 0x6922)
+    }
+
+    pub fn get_data(&self) -> Vec<u8> {
+        find_node_data_mand!(self.get_children(), 0x6933)
+    }
 }
 
 impl ChapterDisplayNode {
@@ -797,6 +2159,10 @@ impl ChapterDisplayNode {
         find_node_data_mand!(self.get_children(), 0x85)
     }
 
+    pub fn string(&self) -> &str {
+        borrow_node_data!(self.0, 0x85).map(ElementData::as_str).unwrap_or_default()
+    }
+
     pub fn get_languages(&self) -> Vec<String> {
         filter_nodes_raw!(self.get_children(), 0x437c)
             .map(|node| node.element.data.into_string())
@@ -810,9 +2176,25 @@ impl TagsNode {
     }
 }
 
-impl TagNode {
-    pub fn get_targets(&self) -> TargetsNode {
-        find_node!(self.get_children(), TargetsNode, 0x63c0).unwrap()
+impl TagNode {
+    pub fn get_targets(&self) -> Option<TargetsNode> {
+        find_node!(self.get_children(), TargetsNode, 0x63c0)
+    }
+
+    pub fn get_simple_tags(&self) -> Vec<SimpleTagNode> {
+        filter_nodes!(self.get_children(), SimpleTagNode, 0x67c8)
+    }
+
+    // A SimpleTag found by the TagNames leading to it through nested
+    // SimpleTags, e.g. ["ARTIST", "URL"] for an artist's URL. The first
+    // match at each level is used.
+    pub fn get_simple_tag(&self, path: &[&str]) -> Option<SimpleTagNode> {
+        let (first, rest) = path.split_first()?;
+        let mut simple_tag = self.get_simple_tags().into_iter().find(|t| t.name() == *first)?;
+        for name in rest {
+            simple_tag = simple_tag.get_simple_tags().into_iter().find(|t| t.name() == *name)?;
+        }
+        Some(simple_tag)
     }
 }
 
@@ -837,6 +2219,11 @@ impl SimpleTagNode {
         find_node_data_mand!(self.get_children(), 0x45a3)
     }
 
+    // SimpleTags nested in this one, which describe it further
+    pub fn get_simple_tags(&self) -> Vec<SimpleTagNode> {
+        filter_nodes!(self.get_children(), SimpleTagNode, 0x67c8)
+    }
+
     pub fn get_language(&self) -> String {
         find_node_data_mand!(self.get_children(), 0x447a)
     }
@@ -852,6 +2239,65 @@ impl SimpleTagNode {
     pub fn get_binary(&self) -> Option<Vec<u8>> {
         find_node_data_opt!(self.get_children(), 0x4485)
     }
+
+    pub fn name(&self) -> &str {
+        borrow_node_data!(self.0, 0x45a3).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    pub fn language(&self) -> &str {
+        borrow_node_data!(self.0, 0x447a).map(ElementData::as_str).unwrap_or_default()
+    }
+
+    // See `TrackEntryNode::codec_id_in`
+    pub fn name_in(&self, strings: &StringPool) -> Arc<str> {
+        borrow_node_data!(self.0, 0x45a3).map_or_else(|| Arc::from(""), |data| strings.lookup(data))
+    }
+
+    pub fn language_in(&self, strings: &StringPool) -> Arc<str> {
+        borrow_node_data!(self.0, 0x447a).map_or_else(|| Arc::from(""), |data| strings.lookup(data))
+    }
+
+    pub fn string(&self) -> Option<&str> {
+        borrow_node_data!(self.0, 0x4487).map(ElementData::as_str)
+    }
+
+    pub fn binary(&self) -> Option<&[u8]> {
+        borrow_node_data!(self.0, 0x4485).map(ElementData::as_bytes)
+    }
+}
+
+impl SignatureSlotNode {
+    pub fn get_algorithm(&self) -> Option<u64> {
+        find_node_data_opt!(self.get_children(), 0x7e8a)
+    }
+
+    pub fn get_hash(&self) -> Option<u64> {
+        find_node_data_opt!(self.get_children(), 0x7e9a)
+    }
+
+    pub fn get_public_key(&self) -> Option<Vec<u8>> {
+        find_node_data_opt!(self.get_children(), 0x7ea5)
+    }
+
+    pub fn get_signature(&self) -> Option<Vec<u8>> {
+        find_node_data_opt!(self.get_children(), 0x7eb5)
+    }
+
+    pub fn get_element_lists(&self) -> Vec<SignatureElementListNode> {
+        match find_node!(self.get_children(), 0x7e5b) {
+            Some(elements) => filter_nodes!(elements.children, SignatureElementListNode, 0x7e7b),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl SignatureElementListNode {
+    // IDs of the elements covered by the signature
+    pub fn get_signed_elements(&self) -> Vec<u64> {
+        filter_nodes_raw!(self.get_children(), 0x6532)
+            .map(|node| node.element.data.into_uint())
+            .collect()
+    }
 }
 
 impl Debug for Element {
@@ -876,11 +2322,79 @@ impl Debug for Element {
     }
 }
 
+// Shares decoded strings between elements with the same bytes, for the
+// values that repeat thousands of times in tag heavy files (languages,
+// codec IDs, tag names). Each distinct value is decoded and allocated once,
+// then handed out as another reference to the same Arc.
+#[derive(Debug, Clone, Default)]
+pub struct StringPool {
+    strings: HashMap<Vec<u8>, Arc<str>>,
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool::default()
+    }
+
+    // Decoded as `ElementData::into_string` does
+    pub fn get(&mut self, data: &ElementData) -> Arc<str> {
+        // bytes past a zero are padding, so they don't make a new value
+        let bytes = nul_terminated(&data.0);
+        if let Some(string) = self.strings.get(bytes) {
+            return string.clone();
+        }
+        let string: Arc<str> = Arc::from(bytes_to_string(bytes));
+        self.strings.insert(bytes.to_vec(), string.clone());
+        string
+    }
+
+    // The pooled copy of a value if there is one, or else a new one that
+    // isn't added, for reading through a pool that's shared
+    pub fn lookup(&self, data: &ElementData) -> Arc<str> {
+        let bytes = nul_terminated(&data.0);
+        match self.strings.get(bytes) {
+            Some(string) => string.clone(),
+            None => Arc::from(bytes_to_string(bytes)),
+        }
+    }
+
+    // The first child of `node` with this ID, without copying the children
+    // as the typed getters do
+    pub fn child(&mut self, node: &Node, id: u64) -> Option<Arc<str>> {
+        let child = node.children.iter().find(|child| child.element.id == id)?;
+        Some(self.get(&child.element.data))
+    }
+
+    // Distinct values seen so far
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[allow(clippy::wrong_self_convention)]
 impl ElementData {
     pub fn into_string(&self) -> String {
         bytes_to_string(&self.0)
     }
 
+    // The string without copying: up to the first zero byte, or the first
+    // invalid UTF-8 where `into_string` would put a replacement character
+    pub fn as_str(&self) -> &str {
+        let bytes = nul_terminated(&self.0);
+        match std::str::from_utf8(bytes) {
+            Ok(string) => string,
+            Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
     pub fn into_uint(&self) -> u64 {
         bytes_to_uint(&self.0)
     }
@@ -889,6 +2403,11 @@ impl ElementData {
         bytes_to_int(&self.0)
     }
 
+    // Date data as ISO 8601 in UTC
+    pub fn as_iso8601(&self) -> String {
+        format::format_date(self.into_int())
+    }
+
     pub fn into_float(&self) -> f64 {
         bytes_to_float(&self.0)
     }
@@ -896,67 +2415,232 @@ impl ElementData {
     pub fn into_vec(&self) -> Vec<u8> {
         self.0.clone()
     }
+
+    // Strict versions of the conversions above, which otherwise replace
+    // invalid UTF-8, keep only the low bytes of oversized integers and read
+    // badly sized floats as NaN. The parser rejects bad sizes for elements
+    // of a known kind unless told to keep floats, so these matter for those,
+    // Unknown elements and data built by hand.
+    pub fn try_into_string(&self) -> Result<String, DataError> {
+        String::from_utf8(nul_terminated(&self.0).to_vec())
+            .map_err(|e| DataError::InvalidUtf8 { valid_up_to: e.utf8_error().valid_up_to() })
+    }
+
+    pub fn try_into_uint(&self) -> Result<u64, DataError> {
+        self.check_int_len()?;
+        Ok(self.into_uint())
+    }
+
+    pub fn try_into_int(&self) -> Result<i64, DataError> {
+        self.check_int_len()?;
+        Ok(self.into_int())
+    }
+
+    pub fn try_into_float(&self) -> Result<f64, DataError> {
+        match self.0.len() {
+            0 | 4 | 8 => Ok(self.into_float()),
+            len => Err(DataError::InvalidFloatLength { len }),
+        }
+    }
+
+    fn check_int_len(&self) -> Result<(), DataError> {
+        match self.0.len() {
+            len if len > 8 => Err(DataError::TooLong { len }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<&ElementData> for String {
+    type Error = DataError;
+
+    fn try_from(data: &ElementData) -> Result<String, DataError> {
+        data.try_into_string()
+    }
+}
+
+impl TryFrom<&ElementData> for u64 {
+    type Error = DataError;
+
+    fn try_from(data: &ElementData) -> Result<u64, DataError> {
+        data.try_into_uint()
+    }
+}
+
+impl TryFrom<&ElementData> for i64 {
+    type Error = DataError;
+
+    fn try_from(data: &ElementData) -> Result<i64, DataError> {
+        data.try_into_int()
+    }
+}
+
+impl TryFrom<&ElementData> for f64 {
+    type Error = DataError;
+
+    fn try_from(data: &ElementData) -> Result<f64, DataError> {
+        data.try_into_float()
+    }
+}
+
+impl From<ElementData> for String {
+    fn from(data: ElementData) -> String {
+        data.into_string()
+    }
 }
 
-impl Into<String> for ElementData {
-    fn into(self) -> String {
-        self.into_string()
+impl From<ElementData> for u64 {
+    fn from(data: ElementData) -> u64 {
+        data.into_uint()
     }
 }
 
-impl Into<u64> for ElementData {
-    fn into(self) -> u64 {
-        self.into_uint()
+impl From<ElementData> for i64 {
+    fn from(data: ElementData) -> i64 {
+        data.into_int()
     }
 }
 
-impl Into<i64> for ElementData {
-    fn into(self) -> i64 {
-        self.into_int()
+impl From<ElementData> for f64 {
+    fn from(data: ElementData) -> f64 {
+        data.into_float()
     }
 }
 
-impl Into<f64> for ElementData {
-    fn into(self) -> f64 {
-        self.into_float()
+impl From<ElementData> for Vec<u8> {
+    fn from(data: ElementData) -> Vec<u8> {
+        data.into_vec()
     }
 }
 
-impl Into<Vec<u8>> for ElementData {
-    fn into(self) -> Vec<u8> {
-        self.into_vec()
+impl From<ElementData> for bool {
+    fn from(data: ElementData) -> bool {
+        data.into_int() == 1
     }
 }
 
-impl Into<bool> for ElementData {
-    fn into(self) -> bool {
-        self.into_int() == 1
+// Replace the children of `node` or its descendants that cover `range`
+// with what `reader` now finds there, see `WebmFile::reparse_range`
+fn splice_range<T: Read + Seek>(node: &mut Node, reader: &mut WebmReader<T>, range: &Range<u64>) -> Result<(), Error> {
+    let invalid = Error::InvalidOffset(range.start);
+    // the child starting last at or before the range, if the range is
+    // within its data
+    let inner = node.children.iter().rposition(|child| child.element.offset <= range.start);
+    if let Some(i) = inner {
+        let child = &node.children[i].element;
+        if child.kind == ElementKind::Master && child.data_offset() <= range.start && range.end <= child.end_offset() {
+            return splice_range(&mut node.children[i], reader, range);
+        }
+    }
+
+    let first = node.children.iter().position(|child| child.element.offset == range.start);
+    let last = node.children.iter().position(|child| child.element.end_offset() == range.end);
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) if first <= last => (first, last),
+        _ => return Err(invalid),
+    };
+    reader.seek_to(range.start)?;
+    let mut children = Vec::new();
+    while reader.position()? < range.end {
+        children.push(reader.build_node_tree()?);
     }
+    if reader.position()? != range.end {
+        return Err(invalid);
+    }
+    node.children.splice(first..=last, children);
+    Ok(())
+}
+
+// Length of an element's ID and size as written by `encode`
+fn header_size(id: u64, size: u64) -> u64 {
+    let id_len = 8 - id.leading_zeros() as u64 / 8;
+    id_len + vint::size_of(size).unwrap_or(8) as u64
 }
 
-fn read_vint(mut r: impl Read) -> u64 {
-    let mut buf = vec![0; 1];
-    r.read_exact(&mut buf).unwrap();
-    let count =
-        (count_leading_zeros(buf[0] as u8) + 1) as usize;
+// Whether an element's size suits its kind: numbers must fit their type and
+// only masters can have an unknown size. Ok(true) for a Float of another
+// length that `invalid_floats` keeps.
+fn check_size(
+    id: u64,
+    kind: &ElementKind,
+    size: u64,
+    offset: u64,
+    invalid_floats: InvalidFloats,
+) -> Result<bool, Error> {
+    let float_length = *kind == ElementKind::Float && size != 0 && size != 4 && size != 8;
+    let valid = match kind {
+        ElementKind::UInt | ElementKind::SInt => size <= 8,
+        ElementKind::Float if float_length => {
+            size != UNKNOWN_SIZE && invalid_floats == InvalidFloats::Keep
+        },
+        ElementKind::Date => size == 0 || size == 8,
+        ElementKind::Master => true,
+        _ => size != UNKNOWN_SIZE,
+    };
+    if !valid {
+        return Err(Error::InvalidElement { id, offset });
+    }
+    Ok(float_length)
+}
 
-    if count > 1 {
-        let mut tmp = vec![0; count - 1];
-        r.read_exact(&mut tmp).unwrap();
+// Apply `invalid_strings` to a String or UTF-8 element
+fn check_string(element: &Element, policy: InvalidStrings, warnings: &mut Vec<Warning>) -> Result<(), Error> {
+    let is_string = element.kind == ElementKind::String || element.kind == ElementKind::UTF8;
+    if !is_string || std::str::from_utf8(&element.data.0).is_ok() {
+        return Ok(());
+    }
+    let (id, offset) = (element.id, element.offset);
+    match policy {
+        InvalidStrings::Replace => warnings.push(Warning::InvalidUtf8 { id, offset }),
+        InvalidStrings::Reject => return Err(Error::InvalidElement { id, offset }),
+    }
+    Ok(())
+}
 
-        buf.append(&mut tmp);
+// Where the children of a master end: at its own end if it has a known
+// size, otherwise at `limit`, where those of its parent do. The readers and
+// `ElementParser` close masters by this and `ends_unknown_size`.
+pub(crate) fn children_end(master: &Element, limit: u64) -> u64 {
+    if master.is_unknown_size() {
+        limit
+    } else {
+        master.end_offset().min(limit)
     }
+}
 
-    let bitmask = 2u8.pow(8 - count as u32) - 1;
-    buf[0] &= bitmask;
+// Whether an element with ID `next` ends a parent master of unknown size,
+// i.e. it can't be one of the parent's children. Trailing garbage ends a
+// Segment.
+pub(crate) fn ends_unknown_size(parent: u64, next: u64) -> bool {
+    match next {
+        ID_EBMLHEADERNODE | ID_SEGMENTNODE => true,
+        _ if parent == ID_SEGMENTNODE => !is_segment_child(next),
+        ID_SEEKHEADNODE | ID_INFONODE | ID_TRACKSNODE |
+        ID_CUESNODE | ID_CHAPTERSNODE | ID_TAGSNODE |
+        ID_CLUSTERNODE | ID_ATTACHMENTSNODE => true,
+        _ => false,
+    }
+}
 
-    bytes_to_uint(&buf)
+pub(crate) fn is_segment_child(id: u64) -> bool {
+    matches!(
+        id,
+        ID_SEEKHEADNODE | ID_INFONODE | ID_TRACKSNODE |
+        ID_CUESNODE | ID_CHAPTERSNODE | ID_TAGSNODE |
+        ID_CLUSTERNODE | ID_ATTACHMENTSNODE | ID_SIGNATURESLOT |
+        ID_VOID | ID_CRC32
+    )
 }
 
-fn read_bytes(mut r: impl Read, num: usize) -> Vec<u8> {
-    let mut buf = vec![0; num];
-    r.read_exact(&mut buf).unwrap();
-    buf
+// Read through `take` so a corrupt size can't allocate more than the stream
+// actually holds
+fn read_bytes(r: impl Read, num: u64) -> Result<Vec<u8>, IOError> {
+    let mut buf = Vec::with_capacity(num.min(1 << 16) as usize);
+    r.take(num).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < num {
+        return Err(IOError::new(ErrorKind::UnexpectedEof, "element data truncated"));
+    }
+    Ok(buf)
 }
 
 fn bytes_to_uint(bytes: &[u8]) -> u64 {
@@ -967,46 +2651,46 @@ fn bytes_to_uint(bytes: &[u8]) -> u64 {
     result
 }
 
+// Big-endian two's complement of 1 to 8 bytes, sign extended from the top
+// bit of the first byte. Empty data is zero, and only the last 8 bytes of
+// anything longer count, as with unsigned integers.
 fn bytes_to_int(bytes: &[u8]) -> i64 {
-    let mut result: i64 = if bytes[0] & 128 == 128 {
-        0x7FFFFFFFFFFFFFFF
-    } else {
-        0
-    };
-    for b in bytes.iter() {
-        result = (result << 8) | (*b as i64);
+    let bytes = &bytes[bytes.len().saturating_sub(8)..];
+    if bytes.is_empty() {
+        return 0;
     }
-    result
+    let unused = 64 - 8 * bytes.len() as u32;
+    ((bytes_to_uint(bytes) << unused) as i64) >> unused
 }
 
+// Floats are 4 or 8 bytes, or empty for zero. Any other length has no
+// meaning, so it reads as NaN rather than as whatever the bits happen to be.
 fn bytes_to_float(bytes: &[u8]) -> f64 {
     let bits = bytes_to_uint(bytes);
-    if bytes.len() > 4 {
-        f64::from_bits(bits)
-    } else {
-        f32::from_bits(bits as u32) as f64
+    match bytes.len() {
+        0 => 0.0,
+        4 => f32::from_bits(bits as u32) as f64,
+        8 => f64::from_bits(bits),
+        _ => f64::NAN,
     }
 }
 
 fn bytes_to_string(bytes: &[u8]) -> String {
-    String::from_utf8(bytes.to_vec()).unwrap()
+    String::from_utf8_lossy(nul_terminated(bytes)).into_owned()
 }
 
-fn count_leading_zeros(mut byte: u8) -> u8 {
-    if byte == 0x0 {
-        8
-    } else {
-        let mut count = 0;
-        while byte & 128 != 128 {
-            byte = byte << 1;
-            count += 1;
-        }
-        count
-    }
+// String and UTF-8 values may be padded with zero bytes, and end at the
+// first one (RFC 8794 section 13), so what follows is ignored
+fn nul_terminated(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..len]
 }
 
+
 #[cfg(test)]
 mod tests {
+    use std::fs::{self, File};
+    use crate::testutil::WebmBuilder;
     use super::*;
 
     #[test]
@@ -1014,6 +2698,55 @@ mod tests {
         assert_eq!(bytes_to_int(&[0x7F]), 127);
         assert_eq!(bytes_to_int(&[0xFE]), -2);
         assert_eq!(bytes_to_int(&[0x00, 0x05]), 5);
+        assert_eq!(bytes_to_int(&[]), 0);
+        assert_eq!(bytes_to_int(&[0xff, 0x80, 0x00]), -32768);
+        assert_eq!(bytes_to_int(&[0x00, 0x80, 0x00]), 32768);
+        assert_eq!(bytes_to_int(&[0x00; 9]), 0);
+    }
+
+    #[test]
+    fn test_bytes_to_int_widths() {
+        // the limits of each width and values around zero, written with
+        // every width that can hold them
+        for width in 1..=8usize {
+            let bits = 8 * width as u32;
+            let (min, max) = (-1i64 << (bits - 1), ((1u64 << (bits - 1)) - 1) as i64);
+            for value in [min, min + 1, -256, -129, -128, -2, -1, 0, 1, 127, 128, 255, 256, max - 1, max] {
+                if value < min || value > max {
+                    continue;
+                }
+                let bytes = &value.to_be_bytes()[8 - width..];
+                assert_eq!(bytes_to_int(bytes), value, "{} in {} bytes", value, width);
+            }
+        }
+        // every value of the widths used by block timestamps
+        for value in i16::MIN..=i16::MAX {
+            assert_eq!(bytes_to_int(&value.to_be_bytes()), value as i64);
+            assert_eq!(bytes_to_int(&encode::int_bytes(value as i64)), value as i64);
+        }
+        for value in [i64::MIN, i64::MIN + 1, -1 << 40, 1 << 40, i64::MAX] {
+            assert_eq!(bytes_to_int(&encode::int_bytes(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_try_into() {
+        let data = |bytes: &[u8]| ElementData(bytes.to_vec());
+        assert_eq!(data(b"webm\0\0").try_into_string(), Ok("webm".to_string()));
+        assert_eq!(data(&[b'a', 0xff]).try_into_string(), Err(DataError::InvalidUtf8 { valid_up_to: 1 }));
+        assert_eq!(data(&[b'a', 0xff]).into_string(), "a\u{fffd}");
+
+        assert_eq!(data(&[1, 0]).try_into_uint(), Ok(256));
+        assert_eq!(data(&[1; 9]).try_into_uint(), Err(DataError::TooLong { len: 9 }));
+        assert_eq!(data(&[0xff; 2]).try_into_int(), Ok(-1));
+        assert_eq!(data(&[0; 9]).try_into_int(), Err(DataError::TooLong { len: 9 }));
+        assert_eq!(data(&[0x3f, 0xc0, 0, 0]).try_into_float(), Ok(1.5));
+        assert_eq!(data(&[0; 3]).try_into_float(), Err(DataError::InvalidFloatLength { len: 3 }));
+
+        assert_eq!(u64::try_from(&data(&[7])), Ok(7));
+        assert_eq!(String::try_from(&data(b"x")), Ok("x".to_string()));
+        assert!(f64::try_from(&data(&[0; 10])).is_err());
+        assert_eq!(i64::try_from(&data(&[0x80])), Ok(-128));
     }
 
     #[test]
@@ -1021,12 +2754,718 @@ mod tests {
         assert_eq!(bytes_to_uint(&[0xFF]), 255);
     }
 
+    // Wrap Segment children in a minimal header and a known sized Segment
+    fn document(children: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0x01]);
+        data.extend(&(children.len() as u64).to_be_bytes()[1..]);
+        data.extend(children);
+        data
+    }
+
     #[test]
-    fn test_count_leading_zeros() {
-        assert_eq!(count_leading_zeros(0x81), 0);
-        assert_eq!(count_leading_zeros(0xe), 4);
-        assert_eq!(count_leading_zeros(0x0), 8);
-        assert_eq!(count_leading_zeros(0x1), 7);
+    fn test_parse_all() {
+        let info = [0x15, 0x49, 0xa9, 0x66, 0x85, 0x2a, 0xd7, 0xb1, 0x81, 0x01];
+        let mut data = document(&info);
+        // a second Segment under the same header
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0x80]);
+        // then a separate document, Void padding and garbage
+        data.extend(document(&[]));
+        data.extend(&[0xec, 0x82, 0x00, 0x00]);
+        data.extend(b"junk");
+
+        let documents = WebmReader::new(Cursor::new(data)).parse_all().unwrap();
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0].root.get_info_nodes().len(), 1);
+        assert!(documents[1].root.get_children().is_empty());
+        assert_eq!(documents[2].header.get_doc_type(), "webm");
+    }
+
+    #[test]
+    fn test_unknown_size_segment_trailing_garbage() {
+        let mut data = document(&[]);
+        data.truncate(data.len() - 8);
+        data.extend(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        data.extend(&[0x15, 0x49, 0xa9, 0x66, 0x80]);
+        data.extend(b"junk");
+
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.root.get_children().len(), 1);
+    }
+
+    #[test]
+    fn test_structural_eq() {
+        use std::collections::HashSet;
+
+        let info = [0x15, 0x49, 0xa9, 0x66, 0x84, 0x4d, 0x80, 0x81, b'a'];
+        let mut padded = vec![0xec, 0x81, 0x00];
+        padded.extend(&info);
+        // same Info with a longer size vint
+        let wide = [0x15, 0x49, 0xa9, 0x66, 0x40, 0x04, 0x4d, 0x80, 0x81, b'a'];
+
+        let a = WebmFile::from_bytes(&document(&info)).unwrap();
+        let b = WebmFile::from_bytes(&document(&padded)).unwrap();
+        let c = WebmFile::from_bytes(&document(&wide)).unwrap();
+        let infos: HashSet<InfoNode> = [&a, &b, &c].iter()
+            .map(|d| d.root.get_info_nodes().remove(0))
+            .collect();
+        assert_eq!(infos.len(), 1);
+
+        assert_eq!(a.root, c.root);
+        assert_ne!(a.root, b.root);
+        assert_eq!(a.header, b.header);
+    }
+
+    #[test]
+    fn test_malformed_errors() {
+        let error = |children: &[u8]| WebmFile::from_bytes(&document(children)).unwrap_err();
+
+        assert!(matches!(error(&[0xe7, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1]), Error::InvalidElement { id: 0xe7, .. }));
+        assert!(matches!(error(&[0x44, 0x89, 0x83, 0, 0, 0]), Error::InvalidElement { id: 0x4489, .. }));
+        assert!(matches!(error(&[0xa3, 0x88, 0x81]), Error::UnexpectedEof { .. }));
+        assert!(matches!(error(&[0x15, 0x49, 0xa9, 0x66, 0x82, 0x00, 0x81]), Error::InvalidVint { .. }));
+        assert!(matches!(error(&[0xb6, 0xff].repeat(MAX_DEPTH + 2)), Error::TooDeep { .. }));
+        assert!(matches!(WebmFile::from_bytes(b"RIFF"), Err(Error::InvalidMagic)));
+    }
+
+    #[test]
+    fn test_scan_for_header() {
+        let data = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).frame(1, 0, true, &[1, 2]).build();
+        let mut junk = b"HTTP/1.1 200 OK\r\nContent-Type: video/webm\r\n\r\n".to_vec();
+        let skipped = junk.len() as u64;
+        junk.extend(&data);
+
+        assert!(matches!(WebmFile::from_bytes(&junk), Err(Error::InvalidMagic)));
+        let mut reader = WebmReader::new(Cursor::new(&junk)).scan_for_header(1024);
+        let document = reader.parse().unwrap();
+        assert_eq!(reader.skipped_prefix(), skipped);
+        assert_eq!(document.header.get_element().offset, skipped);
+        assert_eq!(document.packets().next().unwrap().data, vec![1, 2]);
+
+        // the magic number has to start within the distance
+        let mut reader = WebmReader::new(Cursor::new(&junk)).scan_for_header(skipped - 1);
+        assert!(matches!(reader.parse(), Err(Error::InvalidMagic)));
+        let mut reader = WebmReader::new(Cursor::new(&junk)).scan_for_header(skipped);
+        assert!(reader.parse().is_ok());
+
+        let mut reader = WebmReader::new(Cursor::new(&data)).scan_for_header(1024);
+        reader.parse().unwrap();
+        assert_eq!(reader.skipped_prefix(), 0);
+    }
+
+    #[test]
+    fn test_construct_elements() {
+        let header = Element::master(ID_EBMLHEADERNODE, vec![
+            Element::string(ID_DOCTYPE, "webm").into(),
+        ]);
+        let info = Element::master(ID_INFONODE, vec![
+            Element::uint(ID_TIMESTAMPSCALE, 1_000_000).into(),
+            Element::float(ID_DURATION, 0.5).into(),
+            Element::date(ID_DATEUTC, 7).into(),
+            Element::string(ID_TITLE, "t\u{e9}").into(),
+            Element::binary(ID_SEGMENTUUID, &[1; 16]).into(),
+            Element::int(ID_DISCARDPADDING, -3).into(),
+        ]);
+        let segment = Element::master(ID_SEGMENTNODE, vec![info.clone()]);
+        assert_eq!(segment.get_element().size, info.to_bytes().len() as u64);
+        assert_eq!(info.get_children()[0].get_element().header_size, 4);
+
+        let mut data = header.to_bytes();
+        data.extend(segment.to_bytes());
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.header.as_node(), &header);
+        assert_eq!(document.root.as_node(), &segment);
+
+        let parsed = &document.root.get_info_nodes()[0];
+        assert_eq!(parsed.get_duration(), Some(0.5));
+        assert_eq!(parsed.get_title(), Some("t\u{e9}".to_string()));
+        // the kind comes from the spec where the ID is known
+        assert_eq!(header.get_children()[0].get_element().kind, ElementKind::String);
+        assert_eq!(Element::int(0x5555, -3).kind, ElementKind::SInt);
+    }
+
+    #[test]
+    fn test_element_value() {
+        let document = WebmFile::from_bytes(&WebmBuilder::new().duration(1.5).build()).unwrap();
+        let elements: Vec<Element> = document.header.get_children()
+            .iter()
+            .chain(document.root.get_info_nodes()[0].get_children().iter())
+            .map(Node::get_element)
+            .collect();
+        let values: Vec<(u64, ElementValue)> = elements.iter().map(|e| (e.id, e.decode_value())).collect();
+        assert!(values.contains(&(ID_DOCTYPE, ElementValue::Str("webm".to_string()))));
+        assert!(values.contains(&(ID_TIMESTAMPSCALE, ElementValue::UInt(1_000_000))));
+        assert!(values.contains(&(ID_DURATION, ElementValue::Float(1.5))));
+
+        assert_eq!(Element::int(ID_DISCARDPADDING, -3).decode_value(), ElementValue::Int(-3));
+        assert_eq!(Element::date(ID_DATEUTC, -7).decode_value(), ElementValue::Date(-7));
+        assert_eq!(Element::binary(ID_SEGMENTUUID, &[1, 2]).decode_value(), ElementValue::Binary(&[1, 2]));
+        assert_eq!(Element::binary(0x5555, &[3]).decode_value(), ElementValue::Binary(&[3]));
+        assert_eq!(document.root.get_element().decode_value(), ElementValue::Master);
+    }
+
+    #[test]
+    fn test_date_string() {
+        let date = Element::date_from_iso8601(ID_DATEUTC, "2010-06-15T14:34:56+02:00").unwrap();
+        assert_eq!(date.data.into_int(), 298_298_096_000_000_000);
+        assert_eq!(date.date_string(), Some("2010-06-15T12:34:56Z".to_string()));
+        assert_eq!(Element::date(ID_DATEUTC, 0).data.as_iso8601(), "2001-01-01T00:00:00Z");
+        assert_eq!(Element::uint(ID_TIMESTAMP, 0).date_string(), None);
+        assert!(Element::date_from_iso8601(ID_DATEUTC, "yesterday").is_none());
+    }
+
+    #[test]
+    fn test_edit_tree() {
+        let mut info = Element::master(ID_INFONODE, vec![
+            Element::uint(ID_TIMESTAMPSCALE, 1_000_000).into(),
+            Element::string(ID_MUXINGAPP, "a").into(),
+        ]);
+        let mut segment = Element::master(ID_SEGMENTNODE, vec![info.clone()]);
+
+        info.insert_child(99, Element::string(ID_TITLE, "title").into());
+        assert_eq!(info.get_element().size, 7 + 4 + 8);
+        assert_eq!(info.remove_child(0).unwrap().get_element().id, ID_TIMESTAMPSCALE);
+        assert!(info.remove_child(5).is_none());
+        assert_eq!(info.get_element().size, 4 + 8);
+
+        // nested edits update every ancestor
+        assert!(segment.edit_child(0, |info| {
+            info.edit_child(1, |app| assert!(app.replace_data(vec![b'b'; 200])));
+        }));
+        assert!(!segment.edit_child(1, |_| ()));
+        assert!(!segment.replace_data(Vec::new()));
+        let info_size = 7 + 204;
+        assert_eq!(segment.get_children()[0].get_element().size, info_size);
+        assert_eq!(segment.get_children()[0].get_element().header_size, 6);
+        assert_eq!(segment.get_element().size, info_size + 6);
+
+        segment.set_children(Vec::new());
+        assert_eq!(segment.get_element().size, 0);
+        assert_eq!(segment.to_bytes(), vec![0x18, 0x53, 0x80, 0x67, 0x80]);
+    }
+
+    #[test]
+    fn test_open() {
+        let file = "./sample/big-buck-bunny_trailer.webm";
+        let from_file = WebmFile::open(File::open(file).unwrap()).unwrap();
+        let from_cursor = WebmFile::open(Cursor::new(fs::read(file).unwrap())).unwrap();
+        assert_eq!(from_cursor.root, from_file.root);
+        assert!(WebmFile::open(Cursor::new(vec![0u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_probe() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut reader = WebmReader::new(Cursor::new(&sample));
+        let probe = reader.probe().unwrap();
+        assert_eq!(probe.doc_type, "webm");
+        assert_eq!(probe.duration_ms, Some(32_480));
+        assert_eq!(probe.tracks.len(), 2);
+        assert_eq!(probe.tracks[0].codec.codec_id, "V_VP8");
+        assert_eq!((probe.tracks[0].codec.pixel_width, probe.tracks[0].codec.pixel_height), (Some(640), Some(360)));
+        assert_eq!(probe.tracks[1].codec.codec_id, "A_VORBIS");
+        let document = WebmFile::from_bytes(&sample).unwrap();
+        let cluster = document.root.get_clusters()[0].get_element().offset;
+        assert!(reader.position().unwrap() <= cluster);
+
+        // no Tracks before the media data
+        let data = WebmBuilder::new().duration(1500.0).cluster(0).build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element.id == ID_TRACKSNODE).unwrap();
+        root.remove_child(tracks);
+        let mut data = Cursor::new(Vec::new());
+        document.write_to(&mut data).unwrap();
+        let mut reader = WebmReader::new(Cursor::new(data.get_ref()));
+        assert!(matches!(reader.probe(), Err(Error::MissingElement { id: ID_TRACKSNODE, .. })));
+
+        let data = WebmBuilder::new().audio_track(1, "A_OPUS", 48000.0, 2).build();
+        let probe = WebmReader::new(Cursor::new(&data)).probe().unwrap();
+        assert_eq!((probe.duration_ms, probe.tracks[0].codec.channels), (None, Some(2)));
+    }
+
+    #[test]
+    fn test_write_to() {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut document = WebmFile::open(f).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        let rewritten = WebmFile::from_bytes(out.get_ref()).unwrap();
+        assert_eq!(rewritten.header, document.header);
+        assert_eq!(rewritten.root, document.root);
+        assert_eq!(rewritten.root.get_element().header_size, 12);
+
+        // parse, edit, save
+        let root = document.root.as_node_mut();
+        let info = root.get_children().iter().position(|n| n.element.id == ID_INFONODE).unwrap();
+        root.edit_child(info, |info| {
+            info.insert_child(0, Element::string(ID_TITLE, "Big Buck Bunny").into());
+        });
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        let edited = WebmFile::from_bytes(out.get_ref()).unwrap();
+        let title = edited.root.get_info_nodes()[0].get_title();
+        assert_eq!(title, Some("Big Buck Bunny".to_string()));
+        assert_eq!(edited.packets().count(), document.packets().count());
+    }
+
+    #[test]
+    fn test_check_compatibility() {
+        let header = |doc_type: &[u8], read_version: u8| {
+            let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87 + doc_type.len() as u8];
+            data.extend(&[0x42, 0x82, 0x80 | doc_type.len() as u8]);
+            data.extend(doc_type);
+            data.extend(&[0x42, 0x85, 0x81, read_version]);
+            data.extend(&[0x18, 0x53, 0x80, 0x67, 0x80]);
+            WebmFile::from_bytes(&data).unwrap()
+        };
+
+        assert!(header(b"webm", 2).check_compatibility().is_ok());
+        assert!(matches!(
+            header(b"webm", 5).check_compatibility(),
+            Err(Error::UnsupportedVersion { read_version: 5, .. })
+        ));
+
+        let matroska = header(b"matroska", 4);
+        assert!(matches!(matroska.check_compatibility(), Err(Error::UnsupportedDocType(_))));
+        assert!(matroska.check_compatibility_in(DocTypeMode::Matroska).is_ok());
+        // the minimal test header leaves the read versions at their defaults
+        assert!(WebmFile::from_bytes(&document(&[])).unwrap().check_compatibility().is_ok());
+    }
+
+    #[test]
+    fn test_max_lengths() {
+        // a 5 byte ID is longer than WebM allows
+        let error = WebmFile::from_bytes(&document(&[0x08, 0, 0, 0, 1, 0x80])).unwrap_err();
+        assert!(matches!(error, Error::InvalidVint { .. }));
+
+        // EBMLMaxSizeLength 4, then a Void with an 8 byte size
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x8b, 0x42, 0xf3, 0x81, 0x04, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0x10, 0x00, 0x00, 0x09]);
+        data.extend(&[0xec, 0x01, 0, 0, 0, 0, 0, 0, 0]);
+        let error = WebmFile::from_bytes(&data).unwrap_err();
+        assert!(matches!(error, Error::InvalidVint { .. }));
+
+        // the same Void is fine with a 1 byte size
+        let len = data.len();
+        data[len - 9..].copy_from_slice(&[0xec, 0x87, 0, 0, 0, 0, 0, 0, 0]);
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.header.get_max_size_length(), 4);
+        assert_eq!(document.header.get_max_id_length(), 4);
+        // the other header elements are missing too and read as their defaults
+        assert_eq!(document.header.get_version(), 1);
+        assert_eq!(document.header.get_doc_type_version(), 1);
+    }
+
+    #[test]
+    fn test_unknown_elements() {
+        // an unknown element inside Info, then one at the top level
+        let children = [
+            0x15, 0x49, 0xa9, 0x66, 0x88,
+            0x55, 0x55, 0x82, 0xa3, 0x80,
+            0x4d, 0x80, 0x81, b'a',
+            0x1a, 0x2b, 0x3c, 0x4d, 0x81, 0x00,
+        ];
+        let parse = |policy| {
+            WebmReader::new(Cursor::new(document(&children))).unknown_elements(policy).parse()
+        };
+
+        let kept = parse(UnknownElements::Keep).unwrap();
+        let info = kept.root.get_info_nodes()[0].get_children();
+        assert_eq!(info[0].get_element().kind, ElementKind::Unknown);
+        assert_eq!(info[0].get_element().data.into_vec(), vec![0xa3, 0x80]);
+        assert_eq!(kept.root.get_children().len(), 2);
+
+        let skipped = parse(UnknownElements::Skip).unwrap();
+        assert_eq!(skipped.root.get_children().len(), 1);
+        let info = &skipped.root.get_info_nodes()[0];
+        assert_eq!(info.get_children().len(), 1);
+        assert_eq!(info.get_muxing_app(), "a");
+
+        let rejected = parse(UnknownElements::Reject).unwrap_err();
+        assert!(matches!(rejected, Error::UnknownElement { id: 0x5555, .. }));
+    }
+
+    #[test]
+    fn test_skip_elements() {
+        let builder = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[0; 100])
+            .discard_padding(10)
+            .cluster(1000)
+            .frame(1, 0, true, &[0; 100]);
+        let skipped = [ID_SIMPLEBLOCK, ID_BLOCKGROUPNODE, ID_TRACKSNODE];
+        let document = WebmReader::new(Cursor::new(builder.build()))
+            .skip_elements(skipped)
+            .parse()
+            .unwrap();
+        assert!(document.root.get_tracks().is_empty());
+        let clusters = document.root.get_clusters();
+        let timestamps: Vec<u64> = clusters.iter().map(|c| c.get_timestamp()).collect();
+        assert_eq!(timestamps, vec![0, 1000]);
+        assert!(clusters.iter().all(|c| c.get_children().len() == 1));
+        assert_eq!(document.root.get_cues()[0].get_cue_points().len(), 2);
+
+        // masters of unknown size are still left out
+        let data = builder.unknown_size_clusters().build();
+        let document = WebmReader::new(Cursor::new(data))
+            .skip_elements([ID_CLUSTERNODE])
+            .parse()
+            .unwrap();
+        let ids: Vec<u64> = document.root.get_children().iter().map(|n| n.element.id).collect();
+        assert_eq!(ids, vec![ID_INFONODE, ID_TRACKSNODE, ID_CUESNODE]);
+    }
+
+    #[test]
+    fn test_keep_elements() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, &[0; 100])
+            .build();
+        let document = WebmReader::new(Cursor::new(data.clone()))
+            .keep_elements([ID_TIMESTAMPSCALE, ID_CODECID])
+            .parse()
+            .unwrap();
+        assert_eq!(document.header.get_doc_type(), "webm");
+        let ids: Vec<u64> = document.root.get_children().iter().map(|n| n.element.id).collect();
+        assert_eq!(ids, vec![ID_INFONODE, ID_TRACKSNODE]);
+        let info = &document.root.get_info_nodes()[0];
+        assert_eq!(info.get_children().len(), 1);
+        assert_eq!(info.get_timestamp_scale(), 1_000_000);
+        let entries = document.root.get_tracks()[0].get_track_entries();
+        let codecs: Vec<String> = entries.iter().map(|e| e.get_codec_id()).collect();
+        assert_eq!(codecs, vec!["V_VP9", "A_OPUS"]);
+        assert!(entries.iter().all(|e| e.get_children().len() == 1));
+
+        // everything inside a kept master stays
+        let full = WebmFile::from_bytes(&data).unwrap();
+        let document = WebmReader::new(Cursor::new(data))
+            .keep_elements([ID_TRACKSNODE])
+            .parse()
+            .unwrap();
+        assert_eq!(document.root.get_children().len(), 1);
+        assert_eq!(document.root.get_tracks(), full.root.get_tracks());
+    }
+
+    #[test]
+    fn test_profiling() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[0; 100])
+            .cluster(1000)
+            .frame(1, 0, true, &[0; 100])
+            .build();
+        let mut reader = WebmReader::new(Cursor::new(data.clone())).profiling();
+        let document = reader.parse().unwrap();
+        let profile = reader.profile().unwrap();
+
+        let ids: Vec<u64> = profile.elements.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![ID_EBMLHEADERNODE, ID_SEGMENTNODE, ID_INFONODE, ID_TRACKSNODE, ID_CLUSTERNODE, ID_CUESNODE]);
+        let clusters = profile.get(ID_CLUSTERNODE).unwrap();
+        assert_eq!(clusters.count, 2);
+        let cluster_bytes: u64 = document.root.get_clusters().iter().map(|c| c.get_element().end_offset() - c.get_element().offset).sum();
+        assert_eq!(clusters.bytes, cluster_bytes);
+        // every byte of the file is accounted for
+        assert_eq!(profile.bytes(), data.len() as u64);
+        assert!(profile.total >= clusters.time);
+        assert!(profile.to_string().lines().next().unwrap().starts_with("EBMLHeaderNode"));
+
+        let mut reader = WebmReader::new(Cursor::new(data));
+        reader.parse().unwrap();
+        assert!(reader.profile().is_none());
+    }
+
+    #[test]
+    fn test_max_binary_size() {
+        use crate::mux::{Attachment, Muxer};
+        let font: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        let mut muxer = Muxer::new(Vec::new()).matroska();
+        muxer.add_attachment(Attachment {
+            name: "font.ttf".to_string(),
+            media_type: "font/ttf".to_string(),
+            description: None,
+            data: font.clone(),
+            uid: 1,
+        }).unwrap();
+        let data = muxer.finish().unwrap();
+
+        let mut reader = WebmReader::new(Cursor::new(&data)).max_binary_size(64 * 1024);
+        let document = reader.parse().unwrap();
+        let file = &document.root.get_attachments()[0].get_attached_files()[0];
+        assert_eq!(file.get_name(), "font.ttf");
+        let payload = file.get_children().into_iter().find(|n| n.element.id == ID_FILEDATA).unwrap();
+        assert!(payload.element.is_dropped());
+        assert_eq!(payload.element.size, font.len() as u64);
+
+        let mut streamed = Vec::new();
+        reader.payload_reader(&payload.element).unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, font);
+        let mut streamed = Vec::new();
+        payload.element.payload_reader(Cursor::new(&data)).unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, font);
+
+        // smaller ones are read as usual
+        let document = WebmReader::new(Cursor::new(&data)).max_binary_size(font.len() as u64).parse().unwrap();
+        assert_eq!(document.root.get_attachments()[0].get_attached_files()[0].get_data(), font);
+
+        // frames over the limit are kept
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[1; 4096])
+            .frame(1, 40, false, &[2; 16])
+            .build();
+        let document = WebmReader::new(Cursor::new(&data)).max_binary_size(1024).parse().unwrap();
+        let sizes: Vec<usize> = document.packets().map(|p| p.data.len()).collect();
+        assert_eq!(sizes, vec![4096, 16]);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut builder = WebmBuilder::new().video_track(1, "V_VP9", 64, 64);
+        for second in 0..10 {
+            builder = builder.cluster(second * 1000).frame(1, 0, true, &[0; 1000]);
+        }
+        let data = builder.build();
+        let full = WebmFile::from_bytes(&data).unwrap();
+        let bounded = WebmReader::new(Cursor::new(data)).memory_budget(2500).parse().unwrap();
+
+        // all the metadata and structure is still there
+        let clusters = bounded.root.get_clusters();
+        assert_eq!(clusters.len(), 10);
+        assert_eq!(bounded.root.get_tracks(), full.root.get_tracks());
+        for (cluster, original) in clusters.iter().zip(full.root.get_clusters()) {
+            assert_eq!(cluster.get_timestamp(), original.get_timestamp());
+            let (block, original) = (&cluster.get_simple_blocks()[0], &original.get_simple_blocks()[0]);
+            assert_eq!(block.get_element().offset, original.get_element().offset);
+            assert_eq!(block.get_element().size, original.get_element().size);
+        }
+
+        // only the last two Clusters fit
+        let dropped: Vec<bool> = clusters.iter().map(|c| c.get_simple_blocks()[0].get_element().is_dropped()).collect();
+        assert_eq!(dropped.iter().filter(|d| **d).count(), 8);
+        assert!(!dropped[8] && !dropped[9]);
+        assert_eq!(bounded.packets().count(), 2);
+        assert!(!full.root.get_clusters()[0].get_simple_blocks()[0].get_element().is_dropped());
+    }
+
+    #[test]
+    fn test_invalid_strings() {
+        // Info with a WritingApp of "a\xff"
+        let children = [0x15, 0x49, 0xa9, 0x66, 0x85, 0x57, 0x41, 0x82, b'a', 0xff];
+        let mut reader = WebmReader::new(Cursor::new(document(&children)));
+        let document_ = reader.parse().unwrap();
+        assert_eq!(document_.root.get_info_nodes()[0].get_writing_app(), "a\u{fffd}");
+        assert_eq!(reader.warnings(), &[Warning::InvalidUtf8 { id: ID_WRITINGAPP, offset: 29 }]);
+
+        let strict = WebmReader::new(Cursor::new(document(&children)))
+            .invalid_strings(InvalidStrings::Reject)
+            .parse();
+        assert!(matches!(strict, Err(Error::InvalidElement { id: ID_WRITINGAPP, offset: 29 })));
+
+        let mut reader = WebmReader::new(Cursor::new(WebmBuilder::new().build()));
+        reader.parse().unwrap();
+        assert!(reader.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = [
+            0x12, 0x54, 0xc3, 0x67, 0x96,
+            0x73, 0x73, 0x93,
+            0x63, 0xc0, 0x83, 0x68, 0xca, 0x81, 0x32,
+            0x67, 0xc8, 0x89, 0x45, 0xa3, 0x82, b'h', b'i', 0x44, 0x87, 0x81, b'!',
+        ];
+        let document = WebmFile::from_bytes(&document(&tags)).unwrap();
+        let tag = &document.root.get_tags()[0].get_tags()[0];
+        assert_eq!(tag.get_targets().unwrap().get_type_value(), Some(50));
+
+        let simple_tag = SimpleTagNode::from(tag.get_children()[1].clone());
+        assert_eq!(simple_tag.get_name(), "hi");
+        assert_eq!(simple_tag.get_string(), Some("!".to_string()));
+        assert_eq!(tag.get_simple_tags(), vec![simple_tag]);
+    }
+
+    #[test]
+    fn test_chap_process() {
+        let command = |time: u64, data: &[u8]| Element::master(ID_CHAPPROCESSCOMMANDNODE, vec![
+            Element::uint(ID_CHAPPROCESSTIME, time).into(),
+            Element::binary(ID_CHAPPROCESSDATA, data).into(),
+        ]);
+        let chapters = Element::master(ID_CHAPTERSNODE, vec![
+            Element::master(ID_EDITIONENTRYNODE, vec![
+                Element::master(ID_CHAPTERATOMNODE, vec![
+                    Element::uint(ID_CHAPTERUID, 1).into(),
+                    Element::uint(ID_CHAPTERTIMESTART, 0).into(),
+                    Element::master(ID_CHAPPROCESSNODE, vec![
+                        Element::uint(ID_CHAPPROCESSCODECID, 1).into(),
+                        Element::binary(ID_CHAPPROCESSPRIVATE, &[0x30, 0x01]).into(),
+                        command(1, &[0x71, 0x00, 0x00, 0x0f]),
+                        command(2, &[0x30, 0x02, 0x00, 0x00]),
+                    ]),
+                ]),
+            ]),
+        ]);
+        let data = document(&chapters.to_bytes());
+        let document = WebmReader::new(Cursor::new(&data))
+            .unknown_elements(UnknownElements::Reject)
+            .parse()
+            .unwrap();
+
+        let atom = &document.root.get_chapters()[0].get_edition_entries()[0].get_chapter_atoms()[0];
+        let process = &atom.get_processes()[0];
+        assert_eq!(process.get_codec_id(), 1);
+        assert_eq!(process.get_private(), Some(vec![0x30, 0x01]));
+        let commands = process.get_commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].get_time(), 1);
+        assert_eq!(commands[1].get_data(), vec![0x30, 0x02, 0x00, 0x00]);
+
+        // kept as they are on rewrite
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        assert_eq!(out.into_inner(), data);
+    }
+
+    #[test]
+    fn test_nested_simple_tags() {
+        let simple_tag = |name: &str, value: &str, nested: Vec<Node>| {
+            let mut children = vec![
+                Element::string(ID_TAGNAME, name).into(),
+                Element::string(ID_TAGSTRING, value).into(),
+            ];
+            children.extend(nested);
+            Element::master(ID_SIMPLETAGNODE, children)
+        };
+        let tag = TagNode::from(Element::master(ID_TAGNODE, vec![
+            Element::master(ID_TARGETSNODE, vec![]),
+            simple_tag("TITLE", "Intro", vec![]),
+            simple_tag("ARTIST", "Someone", vec![
+                simple_tag("URL", "https://example.com", vec![]),
+                simple_tag("SORT_WITH", "One, Some", vec![]),
+            ]),
+        ]));
+
+        let names: Vec<String> = tag.get_simple_tags().iter().map(|t| t.get_name()).collect();
+        assert_eq!(names, vec!["TITLE", "ARTIST"]);
+        assert_eq!(tag.get_simple_tags()[1].get_simple_tags().len(), 2);
+        assert!(tag.get_simple_tags()[0].get_simple_tags().is_empty());
+
+        let url = tag.get_simple_tag(&["ARTIST", "URL"]).unwrap();
+        assert_eq!(url.string(), Some("https://example.com"));
+        assert_eq!(tag.get_simple_tag(&["TITLE"]).unwrap().string(), Some("Intro"));
+        assert!(tag.get_simple_tag(&["TITLE", "URL"]).is_none());
+        assert!(tag.get_simple_tag(&[]).is_none());
+    }
+
+    #[test]
+    fn test_slices() {
+        let time_slice = |lace: u64, delay: u64| Element::master(ID_TIMESLICENODE, vec![
+            Element::uint(ID_LACENUMBER, lace).into(),
+            Element::uint(ID_DELAY, delay).into(),
+            Element::uint(ID_SLICEDURATION, 10).into(),
+        ]);
+        let cluster = Element::master(ID_CLUSTERNODE, vec![
+            Element::uint(ID_TIMESTAMP, 0).into(),
+            Element::master(ID_BLOCKGROUPNODE, vec![
+                Element::binary(ID_BLOCK, &[0x81, 0, 0, 0x02, 1, 0x01, 0x02]).into(),
+                Element::master(ID_SLICESNODE, vec![time_slice(0, 0), time_slice(1, 10)]),
+            ]),
+        ]);
+        let document = WebmFile::from_bytes(&document(&cluster.to_bytes())).unwrap();
+        let group = &document.root.get_clusters()[0].get_block_groups()[0];
+        let slices = group.get_slices().unwrap().get_time_slices();
+        let laces: Vec<(u64, u64, u64)> = slices.iter()
+            .map(|s| (s.get_lace_number(), s.get_delay(), s.get_slice_duration()))
+            .collect();
+        assert_eq!(laces, vec![(0, 0, 10), (1, 10, 10)]);
+        assert_eq!(slices[1].get_frame_number(), 0);
+        assert_eq!(slices[1].get_block_addition_id(), 0);
+    }
+
+    #[test]
+    fn test_spec_defaults() {
+        let elements = [
+            0x15, 0x49, 0xa9, 0x66, 0x80,
+            0x16, 0x54, 0xae, 0x6b, 0x86,
+            0xae, 0x84,
+            0xe0, 0x80,
+            0xe1, 0x80,
+        ];
+        let document = WebmFile::from_bytes(&document(&elements)).unwrap();
+        assert_eq!(document.root.get_info_nodes()[0].get_timestamp_scale(), 1_000_000);
+        let entry = &document.root.get_tracks()[0].get_track_entries()[0];
+        assert!(entry.is_enabled() && entry.is_default() && entry.is_laced());
+        assert!(!entry.is_forced());
+        assert_eq!(entry.get_seek_preroll(), 0);
+        assert_eq!(entry.get_video_settings().unwrap().get_interlacing_flag(), 0);
+        let audio = entry.get_audio_settings().unwrap();
+        assert_eq!(audio.get_sampling_frequency(), 8000.0);
+        assert_eq!(audio.get_num_channels(), 1);
+    }
+
+    #[test]
+    fn test_track_is_encrypted() {
+        let tracks = [
+            0x16, 0x54, 0xae, 0x6b, 0x93,
+            0xae, 0x91,
+            0x6d, 0x80, 0x8e,
+            0x62, 0x40, 0x8b,
+            0x50, 0x33, 0x81, 0x01,
+            0x50, 0x35, 0x84, 0x47, 0xe1, 0x81, 0x05,
+        ];
+        let document = WebmFile::from_bytes(&document(&tracks)).unwrap();
+        let entry = &document.root.get_tracks()[0].get_track_entries()[0];
+        assert!(entry.is_encrypted());
+        let encoding = &entry.get_encoding_settings().unwrap().get_encodings()[0];
+        assert_eq!(encoding.get_scope(), 1);
+        assert_eq!(encoding.get_encryption_node().unwrap().get_algorithm_type(), 5);
+    }
+
+    #[test]
+    fn test_unknown_size_cluster() {
+        let mut data = Vec::new();
+        // minimal header
+        data.extend(&[0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84]);
+        data.extend(b"webm");
+        // unknown sized segment with two unknown sized clusters
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0xff]);
+        for timestamp in 0..2 {
+            data.extend(&[0x1f, 0x43, 0xb6, 0x75, 0xff]);
+            data.extend(&[0xe7, 0x81, timestamp]);
+            data.extend(&[0xa3, 0x85, 0x81, 0x00, 0x00, 0x80, 0xaa]);
+        }
+
+        let document = WebmReader::new(Cursor::new(data)).parse().unwrap();
+        let clusters = document.root.get_clusters();
+        assert!(document.root.get_element().is_unknown_size());
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[1].get_timestamp(), 1);
+        assert_eq!(clusters[1].get_simple_blocks().len(), 1);
+    }
+
+    #[test]
+    fn test_resume_from_position() {
+        let file = "./sample/big-buck-bunny_trailer.webm";
+        let mut reader = WebmReader::new(File::open(file).unwrap());
+        reader.parse_header().unwrap();
+
+        // read a couple of top level elements, then give up
+        reader.next_segment_child().unwrap();
+        reader.next_segment_child().unwrap();
+        let saved = reader.position();
+
+        let mut resumed = WebmReader::new(File::open(file).unwrap());
+        let rest = resumed.parse_from(saved.unwrap()).unwrap();
+        let full = WebmReader::new(File::open(file).unwrap()).parse().unwrap();
+
+        let rest_ids: Vec<u64> = rest.root.get_children().iter().map(|n| n.element.id).collect();
+        let full_ids: Vec<u64> = full.root.get_children().iter().map(|n| n.element.id).collect();
+        assert_eq!(rest_ids, full_ids[2..].to_vec());
+        assert_eq!(rest.root.get_clusters().len(), 1);
+        assert!(resumed.parse_from(0).is_err());
     }
 
     #[test]
@@ -1035,6 +3474,179 @@ mod tests {
         assert_eq!(bytes_to_string(&[0xe4, 0xbd, 0x95]), "何");
     }
 
+    #[test]
+    fn test_padded_strings() {
+        assert_eq!(nul_terminated(b"AB\0\0"), b"AB");
+        assert_eq!(nul_terminated(b"AB\0C"), b"AB");
+        assert_eq!(bytes_to_string(b"webm\0"), "webm");
+
+        let data = WebmBuilder::new().doc_type("webm\0\0\0").build();
+        let padded = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(padded.header.get_doc_type(), "webm");
+        assert!(padded.check_compatibility().is_ok());
+
+        // a SimpleTag with TagName "hi\0" and TagString "!\0\0"
+        let tags = [
+            0x12, 0x54, 0xc3, 0x67, 0x90,
+            0x73, 0x73, 0x8d,
+            0x67, 0xc8, 0x8a, 0x45, 0xa3, 0x83, b'h', b'i', 0, 0x44, 0x87, 0x83, b'!', 0, 0,
+        ];
+        let document = WebmFile::from_bytes(&document(&tags)).unwrap();
+        let tag = &document.root.get_tags()[0].get_tags()[0];
+        let simple_tag = SimpleTagNode::from(tag.get_children()[0].clone());
+        assert_eq!(simple_tag.get_name(), "hi");
+        assert_eq!(simple_tag.get_string(), Some("!".to_string()));
+        let data = &simple_tag.get_children()[1].get_element().data;
+        assert_eq!(String::try_from(data), Ok("!".to_string()));
+    }
+
+    #[test]
+    fn test_borrowed_accessors() {
+        let data = WebmBuilder::new()
+            .doc_type("webm\0")
+            .video_track(1, "V_VP9", 64, 64)
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.header.doc_type(), "webm");
+        let info = &document.root.get_info_nodes()[0];
+        assert_eq!(info.writing_app(), info.get_writing_app());
+        assert_eq!(info.title(), None);
+        let entry = &document.root.get_tracks()[0].get_track_entries()[0];
+        assert_eq!(entry.codec_id(), "V_VP9");
+        assert_eq!(entry.codec_private(), entry.get_codec_private().as_deref());
+        assert_eq!(entry.language(), None);
+
+        let private = Element::binary(ID_CODECPRIVATE, &[1, 0, 2]);
+        assert_eq!(private.data.as_bytes(), &[1, 0, 2]);
+        assert_eq!(Element::string(ID_CODECID, "ab\u{e9}").data.as_str(), "ab\u{e9}");
+        assert_eq!(ElementData(vec![b'a', 0xff, b'b']).as_str(), "a");
+    }
+
+    #[test]
+    fn test_iter() {
+        let data = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).frame(1, 0, true, &[0]).build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let elements: Vec<(ElementPath, &Element)> = document.iter().collect();
+        assert_eq!(elements[0].0.to_string(), "EBMLHeaderNode");
+        assert_eq!(elements[1].0.to_string(), "EBMLHeaderNode/EBMLVersion");
+        let segment = elements.iter().position(|(path, _)| path.ids() == [ID_SEGMENTNODE]).unwrap();
+        assert_eq!(elements[segment + 1].0.to_string(), "SegmentNode/InfoNode");
+        assert_eq!(elements[segment + 2].0.to_string(), "SegmentNode/InfoNode/TimestampScale");
+
+        // document order, so offsets only go up
+        assert!(elements.windows(2).all(|pair| pair[0].1.offset < pair[1].1.offset));
+        let codec = elements.iter().find(|(_, e)| e.id == ID_CODECID).unwrap();
+        assert_eq!(codec.0.to_string(), "SegmentNode/TracksNode/TrackEntryNode/CodecID");
+        assert_eq!(codec.0.depth(), 3);
+        assert_eq!(ElementPath(vec![ID_SEGMENTNODE, 0x5555]).to_string(), "SegmentNode/0x5555");
+
+        let info = &document.root.get_info_nodes()[0];
+        let paths: Vec<String> = info.as_node().iter().map(|(path, _)| path.to_string()).collect();
+        assert_eq!(paths.len(), info.get_children().len() + 1);
+        assert_eq!(paths[1], "InfoNode/TimestampScale");
+    }
+
+    #[test]
+    fn test_walk() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[0])
+            .cluster(1000)
+            .frame(1, 0, true, &[0])
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+
+        // skip Cluster subtrees
+        let mut visited = Vec::new();
+        document.walk(|path, element| {
+            visited.push(path.to_string());
+            match element.id {
+                ID_CLUSTERNODE => WalkControl::SkipChildren,
+                _ => WalkControl::Continue,
+            }
+        });
+        assert_eq!(visited.iter().filter(|p| p.as_str() == "SegmentNode/ClusterNode").count(), 2);
+        assert!(!visited.iter().any(|p| p.starts_with("SegmentNode/ClusterNode/")));
+        let all = document.iter().count();
+        assert_eq!(visited.len(), all - 4);
+
+        // the same from the stream, stopping at the first Tracks
+        for data in [data.clone(), WebmBuilder::new().video_track(1, "V_VP9", 64, 64).unknown_size_clusters().unknown_size_segment().build()] {
+            let document = WebmFile::from_bytes(&data).unwrap();
+            let mut expected = Vec::new();
+            document.walk(|path, element| {
+                expected.push((path.clone(), element.offset));
+                if element.id == ID_TRACKSNODE { WalkControl::Stop } else { WalkControl::Continue }
+            });
+            let mut streamed = Vec::new();
+            let mut reader = WebmReader::new(Cursor::new(data));
+            reader.walk(|path, element| {
+                streamed.push((path.clone(), element.offset));
+                if element.id == ID_TRACKSNODE { WalkControl::Stop } else { WalkControl::Continue }
+            }).unwrap();
+            assert_eq!(streamed, expected);
+            assert_eq!(streamed.last().unwrap().0.ids(), [ID_SEGMENTNODE, ID_TRACKSNODE]);
+        }
+
+        let mut streamed = Vec::new();
+        WebmReader::new(Cursor::new(data)).walk(|path, element| {
+            streamed.push(path.to_string());
+            if element.id == ID_CLUSTERNODE { WalkControl::SkipChildren } else { WalkControl::Continue }
+        }).unwrap();
+        assert_eq!(streamed, visited);
+
+        // Clusters of unknown size are stepped over too
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[0])
+            .cluster(1000)
+            .frame(1, 0, true, &[0])
+            .unknown_size_clusters()
+            .build();
+        let mut streamed = Vec::new();
+        WebmReader::new(Cursor::new(data)).walk(|path, element| {
+            streamed.push(path.to_string());
+            if element.id == ID_CLUSTERNODE { WalkControl::SkipChildren } else { WalkControl::Continue }
+        }).unwrap();
+        assert_eq!(streamed, visited);
+    }
+
+    #[test]
+    fn test_string_pool() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .video_track(2, "V_VP9", 64, 64)
+            .audio_track(3, "A_OPUS", 48000.0, 2)
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let mut pool = StringPool::new();
+        let codecs: Vec<Arc<str>> = document.root.get_tracks()[0]
+            .get_track_entries()
+            .iter()
+            .filter_map(|entry| pool.child(entry.as_node(), ID_CODECID))
+            .collect();
+        assert_eq!(codecs.iter().map(|c| &**c).collect::<Vec<&str>>(), vec!["V_VP9", "V_VP9", "A_OPUS"]);
+        assert!(Arc::ptr_eq(&codecs[0], &codecs[1]));
+        assert_eq!(pool.len(), 2);
+
+        // padding doesn't make a new value
+        let padded = pool.get(&Element::string(ID_CODECID, "A_OPUS\0\0").data);
+        assert!(Arc::ptr_eq(&padded, &codecs[2]));
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.child(document.root.as_node(), ID_CODECID), None);
+
+        // filled while parsing, and shared by the getters
+        let mut reader = WebmReader::new(Cursor::new(&data)).string_pool();
+        let document = reader.parse().unwrap();
+        let strings = reader.strings().unwrap();
+        let entries = document.root.get_tracks()[0].get_track_entries();
+        let codecs: Vec<Arc<str>> = entries.iter().map(|entry| entry.codec_id_in(strings)).collect();
+        assert_eq!(&*codecs[2], "A_OPUS");
+        assert!(Arc::ptr_eq(&codecs[0], &codecs[1]));
+        assert!(Arc::ptr_eq(&codecs[0], &strings.lookup(&Element::string(ID_CODECID, "V_VP9").data)));
+        assert_eq!(entries[0].language_in(strings), None);
+    }
+
     #[test]
     fn test_bytes_to_float() {
         assert_eq!(
@@ -1042,5 +3654,22 @@ mod tests {
             12.5
         );
         assert_eq!(bytes_to_float(&[0x47, 0xae, 0x88, 0x80]), 89361.0);
+        assert_eq!(bytes_to_float(&[]), 0.0);
+        assert!(bytes_to_float(&[0x47, 0xae, 0x88]).is_nan());
+        assert!(bytes_to_float(&[0x40, 0x29, 0, 0, 0, 0, 0, 0, 0, 0]).is_nan());
+    }
+
+    #[test]
+    fn test_invalid_floats() {
+        // Info with a 3 byte Duration
+        let children = [0x15, 0x49, 0xa9, 0x66, 0x86, 0x44, 0x89, 0x83, 0x47, 0xae, 0x88];
+        let strict = WebmReader::new(Cursor::new(document(&children))).parse();
+        assert!(matches!(strict, Err(Error::InvalidElement { id: ID_DURATION, offset: 29 })));
+
+        let mut reader = WebmReader::new(Cursor::new(document(&children)))
+            .invalid_floats(InvalidFloats::Keep);
+        let lenient = reader.parse().unwrap();
+        assert!(lenient.root.get_info_nodes()[0].get_duration().unwrap().is_nan());
+        assert_eq!(reader.warnings(), &[Warning::InvalidFloatLength { id: ID_DURATION, offset: 29, len: 3 }]);
     }
 }
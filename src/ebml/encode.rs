@@ -0,0 +1,133 @@
+use crate::consts::UNKNOWN_SIZE;
+use crate::vint;
+
+// Encoders for the EBML element types, each using the shortest valid
+// encoding. Every function appends one complete element (ID, size and
+// payload) to `out`, except for the ID and size helpers.
+
+// Append an element ID, which is stored with its length marker
+pub fn write_element_id(out: &mut Vec<u8>, id: u64) {
+    vint::write_id(out, id);
+}
+
+// Append an element size using the shortest vint that fits. UNKNOWN_SIZE,
+// and anything too big to encode, is written as the 8 byte unknown size.
+pub fn write_size(out: &mut Vec<u8>, size: u64) {
+    if size == UNKNOWN_SIZE || vint::write(out, size).is_none() {
+        vint::write_unknown_size(out);
+    }
+}
+
+// Append an element with a raw payload, e.g. a Binary element or a master
+// whose children are already encoded
+pub fn write_element(out: &mut Vec<u8>, id: u64, data: &[u8]) {
+    write_element_id(out, id);
+    write_size(out, data.len() as u64);
+    out.extend(data);
+}
+
+// Unsigned integers use as few bytes as they need, with at least one
+pub fn write_uint(out: &mut Vec<u8>, id: u64, value: u64) {
+    write_element(out, id, &uint_bytes(value));
+}
+
+// Signed integers drop leading bytes that only repeat the sign
+pub fn write_int(out: &mut Vec<u8>, id: u64, value: i64) {
+    write_element(out, id, &int_bytes(value));
+}
+
+// Floats are written as 4 bytes when that loses nothing, 8 otherwise
+pub fn write_float(out: &mut Vec<u8>, id: u64, value: f64) {
+    write_element(out, id, &float_bytes(value));
+}
+
+// String and UTF-8 elements, without a terminating NUL
+pub fn write_string(out: &mut Vec<u8>, id: u64, value: &str) {
+    write_element(out, id, value.as_bytes());
+}
+
+// Dates are nanoseconds since 2001-01-01T00:00:00 UTC, always 8 bytes
+pub fn write_date(out: &mut Vec<u8>, id: u64, nanoseconds: i64) {
+    write_element(out, id, &nanoseconds.to_be_bytes());
+}
+
+pub(crate) fn uint_bytes(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    bytes[skip..].to_vec()
+}
+
+pub(crate) fn int_bytes(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let sign = if value < 0 { 0xff } else { 0x00 };
+    let skip = (0..7)
+        .take_while(|&i| bytes[i] == sign && (bytes[i + 1] & 0x80) == (sign & 0x80))
+        .count();
+    bytes[skip..].to_vec()
+}
+
+pub(crate) fn float_bytes(value: f64) -> Vec<u8> {
+    let single = value as f32;
+    if single as f64 == value || value.is_nan() {
+        single.to_be_bytes().to_vec()
+    } else {
+        value.to_be_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::*;
+    use crate::ebml::WebmFile;
+    use super::*;
+
+    fn encoded(write: impl Fn(&mut Vec<u8>)) -> Vec<u8> {
+        let mut out = Vec::new();
+        write(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_minimal_lengths() {
+        assert_eq!(encoded(|o| write_uint(o, 0xe7, 0)), vec![0xe7, 0x81, 0x00]);
+        assert_eq!(encoded(|o| write_uint(o, 0xe7, 0x1234)), vec![0xe7, 0x82, 0x12, 0x34]);
+        assert_eq!(encoded(|o| write_int(o, 0xfb, -1)), vec![0xfb, 0x81, 0xff]);
+        assert_eq!(encoded(|o| write_int(o, 0xfb, 127)), vec![0xfb, 0x81, 0x7f]);
+        assert_eq!(encoded(|o| write_int(o, 0xfb, 128)), vec![0xfb, 0x82, 0x00, 0x80]);
+        assert_eq!(encoded(|o| write_int(o, 0xfb, -129)), vec![0xfb, 0x82, 0xff, 0x7f]);
+        assert_eq!(encoded(|o| write_float(o, 0xb5, 48000.0)).len(), 6);
+        assert_eq!(encoded(|o| write_float(o, 0xb5, 0.1)).len(), 10);
+        assert_eq!(encoded(|o| write_date(o, 0x4461, 0)).len(), 11);
+
+        // 127 is the reserved one byte size, so it takes two
+        assert_eq!(encoded(|o| write_size(o, 126)), vec![0xfe]);
+        assert_eq!(encoded(|o| write_size(o, 127)), vec![0x40, 0x7f]);
+        assert_eq!(encoded(|o| write_size(o, UNKNOWN_SIZE)), vec![0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut info = Vec::new();
+        write_uint(&mut info, ID_TIMESTAMPSCALE, 1_000_000);
+        write_float(&mut info, ID_DURATION, 1234.5);
+        write_date(&mut info, ID_DATEUTC, -42);
+        write_string(&mut info, ID_MUXINGAPP, "mux");
+        write_string(&mut info, ID_WRITINGAPP, "wr\u{ef}te");
+
+        let mut header = Vec::new();
+        write_string(&mut header, ID_DOCTYPE, "webm");
+        let mut data = Vec::new();
+        write_element(&mut data, ID_EBMLHEADERNODE, &header);
+        let mut segment = Vec::new();
+        write_element(&mut segment, ID_INFONODE, &info);
+        write_element(&mut data, ID_SEGMENTNODE, &segment);
+
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let info = &document.root.get_info_nodes()[0];
+        assert_eq!(info.get_timestamp_scale(), 1_000_000);
+        assert_eq!(info.get_duration(), Some(1234.5));
+        assert_eq!(info.get_date_created(), Some(-42));
+        assert_eq!(info.get_muxing_app(), "mux");
+        assert_eq!(info.get_writing_app(), "wr\u{ef}te");
+    }
+}
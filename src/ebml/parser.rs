@@ -0,0 +1,493 @@
+use std::io::{Read, Error as IOError};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use crate::consts::*;
+use crate::error::{Error, Warning};
+use crate::vint;
+use super::*;
+
+// The element parser as a state machine that does no IO of its own: feed it
+// bytes as they turn up and pull events until it asks for more. Blocking
+// readers, async runtimes and the browser all drive the same core through
+// the front ends below, `parse_stream`, `parse_async`, `Ingest`,
+// `PushParser` and `TailFollower`. `WebmReader` reads seekable files with
+// its own loop so it can step over payloads it doesn't need, but checks
+// elements and closes masters with the same functions as this.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent {
+    // a master element's header, its children follow until the matching End
+    Start(Element),
+    // a complete non-master element
+    Element(Element),
+    // the end of the master element with this ID
+    End(u64),
+    // nothing more can be parsed until more data is fed or `finish` is called
+    NeedData,
+    // `finish` was called and every element has been closed
+    Done,
+}
+
+pub struct ElementParser {
+    buffer: Vec<u8>,
+    // stream offsets of buffer[0] and of the next unread byte
+    start: u64,
+    position: u64,
+    // masters that are still open, outermost first
+    open: Vec<Element>,
+    // EBMLMaxIDLength and EBMLMaxSizeLength of the current document
+    max_id_length: usize,
+    max_size_length: usize,
+    invalid_strings: InvalidStrings,
+    invalid_floats: InvalidFloats,
+    warnings: Vec<Warning>,
+    // no more data is coming
+    finished: bool,
+}
+
+impl ElementParser {
+    pub fn new() -> ElementParser {
+        ElementParser {
+            buffer: Vec::new(),
+            start: 0,
+            position: 0,
+            open: Vec::new(),
+            max_id_length: MAX_ID_LENGTH,
+            max_size_length: MAX_SIZE_LENGTH,
+            invalid_strings: InvalidStrings::default(),
+            invalid_floats: InvalidFloats::default(),
+            warnings: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn invalid_strings(mut self, policy: InvalidStrings) -> Self {
+        self.invalid_strings = policy;
+        self
+    }
+
+    pub fn invalid_floats(mut self, policy: InvalidFloats) -> Self {
+        self.invalid_floats = policy;
+        self
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    // Add the next piece of the stream. Bytes already parsed are dropped, so
+    // the buffer holds at most one element beyond what was fed last.
+    pub fn feed(&mut self, data: &[u8]) {
+        let consumed = (self.position - self.start) as usize;
+        self.buffer.drain(..consumed);
+        self.start = self.position;
+        self.buffer.extend_from_slice(data);
+    }
+
+    // Mark the end of the stream. Masters still open are closed if it ends
+    // between elements, and an element cut short is an error.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    // Stream offset of the next unread byte, always an element boundary
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Bytes fed but not parsed yet
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - (self.position - self.start) as usize
+    }
+
+    // Number of masters open after the last event
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn next_event(&mut self) -> Result<ParseEvent, Error> {
+        if let Some(id) = self.closing() {
+            self.open.pop();
+            return Ok(ParseEvent::End(id));
+        }
+
+        let offset = self.position;
+        let rest = &self.buffer[(self.position - self.start) as usize..];
+        // the stream has to start with the magic number, once there's enough to tell
+        let magic_known = rest.len() >= 4 || self.finished;
+        if offset == 0 && magic_known && !rest.starts_with(&MAGIC_NUMBER) {
+            return Err(Error::InvalidMagic);
+        }
+        let (id, size, header_size) = match self.read_header(rest, offset)? {
+            Some(header) => header,
+            None if !self.finished => return Ok(ParseEvent::NeedData),
+            // the end of the stream closes the masters around it, and a
+            // truncated Segment ends where the data does
+            None => return match self.open.pop() {
+                Some(master) if rest.is_empty() || master.id == ID_SEGMENTNODE => {
+                    Ok(ParseEvent::End(master.id))
+                },
+                Some(_) => Err(Error::UnexpectedEof { offset }),
+                None => Ok(ParseEvent::Done),
+            },
+        };
+        if self.open.len() > MAX_DEPTH {
+            return Err(Error::TooDeep { offset });
+        }
+        let kind = element_kind(id);
+        if check_size(id, &kind, size, offset, self.invalid_floats)? {
+            self.warnings.push(Warning::InvalidFloatLength { id, offset, len: size });
+        }
+
+        let data_start = header_size as u64;
+        let mut element = Element {
+            id,
+            size,
+            kind,
+            data: ElementData(Vec::new()),
+            offset,
+            header_size: data_start,
+        };
+        if element.kind == ElementKind::Master {
+            if id == ID_EBMLHEADERNODE {
+                // the header itself is read with the WebM limits
+                self.max_id_length = MAX_ID_LENGTH;
+                self.max_size_length = MAX_SIZE_LENGTH;
+            }
+            self.position += data_start;
+            self.open.push(element.clone());
+            return Ok(ParseEvent::Start(element));
+        }
+
+        let data = data_start.checked_add(size)
+            .and_then(|end| rest.get(data_start as usize..usize::try_from(end).ok()?));
+        element.data = match data {
+            Some(data) => ElementData(data.to_vec()),
+            None if !self.finished => return Ok(ParseEvent::NeedData),
+            None => return Err(Error::UnexpectedEof { offset }),
+        };
+        check_string(&element, self.invalid_strings, &mut self.warnings)?;
+        if self.open.last().is_some_and(|parent| parent.id == ID_EBMLHEADERNODE) {
+            match id {
+                ID_EBMLMAXIDLENGTH => {
+                    self.max_id_length = (element.data.into_uint() as usize).clamp(1, MAX_ID_LENGTH);
+                },
+                ID_EBMLMAXSIZELENGTH => {
+                    self.max_size_length = (element.data.into_uint() as usize).clamp(1, MAX_SIZE_LENGTH);
+                },
+                _ => (),
+            }
+        }
+        self.position = element.end_offset();
+        Ok(ParseEvent::Element(element))
+    }
+
+    // The ID of the innermost open master if it ends at the current
+    // position, by the same rules as `WebmReader`
+    fn closing(&self) -> Option<u64> {
+        let parent = self.open.last()?;
+        let limit = self.open.iter().fold(u64::MAX, |limit, master| children_end(master, limit));
+        let rest = &self.buffer[(self.position - self.start) as usize..];
+        let ended = match vint::read_id(rest) {
+            _ if self.position >= limit => true,
+            Some(id) => parent.is_unknown_size() && ends_unknown_size(parent.id, id),
+            None => false,
+        };
+        Some(parent.id).filter(|_| ended)
+    }
+
+    // The ID, size and header length of the element starting `data`, None
+    // if it's cut short
+    fn read_header(&self, data: &[u8], offset: u64) -> Result<Option<(u64, u64, usize)>, Error> {
+        let (id, id_len) = match read_vint(data, offset)? {
+            Some(vint) => vint,
+            None => return Ok(None),
+        };
+        if id_len > self.max_id_length {
+            return Err(Error::InvalidVint { offset });
+        }
+        let size_offset = offset + id_len as u64;
+        let (raw, len) = match read_vint(&data[id_len..], size_offset)? {
+            Some(vint) => vint,
+            None => return Ok(None),
+        };
+        if len > self.max_size_length {
+            return Err(Error::InvalidVint { offset: size_offset });
+        }
+        Ok(Some((id, vint::size(raw, len), id_len + len)))
+    }
+}
+
+impl Default for ElementParser {
+    fn default() -> ElementParser {
+        ElementParser::new()
+    }
+}
+
+// A raw vint, telling data that ends early apart from a first byte with no
+// length marker
+fn read_vint(data: &[u8], offset: u64) -> Result<Option<(u64, usize)>, Error> {
+    match data.first() {
+        Some(0) => Err(Error::InvalidVint { offset }),
+        _ => Ok(vint::read_raw(data)),
+    }
+}
+
+// Assembles events back into nodes. Nodes are returned as they complete
+// outside of any master the builder has seen start, so leaving a master's
+// Start and End out, as `Ingest` does with Clusters, hands over its
+// children one by one instead of holding on to them.
+#[derive(Default)]
+pub struct TreeBuilder {
+    open: Vec<Node>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder::default()
+    }
+
+    pub fn push(&mut self, event: ParseEvent) -> Option<Node> {
+        let node = match event {
+            ParseEvent::Start(element) => {
+                self.open.push(Node { element, children: Vec::new() });
+                return None;
+            },
+            ParseEvent::Element(element) => Node { element, children: Vec::new() },
+            ParseEvent::End(_) => self.open.pop()?,
+            ParseEvent::NeedData | ParseEvent::Done => return None,
+        };
+        match self.open.last_mut() {
+            Some(parent) => {
+                parent.children.push(node);
+                None
+            },
+            None => Some(node),
+        }
+    }
+}
+
+// Builds the first document in a stream from the parser's events
+#[derive(Default)]
+struct DocumentBuilder {
+    tree: TreeBuilder,
+    header: Option<EBMLHeaderNode>,
+}
+
+impl DocumentBuilder {
+    fn push(&mut self, event: ParseEvent) -> Result<Option<WebmFile>, Error> {
+        if event == ParseEvent::Done {
+            return Err(Error::MissingSegment);
+        }
+        let node = match self.tree.push(event) {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+        match (self.header.take(), node.element.id) {
+            (None, ID_EBMLHEADERNODE) => {
+                self.header = Some(EBMLHeaderNode(node));
+                Ok(None)
+            },
+            (Some(header), ID_SEGMENTNODE) => Ok(Some(WebmFile {
+                header,
+                root: SegmentNode(node),
+            })),
+            _ => Err(Error::MissingSegment),
+        }
+    }
+}
+
+// Parse a document from a stream that can't seek, like a pipe or a socket,
+// reading it once from start to end
+pub fn parse_stream<R: Read>(mut r: R) -> Result<WebmFile, Error> {
+    let mut parser = ElementParser::new();
+    let mut document = DocumentBuilder::default();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match parser.next_event()? {
+            ParseEvent::NeedData => match r.read(&mut buf)? {
+                0 => parser.finish(),
+                len => parser.feed(&buf[..len]),
+            },
+            event => {
+                if let Some(document) = document.push(event)? {
+                    return Ok(document);
+                }
+            },
+        }
+    }
+}
+
+// The reading half of an async byte stream, shaped like `AsyncRead` from
+// futures and tokio so either can be adapted in a few lines without this
+// crate depending on a runtime
+pub trait AsyncSource {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, IOError>>;
+}
+
+// `parse_stream` for async sources
+pub async fn parse_async<R: AsyncSource + Unpin>(mut r: R) -> Result<WebmFile, Error> {
+    let mut parser = ElementParser::new();
+    let mut document = DocumentBuilder::default();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match parser.next_event()? {
+            ParseEvent::NeedData => {
+                let len = std::future::poll_fn(|cx| Pin::new(&mut r).poll_read(cx, &mut buf)).await?;
+                match len {
+                    0 => parser.finish(),
+                    len => parser.feed(&buf[..len]),
+                }
+            },
+            event => {
+                if let Some(document) = document.push(event)? {
+                    return Ok(document);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::future::Future;
+    use std::task::Waker;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    // hands out its data a few bytes per read, sometimes after a Pending
+    struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        pending: bool,
+    }
+
+    impl AsyncSource for Trickle {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, IOError>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let len = buf.len().min(self.data.len() - self.position).min(1000);
+            buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+            self.position += len;
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    fn events(data: &[u8], chunk: usize) -> Vec<ParseEvent> {
+        let mut parser = ElementParser::new();
+        let mut events = Vec::new();
+        let mut chunks = data.chunks(chunk);
+        loop {
+            match parser.next_event().unwrap() {
+                ParseEvent::NeedData => match chunks.next() {
+                    Some(chunk) => parser.feed(chunk),
+                    None => parser.finish(),
+                },
+                ParseEvent::Done => return events,
+                event => events.push(event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .frame(1, 0, true, &[1, 2])
+            .build();
+        let all = events(&data, 1);
+        assert_eq!(events(&data, 4096), all);
+        assert!(matches!(&all[0], ParseEvent::Start(e) if e.id == ID_EBMLHEADERNODE));
+
+        // every Start has its End, innermost first
+        let mut open = Vec::new();
+        for event in &all {
+            match event {
+                ParseEvent::Start(element) => open.push(element.id),
+                ParseEvent::End(id) => assert_eq!(open.pop(), Some(*id)),
+                _ => (),
+            }
+        }
+        assert!(open.is_empty());
+        assert_eq!(all.last(), Some(&ParseEvent::End(ID_SEGMENTNODE)));
+    }
+
+    #[test]
+    fn test_front_ends() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let built = WebmBuilder::new()
+            .audio_track(1, "A_OPUS", 48000.0, 2)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .frame(1, 0, true, &[1])
+            .cluster(1000)
+            .frame(1, 0, true, &[2])
+            .build();
+        for data in [sample, built] {
+            let expected = WebmFile::from_bytes(&data).unwrap();
+            let document = parse_stream(&data[..]).unwrap();
+            assert_eq!(document.root, expected.root);
+            assert_eq!(document.header, expected.header);
+
+            let trickle = Trickle { data: data.clone(), position: 0, pending: false };
+            let document = block_on(parse_async(trickle)).unwrap();
+            assert_eq!(document.root, expected.root);
+        }
+    }
+
+    #[test]
+    fn test_closing() {
+        // a Void after the Segment isn't part of its last, unknown sized Cluster
+        let mut data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .unknown_size_clusters()
+            .without_cues()
+            .frame(1, 0, true, &[1])
+            .build();
+        data.extend(&[0xec, 0x81, 0x00]);
+        let read = WebmFile::from_bytes(&data).unwrap();
+        let streamed = parse_stream(&data[..]).unwrap();
+        assert_eq!(streamed.root, read.root);
+        let cluster = &read.root.get_clusters()[0];
+        assert!(cluster.as_node().children().iter().all(|child| child.element().id != ID_VOID));
+    }
+
+    #[test]
+    fn test_errors() {
+        let data = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).frame(1, 0, true, &[1]).build();
+        assert!(matches!(parse_stream(&b"RIFF....WAVE"[..]), Err(Error::InvalidMagic)));
+        assert!(matches!(parse_stream(&data[..data.len() - 1]), Err(Error::UnexpectedEof { .. })));
+        // cut between two Segment children
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let tracks = document.root.get_tracks()[0].get_element().end_offset() as usize;
+        let truncated = parse_stream(&data[..tracks + 2]).unwrap();
+        assert_eq!(truncated.root.get_tracks(), document.root.get_tracks());
+        assert!(truncated.root.get_clusters().is_empty());
+
+        // a header on its own
+        let header = WebmFile::from_bytes(&data).unwrap().header.as_node().to_bytes();
+        assert!(matches!(parse_stream(&header[..]), Err(Error::MissingSegment)));
+
+        let mut parser = ElementParser::new();
+        parser.feed(&data[..3]);
+        assert_eq!(parser.next_event().unwrap(), ParseEvent::NeedData);
+        parser.feed(&data[3..]);
+        assert!(matches!(parser.next_event().unwrap(), ParseEvent::Start(_)));
+        assert_eq!(parser.depth(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+#[cfg(feature = "crypto")]
+use aes::Aes128;
+#[cfg(feature = "crypto")]
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use crate::block::Packet;
+use crate::ebml::{WebmFile, ContentEncryptionNode};
+
+// A run of bytes in an encrypted frame, `clear` bytes followed by
+// `encrypted` bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Subsample {
+    pub clear: u32,
+    pub encrypted: u32,
+}
+
+// A frame from a track using WebM encryption, split into its signal byte
+// fields and the (still encrypted) payload
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncryptedFrame {
+    pub encrypted: bool,
+    // the 8 byte IV, the low 8 bytes of the AES-CTR counter block are zero
+    pub iv: Option<[u8; 8]>,
+    pub subsamples: Vec<Subsample>,
+    pub payload: Vec<u8>,
+}
+
+const SIGNAL_ENCRYPTED: u8 = 0x01;
+const SIGNAL_PARTITIONED: u8 = 0x02;
+
+impl EncryptedFrame {
+    pub fn parse(data: &[u8]) -> Option<EncryptedFrame> {
+        let signal = *data.first()?;
+        if signal & SIGNAL_ENCRYPTED == 0 {
+            return Some(EncryptedFrame {
+                encrypted: false,
+                iv: None,
+                subsamples: Vec::new(),
+                payload: data[1..].to_vec(),
+            });
+        }
+
+        let mut iv = [0u8; 8];
+        iv.copy_from_slice(data.get(1..9)?);
+        let mut pos = 9;
+
+        let mut partitions = Vec::new();
+        if signal & SIGNAL_PARTITIONED == SIGNAL_PARTITIONED {
+            let count = *data.get(pos)? as usize;
+            pos += 1;
+            for _ in 0..count {
+                let offset = data.get(pos..pos + 4)?;
+                partitions.push(u32::from_be_bytes([offset[0], offset[1], offset[2], offset[3]]));
+                pos += 4;
+            }
+        }
+
+        let payload = data[pos..].to_vec();
+        let subsamples = subsamples_from_partitions(&partitions, payload.len() as u32)?;
+        Some(EncryptedFrame {
+            encrypted: true,
+            iv: Some(iv),
+            subsamples,
+            payload,
+        })
+    }
+}
+
+impl Packet {
+    // Split the packet's data per the WebM encryption spec. Only meaningful
+    // for tracks where `TrackEntryNode::is_encrypted()` is true.
+    pub fn encrypted_frame(&self) -> Option<EncryptedFrame> {
+        EncryptedFrame::parse(&self.data)
+    }
+}
+
+#[cfg(feature = "crypto")]
+type Aes128Ctr = ctr::Ctr64BE<Aes128>;
+
+#[cfg(feature = "crypto")]
+impl EncryptedFrame {
+    // Decrypt with AES-128-CTR. The counter block is the IV followed by a
+    // 64 bit block counter starting at zero, and the encrypted ranges of a
+    // partitioned frame form one continuous keystream.
+    pub fn decrypt(&self, key: &[u8; 16]) -> Vec<u8> {
+        let mut data = self.payload.clone();
+        let iv = match self.iv {
+            Some(iv) if self.encrypted => iv,
+            _ => return data,
+        };
+
+        let mut counter = [0u8; 16];
+        counter[..8].copy_from_slice(&iv);
+        let mut cipher = Aes128Ctr::new(key.into(), &counter.into());
+
+        let mut pos = 0;
+        for subsample in self.subsamples.iter() {
+            pos += subsample.clear as usize;
+            let end = pos + subsample.encrypted as usize;
+            cipher.apply_keystream(&mut data[pos..end]);
+            pos = end;
+        }
+        data
+    }
+}
+
+// Decrypt a packet from an encrypted track with a caller-provided key,
+// None if the packet doesn't have a valid signal byte
+#[cfg(feature = "crypto")]
+pub fn decrypt_packet(packet: &Packet, key: &[u8; 16]) -> Option<Vec<u8>> {
+    packet.encrypted_frame().map(|frame| frame.decrypt(key))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncryptionScheme {
+    AesCtr,
+    AesCbc,
+    // any other ContentEncAlgo / cipher mode pair
+    Other(u64, u64),
+}
+
+// Key IDs and schemes of every encrypted track, the information a player
+// needs for its license request (the key ID is the "webm" EME init data)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrmInfo {
+    pub tracks: Vec<TrackDrmInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackDrmInfo {
+    pub track: u64,
+    pub scheme: EncryptionScheme,
+    pub key_ids: Vec<Vec<u8>>,
+}
+
+impl DrmInfo {
+    // None when no track is encrypted
+    pub fn new(document: &WebmFile) -> Option<DrmInfo> {
+        let mut tracks = Vec::new();
+        let entries = document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .collect::<Vec<_>>();
+
+        for entry in entries {
+            let encryptions: Vec<ContentEncryptionNode> = entry.get_encoding_settings()
+                .map(|settings| settings.get_encodings())
+                .unwrap_or_default()
+                .iter()
+                .filter(|encoding| encoding.get_type() == 1)
+                .filter_map(|encoding| encoding.get_encryption_node())
+                .collect();
+            let first = match encryptions.first() {
+                Some(first) => first,
+                None => continue,
+            };
+
+            let algorithm = first.get_algorithm_type();
+            let mode = first.get_aes_settings().map(|aes| aes.get_mode()).unwrap_or(1);
+            let scheme = match (algorithm, mode) {
+                (5, 1) => EncryptionScheme::AesCtr,
+                (5, 2) => EncryptionScheme::AesCbc,
+                (algorithm, mode) => EncryptionScheme::Other(algorithm, mode),
+            };
+
+            let mut key_ids = Vec::new();
+            for key_id in encryptions.iter().filter_map(|e| e.get_key_id()) {
+                if !key_ids.contains(&key_id) {
+                    key_ids.push(key_id);
+                }
+            }
+
+            tracks.push(TrackDrmInfo {
+                track: entry.get_track_number(),
+                scheme,
+                key_ids,
+            });
+        }
+
+        if tracks.is_empty() {
+            None
+        } else {
+            Some(DrmInfo { tracks })
+        }
+    }
+
+    // Every distinct key ID across all tracks
+    pub fn key_ids(&self) -> Vec<Vec<u8>> {
+        let mut key_ids: Vec<Vec<u8>> = Vec::new();
+        for key_id in self.tracks.iter().flat_map(|t| t.key_ids.iter()) {
+            if !key_ids.contains(key_id) {
+                key_ids.push(key_id.clone());
+            }
+        }
+        key_ids
+    }
+}
+
+// Partition offsets alternate clear and encrypted ranges, starting clear.
+// An unpartitioned frame is encrypted all the way through.
+fn subsamples_from_partitions(partitions: &[u32], len: u32) -> Option<Vec<Subsample>> {
+    if partitions.is_empty() {
+        return Some(vec![Subsample { clear: 0, encrypted: len }]);
+    }
+
+    let mut bounds = vec![0];
+    bounds.extend(partitions);
+    bounds.push(len);
+    if bounds.windows(2).any(|w| w[0] > w[1]) {
+        return None;
+    }
+
+    let mut subsamples = Vec::new();
+    let mut i = 0;
+    while i + 1 < bounds.len() {
+        let clear = bounds[i + 1] - bounds[i];
+        let encrypted = match bounds.get(i + 2) {
+            Some(end) => end - bounds[i + 1],
+            None => 0,
+        };
+        subsamples.push(Subsample { clear, encrypted });
+        i += 2;
+    }
+    Some(subsamples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clear_frame() {
+        let frame = EncryptedFrame::parse(&[0x00, 1, 2, 3]).unwrap();
+        assert!(!frame.encrypted);
+        assert_eq!(frame.payload, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_encrypted_frame() {
+        let mut data = vec![0x01, 1, 2, 3, 4, 5, 6, 7, 8];
+        data.extend(&[0xaa; 5]);
+        let frame = EncryptedFrame::parse(&data).unwrap();
+        assert_eq!(frame.iv, Some([1, 2, 3, 4, 5, 6, 7, 8]));
+        assert_eq!(frame.subsamples, vec![Subsample { clear: 0, encrypted: 5 }]);
+        assert_eq!(frame.payload.len(), 5);
+        assert!(EncryptedFrame::parse(&data[..5]).is_none());
+    }
+
+    #[test]
+    fn test_partitioned_frame() {
+        let mut data = vec![0x03, 0, 0, 0, 0, 0, 0, 0, 1, 2];
+        data.extend(&[0, 0, 0, 2, 0, 0, 0, 6]);
+        data.extend(&[0xbb; 10]);
+        let frame = EncryptedFrame::parse(&data).unwrap();
+        assert_eq!(frame.subsamples, vec![
+            Subsample { clear: 2, encrypted: 4 },
+            Subsample { clear: 4, encrypted: 0 },
+        ]);
+
+        // three partitions end on an encrypted range
+        assert_eq!(subsamples_from_partitions(&[1, 2, 3], 5).unwrap(), vec![
+            Subsample { clear: 1, encrypted: 1 },
+            Subsample { clear: 1, encrypted: 2 },
+        ]);
+        assert!(subsamples_from_partitions(&[4, 2], 5).is_none());
+        assert!(subsamples_from_partitions(&[6], 5).is_none());
+    }
+
+    #[test]
+    fn test_drm_info() {
+        // a video track with one key ID and an unencrypted audio track
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0xa6]);
+        data.extend(&[0x16, 0x54, 0xae, 0x6b, 0xa1]);
+        data.extend(&[0xae, 0x9a, 0xd7, 0x81, 0x01]);
+        data.extend(&[0x6d, 0x80, 0x94, 0x62, 0x40, 0x91, 0x50, 0x33, 0x81, 0x01]);
+        data.extend(&[0x50, 0x35, 0x8a, 0x47, 0xe1, 0x81, 0x05, 0x47, 0xe2, 0x83, 0xab, 0xcd, 0xef]);
+        data.extend(&[0xae, 0x83, 0xd7, 0x81, 0x02]);
+
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let info = DrmInfo::new(&document).unwrap();
+        assert_eq!(info.tracks, vec![TrackDrmInfo {
+            track: 1,
+            scheme: EncryptionScheme::AesCtr,
+            key_ids: vec![vec![0xab, 0xcd, 0xef]],
+        }]);
+        assert_eq!(info.key_ids(), vec![vec![0xab, 0xcd, 0xef]]);
+
+        let f = std::fs::File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        assert!(WebmFile::open(f).unwrap().drm_info().is_none());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_decrypt_partitioned() {
+        let key = [7u8; 16];
+        let iv = [1, 2, 3, 4, 5, 6, 7, 8];
+        let clear: Vec<u8> = (0..40).collect();
+
+        // encrypt bytes 4..20 and 30..40 as one keystream
+        let mut counter = [0u8; 16];
+        counter[..8].copy_from_slice(&iv);
+        let mut cipher = Aes128Ctr::new(&key.into(), &counter.into());
+        let mut payload = clear.clone();
+        cipher.apply_keystream(&mut payload[4..20]);
+        cipher.apply_keystream(&mut payload[30..40]);
+
+        let mut data = vec![0x03];
+        data.extend(&iv);
+        data.extend(&[3, 0, 0, 0, 4, 0, 0, 0, 20, 0, 0, 0, 30]);
+        data.extend(&payload);
+
+        let frame = EncryptedFrame::parse(&data).unwrap();
+        assert_ne!(frame.payload, clear);
+        assert_eq!(frame.decrypt(&key), clear);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_decrypt_whole_frame() {
+        let key = [9u8; 16];
+        let frame = EncryptedFrame {
+            encrypted: true,
+            iv: Some([0; 8]),
+            subsamples: vec![Subsample { clear: 0, encrypted: 16 }],
+            payload: vec![0; 16],
+        };
+        // with a zero IV the keystream is just AES(key, 0)
+        let keystream = frame.decrypt(&key);
+        let mut again = Aes128Ctr::new(&key.into(), &[0u8; 16].into());
+        let mut expected = vec![0u8; 16];
+        again.apply_keystream(&mut expected);
+        assert_eq!(keystream, expected);
+
+        let clear = EncryptedFrame::parse(&[0x00, 5, 6]).unwrap();
+        assert_eq!(clear.decrypt(&key), vec![5, 6]);
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::{Display, Formatter, Error as FmtError};
+use std::io::{Error as IOError, ErrorKind};
+
+// Everything that can go wrong while parsing. Malformed input of any kind
+// is reported through one of these rather than a panic.
+#[derive(Debug)]
+pub enum Error {
+    Io(IOError),
+    // the stream doesn't start with an EBML header
+    InvalidMagic,
+    // a vint whose first byte is zero, so it has no length marker, or an ID
+    // or size longer than EBMLMaxIDLength/EBMLMaxSizeLength allow
+    InvalidVint { offset: u64 },
+    // the stream ended inside the element starting at `offset`
+    UnexpectedEof { offset: u64 },
+    // a payload that doesn't fit the element's type, e.g. a 9 byte integer
+    InvalidElement { id: u64, offset: u64 },
+    // an ID outside the WebM spec, with UnknownElements::Reject
+    UnknownElement { id: u64, offset: u64 },
+    // masters nested deeper than MAX_DEPTH
+    TooDeep { offset: u64 },
+    // the EBML header isn't followed by a Segment
+    MissingSegment,
+    // a DocType other than webm (or matroska, when allowed)
+    UnsupportedDocType(String),
+    // a document that needs a newer reader, `doc_type` is "EBML" when it's
+    // the EBMLReadVersion that's too new
+    UnsupportedVersion { doc_type: String, read_version: u64 },
+    // a resume offset outside of the Segment
+    InvalidOffset(u64),
+    // a child a typed view needs isn't in the master at `offset`
+    MissingElement { id: u64, offset: u64 },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::InvalidMagic => write!(f, "incorrect magic number"),
+            Error::InvalidVint { offset } => write!(f, "invalid vint at {}", offset),
+            Error::UnexpectedEof { offset } => {
+                write!(f, "unexpected end of stream in element at {}", offset)
+            },
+            Error::InvalidElement { id, offset } => {
+                write!(f, "invalid payload for element {:#x} at {}", id, offset)
+            },
+            Error::UnknownElement { id, offset } => {
+                write!(f, "unknown element {:#x} at {}", id, offset)
+            },
+            Error::TooDeep { offset } => write!(f, "elements nested too deeply at {}", offset),
+            Error::MissingSegment => write!(f, "no Segment after the EBML header"),
+            Error::UnsupportedDocType(doc_type) => write!(f, "unsupported DocType {:?}", doc_type),
+            Error::UnsupportedVersion { doc_type, read_version } => {
+                write!(f, "{} read version {} is not supported", doc_type, read_version)
+            },
+            Error::InvalidOffset(offset) => write!(f, "offset {} is outside the Segment", offset),
+            Error::MissingElement { id, offset } => {
+                write!(f, "missing element {:#x} in master at {}", id, offset)
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<IOError> for Error {
+    fn from(e: IOError) -> Error {
+        Error::Io(e)
+    }
+}
+
+// io::Errors for the writers and in-place editors, which work on io traits
+// rather than returning Error
+pub(crate) fn invalid_input(msg: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidInput, msg)
+}
+
+pub(crate) fn invalid_data(msg: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidData, msg)
+}
+
+// Why element data couldn't be converted to the requested type, from the
+// `try_into_*` methods and `TryFrom` conversions on ElementData
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataError {
+    // integers are at most 8 bytes
+    TooLong { len: usize },
+    // floats are 0, 4 or 8 bytes
+    InvalidFloatLength { len: usize },
+    // a string that isn't valid UTF-8, with the length of the valid prefix
+    InvalidUtf8 { valid_up_to: usize },
+}
+
+impl Display for DataError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            DataError::TooLong { len } => write!(f, "{} bytes is too long for an integer", len),
+            DataError::InvalidFloatLength { len } => {
+                write!(f, "{} bytes is not a valid float length", len)
+            },
+            DataError::InvalidUtf8 { valid_up_to } => {
+                write!(f, "invalid UTF-8 after {} bytes", valid_up_to)
+            },
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+// Problems the parser worked around rather than failing on, collected by
+// `WebmReader::warnings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    // a String or UTF-8 element that isn't valid UTF-8, read with the bad
+    // bytes replaced by U+FFFD
+    InvalidUtf8 { id: u64, offset: u64 },
+    // a Float element that isn't 0, 4 or 8 bytes, which reads as NaN
+    InvalidFloatLength { id: u64, offset: u64, len: u64 },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Warning::InvalidUtf8 { id, offset } => {
+                write!(f, "invalid UTF-8 in element {:#x} at {}", id, offset)
+            },
+            Warning::InvalidFloatLength { id, offset, len } => {
+                write!(f, "float element {:#x} at {} is {} bytes long", id, offset, len)
+            },
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// C API for using the parser from existing C/C++ media tooling. Enabled with
+// the `ffi` feature; declarations are in include/libwebm_rs.h.
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use crate::block::{Packet, Packets};
+use crate::ebml::{WebmFile, TrackEntryNode};
+
+pub const WEBM_OK: c_int = 0;
+pub const WEBM_END: c_int = 1;
+pub const WEBM_ERROR: c_int = -1;
+
+pub struct WebmHandle {
+    document: WebmFile,
+    tracks: Vec<(TrackEntryNode, CString)>,
+    packets: Packets,
+    // owns the data handed out by the last webm_next_packet call
+    current: Option<Packet>,
+}
+
+#[repr(C)]
+pub struct WebmTrackInfo {
+    pub number: u64,
+    pub uid: u64,
+    pub track_type: u64,
+    // valid for as long as the handle is open
+    pub codec_id: *const c_char,
+    pub pixel_width: u64,
+    pub pixel_height: u64,
+    pub sampling_frequency: f64,
+    pub channels: u64,
+}
+
+#[repr(C)]
+pub struct WebmPacket {
+    pub track: u64,
+    pub timestamp: u64,
+    pub keyframe: c_int,
+    // valid until the next call to webm_next_packet or webm_close
+    pub data: *const u8,
+    pub size: usize,
+}
+
+// Panics must not unwind into C, turn them into an error value instead
+fn guard<R>(error: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(error)
+}
+
+/// # Safety
+/// `path` must be a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn webm_open(path: *const c_char) -> *mut WebmHandle {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path.to_string(),
+        Err(_) => return ptr::null_mut(),
+    };
+
+    guard(ptr::null_mut(), || {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return ptr::null_mut(),
+        };
+        let document = match WebmFile::open(file) {
+            Ok(document) => document,
+            Err(_) => return ptr::null_mut(),
+        };
+        let tracks = document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .map(|entry| {
+                let codec_id = CString::new(entry.get_codec_id()).unwrap_or_default();
+                (entry, codec_id)
+            })
+            .collect();
+        let packets = document.packets();
+
+        Box::into_raw(Box::new(WebmHandle {
+            document,
+            tracks,
+            packets,
+            current: None,
+        }))
+    })
+}
+
+/// # Safety
+/// `handle` must come from `webm_open` and not have been closed already.
+#[no_mangle]
+pub unsafe extern "C" fn webm_close(handle: *mut WebmHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// # Safety
+/// `handle` must come from `webm_open` and not have been closed already.
+#[no_mangle]
+pub unsafe extern "C" fn webm_track_count(handle: *const WebmHandle) -> usize {
+    match handle.as_ref() {
+        Some(handle) => handle.tracks.len(),
+        None => 0,
+    }
+}
+
+/// # Safety
+/// `handle` must come from `webm_open` and `out` must point to writable
+/// memory for a `WebmTrackInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn webm_track_info(
+    handle: *const WebmHandle,
+    index: usize,
+    out: *mut WebmTrackInfo,
+) -> c_int {
+    let (handle, out) = match (handle.as_ref(), out.as_mut()) {
+        (Some(handle), Some(out)) => (handle, out),
+        _ => return WEBM_ERROR,
+    };
+    let (entry, codec_id) = match handle.tracks.get(index) {
+        Some(track) => track,
+        None => return WEBM_ERROR,
+    };
+
+    guard(WEBM_ERROR, || {
+        let video = entry.get_video_settings();
+        let audio = entry.get_audio_settings();
+        *out = WebmTrackInfo {
+            number: entry.get_track_number(),
+            uid: entry.get_track_uid(),
+            track_type: entry.get_track_type(),
+            codec_id: codec_id.as_ptr(),
+            pixel_width: video.as_ref().map(|v| v.get_pixel_width()).unwrap_or(0),
+            pixel_height: video.as_ref().map(|v| v.get_pixel_height()).unwrap_or(0),
+            sampling_frequency: audio.as_ref().map(|a| a.get_sampling_frequency()).unwrap_or(0.0),
+            channels: audio.as_ref().map(|a| a.get_num_channels()).unwrap_or(0),
+        };
+        WEBM_OK
+    })
+}
+
+/// # Safety
+/// `handle` must come from `webm_open` and `out` must point to writable
+/// memory for a `WebmPacket`.
+#[no_mangle]
+pub unsafe extern "C" fn webm_next_packet(handle: *mut WebmHandle, out: *mut WebmPacket) -> c_int {
+    let (handle, out) = match (handle.as_mut(), out.as_mut()) {
+        (Some(handle), Some(out)) => (handle, out),
+        _ => return WEBM_ERROR,
+    };
+
+    guard(WEBM_ERROR, || {
+        handle.current = handle.packets.next();
+        match handle.current.as_ref() {
+            Some(packet) => {
+                *out = WebmPacket {
+                    track: packet.track,
+                    timestamp: packet.timestamp,
+                    keyframe: packet.keyframe as c_int,
+                    data: packet.data.as_ptr(),
+                    size: packet.data.len(),
+                };
+                WEBM_OK
+            },
+            None => WEBM_END,
+        }
+    })
+}
+
+/// # Safety
+/// `handle` must come from `webm_open` and not have been closed already.
+#[no_mangle]
+pub unsafe extern "C" fn webm_timestamp_scale(handle: *const WebmHandle) -> u64 {
+    match handle.as_ref() {
+        Some(handle) => guard(0, || {
+            handle.document.root.get_info_nodes()
+                .first()
+                .map(|info| info.get_timestamp_scale())
+                .unwrap_or(1_000_000)
+        }),
+        None => 0,
+    }
+}
+
+// Duration in TimestampScale units, or a negative value if unknown
+/// # Safety
+/// `handle` must come from `webm_open` and not have been closed already.
+#[no_mangle]
+pub unsafe extern "C" fn webm_duration(handle: *const WebmHandle) -> f64 {
+    match handle.as_ref() {
+        Some(handle) => guard(-1.0, || {
+            handle.document.root.get_info_nodes()
+                .first()
+                .and_then(|info| info.get_duration())
+                .unwrap_or(-1.0)
+        }),
+        None => -1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::MaybeUninit;
+    use super::*;
+
+    #[test]
+    fn test_ffi_round_trip() {
+        let path = CString::new("./sample/big-buck-bunny_trailer.webm").unwrap();
+        unsafe {
+            let handle = webm_open(path.as_ptr());
+            assert!(!handle.is_null());
+            assert_eq!(webm_track_count(handle), 2);
+            assert_eq!(webm_timestamp_scale(handle), 1_000_000);
+            assert_eq!(webm_duration(handle), 32480.0);
+
+            let mut info = MaybeUninit::<WebmTrackInfo>::uninit();
+            assert_eq!(webm_track_info(handle, 0, info.as_mut_ptr()), WEBM_OK);
+            let info = info.assume_init();
+            assert_eq!(CStr::from_ptr(info.codec_id).to_str().unwrap(), "V_VP8");
+            assert_eq!((info.pixel_width, info.pixel_height), (640, 360));
+            let mut audio = MaybeUninit::<WebmTrackInfo>::uninit();
+            assert_eq!(webm_track_info(handle, 1, audio.as_mut_ptr()), WEBM_OK);
+            let audio = audio.assume_init();
+            assert_eq!((audio.sampling_frequency, audio.channels), (44100.0, 1));
+            assert_eq!(webm_track_info(handle, 2, ptr::null_mut()), WEBM_ERROR);
+
+            let mut packet = MaybeUninit::<WebmPacket>::uninit();
+            let mut count = 0;
+            while webm_next_packet(handle, packet.as_mut_ptr()) == WEBM_OK {
+                assert!(packet.assume_init_ref().size > 0);
+                count += 1;
+            }
+            assert!(count > 812);
+            webm_close(handle);
+        }
+        unsafe {
+            let missing = CString::new("./sample/missing.webm").unwrap();
+            assert!(webm_open(missing.as_ptr()).is_null());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{Read, Seek, SeekFrom};
+use crate::consts::*;
+use crate::ebml::{EBMLHeaderNode, ClusterNode, Element, Node};
+use crate::error::Error;
+use crate::push::PushParser;
+
+// Follows a file that is still being written (screen recorders, CCTV).
+// Each call to `poll` parses whatever top level elements have been
+// completely written since the previous call and returns only those.
+pub struct TailFollower<T: Read + Seek> {
+    source: T,
+    parser: PushParser,
+    // how much of the source has been handed to the parser
+    read: u64,
+}
+
+impl<T: Read + Seek> TailFollower<T> {
+    pub fn new(r: T) -> TailFollower<T> {
+        TailFollower {
+            source: r,
+            parser: PushParser::new(),
+            read: 0,
+        }
+    }
+
+    pub fn get_header(&self) -> Option<&EBMLHeaderNode> {
+        self.parser.get_header()
+    }
+
+    pub fn get_segment_element(&self) -> Option<&Element> {
+        self.parser.get_segment_element()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.source
+    }
+
+    // Byte offset up to which the stream has been consumed
+    pub fn position(&self) -> u64 {
+        self.parser.position()
+    }
+
+    // Parse newly completed Segment children. An element which is still
+    // being written (including an unknown sized Cluster that hasn't been
+    // followed by another element yet) is left for a later poll.
+    pub fn poll(&mut self) -> Result<Vec<Node>, Error> {
+        self.source.seek(SeekFrom::Start(self.read))?;
+        let mut data = Vec::new();
+        self.source.read_to_end(&mut data)?;
+        self.read += data.len() as u64;
+        self.parser.push(&data)
+    }
+
+    // Like `poll` but only returns the new Clusters
+    pub fn poll_clusters(&mut self) -> Result<Vec<ClusterNode>, Error> {
+        Ok(self.poll()?
+            .into_iter()
+            .filter(|node| node.get_element().id == ID_CLUSTERNODE)
+            .map(ClusterNode::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use super::*;
+
+    fn cluster(timestamp: u8) -> Vec<u8> {
+        let mut data = vec![0x1f, 0x43, 0xb6, 0x75, 0xff];
+        data.extend(&[0xe7, 0x81, timestamp]);
+        data.extend(&[0xa3, 0x85, 0x81, 0x00, 0x00, 0x80, 0xaa]);
+        data
+    }
+
+    #[test]
+    fn test_follow_unknown_size() {
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0xff]);
+
+        // nothing but a partial header so far
+        let mut follower = TailFollower::new(Cursor::new(data[..6].to_vec()));
+        assert!(follower.poll().unwrap().is_empty());
+        assert!(follower.get_header().is_none());
+
+        // header complete, first cluster still being written
+        data.extend(cluster(0));
+        *follower.get_mut().get_mut() = data.clone();
+        assert!(follower.poll().unwrap().is_empty());
+        assert!(follower.get_header().is_some());
+
+        // the second cluster proves the first one is finished
+        data.extend(cluster(1));
+        *follower.get_mut().get_mut() = data.clone();
+        let clusters = follower.poll_clusters().unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].get_timestamp(), 0);
+
+        // a known sized element completes on its own
+        data.extend(&[0x1f, 0x43, 0xb6, 0x75, 0x83, 0xe7, 0x81, 0x02]);
+        *follower.get_mut().get_mut() = data.clone();
+        let clusters = follower.poll_clusters().unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].get_timestamp(), 1);
+        assert_eq!(clusters[1].get_timestamp(), 2);
+        assert!(follower.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_follow_growing_file() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut follower = TailFollower::new(Cursor::new(sample[..2000].to_vec()));
+        let first: Vec<u64> = follower.poll().unwrap().iter().map(|n| n.get_element().id).collect();
+        assert_eq!(first, vec![ID_SEEKHEADNODE, ID_INFONODE]);
+
+        *follower.get_mut().get_mut() = sample.clone();
+        let clusters = follower.poll_clusters().unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(follower.position(), sample.len() as u64);
+    }
+}
@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Error as FmtError};
+
+// Human readable timestamps and sizes for reports and CLIs
+
+// Nanoseconds as hours, minutes, seconds and milliseconds, e.g.
+// `01:02:03.456`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub u64);
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let millis = self.0 / 1_000_000;
+        let seconds = millis / 1000;
+        write!(
+            f,
+            "{:02}:{:02}:{:02}.{:03}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            millis % 1000,
+        )
+    }
+}
+
+// Byte counts in binary units, e.g. `512 B` or `1.5 MiB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn format_timestamp(nanoseconds: u64) -> String {
+    Timestamp(nanoseconds).to_string()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    ByteSize(bytes).to_string()
+}
+
+// Full precision `HH:MM:SS.nnnnnnnnn`, as used by chapter files
+pub fn format_precise_timestamp(nanoseconds: u64) -> String {
+    let seconds = nanoseconds / 1_000_000_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:09}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        nanoseconds % 1_000_000_000,
+    )
+}
+
+// Parse `HH:MM:SS` with an optional fraction of up to nine digits into
+// nanoseconds. Minutes and seconds must be below 60, hours can be anything.
+pub fn parse_timestamp(s: &str) -> Option<u64> {
+    let (clock, fraction) = match s.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (s, None),
+    };
+    let parts: Vec<&str> = clock.split(':').collect();
+    let [hours, minutes, seconds] = parts[..] else {
+        return None;
+    };
+    let number = |part: &str| -> Option<u64> {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse().ok()
+    };
+    let (hours, minutes, seconds) = (number(hours)?, number(minutes)?, number(seconds)?);
+    if minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(fraction) if fraction.len() <= 9 => {
+            number(fraction)? * 10u64.pow(9 - fraction.len() as u32)
+        },
+        Some(_) => return None,
+        None => 0,
+    };
+
+    hours.checked_mul(3600)?
+        .checked_add(minutes * 60 + seconds)?
+        .checked_mul(1_000_000_000)?
+        .checked_add(nanos)
+}
+
+// Timestamps in ticks of `timestamp_scale` nanoseconds, e.g. CueTime.
+// Parsing rounds down to a whole tick.
+pub fn parse_ticks(s: &str, timestamp_scale: u64) -> Option<u64> {
+    parse_timestamp(s)?.checked_div(timestamp_scale)
+}
+
+pub fn format_ticks(ticks: u64, timestamp_scale: u64) -> String {
+    format_precise_timestamp(ticks.saturating_mul(timestamp_scale))
+}
+
+// Days from 1970-01-01 to 2001-01-01, the epoch of Matroska dates
+const DATE_EPOCH_DAYS: i64 = 11_323;
+
+// A Date element's nanoseconds since 2001-01-01T00:00:00 UTC as ISO 8601 in
+// UTC, e.g. `2001-01-01T00:00:00Z`, with a nine digit fraction when the
+// time isn't a whole second
+pub fn format_date(nanoseconds: i64) -> String {
+    let seconds = nanoseconds.div_euclid(1_000_000_000);
+    let nanos = nanoseconds.rem_euclid(1_000_000_000);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400) + DATE_EPOCH_DAYS);
+    let time = seconds.rem_euclid(86_400);
+    let fraction = if nanos == 0 { String::new() } else { format!(".{:09}", nanos) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        fraction,
+    )
+}
+
+// Parse `YYYY-MM-DDTHH:MM:SS` with an optional fraction of up to nine
+// digits and a `Z` or `+HH:MM`/`-HH:MM` offset into nanoseconds since
+// 2001-01-01T00:00:00 UTC. None for invalid dates and ones Date elements
+// can't hold.
+pub fn parse_date(s: &str) -> Option<i64> {
+    let (date, rest) = s.split_once('T')?;
+    let (clock, offset) = match rest.strip_suffix('Z') {
+        Some(clock) => (clock, 0),
+        None => {
+            let split = rest.len().checked_sub(6)?;
+            let (clock, zone) = (rest.get(..split)?, rest.get(split..)?);
+            let sign = match zone.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = zone[1..].split_once(':')?;
+            let (hours, minutes) = (digits(hours, 2)?, digits(minutes, 2)?);
+            if hours >= 24 || minutes >= 60 {
+                return None;
+            }
+            (clock, sign * (hours * 3600 + minutes * 60))
+        },
+    };
+
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return None;
+    };
+    let (year, month, day) = (digits(year, 4)?, digits(month, 2)?, digits(day, 2)?);
+    let days_in_month = match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if day < 1 || day > days_in_month {
+        return None;
+    }
+    if clock.len() < 8 || clock.as_bytes()[2] != b':' {
+        return None;
+    }
+    let time = parse_timestamp(clock)?;
+    if time >= 86_400 * 1_000_000_000 {
+        return None;
+    }
+
+    let seconds = (days_from_civil(year, month, day) - DATE_EPOCH_DAYS) * 86_400 - offset;
+    i64::try_from(seconds as i128 * 1_000_000_000 + time as i128).ok()
+}
+
+// Exactly `len` ASCII digits
+fn digits(s: &str, len: usize) -> Option<i64> {
+    if s.len() != len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, and
+// back, after Howard Hinnant's `days_from_civil` and `civil_from_days`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "00:00:00.000");
+        assert_eq!(format_timestamp(3_723_456_789_000), "01:02:03.456");
+        assert_eq!(format_timestamp(999_999), "00:00:00.000");
+        assert_eq!(format_timestamp(100 * 3600 * 1_000_000_000), "100:00:00.000");
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("00:00:00.000000000"), Some(0));
+        assert_eq!(parse_timestamp("01:02:03.456"), Some(3_723_456_000_000));
+        assert_eq!(parse_timestamp("01:02:03"), Some(3_723_000_000_000));
+        assert_eq!(parse_timestamp("123:00:00.000000001"), Some(442_800_000_000_001));
+        for invalid in ["", "1:2", "00:60:00", "00:00:60", "00:00:00.", "00:00:00.1234567890", "-1:00:00", "a:00:00"] {
+            assert_eq!(parse_timestamp(invalid), None, "{}", invalid);
+        }
+        assert_eq!(parse_timestamp("99999999999999:00:00"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for nanoseconds in [0, 1, 999_999_999, 3_723_456_789_012] {
+            let text = format_precise_timestamp(nanoseconds);
+            assert_eq!(parse_timestamp(&text), Some(nanoseconds));
+        }
+        assert_eq!(format_precise_timestamp(3_723_456_789_012), "01:02:03.456789012");
+
+        assert_eq!(parse_ticks("00:00:01.5", 1_000_000), Some(1500));
+        assert_eq!(parse_ticks("00:00:01.5", 0), None);
+        assert_eq!(format_ticks(1500, 1_000_000), "00:00:01.500000000");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(format_date(0), "2001-01-01T00:00:00Z");
+        assert_eq!(format_date(-1), "2000-12-31T23:59:59.999999999Z");
+        // 2010-06-15T12:34:56.5Z
+        assert_eq!(format_date(298_298_096_500_000_000), "2010-06-15T12:34:56.500000000Z");
+        assert_eq!(format_date(i64::MIN), "1708-09-22T00:12:43.145224192Z");
+        assert_eq!(format_date(i64::MAX), "2293-04-11T23:47:16.854775807Z");
+
+        for nanoseconds in [0, -1, 1, 298_298_096_500_000_000, -978_307_200_000_000_000, i64::MIN, i64::MAX] {
+            assert_eq!(parse_date(&format_date(nanoseconds)), Some(nanoseconds));
+        }
+        assert_eq!(parse_date("1970-01-01T00:00:00Z"), Some(-978_307_200_000_000_000));
+        assert_eq!(parse_date("2001-01-01T02:00:00+02:00"), Some(0));
+        assert_eq!(parse_date("2000-12-31T19:00:00.25-05:00"), Some(250_000_000));
+        assert_eq!(parse_date("2000-02-29T00:00:00Z"), Some(-26_524_800_000_000_000));
+        for invalid in [
+            "", "2001-01-01", "2001-01-01T00:00:00", "2001-01-01 00:00:00Z", "1900-02-29T00:00:00Z",
+            "2001-13-01T00:00:00Z", "2001-01-00T00:00:00Z", "2001-1-01T00:00:00Z", "2001-01-01T24:00:00Z",
+            "2001-01-01T0:00:00Z", "2001-01-01T00:00:00+2:00", "2001-01-01T00:00:00+24:00",
+            "1700-01-01T00:00:00Z", "2300-01-01T00:00:00Z",
+        ] {
+            assert_eq!(parse_date(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_size(u64::MAX), "16384.0 PiB");
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::Cursor;
+use crate::block::Block;
+use crate::diff::diff;
+use crate::ebml::WebmReader;
+use crate::encryption::EncryptedFrame;
+use crate::index::Index;
+use crate::push::PushParser;
+
+#[cfg(feature = "fuzz")]
+use arbitrary::{Arbitrary, Result as ArbitraryResult, Unstructured};
+#[cfg(feature = "fuzz")]
+use crate::consts::*;
+#[cfg(feature = "fuzz")]
+use crate::ebml::encode::write_element;
+#[cfg(feature = "fuzz")]
+use crate::vint;
+
+// Run everything in the crate that consumes untrusted bytes. None of it may
+// panic, so a fuzz target only needs to call this:
+//
+//     fuzz_target!(|input: FuzzInput| libwebm_rs::fuzz::exercise(&input.0));
+pub fn exercise(data: &[u8]) {
+    if let Ok(documents) = WebmReader::new(Cursor::new(data)).parse_all() {
+        for document in documents {
+            for packet in document.packets() {
+                let _ = packet.encrypted_frame();
+            }
+            let _ = document.media_info().mime_type();
+            let _ = document.drm_info();
+            let _ = Index::build(&document);
+            let _ = diff(&document, &document);
+        }
+    }
+
+    let mut parser = PushParser::new();
+    if data.chunks(4096).all(|chunk| parser.push(chunk).is_ok()) {
+        let _ = parser.finish();
+    }
+
+    let _ = Block::parse(data);
+    let _ = EncryptedFrame::parse(data);
+    let _ = Index::read_from(data);
+}
+
+// Raw bytes rarely make it past the EBML header, so fuzz input is usually
+// built out of real element IDs with arbitrary payloads, nesting and sizes
+// (including wrong and unknown ones) to reach deeper into the parser.
+#[cfg(feature = "fuzz")]
+#[derive(Debug, Clone)]
+pub struct FuzzInput(pub Vec<u8>);
+
+#[cfg(feature = "fuzz")]
+impl<'a> Arbitrary<'a> for FuzzInput {
+    fn arbitrary(u: &mut Unstructured<'a>) -> ArbitraryResult<FuzzInput> {
+        if u.ratio(1, 8)? {
+            return Ok(FuzzInput(u.arbitrary()?));
+        }
+
+        let mut data = Vec::new();
+        let header = arbitrary_elements(u, 1)?;
+        write_element(&mut data, ID_EBMLHEADERNODE, &header);
+        let segment = arbitrary_elements(u, 0)?;
+        arbitrary_element(u, &mut data, ID_SEGMENTNODE, &segment)?;
+        // trailing garbage
+        data.extend(u.arbitrary::<Vec<u8>>()?);
+        Ok(FuzzInput(data))
+    }
+}
+
+#[cfg(feature = "fuzz")]
+fn arbitrary_elements(u: &mut Unstructured, depth: usize) -> ArbitraryResult<Vec<u8>> {
+    let mut out = Vec::new();
+    for _ in 0..u.int_in_range(0..=8)? {
+        let id = u.choose(&NODE_INFOS)?.id;
+        let payload = if depth < 6 && u.ratio(1, 3)? {
+            arbitrary_elements(u, depth + 1)?
+        } else {
+            u.arbitrary::<Vec<u8>>()?
+        };
+        arbitrary_element(u, &mut out, id, &payload)?;
+    }
+    Ok(out)
+}
+
+// Mostly well formed, sometimes with an unknown or plain wrong size
+#[cfg(feature = "fuzz")]
+fn arbitrary_element(u: &mut Unstructured, out: &mut Vec<u8>, id: u64, payload: &[u8]) -> ArbitraryResult<()> {
+    match u.int_in_range(0..=7)? {
+        0 => {
+            vint::write_id(out, id);
+            vint::write_unknown_size(out);
+            out.extend(payload);
+        },
+        1 => {
+            vint::write_id(out, id);
+            let len = u.int_in_range(1..=8)?;
+            out.extend(u.bytes(len)?);
+            out.extend(payload);
+        },
+        _ => write_element(out, id, payload),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    // xorshift, so the test is deterministic without pulling in rand
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_truncated_sample() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        for len in (0..5000).chain((5000..sample.len()).step_by(50_000)) {
+            exercise(&sample[..len]);
+        }
+    }
+
+    #[test]
+    fn test_corrupted_headers() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut state = 0x2545f4914f6cdd1d;
+        for _ in 0..200 {
+            let mut data = sample[..8192].to_vec();
+            for _ in 0..4 {
+                let pos = next(&mut state) as usize % data.len();
+                data[pos] = next(&mut state) as u8;
+            }
+            exercise(&data);
+        }
+    }
+
+    #[test]
+    fn test_pathological_input() {
+        let header = [0x1a, 0x45, 0xdf, 0xa3, 0x80, 0x18, 0x53, 0x80, 0x67, 0xff];
+        // ChapterAtoms nested far past MAX_DEPTH
+        let mut nested = header.to_vec();
+        nested.extend([0xb6, 0xff].repeat(100_000));
+        exercise(&nested);
+
+        // a 9 byte integer, and a Binary element claiming to be huge
+        let mut data = header.to_vec();
+        data.extend(&[0xe7, 0x89, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        exercise(&data);
+        let mut data = header.to_vec();
+        data.extend(&[0xa3, 0x01, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+        exercise(&data);
+    }
+
+    #[cfg(feature = "fuzz")]
+    #[test]
+    fn test_arbitrary_inputs() {
+        let mut state = 0x9e3779b97f4a7c15;
+        for _ in 0..500 {
+            let bytes: Vec<u8> = (0..512).map(|_| next(&mut state) as u8).collect();
+            if let Ok(input) = FuzzInput::arbitrary(&mut Unstructured::new(&bytes)) {
+                exercise(&input.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::{Read, Write, Error as IOError};
+use crate::block::block_of;
+use crate::ebml::WebmFile;
+use crate::error::invalid_data;
+
+// Magic and version for serialized index files
+const INDEX_MAGIC: [u8; 4] = *b"WMIX";
+const INDEX_VERSION: u8 = 1;
+
+// A compact summary of a document that is enough to seek without
+// re-scanning the media file. Cluster offsets are relative to the start of
+// the Segment data, the same as Cues and SeekHead positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub segment_data_offset: u64,
+    pub timestamp_scale: u64,
+    pub tracks: Vec<IndexTrack>,
+    pub clusters: Vec<IndexCluster>,
+    pub keyframes: Vec<IndexKeyframe>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexTrack {
+    pub number: u64,
+    pub uid: u64,
+    pub track_type: u64,
+    pub codec_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexCluster {
+    pub offset: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexKeyframe {
+    pub track: u64,
+    pub timestamp: u64,
+    // position of the owning cluster in `clusters`
+    pub cluster: u32,
+}
+
+impl Index {
+    pub fn build(document: &WebmFile) -> Index {
+        let root = &document.root;
+        let segment_data_offset = root.get_element().data_offset();
+        let timestamp_scale = root.get_info_nodes()
+            .first()
+            .map(|info| info.get_timestamp_scale())
+            .unwrap_or(1_000_000);
+
+        let tracks = root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .map(|entry| IndexTrack {
+                number: entry.get_track_number(),
+                uid: entry.get_track_uid(),
+                track_type: entry.get_track_type(),
+                codec_id: entry.get_codec_id(),
+            })
+            .collect();
+
+        let mut clusters = Vec::new();
+        let mut keyframes = Vec::new();
+        for cluster in root.get_clusters() {
+            let timestamp = cluster.get_timestamp();
+            let position = clusters.len() as u32;
+            clusters.push(IndexCluster {
+                offset: cluster.get_element().offset - segment_data_offset,
+                timestamp,
+            });
+
+            let blocks = cluster.as_node().children().iter()
+                .filter_map(block_of)
+                .filter(|entry| entry.keyframe)
+                .map(|entry| entry.block);
+            for block in blocks {
+                keyframes.push(IndexKeyframe {
+                    track: block.track,
+                    timestamp: block.timestamp(timestamp),
+                    cluster: position,
+                });
+            }
+        }
+        keyframes.sort_by_key(|k| (k.timestamp, k.track));
+
+        Index {
+            segment_data_offset,
+            timestamp_scale,
+            tracks,
+            clusters,
+            keyframes,
+        }
+    }
+
+    // Find the last cluster starting at or before a timestamp
+    pub fn find_cluster(&self, timestamp: u64) -> Option<&IndexCluster> {
+        self.clusters.iter().rev().find(|c| c.timestamp <= timestamp)
+    }
+
+    // Find the last keyframe on a track at or before a timestamp
+    pub fn find_keyframe(&self, track: u64, timestamp: u64) -> Option<&IndexKeyframe> {
+        self.keyframes
+            .iter()
+            .rev()
+            .find(|k| k.track == track && k.timestamp <= timestamp)
+    }
+
+    // Absolute file position of an indexed cluster
+    pub fn cluster_position(&self, cluster: &IndexCluster) -> u64 {
+        self.segment_data_offset.saturating_add(cluster.offset)
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> Result<(), IOError> {
+        w.write_all(&INDEX_MAGIC)?;
+        w.write_all(&[INDEX_VERSION])?;
+        write_u64(&mut w, self.segment_data_offset)?;
+        write_u64(&mut w, self.timestamp_scale)?;
+
+        write_u64(&mut w, self.tracks.len() as u64)?;
+        for track in self.tracks.iter() {
+            write_u64(&mut w, track.number)?;
+            write_u64(&mut w, track.uid)?;
+            write_u64(&mut w, track.track_type)?;
+            write_u64(&mut w, track.codec_id.len() as u64)?;
+            w.write_all(track.codec_id.as_bytes())?;
+        }
+
+        write_u64(&mut w, self.clusters.len() as u64)?;
+        for cluster in self.clusters.iter() {
+            write_u64(&mut w, cluster.offset)?;
+            write_u64(&mut w, cluster.timestamp)?;
+        }
+
+        write_u64(&mut w, self.keyframes.len() as u64)?;
+        for keyframe in self.keyframes.iter() {
+            write_u64(&mut w, keyframe.track)?;
+            write_u64(&mut w, keyframe.timestamp)?;
+            w.write_all(&keyframe.cluster.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut r: impl Read) -> Result<Index, IOError> {
+        let mut magic = [0u8; 5];
+        r.read_exact(&mut magic)?;
+        if magic[..4] != INDEX_MAGIC || magic[4] != INDEX_VERSION {
+            return Err(invalid_data("not a supported index file"));
+        }
+
+        let segment_data_offset = read_u64(&mut r)?;
+        let timestamp_scale = read_u64(&mut r)?;
+
+        let mut tracks = Vec::new();
+        for _ in 0..read_u64(&mut r)? {
+            let number = read_u64(&mut r)?;
+            let uid = read_u64(&mut r)?;
+            let track_type = read_u64(&mut r)?;
+            let mut codec_id = Vec::new();
+            let len = read_u64(&mut r)?;
+            (&mut r).take(len).read_to_end(&mut codec_id)?;
+            if codec_id.len() as u64 != len {
+                return Err(invalid_data("truncated codec ID"));
+            }
+            tracks.push(IndexTrack {
+                number,
+                uid,
+                track_type,
+                codec_id: String::from_utf8(codec_id)
+                    .map_err(|_| invalid_data("codec ID is not UTF-8"))?,
+            });
+        }
+
+        let mut clusters = Vec::new();
+        for _ in 0..read_u64(&mut r)? {
+            clusters.push(IndexCluster {
+                offset: read_u64(&mut r)?,
+                timestamp: read_u64(&mut r)?,
+            });
+        }
+
+        let mut keyframes = Vec::new();
+        for _ in 0..read_u64(&mut r)? {
+            let track = read_u64(&mut r)?;
+            let timestamp = read_u64(&mut r)?;
+            let mut cluster = [0u8; 4];
+            r.read_exact(&mut cluster)?;
+            keyframes.push(IndexKeyframe {
+                track,
+                timestamp,
+                cluster: u32::from_le_bytes(cluster),
+            });
+        }
+
+        Ok(Index {
+            segment_data_offset,
+            timestamp_scale,
+            tracks,
+            clusters,
+            keyframes,
+        })
+    }
+}
+
+fn write_u64(w: &mut impl Write, value: u64) -> Result<(), IOError> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64, IOError> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use super::*;
+
+    fn sample_index() -> Index {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        Index::build(&WebmFile::open(f).unwrap())
+    }
+
+    #[test]
+    fn test_build() {
+        let index = sample_index();
+        assert_eq!(index.timestamp_scale, 1_000_000);
+        assert_eq!(index.tracks.len(), 2);
+        assert_eq!(index.tracks[0].codec_id, "V_VP8");
+        // matches the CueClusterPosition in the sample's Cues
+        assert_eq!(index.clusters, vec![IndexCluster { offset: 3911, timestamp: 0 }]);
+        assert_eq!(index.keyframes[0].timestamp, 0);
+        assert!(index.find_keyframe(1, 10_000).unwrap().timestamp <= 10_000);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let index = sample_index();
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        assert_eq!(Index::read_from(&buf[..]).unwrap(), index);
+        assert!(Index::read_from(&buf[..buf.len() - 1]).is_err());
+        assert!(Index::read_from(&b"nope"[..]).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::block::{block_packets, Packet};
+use crate::consts::*;
+use crate::ebml::{EBMLHeaderNode, Element, Node};
+use crate::ebml::parser::{ElementParser, ParseEvent, TreeBuilder};
+use crate::error::Error;
+
+// Push-based parsing for live streams that arrive in arbitrary pieces, like
+// MediaRecorder uploads over chunked HTTP or a WebSocket. Unlike
+// `PushParser` it doesn't wait for whole Clusters, which a live stream only
+// finishes when the next one starts, but reports each block as soon as it
+// has arrived. Consumed bytes are dropped, so memory use stays at about one
+// element however long the stream runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    // the EBML header and the Segment element, once both are complete
+    Header(EBMLHeaderNode, Element),
+    // a complete top level element other than a Cluster, e.g. Info or Tracks
+    Element(Node),
+    // the start of a Cluster, before any of its children
+    Cluster(Element),
+    // a frame from the current Cluster
+    Packet(Packet),
+}
+
+pub struct Ingest {
+    parser: ElementParser,
+    // builds the top level elements and the children of Clusters
+    tree: TreeBuilder,
+    header: Option<EBMLHeaderNode>,
+    segment: Option<Element>,
+    // the timestamp of the Cluster being read
+    cluster: Option<u64>,
+    // set once the Segment has ended
+    finished: bool,
+}
+
+impl Ingest {
+    pub fn new() -> Ingest {
+        Ingest {
+            parser: ElementParser::new(),
+            tree: TreeBuilder::new(),
+            header: None,
+            segment: None,
+            cluster: None,
+            finished: false,
+        }
+    }
+
+    // Add the next piece of the stream and get back what it completed
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Event>, Error> {
+        let mut events = Vec::new();
+        if self.finished {
+            return Ok(events);
+        }
+        self.parser.feed(data);
+        loop {
+            let event = self.parser.next_event()?;
+            let depth = self.parser.depth();
+            match event {
+                ParseEvent::NeedData | ParseEvent::Done => break,
+                ParseEvent::Start(segment) if depth == 1 && segment.id == ID_SEGMENTNODE => {
+                    let header = self.header.clone().ok_or(Error::MissingSegment)?;
+                    self.segment = Some(segment.clone());
+                    events.push(Event::Header(header, segment));
+                },
+                ParseEvent::Start(cluster) if depth == 2 && cluster.id == ID_CLUSTERNODE => {
+                    self.cluster = Some(0);
+                    events.push(Event::Cluster(cluster));
+                },
+                ParseEvent::End(ID_CLUSTERNODE) if depth == 1 => self.cluster = None,
+                ParseEvent::End(ID_SEGMENTNODE) if depth == 0 => {
+                    self.finished = true;
+                    break;
+                },
+                event => {
+                    let node = match self.tree.push(event) {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    match self.cluster.as_mut() {
+                        _ if node.element().id == ID_EBMLHEADERNODE => {
+                            self.header = Some(EBMLHeaderNode::from(node));
+                        },
+                        Some(timestamp) if node.element().id == ID_TIMESTAMP => {
+                            *timestamp = node.element().data.into_uint();
+                        },
+                        Some(timestamp) => {
+                            events.extend(block_packets(&node, *timestamp).into_iter().map(Event::Packet));
+                        },
+                        None => events.push(Event::Element(node)),
+                    }
+                },
+            }
+        }
+        Ok(events)
+    }
+
+    pub fn get_header(&self) -> Option<&EBMLHeaderNode> {
+        self.header.as_ref()
+    }
+
+    pub fn get_segment_element(&self) -> Option<&Element> {
+        self.segment.as_ref()
+    }
+
+    // Byte offset up to which the stream has been consumed
+    pub fn position(&self) -> u64 {
+        self.parser.position()
+    }
+
+    // Bytes fed but not consumed yet, the start of an incomplete element
+    pub fn buffered(&self) -> usize {
+        self.parser.buffered()
+    }
+
+    // Whether the end of the Segment has been reached, after which further
+    // data is ignored
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Default for Ingest {
+    fn default() -> Ingest {
+        Ingest::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::ebml::WebmFile;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn ingest(data: &[u8], chunk: usize) -> Vec<Event> {
+        let mut ingest = Ingest::new();
+        let mut events = Vec::new();
+        for piece in data.chunks(chunk) {
+            events.extend(ingest.feed(piece).unwrap());
+        }
+        events
+    }
+
+    fn packets(events: &[Event]) -> Vec<Packet> {
+        events.iter()
+            .filter_map(|event| match event {
+                Event::Packet(packet) => Some(packet.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ingest_sample() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let document = WebmFile::from_bytes(&sample).unwrap();
+        let expected: Vec<Packet> = document.packets().collect();
+
+        for chunk in [100, 4096, 64 * 1024] {
+            let events = ingest(&sample, chunk);
+            assert!(matches!(&events[0], Event::Header(header, _) if header.get_doc_type() == "webm"));
+            assert_eq!(packets(&events), expected);
+            let clusters = events.iter().filter(|e| matches!(e, Event::Cluster(_))).count();
+            assert_eq!(clusters, 1);
+        }
+    }
+
+    #[test]
+    fn test_live_stream() {
+        // MediaRecorder writes a Segment and Clusters of unknown size
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP8", 64, 64)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .without_cues()
+            .cluster(0)
+            .frame(1, 0, true, &[1; 100])
+            .frame(1, 33, false, &[2; 100])
+            .cluster(66)
+            .frame(1, 0, true, &[3; 100])
+            .build();
+        let cluster = data.windows(4).rposition(|w| w == [0x1f, 0x43, 0xb6, 0x75]).unwrap();
+
+        let mut ingest = Ingest::new();
+        let events = ingest.feed(&data[..cluster]).unwrap();
+        assert!(matches!(events[0], Event::Header(..)));
+        assert!(matches!(&events[1], Event::Element(node) if node.element().id == ID_INFONODE));
+        // the first Cluster hasn't ended but its blocks are out
+        let timestamps: Vec<u64> = packets(&events).iter().map(|p| p.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 33]);
+        assert!(ingest.buffered() < 20);
+
+        let events = ingest.feed(&data[cluster..]).unwrap();
+        assert!(matches!(&events[0], Event::Cluster(c) if c.offset == cluster as u64));
+        assert_eq!(packets(&events)[0].timestamp, 66);
+        assert_eq!(packets(&events)[0].data, vec![3; 100]);
+        assert_eq!(ingest.position(), data.len() as u64);
+        assert_eq!(ingest.buffered(), 0);
+        assert!(!ingest.is_finished());
+    }
+}
@@ -0,0 +1,254 @@
+use std::io::{Read, Seek, SeekFrom, Write, Error as IOError};
+use std::ops::Range;
+use crate::consts::*;
+use crate::ebml::Element;
+use crate::error::invalid_input;
+use crate::vint;
+
+// Edits that overwrite a file where it is, without a full rewrite. Freed
+// bytes are covered by a Void so everything after the edit keeps its
+// offset, and any Voids directly after the element are merged into the
+// free space (or split, when a replacement grows into them). Only Voids
+// before `parent_end`, the `end_offset` of the master holding the element
+// (the Segment for top level ones), are taken, so the edit stays inside
+// that master. Each edit returns the byte range it rewrote, which
+// `WebmFile::reparse_range` can read back into an already parsed document.
+
+// Turn a parsed element into a Void covering the same bytes
+pub fn remove_in_place<F>(f: F, element: &Element, parent_end: u64) -> Result<Range<u64>, IOError>
+where
+    F: Read + Write + Seek,
+{
+    replace_in_place(f, element, parent_end, &[])
+}
+
+// Overwrite a parsed element with an encoded replacement, e.g. from
+// `Node::to_bytes`. The replacement can be smaller than the original, or
+// bigger if Voids following the original leave enough room.
+pub fn replace_in_place<F>(mut f: F, element: &Element, parent_end: u64, replacement: &[u8]) -> Result<Range<u64>, IOError>
+where
+    F: Read + Write + Seek,
+{
+    if element.is_unknown_size() {
+        return Err(invalid_input("can't replace an element of unknown size"));
+    }
+    let start = element.offset;
+    let end = following_voids_end(&mut f, element.end_offset(), parent_end)?;
+    let available = end - start;
+    let len = replacement.len() as u64;
+    if len > available {
+        return Err(invalid_input("replacement doesn't fit"));
+    }
+
+    let mut bytes = replacement.to_vec();
+    // a Void needs at least two bytes, so absorb a single spare byte by
+    // writing the replacement's size one byte longer
+    if available - len == 1 {
+        bytes = widen_size(replacement)
+            .ok_or_else(|| invalid_input("no room for a Void after the replacement"))?;
+    }
+    let rest = available - bytes.len() as u64;
+    if rest > 0 {
+        bytes.extend(void_header(rest).ok_or_else(|| invalid_input("freed space is too large"))?);
+    }
+
+    f.seek(SeekFrom::Start(start))?;
+    f.write_all(&bytes)?;
+    Ok(start..end)
+}
+
+// Header of a Void element that is `len` bytes long in total, using the
+// shortest size that fits. None below the two byte minimum.
+pub(crate) fn void_header(len: u64) -> Option<Vec<u8>> {
+    (1..=8usize).find_map(|size_len| {
+        let size = len.checked_sub(1 + size_len as u64)?;
+        let mut header = vec![ID_VOID as u8];
+        vint::write_with_length(&mut header, size, size_len)?;
+        Some(header)
+    })
+}
+
+// A whole Void of `len` bytes, at least two
+pub(crate) fn void(len: u64) -> Vec<u8> {
+    let mut void = void_header(len).unwrap_or_default();
+    void.resize(len as usize, 0);
+    void
+}
+
+// End of the run of Void elements starting at `offset` that lie wholly
+// before `limit`, or `offset` itself
+fn following_voids_end<F: Read + Seek>(f: &mut F, mut offset: u64, limit: u64) -> Result<u64, IOError> {
+    loop {
+        if offset >= limit {
+            return Ok(offset);
+        }
+        f.seek(SeekFrom::Start(offset))?;
+        if vint::read_id(&mut *f) != Some(ID_VOID) {
+            return Ok(offset);
+        }
+        let size = match vint::read_size(&mut *f) {
+            Some(size) if size != UNKNOWN_SIZE => size,
+            _ => return Ok(offset),
+        };
+        let data_start = f.stream_position()?;
+        let stream_end = f.seek(SeekFrom::End(0))?;
+        match data_start.checked_add(size) {
+            Some(end) if end <= stream_end && end <= limit => offset = end,
+            _ => return Ok(offset),
+        }
+    }
+}
+
+// Re-encode an element's size vint one byte longer
+fn widen_size(element: &[u8]) -> Option<Vec<u8>> {
+    let id_len = vint::length(*element.first()?)?;
+    let (size, size_len) = vint::parse(element.get(id_len..)?)?;
+    let mut widened = element[..id_len].to_vec();
+    vint::write_with_length(&mut widened, size, size_len + 1)?;
+    widened.extend(&element[id_len + size_len..]);
+    Some(widened)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::ebml::{Element, Node, WebmFile};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn info_child(data: &[u8], id: u64) -> Element {
+        let document = WebmFile::from_bytes(data).unwrap();
+        document.root.get_info_nodes()[0]
+            .get_children()
+            .into_iter()
+            .find(|n| n.get_element().id == id)
+            .unwrap()
+            .get_element()
+    }
+
+    fn info_end(data: &[u8]) -> u64 {
+        WebmFile::from_bytes(data).unwrap().root.get_info_nodes()[0].get_element().end_offset()
+    }
+
+    #[test]
+    fn test_void_header() {
+        assert_eq!(void_header(1), None);
+        assert_eq!(void_header(2), Some(vec![0xec, 0x80]));
+        assert_eq!(void_header(128), Some(vec![0xec, 0xfe]));
+        // 129 would need the reserved one byte size
+        assert_eq!(void_header(129), Some(vec![0xec, 0x40, 0x7e]));
+    }
+
+    #[test]
+    fn test_remove_in_place() {
+        let data = WebmBuilder::new().duration(10.0).build();
+        let duration = info_child(&data, ID_DURATION);
+        let mut file = Cursor::new(data.clone());
+        remove_in_place(&mut file, &duration, info_end(&data)).unwrap();
+
+        let edited = file.into_inner();
+        assert_eq!(edited.len(), data.len());
+        let document = WebmFile::from_bytes(&edited).unwrap();
+        let info = &document.root.get_info_nodes()[0];
+        assert_eq!(info.get_duration(), None);
+        assert_eq!(info.get_children().last().unwrap().get_element().id, ID_VOID);
+    }
+
+    #[test]
+    fn test_reparse_range() {
+        let data = WebmBuilder::new()
+            .duration(10.0)
+            .video_track(1, "V_VP9", 64, 64)
+            .frame(1, 0, true, &[0; 16])
+            .build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        let app = info_child(&data, ID_MUXINGAPP);
+        let end = info_end(&data);
+
+        let mut file = Cursor::new(data);
+        let short = Node::from(Element::string(ID_MUXINGAPP, "x")).to_bytes();
+        let range = replace_in_place(&mut file, &app, end, &short).unwrap();
+        assert_eq!(range, app.offset..app.end_offset());
+        document.reparse_range(&mut file, range).unwrap();
+
+        // the same as parsing the edited file from scratch
+        let reparsed = WebmFile::from_bytes(file.get_ref()).unwrap();
+        assert_eq!(document.root, reparsed.root);
+        assert_eq!(document.root.get_info_nodes()[0].get_muxing_app(), "x");
+        let offsets: Vec<u64> = document.iter().map(|(_, e)| e.offset).collect();
+        assert_eq!(offsets, reparsed.iter().map(|(_, e)| e.offset).collect::<Vec<u64>>());
+
+        // ranges have to line up with whole elements
+        assert!(document.reparse_range(&mut file, app.offset + 1..app.end_offset()).is_err());
+    }
+
+    #[test]
+    fn test_replace_in_place() {
+        let data = WebmBuilder::new().duration(10.0).build();
+        let app = info_child(&data, ID_MUXINGAPP);
+        let end = info_end(&data);
+
+        // shrink, leaving a Void behind
+        let mut file = Cursor::new(data.clone());
+        let short = Node::from(Element::string(ID_MUXINGAPP, "x")).to_bytes();
+        replace_in_place(&mut file, &app, end, &short).unwrap();
+        let shrunk = file.into_inner();
+        assert_eq!(info_child(&shrunk, ID_MUXINGAPP).data.into_string(), "x");
+        let void = info_child(&shrunk, ID_VOID);
+
+        // grow back into that Void, splitting it
+        let mut file = Cursor::new(shrunk.clone());
+        let longer = Node::from(Element::string(ID_MUXINGAPP, "longer")).to_bytes();
+        replace_in_place(&mut file, &info_child(&shrunk, ID_MUXINGAPP), info_end(&shrunk), &longer).unwrap();
+        let grown = file.into_inner();
+        assert_eq!(info_child(&grown, ID_MUXINGAPP).data.into_string(), "longer");
+        assert_eq!(info_child(&grown, ID_VOID).end_offset(), void.end_offset());
+
+        // removing it again merges with the Void after it
+        let mut file = Cursor::new(shrunk.clone());
+        remove_in_place(&mut file, &info_child(&shrunk, ID_MUXINGAPP), info_end(&shrunk)).unwrap();
+        let merged = info_child(file.get_ref(), ID_VOID);
+        assert_eq!((merged.offset, merged.end_offset()), (app.offset, app.end_offset()));
+
+        // one spare byte goes into a longer size vint
+        let mut file = Cursor::new(data.clone());
+        let nine = Node::from(Element::string(ID_MUXINGAPP, "libwebm-r")).to_bytes();
+        replace_in_place(&mut file, &app, end, &nine).unwrap();
+        let widened = info_child(file.get_ref(), ID_MUXINGAPP);
+        assert_eq!(widened.header_size, 4);
+        assert_eq!(widened.end_offset(), app.end_offset());
+
+        // nothing after MuxingApp to grow into
+        let mut file = Cursor::new(data);
+        let too_long = Node::from(Element::string(ID_MUXINGAPP, "libwebm-rs!")).to_bytes();
+        assert!(replace_in_place(&mut file, &app, end, &too_long).is_err());
+    }
+
+    #[test]
+    fn test_parent_end() {
+        // a top level Void right after Info, whose last child is Duration
+        let mut document = WebmFile::from_bytes(&WebmBuilder::new().duration(10.0).build()).unwrap();
+        let root = document.root.as_node_mut();
+        let info = root.children().iter().position(|n| n.element().id == ID_INFONODE).unwrap();
+        root.insert_child(info + 1, Element::binary(ID_VOID, &[0; 32]).into());
+        let mut padded = Cursor::new(Vec::new());
+        document.write_to(&mut padded).unwrap();
+        let data = padded.into_inner();
+        let duration = info_child(&data, ID_DURATION);
+        assert_eq!(duration.end_offset(), info_end(&data));
+
+        // that Void belongs to the Segment, so there's no room to grow
+        let mut file = Cursor::new(data.clone());
+        let title = Node::from(Element::string(ID_TITLE, "a title that is longer")).to_bytes();
+        assert!(replace_in_place(&mut file, &duration, info_end(&data), &title).is_err());
+        assert_eq!(file.get_ref(), &data);
+
+        // and removing stops at the end of Info
+        let range = remove_in_place(&mut file, &duration, info_end(&data)).unwrap();
+        assert_eq!(range, duration.offset..info_end(&data));
+        let edited = WebmFile::from_bytes(file.get_ref()).unwrap();
+        let ids: Vec<u64> = edited.root.as_node().children().iter().map(|n| n.element().id).collect();
+        let original = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(ids, original.root.as_node().children().iter().map(|n| n.element().id).collect::<Vec<u64>>());
+    }
+}
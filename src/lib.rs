@@ -1,9 +1,52 @@
-mod ebml;
-mod consts;
+// lets `#[derive(FromEbml)]` output refer to this crate from inside it too
+extern crate self as libwebm_rs;
+
+pub mod ebml;
+pub mod tree;
+pub mod vint;
+pub mod error;
+pub mod consts;
+pub mod block;
+pub mod index;
+pub mod follow;
+pub mod seek;
+pub mod push;
+pub mod ingest;
+pub mod demux;
+pub mod mux;
+pub mod media_info;
+pub mod media_segments;
+pub mod encryption;
+pub mod signature;
+pub mod linking;
+pub mod chapters;
+pub mod ogg;
+pub mod retime;
+pub mod scrub;
+pub mod inplace;
+pub mod remux;
+pub mod diff;
+pub mod checksum;
+pub mod timeline;
+pub mod format;
+pub mod profile;
+pub mod fuzz;
+pub mod validate;
+pub mod sniff;
+pub mod temporal;
+pub mod display;
+pub mod shared;
+pub mod view;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::fs::{self, File};
     use super::ebml::{WebmReader, WebmFile};
 
     #[test]
@@ -19,8 +62,28 @@ mod tests {
     fn test_file() {
         let file = "./sample/big-buck-bunny_trailer.webm";
         let f = File::open(file).unwrap();
-        let document = WebmFile::open(f);
+        let document = WebmFile::open(f).unwrap();
         assert_eq!(document.header.get_element().id, 0x1a45dfa3);
         assert_eq!(document.root.get_element().id, 0x18538067);
     }
+
+    #[test]
+    fn test_from_bytes() {
+        let data = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.header.get_doc_type(), "webm");
+        assert_eq!(document.root.get_clusters().len(), 1);
+    }
+
+    #[test]
+    fn test_packets() {
+        let file = "./sample/big-buck-bunny_trailer.webm";
+        let document = WebmFile::open(File::open(file).unwrap()).unwrap();
+        let packets: Vec<_> = document.packets().collect();
+
+        assert_eq!(packets.iter().filter(|p| p.track == 1).count(), 812);
+        assert!(packets[0].keyframe);
+        assert!(packets.iter().all(|p| !p.data.is_empty()));
+        assert!(packets.last().unwrap().timestamp <= 32480);
+    }
 }
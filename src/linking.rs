@@ -0,0 +1,459 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{Display, Formatter, Error as FmtError};
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufReader, Error as IOError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::chapters::ordered_timeline;
+use crate::consts::*;
+use crate::ebml::{EditionEntryNode, InfoNode, WebmFile, WebmReader};
+use crate::ebml::encode::write_element;
+
+// 128 bit identifier used for SegmentUUID, SegmentFamily and the
+// Prev/NextUUID links between segments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SegmentUid(pub [u8; 16]);
+
+impl SegmentUid {
+    // A fresh random UID. This only needs to be unique, not unpredictable,
+    // so std's randomly keyed hasher is enough of a source. No clock is
+    // read, as there is none on wasm32-unknown-unknown.
+    pub fn generate() -> SegmentUid {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let mut uid = [0u8; 16];
+        for half in uid.chunks_mut(8) {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+            half.copy_from_slice(&hasher.finish().to_be_bytes());
+        }
+        SegmentUid(uid)
+    }
+
+    // None unless the slice is exactly 16 bytes
+    pub fn from_slice(bytes: &[u8]) -> Option<SegmentUid> {
+        if bytes.len() != 16 {
+            return None;
+        }
+        let mut uid = [0u8; 16];
+        uid.copy_from_slice(bytes);
+        Some(SegmentUid(uid))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl Display for SegmentUid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+// The Info children that chain the parts of a multi-file recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentLinks {
+    pub uid: Option<SegmentUid>,
+    pub filename: Option<String>,
+    pub prev_uid: Option<SegmentUid>,
+    pub prev_filename: Option<String>,
+    pub next_uid: Option<SegmentUid>,
+    pub next_filename: Option<String>,
+}
+
+impl SegmentLinks {
+    // Encode as Info child elements, ready to be placed in an Info master
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let uids = [
+            (ID_SEGMENTUUID, &self.uid),
+            (ID_PREVUUID, &self.prev_uid),
+            (ID_NEXTUUID, &self.next_uid),
+        ];
+        for (id, uid) in uids.iter() {
+            if let Some(uid) = uid {
+                write_element(&mut out, *id, uid.as_bytes());
+            }
+        }
+
+        let names = [
+            (ID_SEGMENTFILENAME, &self.filename),
+            (ID_PREVFILENAME, &self.prev_filename),
+            (ID_NEXTFILENAME, &self.next_filename),
+        ];
+        for (id, name) in names.iter() {
+            if let Some(name) = name {
+                write_element(&mut out, *id, name.as_bytes());
+            }
+        }
+        out
+    }
+}
+
+impl From<&InfoNode> for SegmentLinks {
+    fn from(info: &InfoNode) -> SegmentLinks {
+        SegmentLinks {
+            uid: info.get_segment_uid(),
+            filename: info.get_segment_filename(),
+            prev_uid: info.get_prev_uid(),
+            prev_filename: info.get_prev_filename(),
+            next_uid: info.get_next_uid(),
+            next_filename: info.get_next_filename(),
+        }
+    }
+}
+
+// Where a segment sits on the stitched timeline, in nanoseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkedSegment {
+    pub index: usize,
+    pub start: u64,
+    pub duration: u64,
+}
+
+// Order linked segments by their Prev/NextUUID chain and lay them out end to
+// end. Every segment needs a UID and a duration, and the chain must cover
+// all of them exactly once, or it's a `LinkError::BrokenChain`.
+pub fn resolve_timeline(files: &[WebmFile]) -> Result<Vec<LinkedSegment>, LinkError> {
+    resolve_chain(&files.iter().collect::<Vec<&WebmFile>>())
+}
+
+fn resolve_chain(files: &[&WebmFile]) -> Result<Vec<LinkedSegment>, LinkError> {
+    let mut links = Vec::with_capacity(files.len());
+    let mut durations = Vec::with_capacity(files.len());
+    for file in files {
+        let info = file.root.get_info_nodes().into_iter().next().ok_or(LinkError::BrokenChain)?;
+        let link = SegmentLinks::from(&info);
+        if link.uid.is_none() {
+            return Err(LinkError::BrokenChain);
+        }
+        let duration = info.get_duration().ok_or(LinkError::BrokenChain)?;
+        durations.push((duration * info.get_timestamp_scale() as f64) as u64);
+        links.push(link);
+    }
+
+    let find = |uid: Option<SegmentUid>| {
+        uid.and_then(|uid| links.iter().position(|l| l.uid == Some(uid)))
+    };
+
+    // the head is the one segment whose predecessor isn't in the set
+    let mut heads = (0..links.len()).filter(|&i| find(links[i].prev_uid).is_none());
+    let mut current = heads.next();
+    if heads.next().is_some() {
+        return Err(LinkError::BrokenChain);
+    }
+
+    let mut timeline: Vec<LinkedSegment> = Vec::with_capacity(files.len());
+    let mut start = 0;
+    while let Some(index) = current {
+        if timeline.iter().any(|s| s.index == index) {
+            return Err(LinkError::BrokenChain);
+        }
+        timeline.push(LinkedSegment { index, start, duration: durations[index] });
+        start = start.saturating_add(durations[index]);
+
+        // fall back to matching PrevUUID when NextUUID isn't written
+        current = find(links[index].next_uid).or_else(|| {
+            (0..links.len()).find(|&i| links[i].prev_uid == links[index].uid)
+        });
+    }
+
+    if timeline.len() == files.len() {
+        Ok(timeline)
+    } else {
+        Err(LinkError::BrokenChain)
+    }
+}
+
+// File extensions `SegmentLibrary::open_dir` looks at
+const EXTENSIONS: [&str; 4] = ["webm", "mkv", "mka", "mk3d"];
+
+// The parts of a segment linked release, found by their SegmentUIDs
+#[derive(Debug)]
+pub struct SegmentLibrary {
+    pub files: Vec<LinkedFile>,
+}
+
+#[derive(Debug)]
+pub struct LinkedFile {
+    pub path: PathBuf,
+    pub uid: Option<SegmentUid>,
+    // Info and Chapters only when read by `open_dir`
+    pub document: WebmFile,
+}
+
+// One step of the combined presentation: a range of one of the library's
+// files, in nanoseconds, and where it starts on the combined timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentationPart {
+    pub file: usize,
+    pub start: u64,
+    pub end: u64,
+    pub timeline_start: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    // an ordered chapter plays from a segment that isn't in the library
+    MissingSegment(SegmentUid),
+    // an ordered edition with a chapter that has no usable end
+    InvalidChapters,
+    // the Prev/NextUUID chain doesn't cover every file exactly once, or a
+    // file has no SegmentUUID or Duration to place it with
+    BrokenChain,
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            LinkError::MissingSegment(uid) => write!(f, "linked segment {} not found", uid),
+            LinkError::InvalidChapters => write!(f, "ordered chapter without a valid end"),
+            LinkError::BrokenChain => write!(f, "segments don't form a single chain"),
+        }
+    }
+}
+
+impl SegmentLibrary {
+    pub fn new(files: Vec<LinkedFile>) -> SegmentLibrary {
+        SegmentLibrary { files }
+    }
+
+    // Every WebM and Matroska file directly in `dir`, in name order, with
+    // only Info and Chapters read. Files that don't parse are left out.
+    pub fn open_dir<P: AsRef<Path>>(dir: P) -> Result<SegmentLibrary, IOError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+            if path.is_file() && extension.is_some_and(|e| EXTENSIONS.contains(&e.as_str())) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut files = Vec::new();
+        for path in paths {
+            let reader = BufReader::new(File::open(&path)?);
+            let parsed = WebmReader::new(reader)
+                .keep_elements([ID_INFONODE, ID_CHAPTERSNODE].iter().copied())
+                .parse();
+            if let Ok(document) = parsed {
+                files.push(LinkedFile::new(path, document));
+            }
+        }
+        Ok(SegmentLibrary { files })
+    }
+
+    // Position of the file with this SegmentUUID
+    pub fn find(&self, uid: SegmentUid) -> Option<usize> {
+        self.files.iter().position(|file| file.uid == Some(uid))
+    }
+
+    // The release as one timeline. The first file whose default edition
+    // (or first, without a default) is ordered drives it, pulling chapters
+    // from the other files by ChapterSegmentUUID. Without one the files are
+    // played whole, chained by their Prev/NextUIDs.
+    pub fn presentation(&self) -> Result<Vec<PresentationPart>, LinkError> {
+        for (index, file) in self.files.iter().enumerate() {
+            let edition = match main_edition(&file.document) {
+                Some(edition) if edition.is_ordered() => edition,
+                _ => continue,
+            };
+            let timeline = ordered_timeline(&file.document, &edition).ok_or(LinkError::InvalidChapters)?;
+            return timeline.into_iter()
+                .map(|part| {
+                    let file = match part.segment {
+                        Some(uid) => self.find(uid).ok_or(LinkError::MissingSegment(uid))?,
+                        None => index,
+                    };
+                    Ok(PresentationPart { file, start: part.start, end: part.end, timeline_start: part.timeline_start })
+                })
+                .collect();
+        }
+
+        let documents: Vec<&WebmFile> = self.files.iter().map(|file| &file.document).collect();
+        let segments = resolve_chain(&documents)?;
+        Ok(segments.into_iter()
+            .map(|segment| PresentationPart {
+                file: segment.index,
+                start: 0,
+                end: segment.duration,
+                timeline_start: segment.start,
+            })
+            .collect())
+    }
+}
+
+impl LinkedFile {
+    pub fn new(path: PathBuf, document: WebmFile) -> LinkedFile {
+        let uid = document.root.get_info_nodes().first().and_then(|info| info.get_segment_uid());
+        LinkedFile { path, uid, document }
+    }
+}
+
+// The edition a player starts with
+fn main_edition(document: &WebmFile) -> Option<EditionEntryNode> {
+    let editions: Vec<EditionEntryNode> = document.root.get_chapters()
+        .iter()
+        .flat_map(|chapters| chapters.get_edition_entries())
+        .collect();
+    editions.iter()
+        .find(|edition| edition.is_default())
+        .or_else(|| editions.first())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::chapters::set_chapters;
+    use crate::ebml::{ChaptersNode, Element};
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let a = SegmentUid::generate();
+        let b = SegmentUid::generate();
+        assert_ne!(a, b);
+        assert_ne!(a, SegmentUid([0; 16]));
+    }
+
+    #[test]
+    fn test_read_segment_uid() {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let info = WebmFile::open(f).unwrap().root.get_info_nodes().remove(0);
+        let uid = info.get_segment_uid().unwrap();
+        assert_eq!(uid.to_string(), "46c69d45a185a9294d3d0a2f750056bd");
+        assert!(info.get_segment_families().is_empty());
+        assert_eq!(SegmentUid::from_slice(&[0; 15]), None);
+    }
+
+    fn part(links: &SegmentLinks) -> WebmFile {
+        // Duration of 1000 as a 4 byte float
+        let mut children = vec![0x44, 0x89, 0x84, 0x44, 0x7a, 0x00, 0x00];
+        children.extend(links.to_bytes());
+
+        let mut info = vec![0x15, 0x49, 0xa9, 0x66, 0x80 | children.len() as u8];
+        info.extend(children);
+
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0x01]);
+        data.extend(&(info.len() as u64).to_be_bytes()[1..]);
+        data.extend(info);
+        WebmFile::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn test_links_roundtrip() {
+        let links = SegmentLinks {
+            uid: Some(SegmentUid::generate()),
+            filename: Some("part2.webm".to_string()),
+            prev_uid: Some(SegmentUid::generate()),
+            prev_filename: Some("part1.webm".to_string()),
+            next_uid: None,
+            next_filename: None,
+        };
+        let file = part(&links);
+        let info = file.root.get_info_nodes().remove(0);
+        assert_eq!(SegmentLinks::from(&info), links);
+    }
+
+    #[test]
+    fn test_resolve_timeline() {
+        let uids: Vec<SegmentUid> = (0..3).map(|_| SegmentUid::generate()).collect();
+        let link = |i: usize| SegmentLinks {
+            uid: Some(uids[i]),
+            prev_uid: if i > 0 { Some(uids[i - 1]) } else { None },
+            // leave NextUUID off the middle part to exercise the fallback
+            next_uid: if i == 0 { Some(uids[1]) } else { None },
+            ..SegmentLinks::default()
+        };
+        let files = vec![part(&link(2)), part(&link(0)), part(&link(1))];
+
+        let timeline = resolve_timeline(&files).unwrap();
+        let order: Vec<usize> = timeline.iter().map(|s| s.index).collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        assert_eq!(timeline[2].start, 2_000_000_000);
+
+        // a broken chain can't be stitched
+        assert_eq!(resolve_timeline(&[part(&link(0)), part(&link(2))]).unwrap_err(), LinkError::BrokenChain);
+    }
+
+    #[test]
+    fn test_ordered_presentation() {
+        let (main, opening) = (SegmentUid::generate(), SegmentUid::generate());
+        let atom = |start: u64, end: u64, segment: Option<SegmentUid>| {
+            let mut children = vec![
+                Element::uint(ID_CHAPTERUID, start + 1).into(),
+                Element::uint(ID_CHAPTERTIMESTART, start).into(),
+                Element::uint(ID_CHAPTERTIMEEND, end).into(),
+            ];
+            if let Some(uid) = segment {
+                children.push(Element::binary(ID_CHAPTERSEGMENTUUID, uid.as_bytes()).into());
+            }
+            Element::master(ID_CHAPTERATOMNODE, children)
+        };
+        let mut episode = part(&SegmentLinks { uid: Some(main), ..SegmentLinks::default() });
+        set_chapters(&mut episode, ChaptersNode::from(Element::master(ID_CHAPTERSNODE, vec![
+            Element::master(ID_EDITIONENTRYNODE, vec![
+                Element::uint(ID_EDITIONFLAGORDERED, 1).into(),
+                atom(0, 100, None),
+                atom(0, 90, Some(opening)),
+                atom(100, 1000, None),
+            ]),
+        ])));
+        let opening_file = part(&SegmentLinks { uid: Some(opening), ..SegmentLinks::default() });
+
+        let library = SegmentLibrary::new(vec![
+            LinkedFile::new(PathBuf::from("op.mkv"), opening_file),
+            LinkedFile::new(PathBuf::from("episode.mkv"), episode),
+        ]);
+        let parts: Vec<(usize, u64, u64, u64)> = library.presentation()
+            .unwrap()
+            .iter()
+            .map(|p| (p.file, p.start, p.end, p.timeline_start))
+            .collect();
+        assert_eq!(parts, vec![(1, 0, 100, 0), (0, 0, 90, 100), (1, 100, 1000, 190)]);
+
+        let library = SegmentLibrary::new(library.files.into_iter().skip(1).collect());
+        assert_eq!(library.presentation(), Err(LinkError::MissingSegment(opening)));
+    }
+
+    #[test]
+    fn test_open_dir() {
+        let dir = std::env::temp_dir().join(format!("libwebm-rs-linking-{}", SegmentUid::generate()));
+        fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (SegmentUid::generate(), SegmentUid::generate());
+        let parts = [
+            ("b.webm", SegmentLinks { uid: Some(second), prev_uid: Some(first), ..SegmentLinks::default() }),
+            ("a.webm", SegmentLinks { uid: Some(first), next_uid: Some(second), ..SegmentLinks::default() }),
+        ];
+        for (name, links) in parts.iter() {
+            let mut out = Cursor::new(Vec::new());
+            part(links).write_to(&mut out).unwrap();
+            fs::write(dir.join(name), out.into_inner()).unwrap();
+        }
+        fs::write(dir.join("c.webm"), b"not a webm file").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let library = SegmentLibrary::open_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(library.files.len(), 2);
+        assert_eq!(library.find(second), Some(1));
+        assert_eq!(library.files[0].path.file_name().unwrap(), "a.webm");
+
+        let presentation = library.presentation().unwrap();
+        assert_eq!(presentation[1], PresentationPart {
+            file: 1,
+            start: 0,
+            end: 1_000_000_000,
+            timeline_start: 1_000_000_000,
+        });
+    }
+}
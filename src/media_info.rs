@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use crate::demux::Track;
+use crate::ebml::WebmFile;
+use crate::ogg::opus_packet_samples;
+
+// ffprobe-style summary of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    pub doc_type: String,
+    // in seconds, from Info or estimated from the last packet
+    pub duration: Option<f64>,
+    // bits per second over the whole Segment
+    pub bitrate: Option<u64>,
+    pub tracks: Vec<TrackInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+    pub track: Track,
+    // language with the spec default ("eng") applied
+    pub language: String,
+    pub frame_rate: Option<f64>,
+    // estimated from the payload size of the track's packets
+    pub bitrate: Option<u64>,
+    pub packet_count: u64,
+    // in seconds, from the track's first sample to the end of its last
+    // packet, less CodecDelay and the last packet's DiscardPadding
+    pub duration: Option<f64>,
+}
+
+// What `WebmReader::probe` finds before the media data, enough to check
+// an upload without reading all of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub doc_type: String,
+    // from Info, rounded to the nearest millisecond
+    pub duration_ms: Option<u64>,
+    pub tracks: Vec<Track>,
+}
+
+impl MediaInfo {
+    pub fn new(document: &WebmFile) -> MediaInfo {
+        let info = document.root.get_info_nodes().into_iter().next();
+        let scale = info.as_ref().map(|i| i.get_timestamp_scale()).unwrap_or(1_000_000);
+
+        // one pass over the packets for sizes, counts and the last timestamp
+        let mut bytes: HashMap<u64, u64> = HashMap::new();
+        let mut counts: HashMap<u64, u64> = HashMap::new();
+        let mut end = 0;
+        let mut last = HashMap::new();
+        for packet in document.packets() {
+            *bytes.entry(packet.track).or_insert(0) += packet.data.len() as u64;
+            *counts.entry(packet.track).or_insert(0) += 1;
+            end = end.max(packet.timestamp.saturating_add(packet.duration.unwrap_or(0)));
+            let samples = opus_packet_samples(&packet.data);
+            last.insert(packet.track, (packet.timestamp, packet.duration, samples, packet.discard_padding));
+        }
+
+        let duration = info.as_ref()
+            .and_then(|i| i.get_duration())
+            .or(if end > 0 { Some(end as f64) } else { None })
+            .map(|ticks| ticks * scale as f64 / 1e9);
+
+        let per_second = |total: u64| match duration {
+            Some(d) if d > 0.0 => Some((total as f64 * 8.0 / d) as u64),
+            _ => None,
+        };
+
+        let tracks = document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .map(|entry| {
+                let track = Track::from(&entry);
+                let packet_count = counts.get(&track.number).cloned().unwrap_or(0);
+                // prefer the declared frame duration, fall back to counting
+                let frame_rate = match (track.track_type, track.codec.default_duration, duration) {
+                    (1, Some(d), _) if d > 0 => Some(1e9 / d as f64),
+                    (1, None, Some(d)) if d > 0.0 => Some(packet_count as f64 / d),
+                    _ => None,
+                };
+
+                // the last packet lasts for its BlockDuration, DefaultDuration
+                // or, for Opus, the samples it holds
+                let duration = last.get(&track.number).map(|&(timestamp, block_duration, samples, padding)| {
+                    let length = match (block_duration, track.codec.default_duration) {
+                        (Some(ticks), _) => ticks.saturating_mul(scale),
+                        (None, Some(nanoseconds)) => nanoseconds,
+                        (None, None) if track.codec.codec_id == "A_OPUS" => {
+                            samples.map(|s| s * 1_000_000_000 / 48_000).unwrap_or(0)
+                        },
+                        (None, None) => 0,
+                    };
+                    let end = timestamp.saturating_mul(scale)
+                        .saturating_add(length)
+                        .saturating_sub(padding.unwrap_or(0).max(0) as u64)
+                        .saturating_sub(track.codec.codec_delay);
+                    end as f64 / 1e9
+                });
+
+                TrackInfo {
+                    language: track.language.clone().unwrap_or_else(|| "eng".to_string()),
+                    frame_rate,
+                    bitrate: per_second(bytes.get(&track.number).cloned().unwrap_or(0)),
+                    packet_count,
+                    duration,
+                    track,
+                }
+            })
+            .collect();
+
+        let segment = document.root.get_element();
+        MediaInfo {
+            doc_type: document.header.get_doc_type(),
+            duration,
+            bitrate: if segment.is_unknown_size() { None } else { per_second(segment.size) },
+            tracks,
+        }
+    }
+}
+
+impl MediaInfo {
+    // MIME type with an RFC 6381 codecs parameter, as used by MSE's
+    // isTypeSupported() and DASH/HLS manifests. The parameter is left out
+    // if any track's codec has no known codec string, as a partial list
+    // would claim fewer codecs than the file needs.
+    pub fn mime_type(&self) -> String {
+        let has_video = self.tracks.iter().any(|t| t.track.track_type == 1);
+        let container = match (self.doc_type.as_str(), has_video) {
+            ("webm", true) => "video/webm",
+            ("webm", false) => "audio/webm",
+            (_, true) => "video/x-matroska",
+            (_, false) => "audio/x-matroska",
+        };
+
+        let codecs: Option<Vec<String>> = self.tracks.iter()
+            .filter(|t| t.track.track_type == 1 || t.track.track_type == 2)
+            .map(|t| t.codec_string())
+            .collect();
+        match codecs {
+            Some(codecs) if !codecs.is_empty() => format!("{}; codecs=\"{}\"", container, codecs.join(",")),
+            _ => container.to_string(),
+        }
+    }
+}
+
+impl TrackInfo {
+    // The RFC 6381 codec identifier for the track. None for codecs without
+    // one, and for AV1 without the CodecPrivate its identifier is built from.
+    pub fn codec_string(&self) -> Option<String> {
+        let private = self.track.codec.codec_private.as_deref();
+        match self.track.codec.codec_id.as_str() {
+            "V_VP8" => Some("vp8".to_string()),
+            // plain "vp9" is valid in WebM when the details aren't known
+            "V_VP9" => Some(private.and_then(vp9_codec_string).unwrap_or_else(|| "vp9".to_string())),
+            "V_AV1" => private.and_then(av1_codec_string),
+            "A_OPUS" => Some("opus".to_string()),
+            "A_VORBIS" => Some("vorbis".to_string()),
+            "A_FLAC" => Some("flac".to_string()),
+            _ => None,
+        }
+    }
+}
+
+// VP9 CodecPrivate is a list of (id, length, value) features
+fn vp9_codec_string(private: &[u8]) -> Option<String> {
+    let (mut profile, mut level, mut bit_depth, mut chroma) = (None, None, None, None);
+    let mut pos = 0;
+    while pos + 2 <= private.len() {
+        let (id, len) = (private[pos], private[pos + 1] as usize);
+        let value = *private.get(pos + 2..pos + 2 + len)?.first()?;
+        match id {
+            1 => profile = Some(value),
+            2 => level = Some(value),
+            3 => bit_depth = Some(value),
+            4 => chroma = Some(value),
+            _ => (),
+        }
+        pos += 2 + len;
+    }
+
+    let mut codec = format!("vp09.{:02}.{:02}.{:02}", profile?, level?, bit_depth?);
+    if let Some(chroma) = chroma {
+        codec.push_str(&format!(".{:02}", chroma));
+    }
+    Some(codec)
+}
+
+// AV1 CodecPrivate is an AV1CodecConfigurationRecord
+fn av1_codec_string(private: &[u8]) -> Option<String> {
+    if private.len() < 4 || private[0] != 0x81 {
+        return None;
+    }
+    let profile = private[1] >> 5;
+    let level = private[1] & 0x1f;
+    let tier = if private[2] & 0x80 == 0x80 { 'H' } else { 'M' };
+    let bit_depth = match (private[2] & 0x40 == 0x40, private[2] & 0x20 == 0x20) {
+        (true, true) => 12,
+        (true, false) => 10,
+        _ => 8,
+    };
+    Some(format!("av01.{}.{:02}{}.{:02}", profile, level, tier, bit_depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    #[test]
+    fn test_media_info() {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let info = WebmFile::open(f).unwrap().media_info();
+
+        assert_eq!(info.doc_type, "webm");
+        assert_eq!(info.duration, Some(32.48));
+        assert_eq!(info.tracks.len(), 2);
+
+        let video = &info.tracks[0];
+        assert_eq!(video.frame_rate, Some(25.0));
+        assert_eq!(video.packet_count, 812);
+        assert_eq!(video.language, "eng");
+        assert_eq!(info.tracks[1].frame_rate, None);
+        assert_eq!(video.duration, Some(32.48));
+
+        let total: u64 = info.tracks.iter().map(|t| t.bitrate.unwrap()).sum();
+        assert!(total < info.bitrate.unwrap());
+    }
+
+    #[test]
+    fn test_track_duration() {
+        // three 20ms packets, 6.5ms of delay and 10ms of padding
+        let data = WebmBuilder::new()
+            .audio_track(1, "A_OPUS", 48000.0, 2)
+            .codec_delay(6_500_000)
+            .frame(1, 0, true, &[0xf8])
+            .frame(1, 20, true, &[0xf8])
+            .frame(1, 40, true, &[0xf8])
+            .discard_padding(10_000_000)
+            .build();
+        let info = WebmFile::from_bytes(&data).unwrap().media_info();
+        assert_eq!(info.tracks[0].duration, Some(0.0435));
+    }
+
+    #[test]
+    fn test_mime_type() {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let info = WebmFile::open(f).unwrap().media_info();
+        assert_eq!(info.mime_type(), "video/webm; codecs=\"vp8,vorbis\"");
+
+        // no made up codec strings
+        let data = WebmBuilder::new()
+            .doc_type("matroska")
+            .video_track(1, "V_MPEG4/ISO/AVC", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .build();
+        let info = WebmFile::from_bytes(&data).unwrap().media_info();
+        assert_eq!(info.tracks[0].codec_string(), None);
+        assert_eq!(info.tracks[1].codec_string(), Some("opus".to_string()));
+        assert_eq!(info.mime_type(), "video/x-matroska");
+    }
+
+    #[test]
+    fn test_vp9_codec_string() {
+        assert_eq!(
+            vp9_codec_string(&[1, 1, 0, 2, 1, 10, 3, 1, 8]),
+            Some("vp09.00.10.08".to_string())
+        );
+        assert_eq!(
+            vp9_codec_string(&[1, 1, 2, 2, 1, 41, 3, 1, 10, 4, 1, 1]),
+            Some("vp09.02.41.10.01".to_string())
+        );
+        assert_eq!(vp9_codec_string(&[1, 1, 0]), None);
+    }
+
+    #[test]
+    fn test_av1_codec_string() {
+        assert_eq!(
+            av1_codec_string(&[0x81, 0x08, 0x0c, 0x00]),
+            Some("av01.0.08M.08".to_string())
+        );
+        assert_eq!(
+            av1_codec_string(&[0x81, 0x2d, 0xc0, 0x00]),
+            Some("av01.1.13H.10".to_string())
+        );
+        assert_eq!(av1_codec_string(&[0x00, 0x08]), None);
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use crate::block::{block_of, BlockEntry};
+use crate::consts::*;
+use crate::ebml::{ClusterNode, Element, Node, WebmFile};
+use crate::ebml::encode::write_element_id;
+use crate::vint;
+
+// Splits a document into self-contained media segments for streaming (MSE,
+// DASH, low-latency HTTP). Each segment starts on a video keyframe (any
+// keyframe for files without video) and runs for at least the target
+// duration. A segment is sent either as its byte range in the original
+// file, when it lines up with whole Clusters, or as rewritten Clusters that
+// only hold its blocks. Either way it goes after `init_segment`.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSegment {
+    // timestamp of the first block and time until the next segment, both
+    // in TimestampScale units
+    pub timestamp: u64,
+    pub duration: u64,
+    // file offsets of the original Clusters, None when the segment starts
+    // or ends part way through a Cluster, or one has an unknown size
+    pub byte_range: Option<Range<u64>>,
+    pub clusters: Vec<ClusterNode>,
+}
+
+impl MediaSegment {
+    // The segment's Clusters, encoded with known sizes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.clusters.iter().flat_map(|c| c.as_node().to_bytes()).collect()
+    }
+}
+
+// The EBML header, the start of a Segment of unknown size and its Info and
+// Tracks, which media segments can then be appended to
+pub fn init_segment(document: &WebmFile) -> Vec<u8> {
+    let mut out = document.header.as_node().to_bytes();
+    write_element_id(&mut out, ID_SEGMENTNODE);
+    vint::write_unknown_size(&mut out);
+    for child in document.root.get_children() {
+        if matches!(child.get_element().id, ID_INFONODE | ID_TRACKSNODE) {
+            out.extend(child.to_bytes());
+        }
+    }
+    out
+}
+
+pub struct MediaSegments {
+    segments: VecDeque<MediaSegment>,
+}
+
+impl MediaSegments {
+    // `target_duration` is in nanoseconds
+    pub fn new(document: &WebmFile, target_duration: u64) -> MediaSegments {
+        let scale = document.root.get_info_nodes()
+            .first()
+            .map(|info| info.get_timestamp_scale())
+            .unwrap_or(1_000_000)
+            .max(1);
+        let target = target_duration.div_ceil(scale).max(1);
+        let video = document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .find(|entry| entry.get_track_type() == 1)
+            .map(|entry| entry.get_track_number());
+
+        let mut builder = Builder::default();
+        for cluster in document.root.get_clusters() {
+            builder.add_cluster(&cluster, video, target);
+        }
+        builder.finish();
+
+        MediaSegments {
+            segments: builder.segments.into(),
+        }
+    }
+}
+
+impl Iterator for MediaSegments {
+    type Item = MediaSegment;
+
+    fn next(&mut self) -> Option<MediaSegment> {
+        self.segments.pop_front()
+    }
+}
+
+// The blocks of one original Cluster that fall in the current segment
+struct Part {
+    cluster: Element,
+    timestamp: u64,
+    blocks: Vec<Node>,
+    // whether the part starts with the Cluster's first block
+    from_start: bool,
+}
+
+impl Part {
+    fn new(cluster: &ClusterNode, from_start: bool) -> Part {
+        Part {
+            cluster: cluster.get_element(),
+            timestamp: cluster.get_timestamp(),
+            blocks: Vec::new(),
+            from_start,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    segments: Vec<MediaSegment>,
+    // start of the open segment and its finished parts, each with whether
+    // it also reached the end of its Cluster
+    start: Option<u64>,
+    parts: Vec<(Part, bool)>,
+}
+
+impl Builder {
+    fn add_cluster(&mut self, cluster: &ClusterNode, video: Option<u64>, target: u64) {
+        let cluster_timestamp = cluster.get_timestamp();
+        let mut part = Part::new(cluster, true);
+        for node in cluster.get_children() {
+            let BlockEntry { block, keyframe, .. } = match block_of(&node) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let timestamp = block.timestamp(cluster_timestamp);
+            let boundary = keyframe && video.is_none_or(|track| track == block.track);
+            let due = self.start.is_none_or(|start| timestamp >= start.saturating_add(target));
+            if boundary && due {
+                let from_start = part.blocks.is_empty() && part.from_start;
+                if !part.blocks.is_empty() {
+                    self.parts.push((part, false));
+                }
+                self.close(Some(timestamp));
+                self.start = Some(timestamp);
+                part = Part::new(cluster, from_start);
+            }
+            if self.start.is_none() {
+                self.start = Some(timestamp);
+            }
+            part.blocks.push(node);
+        }
+        if !part.blocks.is_empty() {
+            self.parts.push((part, true));
+        }
+    }
+
+    fn finish(&mut self) {
+        self.close(None);
+    }
+
+    // End the open segment, where the next one starts at `next`
+    fn close(&mut self, next: Option<u64>) {
+        let (start, parts) = match self.start.take() {
+            Some(start) if !self.parts.is_empty() => (start, std::mem::take(&mut self.parts)),
+            _ => return,
+        };
+
+        let whole = parts.iter()
+            .all(|(part, to_end)| part.from_start && *to_end && !part.cluster.is_unknown_size());
+        let byte_range = match (parts.first(), parts.last()) {
+            (Some((first, _)), Some((last, _))) if whole => {
+                Some(first.cluster.offset..last.cluster.end_offset())
+            },
+            _ => None,
+        };
+
+        let mut last_timestamp = start;
+        let clusters = parts.into_iter()
+            .map(|(part, _)| {
+                for entry in part.blocks.iter().filter_map(block_of) {
+                    last_timestamp = last_timestamp.max(entry.block.timestamp(part.timestamp));
+                }
+                let mut children = vec![Node::from(Element::uint(ID_TIMESTAMP, part.timestamp))];
+                children.extend(part.blocks);
+                ClusterNode::from(Element::master(ID_CLUSTERNODE, children))
+            })
+            .collect();
+
+        self.segments.push(MediaSegment {
+            timestamp: start,
+            duration: next.unwrap_or(last_timestamp).saturating_sub(start),
+            byte_range,
+            clusters,
+        });
+    }
+}
+
+// The Block in a SimpleBlock or BlockGroup and whether it's a keyframe
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    // a keyframe every 500ms in clusters of one second, with audio
+    fn document() -> (Vec<u8>, WebmFile) {
+        let mut builder = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2);
+        for second in 0..4 {
+            builder = builder.cluster(second * 1000);
+            for i in 0..10 {
+                builder = builder.frame(1, i * 100, i % 5 == 0, &[i as u8; 8])
+                    .frame(2, i * 100 + 50, true, &[0xf8]);
+            }
+        }
+        let data = builder.build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        (data, document)
+    }
+
+    #[test]
+    fn test_whole_clusters() {
+        let (data, document) = document();
+        let segments: Vec<_> = MediaSegments::new(&document, 2_000_000_000).collect();
+        let starts: Vec<u64> = segments.iter().map(|s| s.timestamp).collect();
+        assert_eq!(starts, vec![0, 2000]);
+        assert_eq!(segments[0].duration, 2000);
+
+        // these line up with the original Clusters, so byte ranges work
+        let clusters = document.root.get_clusters();
+        let range = segments[1].byte_range.clone().unwrap();
+        assert_eq!(range.start, clusters[2].get_element().offset);
+        assert_eq!(range.end, clusters[3].get_element().end_offset());
+        assert_eq!(&data[range.start as usize..range.end as usize], &segments[1].to_bytes()[..]);
+    }
+
+    #[test]
+    fn test_split_clusters() {
+        let (_, document) = document();
+        let segments: Vec<_> = MediaSegments::new(&document, 700_000_000).collect();
+        let starts: Vec<u64> = segments.iter().map(|s| s.timestamp).collect();
+        assert_eq!(starts, vec![0, 1000, 2000, 3000]);
+
+        let segments: Vec<_> = MediaSegments::new(&document, 500_000_000).collect();
+        assert_eq!(segments.len(), 8);
+        assert_eq!(segments[1].timestamp, 500);
+        assert!(segments[0].byte_range.is_none());
+        assert_eq!(segments[7].duration, 450);
+
+        // the init segment and every media segment play back as the original
+        let mut stream = init_segment(&document);
+        for segment in &segments {
+            assert_eq!(segment.clusters[0].get_children().len(), 11);
+            stream.extend(segment.to_bytes());
+        }
+        let rebuilt = WebmFile::from_bytes(&stream).unwrap();
+        let packets = |document: &WebmFile| -> Vec<(u64, u64, Vec<u8>)> {
+            document.packets().map(|p| (p.track, p.timestamp, p.data)).collect()
+        };
+        assert_eq!(packets(&rebuilt), packets(&document));
+        assert_eq!(rebuilt.root.get_tracks(), document.root.get_tracks());
+    }
+}
@@ -0,0 +1,999 @@
+use std::io::{Seek, SeekFrom, Write, Error as IOError};
+use crate::block::Packet;
+use crate::checksum::crc32;
+use crate::consts::*;
+use crate::demux::Track;
+use crate::ebml::{Element, Node};
+use crate::ebml::encode::write_element_id;
+use crate::error::invalid_input;
+use crate::inplace::{void, void_header};
+use crate::linking::SegmentUid;
+use crate::vint;
+
+// A streaming WebM writer for live output (recorders, WebRTC, MediaRecorder
+// style uploads). The Segment has an unknown size and each Cluster is held
+// in memory until it's closed, so nothing written needs patching later and
+// `W` only has to implement Write.
+//
+//     let mut muxer = Muxer::new(out);
+//     muxer.add_track(track)?;
+//     muxer.write_packet(&packet)?;
+//     muxer.flush()?;
+//
+// The output is WebM unless `matroska` is used, which allows any codec and
+// attachments.
+//
+// Writers that can seek can also reserve room for a SeekHead and Cues up
+// front with `cues_placement`, filled in by `finish_seekable`.
+pub struct Muxer<W: Write> {
+    out: W,
+    timestamp_scale: u64,
+    max_cluster_duration: u64,
+    muxing_app: String,
+    writing_app: String,
+    segment_uid: Option<SegmentUid>,
+    cue_interval: Option<u64>,
+    cues_placement: Option<CuesPlacement>,
+    crc32: bool,
+    ebml_header: EbmlHeader,
+    tracks: Vec<Track>,
+    attachments: Vec<Attachment>,
+    started: bool,
+    cluster: Option<OpenCluster>,
+    // timestamp of the previous block on each track, for ReferenceBlock
+    previous: Vec<(u64, u64)>,
+    // bytes written before the Segment's data, and the (timestamp, track,
+    // Cluster position) of each CuePoint so far
+    segment_data_start: u64,
+    cue_points: Vec<(u64, u64, u64)>,
+    // positions in the Segment data of what `finish_seekable` fills in
+    layout: Layout,
+    stats: MuxerStats,
+    on_progress: Option<ProgressCallback>,
+}
+
+// Running totals for recording UIs, see `Muxer::on_progress`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MuxerStats {
+    pub tracks: Vec<TrackStats>,
+    // Clusters written out so far
+    pub clusters: u64,
+    // all bytes written to `W`, headers included
+    pub bytes: u64,
+    // nanoseconds up to the end of the latest packet
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackStats {
+    pub track: u64,
+    pub frames: u64,
+    // frame payload bytes
+    pub bytes: u64,
+}
+
+// Codecs WebM allows, anything else needs `Muxer::matroska`
+const WEBM_CODECS: [&str; 10] = [
+    "V_VP8", "V_VP9", "V_AV1", "A_VORBIS", "A_OPUS", "S_TEXT/WEBVTT",
+    "D_WEBVTT/SUBTITLES", "D_WEBVTT/CAPTIONS", "D_WEBVTT/DESCRIPTIONS", "D_WEBVTT/METADATA",
+];
+
+// A file stored in a Matroska Segment, e.g. a font for subtitles
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub media_type: String,
+    pub description: Option<String>,
+    pub data: Vec<u8>,
+    // unique and non-zero
+    pub uid: u64,
+}
+
+// The fields of the EBML header that describe the document. Everything is
+// written with IDs of up to 4 bytes and sizes of up to 8, so the maximum
+// lengths can't be lower than that.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EbmlHeader {
+    pub doc_type: String,
+    pub doc_type_version: u64,
+    pub doc_type_read_version: u64,
+    pub max_id_length: u64,
+    pub max_size_length: u64,
+}
+
+impl Default for EbmlHeader {
+    fn default() -> EbmlHeader {
+        EbmlHeader {
+            doc_type: "webm".to_string(),
+            doc_type_version: 4,
+            doc_type_read_version: 2,
+            max_id_length: 4,
+            max_size_length: 8,
+        }
+    }
+}
+
+impl EbmlHeader {
+    pub fn matroska() -> EbmlHeader {
+        EbmlHeader { doc_type: "matroska".to_string(), ..EbmlHeader::default() }
+    }
+
+    pub fn to_node(&self) -> Node {
+        Element::master(ID_EBMLHEADERNODE, vec![
+            Element::uint(ID_EBMLVERSION, 1).into(),
+            Element::uint(ID_EBMLREADVERSION, 1).into(),
+            Element::uint(ID_EBMLMAXIDLENGTH, self.max_id_length).into(),
+            Element::uint(ID_EBMLMAXSIZELENGTH, self.max_size_length).into(),
+            Element::string(ID_DOCTYPE, &self.doc_type).into(),
+            Element::uint(ID_DOCTYPEVERSION, self.doc_type_version).into(),
+            Element::uint(ID_DOCTYPEREADVERSION, self.doc_type_read_version).into(),
+        ])
+    }
+}
+
+// Where `finish_seekable` puts the Cues. In front is better for
+// progressive playback, since players find the index without a seek to the
+// end; like mkvmerge, they go at the end anyway when they outgrow the
+// reserved space.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CuesPlacement {
+    #[default]
+    End,
+    // in a Void of this many bytes after Tracks
+    Front { reserved: u64 },
+}
+
+// Room for a SeekHead with Info, Tracks, Attachments and Cues entries and
+// their largest positions, plus a Void
+const SEEK_HEAD_SPACE: u64 = 100;
+
+#[derive(Default)]
+struct Layout {
+    seek_head: Option<u64>,
+    info: u64,
+    tracks: u64,
+    attachments: Option<u64>,
+    // position and length
+    cues: Option<(u64, u64)>,
+}
+
+type ProgressCallback = Box<dyn FnMut(&MuxerStats) + Send>;
+
+struct OpenCluster {
+    timestamp: u64,
+    blocks: Vec<Node>,
+}
+
+impl<W: Write> Muxer<W> {
+    pub fn new(out: W) -> Muxer<W> {
+        Muxer {
+            out,
+            timestamp_scale: 1_000_000,
+            max_cluster_duration: 5_000_000_000,
+            muxing_app: "libwebm-rs".to_string(),
+            writing_app: "libwebm-rs".to_string(),
+            segment_uid: Some(SegmentUid::generate()),
+            cue_interval: None,
+            cues_placement: None,
+            crc32: false,
+            ebml_header: EbmlHeader::default(),
+            tracks: Vec::new(),
+            attachments: Vec::new(),
+            started: false,
+            cluster: None,
+            previous: Vec::new(),
+            segment_data_start: 0,
+            cue_points: Vec::new(),
+            layout: Layout::default(),
+            stats: MuxerStats::default(),
+            on_progress: None,
+        }
+    }
+
+    // Nanoseconds per timestamp tick, 1ms by default
+    pub fn timestamp_scale(mut self, scale: u64) -> Self {
+        self.timestamp_scale = scale.max(1);
+        self
+    }
+
+    // Start a new Cluster on the first keyframe after this many nanoseconds,
+    // 5 seconds by default
+    pub fn max_cluster_duration(mut self, duration: u64) -> Self {
+        self.max_cluster_duration = duration;
+        self
+    }
+
+    // Info's MuxingApp and WritingApp, both "libwebm-rs" by default
+    pub fn muxing_app(mut self, name: &str) -> Self {
+        self.muxing_app = name.to_string();
+        self
+    }
+
+    pub fn writing_app(mut self, name: &str) -> Self {
+        self.writing_app = name.to_string();
+        self
+    }
+
+    // Info's SegmentUUID, a fresh random one by default so the output can be
+    // linked to, see the `linking` module. None leaves it out.
+    pub fn segment_uid(mut self, uid: Option<SegmentUid>) -> Self {
+        self.segment_uid = uid;
+        self
+    }
+
+    // Write Cues when finishing, with a CuePoint at least every `interval`
+    // nanoseconds. Each one starts a new Cluster, on a keyframe of the
+    // video track or, in audio-only files, on any block, so long podcasts
+    // stay seekable without video keyframes to go by.
+    pub fn cue_interval(mut self, interval: u64) -> Self {
+        self.cue_interval = Some(interval);
+        self
+    }
+
+    // DocType, versions and maximum lengths for the EBML header, a WebM
+    // header by default
+    pub fn ebml_header(mut self, header: EbmlHeader) -> Self {
+        self.ebml_header = header;
+        self
+    }
+
+    // Write Matroska instead of WebM, see `EbmlHeader::matroska`
+    pub fn matroska(self) -> Self {
+        self.ebml_header(EbmlHeader::matroska())
+    }
+
+    // Reserve space for a SeekHead, and for the Cues if they go in front,
+    // ahead of the first Cluster. Without `finish_seekable` it's left as
+    // Voids.
+    pub fn cues_placement(mut self, placement: CuesPlacement) -> Self {
+        self.cues_placement = Some(placement);
+        self
+    }
+
+    // Start every Cluster and the other top level masters with a CRC-32 of
+    // their data, as mkvmerge does, so archived files can be checked for
+    // corruption element by element
+    pub fn crc32(mut self) -> Self {
+        self.crc32 = true;
+        self
+    }
+
+    // Called with the totals each time a Cluster is written out
+    pub fn on_progress(mut self, callback: impl FnMut(&MuxerStats) + Send + 'static) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    // Totals so far, counting packets that are still in the open Cluster
+    pub fn stats(&self) -> &MuxerStats {
+        &self.stats
+    }
+
+    // Tracks can only be added before the first packet
+    pub fn add_track(&mut self, track: Track) -> Result<(), IOError> {
+        if self.started {
+            return Err(invalid_input("tracks can't be added once writing has started"));
+        }
+        if track.number == 0 || self.tracks.iter().any(|t| t.number == track.number) {
+            return Err(invalid_input("track numbers must be unique and non-zero"));
+        }
+        if self.is_webm() && !WEBM_CODECS.contains(&track.codec.codec_id.as_str()) {
+            return Err(invalid_input("codec not allowed in WebM, write Matroska instead"));
+        }
+        if track.codec.projection.as_ref().is_some_and(|p| !p.is_valid()) {
+            return Err(invalid_input("projection out of range"));
+        }
+        self.stats.tracks.push(TrackStats { track: track.number, ..TrackStats::default() });
+        self.tracks.push(track);
+        Ok(())
+    }
+
+    // Attachments are Matroska only and, like tracks, can only be added
+    // before the first packet
+    pub fn add_attachment(&mut self, attachment: Attachment) -> Result<(), IOError> {
+        if self.started {
+            return Err(invalid_input("attachments can't be added once writing has started"));
+        }
+        if self.is_webm() {
+            return Err(invalid_input("WebM doesn't allow attachments, write Matroska instead"));
+        }
+        if attachment.uid == 0 || self.attachments.iter().any(|a| a.uid == attachment.uid) {
+            return Err(invalid_input("attachment UIDs must be unique and non-zero"));
+        }
+        self.attachments.push(attachment);
+        Ok(())
+    }
+
+    // Add a frame, with its timestamp in TimestampScale units. Packets must
+    // come in timestamp order, or close to it across tracks. A Duration or
+    // DiscardPadding makes it a BlockGroup, otherwise it's a SimpleBlock.
+    pub fn write_packet(&mut self, packet: &Packet) -> Result<(), IOError> {
+        if !self.tracks.iter().any(|t| t.number == packet.track) {
+            return Err(invalid_input("packet for an unknown track"));
+        }
+        self.start()?;
+
+        let video = self.tracks.iter().find(|t| t.track_type == 1).map(|t| t.number);
+        let boundary = packet.keyframe && video.is_none_or(|track| track == packet.track);
+        let max_ticks = self.max_cluster_duration / self.timestamp_scale;
+        let cue_due = boundary && self.cue_interval.is_some_and(|interval| {
+            let ticks = interval / self.timestamp_scale;
+            self.cue_points.last().is_none_or(|&(last, _, _)| packet.timestamp >= last.saturating_add(ticks))
+        });
+        let new_cluster = match &self.cluster {
+            Some(cluster) => {
+                let relative = packet.timestamp as i64 - cluster.timestamp as i64;
+                relative < i16::MIN as i64
+                    || relative > i16::MAX as i64
+                    || cue_due
+                    || (boundary && packet.timestamp >= cluster.timestamp.saturating_add(max_ticks))
+            },
+            None => true,
+        };
+        if new_cluster {
+            self.close_cluster()?;
+            self.cluster = Some(OpenCluster { timestamp: packet.timestamp, blocks: Vec::new() });
+            if cue_due {
+                let position = self.stats.bytes - self.segment_data_start;
+                self.cue_points.push((packet.timestamp, packet.track, position));
+            }
+        }
+
+        let previous = self.previous.iter().find(|(t, _)| *t == packet.track).map(|(_, ts)| *ts);
+        let cluster = self.cluster.as_mut().unwrap();
+        let timecode = (packet.timestamp as i64 - cluster.timestamp as i64) as i16;
+        cluster.blocks.push(block_node(packet, timecode, previous));
+        self.previous.retain(|(t, _)| *t != packet.track);
+        self.previous.push((packet.track, packet.timestamp));
+
+        if let Some(stats) = self.stats.tracks.iter_mut().find(|t| t.track == packet.track) {
+            stats.frames += 1;
+            stats.bytes += packet.data.len() as u64;
+        }
+        let end = packet.timestamp.saturating_add(packet.duration.unwrap_or(0));
+        self.stats.duration = self.stats.duration.max(end.saturating_mul(self.timestamp_scale));
+        Ok(())
+    }
+
+    // Write out the open Cluster and flush `W`. Everything written so far is
+    // then a complete, playable prefix of the stream, so each flush can be
+    // sent on as one HTTP chunk or WebSocket message.
+    pub fn flush(&mut self) -> Result<(), IOError> {
+        self.start()?;
+        self.close_cluster()?;
+        self.out.flush()
+    }
+
+    // Flush, write the Cues if there are any and hand back the writer
+    pub fn finish(mut self) -> Result<W, IOError> {
+        self.flush()?;
+        if let Some(cues) = self.cues() {
+            self.write(&cues)?;
+            self.out.flush()?;
+        }
+        Ok(self.out)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    // The EBML header, the Segment header, Info and Tracks
+    fn start(&mut self) -> Result<(), IOError> {
+        if self.started {
+            return Ok(());
+        }
+        let header = &self.ebml_header;
+        if header.max_id_length < 4 || header.max_size_length < 8 {
+            return Err(invalid_input("EBMLMaxIDLength must be at least 4 and EBMLMaxSizeLength at least 8"));
+        }
+        if header.doc_type_read_version > header.doc_type_version {
+            return Err(invalid_input("DocTypeReadVersion is newer than DocTypeVersion"));
+        }
+        self.started = true;
+
+        let mut out = header.to_node().to_bytes();
+        write_element_id(&mut out, ID_SEGMENTNODE);
+        vint::write_unknown_size(&mut out);
+        self.segment_data_start = out.len() as u64;
+
+        if self.cues_placement.is_some() {
+            self.layout.seek_head = Some(out.len() as u64 - self.segment_data_start);
+            out.extend(void(SEEK_HEAD_SPACE));
+        }
+        self.layout.info = out.len() as u64 - self.segment_data_start;
+        let mut info: Vec<Node> = Vec::new();
+        if let Some(uid) = &self.segment_uid {
+            info.push(Element::binary(ID_SEGMENTUUID, uid.as_bytes()).into());
+        }
+        info.extend([
+            Node::from(Element::uint(ID_TIMESTAMPSCALE, self.timestamp_scale)),
+            Element::string(ID_MUXINGAPP, &self.muxing_app).into(),
+            Element::string(ID_WRITINGAPP, &self.writing_app).into(),
+        ]);
+        out.extend(self.master(ID_INFONODE, info));
+        self.layout.tracks = out.len() as u64 - self.segment_data_start;
+        let entries = self.tracks.iter().map(track_entry).collect();
+        out.extend(self.master(ID_TRACKSNODE, entries));
+        if !self.attachments.is_empty() {
+            self.layout.attachments = Some(out.len() as u64 - self.segment_data_start);
+            let files = self.attachments.iter().map(attached_file).collect();
+            out.extend(self.master(ID_ATTACHMENTSNODE, files));
+        }
+        if let Some(CuesPlacement::Front { reserved }) = self.cues_placement {
+            let reserved = reserved.max(2);
+            self.layout.cues = Some((out.len() as u64 - self.segment_data_start, reserved));
+            out.extend(void(reserved));
+        }
+        self.write(&out)
+    }
+
+    fn is_webm(&self) -> bool {
+        self.ebml_header.doc_type == "webm"
+    }
+
+    // The Cues for the CuePoints so far, if there are any
+    fn cues(&self) -> Option<Vec<u8>> {
+        if self.cue_points.is_empty() {
+            return None;
+        }
+        let points = self.cue_points.iter()
+            .map(|&(timestamp, track, position)| Element::master(ID_CUEPOINTNODE, vec![
+                Element::uint(ID_CUETIME, timestamp).into(),
+                Element::master(ID_CUETRACKPOSITIONSNODE, vec![
+                    Element::uint(ID_CUETRACK, track).into(),
+                    Element::uint(ID_CUECLUSTERPOSITION, position).into(),
+                ]),
+            ]))
+            .collect();
+        Some(self.master(ID_CUESNODE, points))
+    }
+
+    // A top level master, with a CRC-32 of its children first if asked for
+    fn master(&self, id: u64, mut children: Vec<Node>) -> Vec<u8> {
+        if self.crc32 {
+            let data: Vec<u8> = children.iter().flat_map(|child| child.to_bytes()).collect();
+            children.insert(0, Element::binary(ID_CRC32, &crc32(&data).to_le_bytes()).into());
+        }
+        Element::master(id, children).to_bytes()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), IOError> {
+        self.out.write_all(bytes)?;
+        self.stats.bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn close_cluster(&mut self) -> Result<(), IOError> {
+        let cluster = match self.cluster.take() {
+            Some(cluster) => cluster,
+            None => return Ok(()),
+        };
+        let mut children = vec![Node::from(Element::uint(ID_TIMESTAMP, cluster.timestamp))];
+        children.extend(cluster.blocks);
+        let cluster = self.master(ID_CLUSTERNODE, children);
+        self.write(&cluster)?;
+
+        self.stats.clusters += 1;
+        if let Some(callback) = self.on_progress.as_mut() {
+            callback(&self.stats);
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Muxer<W> {
+    // Like `finish`, then go back to fill in what `cues_placement` reserved
+    // and the Segment size, so the result is an ordinary seekable file.
+    // The Cues go in their reserved space when they fit, or at the end.
+    pub fn finish_seekable(mut self) -> Result<W, IOError> {
+        self.flush()?;
+        // where this muxer started writing
+        let base = self.out.stream_position()? - self.stats.bytes;
+        let segment_data = base + self.segment_data_start;
+
+        let cues = self.cues();
+        let mut cues_position = None;
+        if let (Some(cues), Some((position, reserved))) = (&cues, self.layout.cues) {
+            // what's left over becomes a Void, which can't be one byte
+            let padding = match reserved.checked_sub(cues.len() as u64) {
+                Some(0) => Some(Vec::new()),
+                Some(rest) => void_header(rest),
+                None => None,
+            };
+            if let Some(padding) = padding {
+                self.out.seek(SeekFrom::Start(segment_data + position))?;
+                self.out.write_all(cues)?;
+                self.out.write_all(&padding)?;
+                self.out.seek(SeekFrom::Start(base + self.stats.bytes))?;
+                cues_position = Some(position);
+            }
+        }
+        if let (Some(cues), None) = (&cues, cues_position) {
+            cues_position = Some(self.stats.bytes - self.segment_data_start);
+            self.write(cues)?;
+        }
+        let end = base + self.stats.bytes;
+
+        if let Some(position) = self.layout.seek_head {
+            let mut entries = vec![(ID_INFONODE, self.layout.info), (ID_TRACKSNODE, self.layout.tracks)];
+            entries.extend(self.layout.attachments.map(|attachments| (ID_ATTACHMENTSNODE, attachments)));
+            entries.extend(cues_position.map(|cues| (ID_CUESNODE, cues)));
+            let seeks = entries.into_iter()
+                .map(|(id, position)| {
+                    let mut seek_id = Vec::new();
+                    write_element_id(&mut seek_id, id);
+                    Element::master(ID_SEEKNODE, vec![
+                        Element::binary(ID_SEEKID, &seek_id).into(),
+                        Element::uint(ID_SEEKPOSITION, position).into(),
+                    ])
+                })
+                .collect();
+            let mut seek_head = self.master(ID_SEEKHEADNODE, seeks);
+            seek_head.extend(void_header(SEEK_HEAD_SPACE - seek_head.len() as u64).unwrap_or_default());
+            self.out.seek(SeekFrom::Start(segment_data + position))?;
+            self.out.write_all(&seek_head)?;
+        }
+
+        // the unknown size written by `start` is 8 bytes long
+        let mut size = Vec::new();
+        vint::write_with_length(&mut size, end - segment_data, 8)
+            .ok_or_else(|| invalid_input("Segment too large"))?;
+        self.out.seek(SeekFrom::Start(segment_data - 8))?;
+        self.out.write_all(&size)?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn track_entry(track: &Track) -> Node {
+    let codec = &track.codec;
+    let mut children: Vec<Node> = vec![
+        Element::uint(ID_TRACKNUMBER, track.number).into(),
+        Element::uint(ID_TRACKUID, track.uid.max(1)).into(),
+        Element::uint(ID_TRACKTYPE, track.track_type).into(),
+        Element::string(ID_CODECID, &codec.codec_id).into(),
+    ];
+    if let Some(language) = &track.language {
+        children.push(Element::string(ID_LANGUAGE, language).into());
+    }
+    if let Some(private) = &codec.codec_private {
+        children.push(Element::binary(ID_CODECPRIVATE, private).into());
+    }
+    if codec.codec_delay > 0 {
+        children.push(Element::uint(ID_CODECDELAY, codec.codec_delay).into());
+    }
+    if codec.seek_preroll > 0 {
+        children.push(Element::uint(ID_SEEKPREROLL, codec.seek_preroll).into());
+    }
+    if let Some(duration) = codec.default_duration {
+        children.push(Element::uint(ID_DEFAULTDURATION, duration).into());
+    }
+
+    if let (Some(width), Some(height)) = (codec.pixel_width, codec.pixel_height) {
+        let mut video = vec![
+            Node::from(Element::uint(ID_PIXELWIDTH, width)),
+            Element::uint(ID_PIXELHEIGHT, height).into(),
+        ];
+        if let Some(projection) = &codec.projection {
+            video.push(projection.to_node());
+        }
+        children.push(Element::master(ID_VIDEONODE, video));
+    }
+    if let Some(frequency) = codec.sampling_frequency {
+        let mut audio = vec![Node::from(Element::float(ID_SAMPLINGFREQUENCY, frequency))];
+        if let Some(channels) = codec.channels {
+            audio.push(Element::uint(ID_CHANNELS, channels).into());
+        }
+        if let Some(depth) = codec.bit_depth {
+            audio.push(Element::uint(ID_BITDEPTH, depth).into());
+        }
+        children.push(Element::master(ID_AUDIONODE, audio));
+    }
+    Element::master(ID_TRACKENTRYNODE, children)
+}
+
+fn attached_file(attachment: &Attachment) -> Node {
+    let mut children: Vec<Node> = Vec::new();
+    if let Some(description) = &attachment.description {
+        children.push(Element::string(ID_FILEDESCRIPTION, description).into());
+    }
+    children.extend([
+        Node::from(Element::string(ID_FILENAME, &attachment.name)),
+        Element::string(ID_FILEMEDIATYPE, &attachment.media_type).into(),
+        Element::binary(ID_FILEDATA, &attachment.data).into(),
+        Element::uint(ID_FILEUID, attachment.uid).into(),
+    ]);
+    Element::master(ID_ATTACHEDFILENODE, children)
+}
+
+fn block_node(packet: &Packet, timecode: i16, previous: Option<u64>) -> Node {
+    let mut block = Vec::with_capacity(packet.data.len() + 4);
+    vint::write(&mut block, packet.track);
+    block.extend(timecode.to_be_bytes());
+    if packet.duration.is_none() && packet.discard_padding.is_none() {
+        block.push(if packet.keyframe { 0x80 } else { 0x00 });
+        block.extend(&packet.data);
+        return Element::binary(ID_SIMPLEBLOCK, &block).into();
+    }
+
+    block.push(0x00);
+    block.extend(&packet.data);
+    let mut group = vec![Node::from(Element::binary(ID_BLOCK, &block))];
+    if let Some(duration) = packet.duration {
+        group.push(Element::uint(ID_BLOCKDURATION, duration).into());
+    }
+    // relative to the previous block on the track. With none there's
+    // nothing to reference, and 0 would make the block reference itself.
+    if let (false, Some(previous)) = (packet.keyframe, previous) {
+        let reference = previous as i64 - packet.timestamp as i64;
+        group.push(Element::int(ID_REFERENCEBLOCK, reference).into());
+    }
+    if let Some(padding) = packet.discard_padding {
+        group.push(Element::int(ID_DISCARDPADDING, padding).into());
+    }
+    Element::master(ID_BLOCKGROUPNODE, group)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use crate::demux::CodecParameters;
+    use crate::demux::Projection;
+    use crate::ebml::{DocTypeMode, WebmFile};
+    use super::*;
+
+    fn track(number: u64, track_type: u64, codec_id: &str) -> Track {
+        Track {
+            number,
+            uid: number,
+            track_type,
+            language: None,
+            codec: CodecParameters {
+                codec_id: codec_id.to_string(),
+                codec_private: None,
+                codec_delay: 0,
+                seek_preroll: 0,
+                default_duration: None,
+                pixel_width: if track_type == 1 { Some(64) } else { None },
+                pixel_height: if track_type == 1 { Some(48) } else { None },
+                projection: None,
+                sampling_frequency: if track_type == 2 { Some(48000.0) } else { None },
+                channels: if track_type == 2 { Some(2) } else { None },
+                bit_depth: None,
+            },
+        }
+    }
+
+    fn packet(track: u64, timestamp: u64, keyframe: bool) -> Packet {
+        Packet::new(track, timestamp, keyframe, vec![track as u8, timestamp as u8])
+    }
+
+    fn muxer() -> Muxer<Vec<u8>> {
+        let mut muxer = Muxer::new(Vec::new()).max_cluster_duration(1_000_000_000);
+        muxer.add_track(track(1, 1, "V_VP9")).unwrap();
+        muxer.add_track(track(2, 2, "A_OPUS")).unwrap();
+        muxer
+    }
+
+    #[test]
+    fn test_write_packets() {
+        let mut muxer = muxer();
+        let mut packets = Vec::new();
+        for i in 0..50 {
+            packets.push(packet(1, i * 100, i % 10 == 0));
+            packets.push(packet(2, i * 100 + 50, true));
+        }
+        let mut last = packet(2, 5000, true);
+        last.duration = Some(20);
+        last.discard_padding = Some(1_000_000);
+        packets.push(last);
+        for packet in &packets {
+            muxer.write_packet(packet).unwrap();
+        }
+        assert!(muxer.add_track(track(3, 2, "A_OPUS")).is_err());
+        assert!(muxer.write_packet(&packet(9, 0, true)).is_err());
+
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        document.check_compatibility().unwrap();
+        assert_eq!(document.root.get_tracks()[0].get_track_entries().len(), 2);
+        // a new Cluster on every keyframe a second apart
+        let starts: Vec<u64> = document.root.get_clusters().iter().map(|c| c.get_timestamp()).collect();
+        assert_eq!(starts, vec![0, 1000, 2000, 3000, 4000]);
+
+        let written: Vec<Packet> = document.packets().collect();
+        assert_eq!(written.len(), packets.len());
+        for (written, packet) in written.iter().zip(&packets) {
+            assert_eq!((written.track, written.timestamp, written.keyframe), (packet.track, packet.timestamp, packet.keyframe));
+            assert_eq!(written.data, packet.data);
+        }
+        let last = written.last().unwrap();
+        assert_eq!((last.duration, last.discard_padding), (Some(20), Some(1_000_000)));
+    }
+
+    #[test]
+    fn test_reference_block() {
+        let mut muxer = muxer();
+        for (timestamp, keyframe) in [(0, false), (40, false)] {
+            let mut packet = packet(1, timestamp, keyframe);
+            packet.duration = Some(40);
+            muxer.write_packet(&packet).unwrap();
+        }
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        let groups = document.root.get_clusters()[0].get_block_groups();
+        let references: Vec<Vec<i64>> = groups.iter()
+            .map(|group| group.get_reference_blocks())
+            .collect();
+        assert_eq!(references, vec![vec![], vec![-40]]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let mut muxer = Muxer::new(Vec::new())
+            .max_cluster_duration(1_000_000_000)
+            .on_progress(move |stats| sink.lock().unwrap().push(stats.clone()));
+        muxer.add_track(track(1, 1, "V_VP9")).unwrap();
+        muxer.add_track(track(2, 2, "A_OPUS")).unwrap();
+        for i in 0..20 {
+            muxer.write_packet(&packet(1, i * 100, i % 10 == 0)).unwrap();
+        }
+        muxer.write_packet(&packet(2, 1950, true)).unwrap();
+
+        // the first Cluster closed when the second keyframe came
+        assert_eq!(reports.lock().unwrap().len(), 1);
+        assert_eq!(reports.lock().unwrap()[0].tracks[0].frames, 10);
+        let stats = muxer.stats().clone();
+        assert_eq!(stats.tracks[0], TrackStats { track: 1, frames: 20, bytes: 40 });
+        assert_eq!(stats.tracks[1], TrackStats { track: 2, frames: 1, bytes: 2 });
+        assert_eq!(stats.duration, 1_950_000_000);
+
+        let data = muxer.finish().unwrap();
+        let reports = reports.lock().unwrap();
+        let last = reports.last().unwrap();
+        assert_eq!(last.clusters, 2);
+        assert_eq!(last.bytes, data.len() as u64);
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut muxer = muxer();
+        muxer.flush().unwrap();
+        let headers = muxer.get_ref().len();
+        assert!(WebmFile::from_bytes(muxer.get_ref()).is_ok());
+
+        muxer.write_packet(&packet(1, 0, true)).unwrap();
+        muxer.write_packet(&packet(2, 10, true)).unwrap();
+        // the open Cluster is held back until a flush
+        assert_eq!(muxer.get_ref().len(), headers);
+        muxer.flush().unwrap();
+        let document = WebmFile::from_bytes(muxer.get_ref()).unwrap();
+        assert_eq!(document.packets().count(), 2);
+
+        // packets after a flush go in a new Cluster, even without a keyframe
+        muxer.write_packet(&packet(1, 40, false)).unwrap();
+        let data = muxer.finish().unwrap();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(document.root.get_clusters().len(), 2);
+        assert_eq!(document.packets().count(), 3);
+    }
+
+    #[test]
+    fn test_app_names() {
+        let mut muxer = Muxer::new(Vec::new()).writing_app("recorder 2.1");
+        muxer.add_track(track(1, 1, "V_VP9")).unwrap();
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        let info = &document.root.get_info_nodes()[0];
+        assert_eq!(info.get_muxing_app(), "libwebm-rs");
+        assert_eq!(info.get_writing_app(), "recorder 2.1");
+    }
+
+    #[test]
+    fn test_segment_uid() {
+        let uid = |muxer: Muxer<Vec<u8>>| {
+            let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+            document.root.get_info_nodes()[0].get_segment_uid()
+        };
+        let first = uid(Muxer::new(Vec::new())).unwrap();
+        assert_ne!(Some(first), uid(Muxer::new(Vec::new())));
+
+        let chosen = SegmentUid([7; 16]);
+        assert_eq!(uid(Muxer::new(Vec::new()).segment_uid(Some(chosen))), Some(chosen));
+        assert_eq!(uid(Muxer::new(Vec::new()).segment_uid(None)), None);
+    }
+
+    #[test]
+    fn test_audio_cues() {
+        // a minute of 20ms Opus packets
+        let mut podcast = Muxer::new(Vec::new()).cue_interval(10_000_000_000);
+        podcast.add_track(track(1, 2, "A_OPUS")).unwrap();
+        for i in 0..3000 {
+            podcast.write_packet(&packet(1, i * 20, true)).unwrap();
+        }
+        let document = WebmFile::from_bytes(&podcast.finish().unwrap()).unwrap();
+
+        let segment_data = document.root.get_element().data_offset();
+        let clusters = document.root.get_clusters();
+        let cue_points = document.root.get_cues()[0].get_cue_points();
+        let times: Vec<u64> = cue_points.iter().map(|point| point.get_time()).collect();
+        assert_eq!(times, vec![0, 10_000, 20_000, 30_000, 40_000, 50_000]);
+        for point in &cue_points {
+            let positions = &point.get_positions()[0];
+            assert_eq!(positions.get_track(), 1);
+            let cluster = clusters.iter()
+                .find(|c| c.get_element().offset == segment_data + positions.get_cluster_position())
+                .unwrap();
+            assert_eq!(cluster.get_timestamp(), point.get_time());
+        }
+        assert_eq!(document.packets().count(), 3000);
+
+        // no Cues unless asked for
+        let mut muxer = muxer();
+        muxer.write_packet(&packet(2, 0, true)).unwrap();
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        assert!(document.root.get_cues().is_empty());
+    }
+
+    #[test]
+    fn test_cues_placement() {
+        let write = |placement: CuesPlacement| -> WebmFile {
+            let mut muxer = Muxer::new(Cursor::new(Vec::new()))
+                .cue_interval(1_000_000_000)
+                .cues_placement(placement);
+            muxer.add_track(track(1, 2, "A_OPUS")).unwrap();
+            for i in 0..500 {
+                muxer.write_packet(&packet(1, i * 20, true)).unwrap();
+            }
+            let out = muxer.finish_seekable().unwrap().into_inner();
+            WebmFile::from_bytes(&out).unwrap()
+        };
+        // (ID, position in the Segment) of each top level element, and
+        // where the SeekHead says Cues are
+        let layout = |document: &WebmFile| -> (Vec<(u64, u64)>, u64) {
+            let segment_data = document.root.get_element().data_offset();
+            let children = document.root.get_children()
+                .iter()
+                .map(|child| (child.get_element().id, child.get_element().offset - segment_data))
+                .filter(|(id, _)| *id != ID_VOID)
+                .collect();
+            let seek = document.root.get_seek_head_nodes()[0]
+                .get_seek_nodes()
+                .into_iter()
+                .find(|seek| seek.get_seek_id() == vec![0x1c, 0x53, 0xbb, 0x6b])
+                .unwrap();
+            (children, seek.get_seek_position())
+        };
+
+        let document = write(CuesPlacement::Front { reserved: 1024 });
+        assert!(!document.root.get_element().is_unknown_size());
+        let (children, cues) = layout(&document);
+        let ids: Vec<u64> = children.iter().map(|(id, _)| *id).take(4).collect();
+        assert_eq!(ids, vec![ID_SEEKHEADNODE, ID_INFONODE, ID_TRACKSNODE, ID_CUESNODE]);
+        assert_eq!(children[3].1, cues);
+        assert_eq!(document.root.get_cues()[0].get_cue_points().len(), 10);
+        assert_eq!(document.packets().count(), 500);
+
+        // too small, so they go at the end like with CuesPlacement::End
+        for placement in [CuesPlacement::Front { reserved: 16 }, CuesPlacement::End].iter() {
+            let document = write(*placement);
+            let (children, cues) = layout(&document);
+            assert_eq!(children.last().unwrap(), &(ID_CUESNODE, cues));
+            assert_eq!(document.root.get_cues()[0].get_cue_points().len(), 10);
+            let info = document.root.get_seek_head_nodes()[0].get_seek_nodes()[0].get_seek_position();
+            assert_eq!(children[1], (ID_INFONODE, info));
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        let mut muxer = Muxer::new(Cursor::new(Vec::new()))
+            .max_cluster_duration(1_000_000_000)
+            .cue_interval(1_000_000_000)
+            .cues_placement(CuesPlacement::Front { reserved: 256 })
+            .crc32();
+        muxer.add_track(track(1, 2, "A_OPUS")).unwrap();
+        for i in 0..100 {
+            muxer.write_packet(&packet(1, i * 20, true)).unwrap();
+        }
+        let out = muxer.finish_seekable().unwrap().into_inner();
+        let document = WebmFile::from_bytes(&out).unwrap();
+        assert_eq!(document.packets().count(), 100);
+        assert_eq!(document.root.get_clusters()[1].get_timestamp(), 1000);
+
+        let masters: Vec<Node> = document.root.get_children()
+            .into_iter()
+            .filter(|child| child.get_element().id != ID_VOID)
+            .collect();
+        let ids: Vec<u64> = masters.iter().map(|m| m.get_element().id).take(4).collect();
+        assert_eq!(ids, vec![ID_SEEKHEADNODE, ID_INFONODE, ID_TRACKSNODE, ID_CUESNODE]);
+        assert_eq!(masters.len(), 6);
+        for master in &masters {
+            let crc = &master.get_children()[0];
+            assert_eq!(crc.get_element().id, ID_CRC32);
+            let data = &out[crc.get_element().end_offset() as usize..master.get_element().end_offset() as usize];
+            assert_eq!(crc.get_element().data.as_bytes(), crc32(data).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_ebml_header() {
+        let header = EbmlHeader { doc_type: "matroska".to_string(), doc_type_version: 4, ..EbmlHeader::default() };
+        let mut muxer = Muxer::new(Vec::new()).ebml_header(header);
+        muxer.add_track(track(1, 1, "V_VP9")).unwrap();
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        assert_eq!(document.header.get_doc_type(), "matroska");
+        assert_eq!(document.header.get_doc_type_read_version(), 2);
+        assert_eq!(document.header.get_max_size_length(), 8);
+
+        let header = EbmlHeader { max_size_length: 4, ..EbmlHeader::default() };
+        let mut muxer = Muxer::new(Vec::new()).ebml_header(header);
+        muxer.add_track(track(1, 1, "V_VP9")).unwrap();
+        assert!(muxer.write_packet(&packet(1, 0, true)).is_err());
+        assert!(muxer.get_ref().is_empty());
+    }
+
+    #[test]
+    fn test_matroska() {
+        let font = Attachment {
+            name: "font.ttf".to_string(),
+            media_type: "font/ttf".to_string(),
+            description: None,
+            data: vec![0, 1, 0, 0],
+            uid: 7,
+        };
+
+        // WebM only takes WebM codecs and no attachments
+        let mut muxer = Muxer::new(Vec::new());
+        assert!(muxer.add_track(track(1, 2, "A_FLAC")).is_err());
+        assert!(muxer.add_attachment(font.clone()).is_err());
+
+        let mut muxer = Muxer::new(Cursor::new(Vec::new()))
+            .matroska()
+            .cues_placement(CuesPlacement::End);
+        muxer.add_track(track(1, 1, "V_MPEG4/ISO/AVC")).unwrap();
+        muxer.add_track(track(2, 2, "A_FLAC")).unwrap();
+        muxer.add_attachment(font.clone()).unwrap();
+        assert!(muxer.add_attachment(font.clone()).is_err());
+        muxer.write_packet(&packet(1, 0, true)).unwrap();
+        muxer.write_packet(&packet(2, 0, true)).unwrap();
+        assert!(muxer.add_attachment(Attachment { uid: 8, ..font }).is_err());
+
+        let document = WebmFile::from_bytes(&muxer.finish_seekable().unwrap().into_inner()).unwrap();
+        assert_eq!(document.header.get_doc_type(), "matroska");
+        assert!(document.check_compatibility_in(DocTypeMode::Matroska).is_ok());
+        let files = document.root.get_attachments()[0].get_attached_files();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].get_name(), files[0].get_media_type()), ("font.ttf".to_string(), "font/ttf".to_string()));
+        assert_eq!((files[0].get_data(), files[0].get_uid()), (vec![0, 1, 0, 0], 7));
+        assert_eq!(document.packets().count(), 2);
+
+        let segment_data = document.root.get_element().data_offset();
+        let attachments = document.root.get_seek_head_nodes()[0].get_seek_nodes()[2].get_seek_position();
+        assert_eq!(document.root.get_attachments()[0].get_element().offset, segment_data + attachments);
+    }
+
+    #[test]
+    fn test_projection() {
+        let mut muxer = Muxer::new(Vec::new());
+        let mut rotated = track(1, 1, "V_VP9");
+        rotated.codec.projection = Some(Projection { pose_yaw: 270.0, ..Projection::default() });
+        assert!(muxer.add_track(rotated.clone()).is_err());
+        rotated.codec.projection = Some(Projection::rotation(270.0));
+        muxer.add_track(rotated.clone()).unwrap();
+        muxer.write_packet(&packet(1, 0, true)).unwrap();
+
+        let document = WebmFile::from_bytes(&muxer.finish().unwrap()).unwrap();
+        document.check_compatibility().unwrap();
+        let entry = &document.root.get_tracks()[0].get_track_entries()[0];
+        let projection = entry.get_video_settings().unwrap().get_projection().unwrap();
+        assert_eq!((projection.get_type(), projection.get_pose_roll()), (0, -90.0));
+        assert_eq!(Track::from(entry), rotated);
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{Write, Error as IOError};
+use crate::demux::Track;
+use crate::ebml::WebmFile;
+use crate::error::{invalid_data, invalid_input};
+
+// Opus always runs at 48kHz inside the codec, whatever the input rate was
+const OPUS_RATE: u64 = 48_000;
+
+// Copy an Opus track into an Ogg Opus stream (RFC 7845) without decoding.
+// CodecDelay becomes the pre-skip and the last block's DiscardPadding trims
+// the final granule position, so players get exactly the original samples.
+// Each packet is written on its own page.
+pub fn extract_opus<W: Write>(document: &WebmFile, track: u64, mut out: W) -> Result<(), IOError> {
+    let entry = document.root.get_tracks()
+        .iter()
+        .flat_map(|tracks| tracks.get_track_entries())
+        .map(|entry| Track::from(&entry))
+        .find(|t| t.number == track)
+        .ok_or_else(|| invalid_input("no such track"))?;
+    if entry.codec.codec_id != "A_OPUS" {
+        return Err(invalid_input("not an Opus track"));
+    }
+
+    let serial = entry.uid as u32;
+    let mut sequence = 0;
+    let mut page = |out: &mut W, flags: u8, granule: u64, packet: &[u8]| -> Result<(), IOError> {
+        out.write_all(&ogg_page(flags, granule, serial, sequence, packet)?)?;
+        sequence += 1;
+        Ok(())
+    };
+    page(&mut out, 0x02, 0, &opus_head(&entry)?)?;
+    page(&mut out, 0x00, 0, &opus_tags())?;
+
+    // the granule position counts samples including the pre-skip, so it
+    // starts at zero rather than at -CodecDelay
+    let mut packets = document.packets().filter(|p| p.track == track).peekable();
+    let mut granule = 0u64;
+    while let Some(packet) = packets.next() {
+        granule += opus_packet_samples(&packet.data).ok_or_else(|| invalid_data("bad Opus packet"))?;
+        let last = packets.peek().is_none();
+        let flags = if last { 0x04 } else { 0x00 };
+        if let Some(padding) = packet.discard_padding.filter(|_| last) {
+            granule = granule.saturating_sub(nanoseconds_to_samples(padding.max(0) as u64));
+        }
+        page(&mut out, flags, granule, &packet.data)?;
+    }
+    Ok(())
+}
+
+// Samples at 48kHz in an Opus packet, from its TOC byte and frame count
+// (RFC 6716 section 3.1)
+pub fn opus_packet_samples(packet: &[u8]) -> Option<u64> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+    let frame_size = match config {
+        // SILK: 10, 20, 40 and 60ms
+        0..=11 => [480, 960, 1920, 2880][config as usize % 4],
+        // hybrid: 10 and 20ms
+        12..=15 => [480, 960][config as usize % 2],
+        // CELT: 2.5, 5, 10 and 20ms
+        _ => [120, 240, 480, 960][config as usize % 4],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3f) as u64,
+    };
+    Some(frame_size * frames)
+}
+
+fn nanoseconds_to_samples(nanoseconds: u64) -> u64 {
+    (nanoseconds as u128 * OPUS_RATE as u128 / 1_000_000_000) as u64
+}
+
+// WebM's CodecPrivate is already an OpusHead, but CodecDelay is the
+// authoritative pre-skip. Mono and stereo tracks without one get a minimal
+// header.
+fn opus_head(track: &Track) -> Result<Vec<u8>, IOError> {
+    let pre_skip = nanoseconds_to_samples(track.codec.codec_delay) as u16;
+    let mut head = match &track.codec.codec_private {
+        Some(private) if private.len() >= 19 && private.starts_with(b"OpusHead") => private.clone(),
+        Some(_) => return Err(invalid_data("CodecPrivate isn't an OpusHead")),
+        None => {
+            let channels = track.codec.channels.unwrap_or(2);
+            if !(1..=2).contains(&channels) {
+                return Err(invalid_data("a channel mapping is needed for more than two channels"));
+            }
+            let rate = track.codec.sampling_frequency.unwrap_or(OPUS_RATE as f64) as u32;
+            let mut head = b"OpusHead".to_vec();
+            head.extend([1, channels as u8, 0, 0]);
+            head.extend(rate.to_le_bytes());
+            // output gain and channel mapping family
+            head.extend([0, 0, 0]);
+            head
+        },
+    };
+    if track.codec.codec_delay > 0 {
+        head[10..12].copy_from_slice(&pre_skip.to_le_bytes());
+    }
+    Ok(head)
+}
+
+fn opus_tags() -> Vec<u8> {
+    let vendor = b"libwebm-rs";
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor);
+    // no user comments
+    tags.extend(0u32.to_le_bytes());
+    tags
+}
+
+// A page holding one whole packet
+fn ogg_page(flags: u8, granule: u64, serial: u32, sequence: u32, packet: &[u8]) -> Result<Vec<u8>, IOError> {
+    // lacing values are 255 until the last, which is below 255 (maybe 0)
+    let segments = packet.len() / 255 + 1;
+    if segments > 255 {
+        return Err(invalid_data("packet too large for one Ogg page"));
+    }
+    let mut page = b"OggS".to_vec();
+    page.push(0);
+    page.push(flags);
+    page.extend(granule.to_le_bytes());
+    page.extend(serial.to_le_bytes());
+    page.extend(sequence.to_le_bytes());
+    // checksum, filled in below
+    page.extend([0; 4]);
+    page.push(segments as u8);
+    page.extend(std::iter::repeat_n(255, segments - 1));
+    page.push((packet.len() % 255) as u8);
+    page.extend(packet);
+
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    Ok(page)
+}
+
+// CRC-32 with polynomial 0x04c11db7, no reflection, a zero initial value
+// and no final xor
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    // three 20ms CELT packets, with 6.5ms of delay and 10ms of padding
+    fn opus_file() -> Vec<u8> {
+        WebmBuilder::new()
+            .audio_track(1, "A_OPUS", 48000.0, 2)
+            .codec_delay(6_500_000)
+            .frame(1, 0, true, &[0xf8, 1])
+            .frame(1, 20, true, &[0xf8, 2])
+            .frame(1, 40, true, &[0xf8, 3])
+            .discard_padding(10_000_000)
+            .build()
+    }
+
+    // (flags, granule, packet) for each page
+    fn pages(data: &[u8]) -> Vec<(u8, u64, Vec<u8>)> {
+        let mut pages = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            assert_eq!(&rest[..4], b"OggS");
+            let segments = rest[26] as usize;
+            let len: usize = rest[27..27 + segments].iter().map(|&l| l as usize).sum();
+            let end = 27 + segments + len;
+            let mut unchecked = rest[..end].to_vec();
+            unchecked[22..26].copy_from_slice(&[0; 4]);
+            assert_eq!(ogg_crc(&unchecked).to_le_bytes(), rest[22..26]);
+
+            let mut granule = [0; 8];
+            granule.copy_from_slice(&rest[6..14]);
+            let granule = u64::from_le_bytes(granule);
+            pages.push((rest[5], granule, rest[27 + segments..end].to_vec()));
+            rest = &rest[end..];
+        }
+        pages
+    }
+
+    #[test]
+    fn test_ogg_crc() {
+        assert_eq!(ogg_crc(b"123456789"), 0x89a1_897f);
+    }
+
+    #[test]
+    fn test_opus_packet_samples() {
+        assert_eq!(opus_packet_samples(&[0xf8]), Some(960));
+        assert_eq!(opus_packet_samples(&[0x19]), Some(2880 * 2));
+        assert_eq!(opus_packet_samples(&[0x83, 0x05]), Some(120 * 5));
+        assert_eq!(opus_packet_samples(&[0x03]), None);
+        assert_eq!(opus_packet_samples(&[]), None);
+    }
+
+    #[test]
+    fn test_extract_opus() {
+        let document = WebmFile::from_bytes(&opus_file()).unwrap();
+        let mut out = Vec::new();
+        extract_opus(&document, 1, &mut out).unwrap();
+
+        let pages = pages(&out);
+        assert_eq!(pages.len(), 5);
+        let (flags, _, head) = &pages[0];
+        assert_eq!(*flags, 0x02);
+        assert!(head.starts_with(b"OpusHead"));
+        // 6.5ms at 48kHz
+        assert_eq!(u16::from_le_bytes([head[10], head[11]]), 312);
+        assert!(pages[1].2.starts_with(b"OpusTags"));
+
+        let granules: Vec<u64> = pages[2..].iter().map(|p| p.1).collect();
+        assert_eq!(granules, vec![960, 1920, 2880 - 480]);
+        assert_eq!(pages[4].0, 0x04);
+        assert_eq!(pages[4].2, vec![0xf8, 3]);
+
+        assert!(extract_opus(&document, 2, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::{Display, Formatter, Error as FmtError};
+use std::time::Duration;
+use crate::consts::element_name;
+use crate::format::ByteSize;
+
+// Where parsing spends its time and bytes, per top level element type, as
+// collected by `WebmReader::profiling`. Times include reading from the
+// source, so slow storage shows up here too. The clock isn't available on
+// wasm32-unknown-unknown, so profiling is only for native builds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseProfile {
+    // in the order each type was first seen, starting with the EBML header
+    // and the Segment's own header
+    pub elements: Vec<ElementProfile>,
+    // every parse since profiling was turned on
+    pub total: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementProfile {
+    pub id: u64,
+    pub count: u64,
+    pub bytes: u64,
+    pub time: Duration,
+}
+
+impl ElementProfile {
+    pub fn name(&self) -> &'static str {
+        element_name(self.id).unwrap_or("Unknown")
+    }
+}
+
+impl ParseProfile {
+    pub fn get(&self, id: u64) -> Option<&ElementProfile> {
+        self.elements.iter().find(|e| e.id == id)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.elements.iter().map(|e| e.bytes).sum()
+    }
+
+    pub(crate) fn record(&mut self, id: u64, bytes: u64, time: Duration) {
+        match self.elements.iter_mut().find(|e| e.id == id) {
+            Some(element) => {
+                element.count += 1;
+                element.bytes += bytes;
+                element.time += time;
+            },
+            None => self.elements.push(ElementProfile { id, count: 1, bytes, time }),
+        }
+    }
+}
+
+// One line per element type, e.g.
+// `ClusterNode  x12  1.5 MiB  3.201ms`
+impl Display for ParseProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        for element in &self.elements {
+            writeln!(
+                f,
+                "{:<24} x{:<6} {:>10} {:>10.3}ms",
+                element.name(),
+                element.count,
+                ByteSize(element.bytes).to_string(),
+                element.time.as_secs_f64() * 1000.0,
+            )?;
+        }
+        write!(f, "{:<32} {:>10} {:>10.3}ms", "total", ByteSize(self.bytes()).to_string(), self.total.as_secs_f64() * 1000.0)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::consts::*;
+use crate::ebml::{EBMLHeaderNode, ClusterNode, Element, Node};
+use crate::ebml::parser::{ElementParser, ParseEvent, TreeBuilder};
+use crate::error::Error;
+
+// Incremental parser for data that arrives in pieces rather than from a
+// seekable source, e.g. chunks of an ArrayBuffer or a fetch() stream in the
+// browser. Push bytes in as they arrive and get back any Segment children
+// that are now complete. Bytes are dropped once parsed, so only the
+// elements still being assembled are held on to.
+pub struct PushParser {
+    parser: ElementParser,
+    // builds the EBML header and the Segment children
+    tree: TreeBuilder,
+    header: Option<EBMLHeaderNode>,
+    segment: Option<Element>,
+    // set once the Segment has ended
+    finished: bool,
+}
+
+impl PushParser {
+    pub fn new() -> PushParser {
+        PushParser {
+            parser: ElementParser::new(),
+            tree: TreeBuilder::new(),
+            header: None,
+            segment: None,
+            finished: false,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Node>, Error> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+        self.parser.feed(data);
+        self.drain()
+    }
+
+    // Mark the end of the stream and get back what it completes, e.g. an
+    // unknown-sized last Cluster. An element cut short is an error.
+    pub fn finish(&mut self) -> Result<Vec<Node>, Error> {
+        if self.finished {
+            return Ok(Vec::new());
+        }
+        self.parser.finish();
+        self.drain()
+    }
+
+    // The Segment children completed by the data parsed so far
+    fn drain(&mut self) -> Result<Vec<Node>, Error> {
+        let mut nodes = Vec::new();
+        loop {
+            let event = self.parser.next_event()?;
+            let depth = self.parser.depth();
+            match event {
+                ParseEvent::NeedData | ParseEvent::Done => break,
+                ParseEvent::Start(segment) if depth == 1 && segment.id == ID_SEGMENTNODE => {
+                    if self.header.is_none() {
+                        return Err(Error::MissingSegment);
+                    }
+                    self.segment = Some(segment);
+                },
+                ParseEvent::End(ID_SEGMENTNODE) if depth == 0 => {
+                    self.finished = true;
+                    break;
+                },
+                event => match self.tree.push(event) {
+                    Some(node) if node.element().id == ID_EBMLHEADERNODE && self.header.is_none() => {
+                        self.header = Some(EBMLHeaderNode::from(node));
+                    },
+                    Some(_) if self.segment.is_none() => return Err(Error::MissingSegment),
+                    Some(node) => nodes.push(node),
+                    None => (),
+                },
+            }
+        }
+        Ok(nodes)
+    }
+
+    // Like `push` but only returns the new Clusters
+    pub fn push_clusters(&mut self, data: &[u8]) -> Result<Vec<ClusterNode>, Error> {
+        Ok(clusters(self.push(data)?))
+    }
+
+    // Like `finish` but only returns the last Clusters
+    pub fn finish_clusters(&mut self) -> Result<Vec<ClusterNode>, Error> {
+        Ok(clusters(self.finish()?))
+    }
+
+    pub fn get_header(&self) -> Option<&EBMLHeaderNode> {
+        self.header.as_ref()
+    }
+
+    pub fn get_segment_element(&self) -> Option<&Element> {
+        self.segment.as_ref()
+    }
+
+    // Byte offset up to which the stream has been parsed
+    pub fn position(&self) -> u64 {
+        self.parser.position()
+    }
+
+    // Bytes pushed but not parsed yet, the start of an incomplete element
+    pub fn buffered(&self) -> usize {
+        self.parser.buffered()
+    }
+}
+
+fn clusters(nodes: Vec<Node>) -> Vec<ClusterNode> {
+    nodes.into_iter()
+        .filter(|node| node.get_element().id == ID_CLUSTERNODE)
+        .map(ClusterNode::from)
+        .collect()
+}
+
+impl Default for PushParser {
+    fn default() -> PushParser {
+        PushParser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    #[test]
+    fn test_push_in_chunks() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut parser = PushParser::new();
+
+        let mut ids = Vec::new();
+        for chunk in sample.chunks(64 * 1024) {
+            ids.extend(parser.push(chunk).unwrap().iter().map(|n| n.get_element().id));
+        }
+
+        assert_eq!(parser.get_header().unwrap().get_doc_type(), "webm");
+        assert_eq!(ids.iter().filter(|id| **id == ID_CLUSTERNODE).count(), 1);
+        assert_eq!(ids.first(), Some(&ID_SEEKHEADNODE));
+        assert_eq!(parser.position(), sample.len() as u64);
+    }
+
+    #[test]
+    fn test_drops_parsed_bytes() {
+        let mut builder = WebmBuilder::new()
+            .video_track(1, "V_VP8", 64, 64)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .without_cues();
+        for i in 0..50 {
+            builder = builder.cluster(i * 100).frame(1, 0, true, &[i as u8; 1000]);
+        }
+        let data = builder.build();
+
+        let mut parser = PushParser::new();
+        let mut clusters = 0;
+        for chunk in data.chunks(100) {
+            clusters += parser.push_clusters(chunk).unwrap().len();
+            assert!(parser.buffered() < 1100);
+        }
+        // the last Cluster ends with the stream, which only finish says
+        assert_eq!(clusters, 49);
+        let last = parser.finish_clusters().unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].get_timestamp(), 4900);
+        assert_eq!(clusters + last.len(), 50);
+        assert!(parser.get_segment_element().unwrap().is_unknown_size());
+        assert!(parser.finish().unwrap().is_empty());
+
+        // a stream cut off inside an element
+        let mut parser = PushParser::new();
+        parser.push(&data[..data.len() - 10]).unwrap();
+        assert!(parser.finish().is_err());
+    }
+}
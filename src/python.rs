@@ -0,0 +1,218 @@
+// Python bindings, enabled with the `python` feature. Build the extension
+// module with maturin and use it as:
+//
+//     import libwebm_rs
+//     f = libwebm_rs.WebmFile.open("video.webm")
+//     for track in f.tracks():
+//         print(track.number, track.codec_id)
+use std::fs::File;
+use std::sync::Arc;
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyBytes;
+use crate::block::{Packet, Packets};
+use crate::ebml::{WebmFile, WebmReader, TrackEntryNode};
+
+#[pyclass(name = "WebmFile", frozen)]
+pub struct PyWebmFile {
+    document: Arc<WebmFile>,
+}
+
+#[pyclass(name = "Track", frozen, get_all)]
+pub struct PyTrack {
+    number: u64,
+    uid: u64,
+    track_type: u64,
+    codec_id: String,
+    codec_name: Option<String>,
+    name: Option<String>,
+    language: Option<String>,
+    default_duration: Option<u64>,
+    pixel_width: Option<u64>,
+    pixel_height: Option<u64>,
+    sampling_frequency: Option<f64>,
+    channels: Option<u64>,
+}
+
+// One SimpleTag along with the targets of the Tag it belongs to
+#[pyclass(name = "Tag", frozen, get_all)]
+pub struct PyTag {
+    target_type_value: Option<u64>,
+    target_type: Option<String>,
+    track_uids: Vec<u64>,
+    name: String,
+    value: Option<String>,
+}
+
+#[pyclass(name = "Packet", frozen, get_all)]
+pub struct PyPacket {
+    track: u64,
+    timestamp: u64,
+    duration: Option<u64>,
+    keyframe: bool,
+    data: Py<PyBytes>,
+}
+
+#[pyclass(name = "PacketIterator")]
+pub struct PyPacketIterator {
+    packets: Packets,
+}
+
+#[pymethods]
+impl PyWebmFile {
+    #[staticmethod]
+    fn open(path: &str) -> PyResult<PyWebmFile> {
+        let document = WebmReader::new(File::open(path)?)
+            .parse()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyWebmFile { document: Arc::new(document) })
+    }
+
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<PyWebmFile> {
+        let document = WebmFile::from_bytes(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyWebmFile { document: Arc::new(document) })
+    }
+
+    #[getter]
+    fn doc_type(&self) -> String {
+        self.document.header.get_doc_type()
+    }
+
+    #[getter]
+    fn timestamp_scale(&self) -> u64 {
+        self.document.root.get_info_nodes()
+            .first()
+            .map(|info| info.get_timestamp_scale())
+            .unwrap_or(1_000_000)
+    }
+
+    // In TimestampScale units
+    #[getter]
+    fn duration(&self) -> Option<f64> {
+        self.document.root.get_info_nodes()
+            .first()
+            .and_then(|info| info.get_duration())
+    }
+
+    #[getter]
+    fn muxing_app(&self) -> Option<String> {
+        self.document.root.get_info_nodes()
+            .first()
+            .map(|info| info.get_muxing_app())
+    }
+
+    #[getter]
+    fn writing_app(&self) -> Option<String> {
+        self.document.root.get_info_nodes()
+            .first()
+            .map(|info| info.get_writing_app())
+    }
+
+    fn tracks(&self) -> Vec<PyTrack> {
+        self.document.root.get_tracks()
+            .iter()
+            .flat_map(|tracks| tracks.get_track_entries())
+            .map(|entry| PyTrack::from(&entry))
+            .collect()
+    }
+
+    fn tags(&self) -> Vec<PyTag> {
+        let mut result = Vec::new();
+        for tag in self.document.root.get_tags().iter().flat_map(|tags| tags.get_tags()) {
+            let targets = tag.get_targets();
+            for simple_tag in tag.get_simple_tags() {
+                result.push(PyTag {
+                    target_type_value: targets.as_ref().and_then(|t| t.get_type_value()),
+                    target_type: targets.as_ref().and_then(|t| t.get_type()),
+                    track_uids: targets.as_ref().map(|t| t.get_track_uid()).unwrap_or_default(),
+                    name: simple_tag.get_name(),
+                    value: simple_tag.get_string(),
+                });
+            }
+        }
+        result
+    }
+
+    fn packets(&self) -> PyPacketIterator {
+        PyPacketIterator { packets: self.document.packets() }
+    }
+}
+
+impl From<&TrackEntryNode> for PyTrack {
+    fn from(entry: &TrackEntryNode) -> PyTrack {
+        let video = entry.get_video_settings();
+        let audio = entry.get_audio_settings();
+        PyTrack {
+            number: entry.get_track_number(),
+            uid: entry.get_track_uid(),
+            track_type: entry.get_track_type(),
+            codec_id: entry.get_codec_id(),
+            codec_name: entry.get_codec_name(),
+            name: entry.get_name(),
+            language: entry.get_language(),
+            default_duration: entry.get_default_duration(),
+            pixel_width: video.as_ref().map(|v| v.get_pixel_width()),
+            pixel_height: video.as_ref().map(|v| v.get_pixel_height()),
+            sampling_frequency: audio.as_ref().map(|a| a.get_sampling_frequency()),
+            channels: audio.as_ref().map(|a| a.get_num_channels()),
+        }
+    }
+}
+
+#[pymethods]
+impl PyPacketIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyPacket> {
+        let packet: Packet = slf.packets.next()?;
+        let py = slf.py();
+        Some(PyPacket {
+            track: packet.track,
+            timestamp: packet.timestamp,
+            duration: packet.duration,
+            keyframe: packet.keyframe,
+            data: PyBytes::new(py, &packet.data).unbind(),
+        })
+    }
+}
+
+#[pymodule]
+fn libwebm_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyWebmFile>()?;
+    m.add_class::<PyTrack>()?;
+    m.add_class::<PyTag>()?;
+    m.add_class::<PyPacket>()?;
+    m.add_class::<PyPacketIterator>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let data = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let file = PyWebmFile::from_bytes(&data).unwrap();
+        assert_eq!(file.doc_type(), "webm");
+        assert_eq!(file.timestamp_scale(), 1_000_000);
+        assert!(file.duration().is_some());
+
+        let tracks = file.tracks();
+        assert_eq!(tracks.len(), 2);
+        let video = &tracks[0];
+        assert_eq!((video.number, video.codec_id.as_str()), (1, "V_VP8"));
+        assert_eq!((video.pixel_width, video.pixel_height), (Some(640), Some(360)));
+        assert_eq!(video.sampling_frequency, None);
+        let audio = &tracks[1];
+        assert_eq!(audio.codec_id, "A_VORBIS");
+        assert_eq!((audio.sampling_frequency, audio.channels), (Some(44100.0), Some(1)));
+
+        assert!(PyWebmFile::from_bytes(&[0; 16]).is_err());
+    }
+}
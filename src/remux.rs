@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write, Error as IOError};
+use crate::checksum::update_crc32;
+use crate::consts::*;
+use crate::ebml::{Element, Node, WebmFile};
+use crate::ebml::encode::{write_element_id, write_size};
+use crate::error::invalid_data;
+use crate::vint;
+
+// Rewrites a file around its Clusters: the EBML header, Info, Tracks and
+// other top level metadata are encoded from the (possibly edited) document
+// while every Cluster is copied byte for byte from the input, so fixing
+// metadata never touches media data. A new SeekHead is written first and
+// the Cues follow the Clusters, with their positions moved to match. Top
+// level Voids and CRC-32s are dropped.
+//
+// Cluster content comes from the input, not the document, so it can be
+// parsed with `WebmReader::skip_elements` to leave the blocks out.
+// Clusters of unknown size get a known one. Position elements inside
+// Clusters are copied as they are and go stale when the Clusters move.
+//
+//     let document = WebmReader::new(&mut input).parse()?;
+//     Remuxer::new(&document).write(&mut input, out)?;
+pub struct Remuxer<'a> {
+    document: &'a WebmFile,
+    muxing_app: AppName,
+    writing_app: AppName,
+    preserve_apps: bool,
+}
+
+// What to write as Info's MuxingApp or WritingApp
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum AppName {
+    #[default]
+    Keep,
+    Replace(String),
+    // after the original, separated by "; "
+    Append(String),
+}
+
+impl AppName {
+    // None when the original stays
+    fn apply(&self, original: &str) -> Option<String> {
+        match self {
+            AppName::Keep => None,
+            AppName::Replace(name) => Some(name.clone()),
+            AppName::Append(name) if original.is_empty() => Some(name.clone()),
+            AppName::Append(name) => Some(format!("{}; {}", original, name)),
+        }
+    }
+}
+
+// Where a Cluster's bytes are in the input
+struct ClusterSource {
+    offset: u64,
+    // the header to write instead of the original, for unknown sizes
+    header: Option<Vec<u8>>,
+    // bytes copied from `offset`
+    len: u64,
+}
+
+impl ClusterSource {
+    fn encoded_len(&self) -> u64 {
+        self.header.as_ref().map_or(0, |header| header.len() as u64) + self.len
+    }
+}
+
+impl<'a> Remuxer<'a> {
+    pub fn new(document: &'a WebmFile) -> Remuxer<'a> {
+        Remuxer {
+            document,
+            muxing_app: AppName::Keep,
+            writing_app: AppName::Keep,
+            preserve_apps: false,
+        }
+    }
+
+    pub fn muxing_app(mut self, name: AppName) -> Self {
+        self.muxing_app = name;
+        self
+    }
+
+    pub fn writing_app(mut self, name: AppName) -> Self {
+        self.writing_app = name;
+        self
+    }
+
+    // Keep the original MuxingApp and WritingApp as ORIGINAL_MUXING_APP and
+    // ORIGINAL_WRITING_APP tags when they're changed, in the first Tags or
+    // a new one
+    pub fn preserve_apps(mut self, preserve: bool) -> Self {
+        self.preserve_apps = preserve;
+        self
+    }
+
+    // Write the remuxed file to `out`, reading Clusters from `input`, the
+    // source `document` was parsed from
+    pub fn write<R, W>(&self, mut input: R, mut out: W) -> Result<(), IOError>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        let children = self.document.root.get_children();
+        let metadata: Vec<Vec<u8>> = self.metadata(&children)
+            .iter()
+            .map(Node::to_bytes)
+            .collect();
+        let clusters = self.cluster_sources(&mut input, &children)?;
+        let metadata_len: u64 = metadata.iter().map(|bytes| bytes.len() as u64).sum();
+        let clusters_len: u64 = clusters.iter().map(ClusterSource::encoded_len).sum();
+
+        // the SeekHead's size depends on the positions in it, which depend
+        // on its size, so grow it until they agree
+        let mut seek_head = Vec::new();
+        let mut cues;
+        loop {
+            let metadata_start = seek_head.len() as u64;
+            let clusters_start = metadata_start + metadata_len;
+            cues = self.cues(&clusters, clusters_start);
+            let cues_start = clusters_start + clusters_len;
+
+            let mut entries = Vec::new();
+            let mut position = metadata_start;
+            for bytes in &metadata {
+                entries.push((bytes.as_slice(), position));
+                position += bytes.len() as u64;
+            }
+            if let Some(cues) = &cues {
+                entries.push((cues.as_slice(), cues_start));
+            }
+            let encoded = encode_seek_head(&entries);
+            let settled = encoded.len() == seek_head.len();
+            seek_head = encoded;
+            if settled {
+                break;
+            }
+        }
+
+        out.write_all(&self.document.header.as_node().to_bytes())?;
+        let size = seek_head.len() as u64
+            + metadata_len
+            + clusters_len
+            + cues.as_ref().map_or(0, |cues| cues.len() as u64);
+        let mut segment_header = Vec::new();
+        write_element_id(&mut segment_header, ID_SEGMENTNODE);
+        write_size(&mut segment_header, size);
+        out.write_all(&segment_header)?;
+        out.write_all(&seek_head)?;
+        for bytes in &metadata {
+            out.write_all(bytes)?;
+        }
+        for cluster in &clusters {
+            if let Some(header) = &cluster.header {
+                out.write_all(header)?;
+            }
+            input.seek(SeekFrom::Start(cluster.offset))?;
+            let copied = io::copy(&mut (&mut input).take(cluster.len), &mut out)?;
+            if copied != cluster.len {
+                return Err(invalid_data("input ends inside a Cluster"));
+            }
+        }
+        if let Some(cues) = &cues {
+            out.write_all(cues)?;
+        }
+        Ok(())
+    }
+
+    // The top level elements written before the Clusters, with the apps
+    // changed as asked
+    fn metadata(&self, children: &[Node]) -> Vec<Node> {
+        let mut metadata: Vec<Node> = children.iter()
+            .filter(|child| !matches!(
+                child.get_element().id,
+                ID_SEEKHEADNODE | ID_CUESNODE | ID_CLUSTERNODE | ID_VOID | ID_CRC32
+            ))
+            .cloned()
+            .collect();
+
+        let mut originals = Vec::new();
+        if let Some(info) = metadata.iter_mut().find(|node| node.get_element().id == ID_INFONODE) {
+            let apps = [
+                (ID_MUXINGAPP, &self.muxing_app, "ORIGINAL_MUXING_APP"),
+                (ID_WRITINGAPP, &self.writing_app, "ORIGINAL_WRITING_APP"),
+            ];
+            for (id, app, tag) in apps.iter() {
+                let index = info.get_children().iter().position(|child| child.get_element().id == *id);
+                let original = index
+                    .map(|i| info.get_children()[i].get_element().data.into_string())
+                    .unwrap_or_default();
+                let name = match app.apply(&original) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let element = Node::from(Element::string(*id, &name));
+                match index {
+                    Some(i) => {
+                        info.edit_child(i, |child| *child = element);
+                    },
+                    None => info.insert_child(info.get_children().len(), element),
+                }
+                if !original.is_empty() {
+                    originals.push(simple_tag(tag, &original));
+                }
+            }
+            update_crc32(info);
+        }
+
+        if self.preserve_apps && !originals.is_empty() {
+            let mut children = vec![Element::master(ID_TARGETSNODE, vec![
+                Element::uint(ID_TARGETTYPEVALUE, 50).into(),
+            ])];
+            children.extend(originals);
+            let tag = Element::master(ID_TAGNODE, children);
+            match metadata.iter_mut().find(|node| node.get_element().id == ID_TAGSNODE) {
+                Some(tags) => {
+                    tags.insert_child(tags.get_children().len(), tag);
+                    update_crc32(tags);
+                },
+                None => metadata.push(Element::master(ID_TAGSNODE, vec![tag])),
+            }
+        }
+        metadata
+    }
+
+    // Clusters of unknown size end where the next top level element starts,
+    // or with the Segment or the input
+    fn cluster_sources<R: Seek>(&self, input: &mut R, children: &[Node]) -> Result<Vec<ClusterSource>, IOError> {
+        let segment = self.document.root.get_element();
+        let mut sources = Vec::new();
+        for (i, child) in children.iter().enumerate() {
+            let element = child.get_element();
+            if element.id != ID_CLUSTERNODE {
+                continue;
+            }
+            if !element.is_unknown_size() {
+                sources.push(ClusterSource {
+                    offset: element.offset,
+                    header: None,
+                    len: element.end_offset() - element.offset,
+                });
+                continue;
+            }
+            let end = match children.get(i + 1) {
+                Some(next) => next.get_element().offset,
+                None if !segment.is_unknown_size() => segment.end_offset(),
+                None => input.seek(SeekFrom::End(0))?,
+            };
+            let len = end.checked_sub(element.data_offset())
+                .ok_or_else(|| invalid_data("Cluster ends before its data"))?;
+            let mut header = Vec::new();
+            write_element_id(&mut header, ID_CLUSTERNODE);
+            write_size(&mut header, len);
+            sources.push(ClusterSource {
+                offset: element.data_offset(),
+                header: Some(header),
+                len,
+            });
+        }
+        Ok(sources)
+    }
+
+    // The document's Cues with CueClusterPositions moved to where each
+    // Cluster is written, relative to the Segment data like the originals.
+    // Positions that don't point at a Cluster are dropped.
+    fn cues(&self, clusters: &[ClusterSource], clusters_start: u64) -> Option<Vec<u8>> {
+        let segment_data = self.document.root.get_element().data_offset();
+        let mut moved = HashMap::new();
+        let mut position = clusters_start;
+        for (cluster, node) in clusters.iter().zip(self.document.root.get_clusters()) {
+            moved.insert(node.get_element().offset - segment_data, position);
+            position += cluster.encoded_len();
+        }
+
+        let mut points = Vec::new();
+        for cues in self.document.root.get_cues() {
+            for mut point in cues.as_node().get_children() {
+                if point.get_element().id != ID_CUEPOINTNODE {
+                    continue;
+                }
+                let mut children = Vec::new();
+                for mut child in point.get_children() {
+                    if child.get_element().id == ID_CUETRACKPOSITIONSNODE && !move_position(&mut child, &moved) {
+                        continue;
+                    }
+                    children.push(child);
+                }
+                if children.iter().any(|child| child.get_element().id == ID_CUETRACKPOSITIONSNODE) {
+                    point.set_children(children);
+                    points.push(point);
+                }
+            }
+        }
+        if points.is_empty() {
+            return None;
+        }
+        Some(Element::master(ID_CUESNODE, points).to_bytes())
+    }
+}
+
+// Point a CueTrackPositions at its Cluster's new position, returning false
+// if it doesn't point at one
+fn move_position(positions: &mut Node, moved: &HashMap<u64, u64>) -> bool {
+    let index = positions.get_children()
+        .iter()
+        .position(|child| child.get_element().id == ID_CUECLUSTERPOSITION);
+    let new_position = index
+        .and_then(|i| moved.get(&positions.get_children()[i].get_element().data.into_uint()))
+        .copied();
+    match (index, new_position) {
+        (Some(i), Some(new_position)) => positions.edit_child(i, |position| {
+            position.replace_data(Element::uint(ID_CUECLUSTERPOSITION, new_position).data.into_vec());
+        }),
+        _ => false,
+    }
+}
+
+fn simple_tag(name: &str, value: &str) -> Node {
+    Element::master(ID_SIMPLETAGNODE, vec![
+        Element::string(ID_TAGNAME, name).into(),
+        Element::string(ID_TAGSTRING, value).into(),
+    ])
+}
+
+// A SeekHead with a Seek for each encoded element and its position
+fn encode_seek_head(entries: &[(&[u8], u64)]) -> Vec<u8> {
+    let seeks = entries.iter()
+        .filter_map(|(bytes, position)| {
+            let id_len = vint::length(*bytes.first()?)?;
+            Some(Element::master(ID_SEEKNODE, vec![
+                Element::binary(ID_SEEKID, bytes.get(..id_len)?).into(),
+                Element::uint(ID_SEEKPOSITION, *position).into(),
+            ]))
+        })
+        .collect();
+    Element::master(ID_SEEKHEADNODE, seeks).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::checksum::crc32;
+    use crate::ebml::WebmReader;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn sample() -> WebmBuilder {
+        WebmBuilder::new()
+            .duration(2000.0)
+            .video_track(1, "V_VP9", 64, 64)
+            .cluster(0)
+            .frame(1, 0, true, &[1; 32])
+            .frame(1, 500, false, &[2; 32])
+            .cluster(1000)
+            .frame(1, 0, true, &[3; 32])
+    }
+
+    fn packets(document: &WebmFile) -> Vec<(u64, u64, Vec<u8>)> {
+        document.packets().map(|p| (p.track, p.timestamp, p.data)).collect()
+    }
+
+    // every Seek and CueClusterPosition points at the element it names
+    fn assert_positions(document: &WebmFile) {
+        let segment_data = document.root.get_element().data_offset();
+        let offsets: HashMap<u64, u64> = document.root.get_children()
+            .iter()
+            .map(|child| (child.get_element().offset - segment_data, child.get_element().id))
+            .collect();
+        for seek in document.root.get_seek_head_nodes()[0].get_seek_nodes() {
+            let id = vint::read_id(&seek.get_seek_id()[..]).unwrap();
+            assert_eq!(offsets[&seek.get_seek_position()], id);
+        }
+        for point in document.root.get_cues()[0].get_cue_points() {
+            for positions in point.get_positions() {
+                assert_eq!(offsets[&positions.get_cluster_position()], ID_CLUSTERNODE);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remux() {
+        let data = sample().build();
+        let mut input = Cursor::new(data);
+        let mut document = WebmReader::new(&mut input).parse().unwrap();
+        // a longer Title moves every Cluster
+        let info = document.root.get_children()
+            .iter()
+            .position(|child| child.get_element().id == ID_INFONODE)
+            .unwrap();
+        document.root.as_node_mut().edit_child(info, |info| {
+            info.insert_child(0, Element::string(ID_TITLE, "a new title").into());
+        });
+
+        let mut out = Vec::new();
+        Remuxer::new(&document).write(&mut input, &mut out).unwrap();
+        let remuxed = WebmFile::from_bytes(&out).unwrap();
+        assert_eq!(remuxed.root.get_info_nodes()[0].get_title(), Some("a new title".to_string()));
+        assert_eq!(packets(&remuxed), packets(&document));
+        assert_positions(&remuxed);
+        assert_eq!(remuxed.root.get_seek_head_nodes()[0].get_seek_nodes().len(), 3);
+
+        // the Clusters themselves are unchanged
+        let original = document.root.get_clusters()[1].get_element();
+        let copied = remuxed.root.get_clusters()[1].get_element();
+        assert_eq!(
+            &input.get_ref()[original.offset as usize..original.end_offset() as usize],
+            &out[copied.offset as usize..copied.end_offset() as usize]
+        );
+    }
+
+    #[test]
+    fn test_apps() {
+        let data = sample().build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let remux = |remuxer: Remuxer| -> WebmFile {
+            let mut out = Vec::new();
+            remuxer.write(Cursor::new(&data), &mut out).unwrap();
+            WebmFile::from_bytes(&out).unwrap()
+        };
+
+        let remuxed = remux(Remuxer::new(&document)
+            .muxing_app(AppName::Replace("fixer".to_string()))
+            .writing_app(AppName::Append("fixer 1.0".to_string())));
+        let info = &remuxed.root.get_info_nodes()[0];
+        assert_eq!(info.get_muxing_app(), "fixer");
+        assert_eq!(info.get_writing_app(), "libwebm-rs; fixer 1.0");
+        assert!(remuxed.root.get_tags().is_empty());
+
+        let remuxed = remux(Remuxer::new(&document)
+            .writing_app(AppName::Replace("fixer".to_string()))
+            .preserve_apps(true));
+        assert_eq!(remuxed.root.get_info_nodes()[0].get_muxing_app(), "libwebm-rs");
+        let simple_tags = remuxed.root.get_tags()[0].get_tags()[0].get_simple_tags();
+        assert_eq!(simple_tags.len(), 1);
+        let simple_tag = &simple_tags[0];
+        assert_eq!(simple_tag.get_name(), "ORIGINAL_WRITING_APP");
+        assert_eq!(simple_tag.get_string(), Some("libwebm-rs".to_string()));
+        assert_positions(&remuxed);
+    }
+
+    #[test]
+    fn test_apps_crc32() {
+        let data = sample().bad_crc().build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let mut children = document.root.get_children();
+        children.push(Element::master(ID_TAGSNODE, vec![
+            Element::binary(ID_CRC32, &[0; 4]).into(),
+            Element::master(ID_TAGNODE, vec![simple_tag("TITLE", "sample")]),
+        ]));
+        let metadata = Remuxer::new(&document)
+            .writing_app(AppName::Replace("fixer".to_string()))
+            .preserve_apps(true)
+            .metadata(&children);
+        for id in [ID_INFONODE, ID_TAGSNODE] {
+            let node = metadata.iter().find(|node| node.get_element().id == id).unwrap();
+            let rest: Vec<u8> = node.get_children()[1..].iter().flat_map(Node::to_bytes).collect();
+            assert_eq!(node.get_children()[0].get_element().data.as_bytes(), &crc32(&rest).to_le_bytes()[..]);
+        }
+    }
+
+    #[test]
+    fn test_remux_skipped_blocks() {
+        let data = sample().unknown_size_clusters().build();
+        let mut input = Cursor::new(data);
+        let document = WebmReader::new(&mut input)
+            .skip_elements([ID_SIMPLEBLOCK, ID_BLOCKGROUPNODE].iter().copied())
+            .parse()
+            .unwrap();
+        assert!(document.packets().next().is_none());
+
+        let mut out = Vec::new();
+        Remuxer::new(&document).write(&mut input, &mut out).unwrap();
+        let remuxed = WebmFile::from_bytes(&out).unwrap();
+        let original = WebmFile::from_bytes(input.get_ref()).unwrap();
+        assert_eq!(packets(&remuxed), packets(&original));
+        assert!(remuxed.root.get_clusters().iter().all(|c| !c.get_element().is_unknown_size()));
+        assert_positions(&remuxed);
+    }
+}
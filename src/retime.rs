@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use crate::consts::*;
+use crate::ebml::{Element, Node, WebmFile};
+use crate::block::block_of;
+use crate::checksum::update_crc32;
+use crate::vint;
+
+// Fix out of sync subtitles by moving every block of one track to
+// `time * scale + offset`, with times and the offset in nanoseconds.
+// BlockDurations are scaled too. Blocks that no longer fit their cluster's
+// 16 bit relative timestamp are moved to another cluster, and a new
+// cluster is added when none fits. Times before zero are clamped to zero.
+// Cues for the track are dropped, as are CueRelativePositions and
+// CueBlockNumbers, which may have moved within their cluster. SeekHead
+// entries, the other CueClusterPositions and cluster Positions and
+// PrevSizes are moved to where their elements end up, and CRC-32s are
+// recomputed, so the result can be saved with `WebmFile::write_to`.
+// Returns the number of blocks moved.
+pub fn retime_track(document: &mut WebmFile, track: u64, offset: i64, scale: f64) -> usize {
+    let timestamp_scale = document.root.get_info_nodes()
+        .first()
+        .map(|info| info.get_timestamp_scale())
+        .unwrap_or(1_000_000)
+        .max(1);
+    let retime = |ticks: u64| -> u64 {
+        let nanoseconds = ticks as f64 * timestamp_scale as f64 * scale + offset as f64;
+        (nanoseconds.max(0.0) / timestamp_scale as f64).round() as u64
+    };
+
+    // take the track's blocks out of every cluster, then put them back,
+    // editing the clusters directly and fixing sizes once at the end
+    let segment_data = document.root.get_element().data_offset();
+    let root = document.root.as_node_mut();
+    let children = root.children_mut();
+    let mut moved = Vec::new();
+    for cluster in children.iter_mut().filter(|n| n.element().id == ID_CLUSTERNODE) {
+        let cluster_timestamp = cluster_timestamp(cluster);
+        let (blocks, rest): (Vec<Node>, Vec<Node>) = std::mem::take(cluster.children_mut())
+            .into_iter()
+            .partition(|child| block_of(child).is_some_and(|entry| entry.block.track == track));
+        *cluster.children_mut() = rest;
+        for block in blocks {
+            let timestamp = block_of(&block).map_or(0, |entry| entry.block.timestamp(cluster_timestamp));
+            moved.push((retime(timestamp), scale_duration(block, scale)));
+        }
+    }
+
+    let mut clusters: Vec<(usize, u64)> = children.iter()
+        .enumerate()
+        .filter(|(_, n)| n.element().id == ID_CLUSTERNODE)
+        .map(|(i, n)| (i, cluster_timestamp(n)))
+        .collect();
+    let count = moved.len();
+    for (timestamp, block) in moved {
+        insert_block(children, &mut clusters, timestamp, block);
+    }
+    drop_cues(children, track);
+    root.update_sizes();
+    update_positions(root, segment_data);
+    for child in root.children_mut() {
+        update_crc32(child);
+    }
+    root.update_sizes();
+    count
+}
+
+// Remove the track's CueTrackPositions, and the CuePoints and Cues left
+// empty
+fn drop_cues(children: &mut Vec<Node>, track: u64) {
+    let has = |node: &Node, id: u64| node.children().iter().any(|child| child.element().id == id);
+    for cues in children.iter_mut().filter(|n| n.element().id == ID_CUESNODE) {
+        for point in cues.children_mut().iter_mut().filter(|n| n.element().id == ID_CUEPOINTNODE) {
+            point.children_mut().retain(|child| {
+                child.element().id != ID_CUETRACKPOSITIONSNODE || cue_track(child) != Some(track)
+            });
+            for positions in point.children_mut() {
+                positions.children_mut().retain(|child| {
+                    !matches!(child.element().id, ID_CUERELATIVEPOSITION | ID_CUEBLOCKNUMBER)
+                });
+            }
+        }
+        cues.children_mut().retain(|n| n.element().id != ID_CUEPOINTNODE || has(n, ID_CUETRACKPOSITIONSNODE));
+    }
+    children.retain(|n| n.element().id != ID_CUESNODE || has(n, ID_CUEPOINTNODE));
+}
+
+fn cue_track(positions: &Node) -> Option<u64> {
+    positions.children().iter()
+        .find(|child| child.element().id == ID_CUETRACK)
+        .map(|track| track.element().data.into_uint())
+}
+
+// Point SeekPositions and CueClusterPositions at the element they named
+// before the edit, dropping Seeks for elements that are gone, and set
+// each cluster's Position and PrevSize. Positions are relative to the
+// Segment data and their own sizes move what follows, so this repeats
+// until they settle.
+fn update_positions(segment: &mut Node, segment_data: u64) {
+    // elements added by the edit have no offset in the parsed stream
+    let index: HashMap<u64, usize> = segment.children().iter()
+        .enumerate()
+        .filter_map(|(i, child)| Some((child.element().offset.checked_sub(segment_data)?, i)))
+        .collect();
+    let target = |node: &Node| index.get(&node.element().data.into_uint()).copied();
+    for seek_head in segment.children_mut().iter_mut().filter(|n| n.element().id == ID_SEEKHEADNODE) {
+        seek_head.children_mut().retain(|seek| {
+            seek.element().id != ID_SEEKNODE || seek.children().iter()
+                .any(|child| child.element().id == ID_SEEKPOSITION && target(child).is_some())
+        });
+    }
+    let targets: Vec<Option<usize>> = position_nodes(segment).into_iter().map(|node| target(node)).collect();
+    segment.update_sizes();
+
+    loop {
+        let sizes: Vec<u64> = segment.children().iter()
+            .map(|child| child.element().header_size + child.element().size)
+            .collect();
+        let starts: Vec<u64> = sizes.iter()
+            .scan(0, |position, size| {
+                let start = *position;
+                *position += size;
+                Some(start)
+            })
+            .collect();
+        let mut settled = true;
+        for (node, target) in position_nodes(segment).into_iter().zip(&targets) {
+            if let Some(i) = target {
+                settled &= !set_uint(node, starts[*i]);
+            }
+        }
+        let mut prev_cluster = None;
+        for (i, child) in segment.children_mut().iter_mut().enumerate() {
+            if child.element().id != ID_CLUSTERNODE {
+                continue;
+            }
+            for node in child.children_mut() {
+                match (node.element().id, prev_cluster) {
+                    (ID_POSITION, _) => settled &= !set_uint(node, starts[i]),
+                    (ID_PREVSIZE, Some(prev)) => settled &= !set_uint(node, sizes[prev]),
+                    _ => (),
+                }
+            }
+            prev_cluster = Some(i);
+        }
+        if settled {
+            break;
+        }
+        segment.update_sizes();
+    }
+}
+
+// Returns true if the value changed
+fn set_uint(node: &mut Node, value: u64) -> bool {
+    if node.element().data.into_uint() == value {
+        return false;
+    }
+    node.replace_data(Element::uint(node.element().id, value).data.into_vec())
+}
+
+// Every SeekPosition and CueClusterPosition under the SeekHeads and Cues
+fn position_nodes(segment: &mut Node) -> Vec<&mut Node> {
+    fn collect<'a>(node: &'a mut Node, out: &mut Vec<&'a mut Node>) {
+        for child in node.children_mut() {
+            match child.element().id {
+                ID_SEEKPOSITION | ID_CUECLUSTERPOSITION => out.push(child),
+                ID_SEEKNODE | ID_CUEPOINTNODE | ID_CUETRACKPOSITIONSNODE => collect(child, out),
+                _ => (),
+            }
+        }
+    }
+    let mut out = Vec::new();
+    for child in segment.children_mut() {
+        if matches!(child.element().id, ID_SEEKHEADNODE | ID_CUESNODE) {
+            collect(child, &mut out);
+        }
+    }
+    out
+}
+
+// Put a block into the last cluster that can hold its timestamp, after any
+// blocks that aren't later than it. `clusters` holds the index and
+// timestamp of each cluster in `children`.
+fn insert_block(children: &mut Vec<Node>, clusters: &mut Vec<(usize, u64)>, timestamp: u64, block: Node) {
+    let fits = clusters.iter()
+        .rev()
+        .find(|(_, start)| timestamp >= *start && timestamp - start <= i16::MAX as u64);
+
+    let (index, cluster_timestamp) = match fits {
+        Some(&(index, start)) => (index, start),
+        None => {
+            // a new cluster, in timestamp order among the others
+            let position = clusters.iter()
+                .position(|(_, start)| *start > timestamp)
+                .unwrap_or(clusters.len());
+            let index = clusters.get(position)
+                .map(|(i, _)| *i)
+                .or_else(|| clusters.last().map(|(i, _)| i + 1))
+                .unwrap_or(children.len());
+            let cluster = Element::master(ID_CLUSTERNODE, vec![
+                Element::uint(ID_TIMESTAMP, timestamp).into(),
+            ]);
+            children.insert(index, cluster);
+            for (i, _) in clusters.iter_mut().filter(|(i, _)| *i >= index) {
+                *i += 1;
+            }
+            clusters.insert(position, (index, timestamp));
+            (index, timestamp)
+        },
+    };
+
+    let timecode = (timestamp - cluster_timestamp) as i16;
+    let block = set_timecode(block, timecode);
+    let cluster = children[index].children_mut();
+    let position = cluster.iter()
+        .position(|child| block_of(child).is_some_and(|entry| entry.block.timecode > timecode))
+        .unwrap_or(cluster.len());
+    cluster.insert(position, block);
+}
+
+fn cluster_timestamp(cluster: &Node) -> u64 {
+    cluster.children().iter()
+        .find(|child| child.element().id == ID_TIMESTAMP)
+        .map_or(0, |timestamp| timestamp.element().data.into_uint())
+}
+
+fn set_timecode(mut node: Node, timecode: i16) -> Node {
+    let rewrite = |node: &mut Node| {
+        let mut data = node.get_element().data.into_vec();
+        if let Some((_, len)) = vint::parse(&data) {
+            if let Some(bytes) = data.get_mut(len..len + 2) {
+                bytes.copy_from_slice(&timecode.to_be_bytes());
+                node.replace_data(data);
+            }
+        }
+    };
+    if node.get_element().id == ID_SIMPLEBLOCK {
+        rewrite(&mut node);
+    } else if let Some(i) = node.children().iter().position(|n| n.element().id == ID_BLOCK) {
+        node.edit_child(i, rewrite);
+    }
+    node
+}
+
+fn scale_duration(mut group: Node, scale: f64) -> Node {
+    let position = group.children()
+        .iter()
+        .position(|n| n.element().id == ID_BLOCKDURATION);
+    if let Some(i) = position {
+        group.edit_child(i, |duration| {
+            let scaled = (duration.get_element().data.into_uint() as f64 * scale).round() as u64;
+            duration.replace_data(Element::uint(ID_BLOCKDURATION, scaled).data.into_vec());
+        });
+    }
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::remux::Remuxer;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn timestamps(document: &WebmFile, track: u64) -> Vec<u64> {
+        document.packets().filter(|p| p.track == track).map(|p| p.timestamp).collect()
+    }
+
+    #[test]
+    fn test_retime_track() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .video_track(2, "S_TEXT/WEBVTT", 0, 0)
+            .cluster(0)
+            .frame(1, 0, true, &[0])
+            .frame(2, 100, true, b"one")
+            .cluster(1000)
+            .frame(1, 0, true, &[0])
+            .frame(2, 500, true, b"two")
+            .build();
+        let original = WebmFile::from_bytes(&data).unwrap();
+
+        // one second late, 10% too slow
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(retime_track(&mut document, 2, 1_000_000_000, 1.1), 2);
+        assert_eq!(timestamps(&document, 2), vec![1110, 2650]);
+        assert_eq!(timestamps(&document, 1), timestamps(&original, 1));
+
+        // far past the last cluster, which takes a new one
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        retime_track(&mut document, 2, 60_000_000_000, 1.0);
+        assert_eq!(document.root.get_clusters().len(), 3);
+        assert_eq!(timestamps(&document, 2), vec![60100, 61500]);
+        // sizes were fixed after the edits
+        for cluster in document.root.get_clusters() {
+            let content: usize = cluster.as_node().children().iter().map(|child| child.to_bytes().len()).sum();
+            assert_eq!(cluster.get_element().size, content as u64);
+        }
+
+        // earlier than the start clamps to zero
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        retime_track(&mut document, 2, -1_000_000_000, 1.0);
+        assert_eq!(timestamps(&document, 2), vec![0, 500]);
+        let packets: Vec<_> = document.packets().collect();
+        assert_eq!(packets[0].track, 1);
+        assert_eq!(packets[1].data, b"one".to_vec());
+
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        let saved = WebmFile::from_bytes(out.get_ref()).unwrap();
+        assert_eq!(timestamps(&saved, 2), vec![0, 500]);
+
+        // positions follow the elements they point at once saved, and the
+        // retimed track's Cues are gone
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .video_track(2, "S_TEXT/WEBVTT", 0, 0)
+            .prev_sizes()
+            .cluster(0)
+            .frame(1, 0, true, &[0])
+            .frame(2, 100, true, b"one")
+            .cluster(1000)
+            .frame(2, 0, true, b"two")
+            .frame(1, 500, true, &[0])
+            .build();
+        let mut remuxed = Vec::new();
+        Remuxer::new(&WebmFile::from_bytes(&data).unwrap()).write(Cursor::new(&data), &mut remuxed).unwrap();
+        let mut document = WebmFile::from_bytes(&remuxed).unwrap();
+        assert_eq!(document.root.get_cues()[0].get_cue_points().len(), 2);
+        retime_track(&mut document, 2, 60_000_000_000, 1.0);
+        let mut out = Cursor::new(Vec::new());
+        document.write_to(&mut out).unwrap();
+        let saved = WebmFile::from_bytes(out.get_ref()).unwrap();
+        assert_eq!(timestamps(&saved, 2), vec![60100, 61000]);
+
+        let segment_data = saved.root.get_element().data_offset();
+        let ids: HashMap<u64, u64> = saved.root.get_children()
+            .iter()
+            .map(|child| (child.get_element().offset - segment_data, child.get_element().id))
+            .collect();
+        for seek in saved.root.get_seek_head_nodes()[0].get_seek_nodes() {
+            let id = vint::read_id(&seek.get_seek_id()[..]).unwrap();
+            assert_eq!(ids[&seek.get_seek_position()], id);
+        }
+        let points = saved.root.get_cues()[0].get_cue_points();
+        assert_eq!(points.len(), 1);
+        let positions = &points[0].get_positions()[0];
+        assert_eq!(positions.get_track(), 1);
+        assert_eq!(ids[&positions.get_cluster_position()], ID_CLUSTERNODE);
+        let clusters = saved.root.get_clusters();
+        assert_eq!(clusters.len(), 3);
+        // the new cluster has neither
+        for cluster in &clusters[..2] {
+            let position = cluster.get_children().iter()
+                .find(|child| child.get_element().id == ID_POSITION)
+                .map(|position| position.get_element().data.into_uint());
+            assert_eq!(position, Some(cluster.get_element().offset - segment_data));
+        }
+        let first = clusters[0].get_element();
+        assert_eq!(clusters[1].get_prev_size(), Some(first.header_size + first.size));
+    }
+}
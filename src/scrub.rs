@@ -0,0 +1,200 @@
+use std::io::{self, Read, Seek, SeekFrom, Write, Error as IOError};
+use crate::consts::*;
+use crate::ebml::{Element, Node, WebmReader};
+use crate::error::invalid_data;
+use crate::inplace::void_header;
+use crate::linking::SegmentUid;
+
+// What to strip from a document before publishing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrubPolicy {
+    pub date: bool,
+    // MuxingApp/WritingApp are mandatory, so they're blanked rather than removed
+    pub apps: bool,
+    // replaced with a fresh random UID
+    pub segment_uid: bool,
+    pub title: bool,
+    pub tags: bool,
+    pub attachments: bool,
+}
+
+impl Default for ScrubPolicy {
+    fn default() -> ScrubPolicy {
+        ScrubPolicy {
+            date: true,
+            apps: true,
+            segment_uid: true,
+            title: true,
+            tags: true,
+            attachments: true,
+        }
+    }
+}
+
+// Copy `input` to `output` with identifying metadata removed. Removed
+// elements are turned into Void elements of the same length, so the output
+// is byte for byte the same size and every SeekHead, Cues and cluster
+// offset stays valid.
+pub fn scrub<R, W>(mut input: R, mut output: W, policy: &ScrubPolicy) -> Result<(), IOError>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    let documents = WebmReader::new(&mut input)
+        .parse_all()
+        .map_err(|_| invalid_data("could not parse document"))?;
+
+    let mut patches = Vec::new();
+    for document in documents {
+        scrub_segment(&document.root.get_children(), policy, &mut patches);
+    }
+    patches.sort_by_key(|(offset, _)| *offset);
+
+    input.seek(SeekFrom::Start(0))?;
+    let mut position = 0;
+    for (offset, bytes) in patches {
+        io::copy(&mut (&mut input).take(offset - position), &mut output)?;
+        output.write_all(&bytes)?;
+        input.seek(SeekFrom::Current(bytes.len() as i64))?;
+        position = offset + bytes.len() as u64;
+    }
+    io::copy(&mut input, &mut output)?;
+    Ok(())
+}
+
+fn scrub_segment(children: &[Node], policy: &ScrubPolicy, patches: &mut Vec<(u64, Vec<u8>)>) {
+    let mut removed = Vec::new();
+    if policy.tags {
+        removed.push(ID_TAGSNODE);
+    }
+    if policy.attachments {
+        removed.push(ID_ATTACHMENTSNODE);
+    }
+
+    for node in children {
+        let element = node.get_element();
+        match element.id {
+            ID_INFONODE => scrub_info(&node.get_children(), policy, patches),
+            // don't leave the SeekHead pointing at Voids
+            ID_SEEKHEADNODE => {
+                let seeks = node.get_children();
+                let mut changed = false;
+                for seek in seeks.iter().filter(|n| n.get_element().id == ID_SEEKNODE) {
+                    let target = seek.get_children().into_iter()
+                        .find(|n| n.get_element().id == ID_SEEKID)
+                        .map(|n| n.get_element().data.into_vec())
+                        .map(|id| id.iter().fold(0, |acc, b| (acc << 8) | *b as u64));
+                    if target.is_some_and(|id| removed.contains(&id)) {
+                        void(&seek.get_element(), patches);
+                        changed = true;
+                    }
+                }
+                if changed {
+                    void_checksums(&seeks, patches);
+                }
+            },
+            id if removed.contains(&id) => void(&element, patches),
+            _ => (),
+        }
+    }
+}
+
+fn scrub_info(children: &[Node], policy: &ScrubPolicy, patches: &mut Vec<(u64, Vec<u8>)>) {
+    let count = patches.len();
+    for node in children {
+        let element = node.get_element();
+        match element.id {
+            ID_DATEUTC if policy.date => void(&element, patches),
+            ID_TITLE if policy.title => void(&element, patches),
+            // strings may be zero padded, so this reads back as empty
+            ID_MUXINGAPP | ID_WRITINGAPP if policy.apps => {
+                patches.push((element.data_offset(), vec![0; element.size as usize]));
+            },
+            ID_SEGMENTUUID if policy.segment_uid && element.size == 16 => {
+                let uid = SegmentUid::generate();
+                patches.push((element.data_offset(), uid.as_bytes().to_vec()));
+            },
+            _ => (),
+        }
+    }
+    if patches.len() > count {
+        void_checksums(children, patches);
+    }
+}
+
+// A CRC-32 no longer matches once its parent has been changed
+fn void_checksums(children: &[Node], patches: &mut Vec<(u64, Vec<u8>)>) {
+    for node in children.iter().filter(|n| n.get_element().id == ID_CRC32) {
+        void(&node.get_element(), patches);
+    }
+}
+
+// Overwrite an element's header so the whole element becomes a Void. The
+// Void's header is never longer than the one it replaces, so the old bytes
+// left after it are just part of the Void's payload.
+fn void(element: &Element, patches: &mut Vec<(u64, Vec<u8>)>) {
+    // an unknown size can't be covered without knowing where it ends
+    if element.is_unknown_size() {
+        return;
+    }
+    if let Some(header) = void_header(element.header_size + element.size) {
+        patches.push((element.offset, header));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Cursor;
+    use crate::ebml::WebmFile;
+    use super::*;
+
+    #[test]
+    fn test_scrub_sample() {
+        let input = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut output = Vec::new();
+        scrub(Cursor::new(&input), &mut output, &ScrubPolicy::default()).unwrap();
+        assert_eq!(output.len(), input.len());
+
+        let file = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let original = WebmFile::open(file).unwrap();
+        let scrubbed = WebmFile::from_bytes(&output).unwrap();
+        let info = scrubbed.root.get_info_nodes().remove(0);
+        assert_eq!(info.get_muxing_app(), "");
+        assert_eq!(info.get_writing_app(), "");
+        assert_eq!(info.get_date_created(), None);
+        assert_ne!(
+            info.get_segment_uid(),
+            original.root.get_info_nodes()[0].get_segment_uid()
+        );
+
+        // the media itself is untouched
+        assert!(original.packets().map(|p| p.data).eq(scrubbed.packets().map(|p| p.data)));
+    }
+
+    #[test]
+    fn test_scrub_tags() {
+        let tags = [
+            0x12, 0x54, 0xc3, 0x67, 0x96,
+            0x73, 0x73, 0x93,
+            0x63, 0xc0, 0x83, 0x68, 0xca, 0x81, 0x32,
+            0x67, 0xc8, 0x89, 0x45, 0xa3, 0x82, b'h', b'i', 0x44, 0x87, 0x81, b'!',
+        ];
+        let mut input = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        input.extend(b"webm");
+        input.extend(&[0x18, 0x53, 0x80, 0x67, 0x80 | tags.len() as u8]);
+        input.extend(&tags);
+
+        let mut output = Vec::new();
+        scrub(Cursor::new(&input), &mut output, &ScrubPolicy::default()).unwrap();
+        let document = WebmFile::from_bytes(&output).unwrap();
+        assert!(document.root.get_tags().is_empty());
+
+        // the 5 byte header starts with a shorter Void header, covering the
+        // rest of it along with the payload
+        let void = document.root.get_children()[0].get_element();
+        assert_eq!(void.id, ID_VOID);
+        assert_eq!((void.offset, void.end_offset()), (17, input.len() as u64));
+        assert_eq!(&output[17..19], &[0xec, 0x80 | 25]);
+    }
+}
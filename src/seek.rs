@@ -0,0 +1,581 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use crate::block::{cluster_packets_rev, Packet};
+use crate::consts::*;
+use crate::ebml::{WebmReader, ClusterNode, is_segment_child};
+use crate::error::Error;
+use crate::index::{Index, IndexCluster};
+
+// How much is read at a time when looking for the next Cluster ID
+const RESYNC_CHUNK: u64 = 64 * 1024;
+// How much of the end of the file is searched first for the last Cluster,
+// doubling until one is found
+const TAIL_LENGTH: u64 = 1024 * 1024;
+
+// Seeking in files without Cues. Rather than parsing the whole file up
+// front, Cluster headers are read on demand: only each Cluster's Timestamp
+// is read and everything else is stepped over using element sizes. Every
+// Cluster found is cached, so later seeks only scan what hasn't been seen.
+// Offsets are relative to the start of the Segment data, as in `Index`.
+// For very large files `bisect` finds a Cluster in a few reads instead,
+// and `last` and `prev` walk backwards from the end.
+pub struct ClusterSeeker<T: Read + Seek> {
+    reader: WebmReader<T>,
+    // absolute offset to timestamp of every Cluster found so far
+    clusters: BTreeMap<u64, u64>,
+    // the linear scan has seen every Cluster before this absolute offset
+    scanned_to: u64,
+    done: bool,
+}
+
+impl<T: Read + Seek> ClusterSeeker<T> {
+    pub fn new(r: T) -> Result<ClusterSeeker<T>, Error> {
+        let mut reader = WebmReader::new(r);
+        reader.parse_header()?;
+        let scanned_to = reader.position()?;
+        Ok(ClusterSeeker {
+            reader,
+            clusters: BTreeMap::new(),
+            scanned_to,
+            done: false,
+        })
+    }
+
+    // Take the Clusters from an index of the same file, so nothing needs
+    // to be scanned
+    pub fn with_index(mut self, index: &Index) -> Self {
+        let base = self.segment_data_offset();
+        for cluster in &index.clusters {
+            self.clusters.insert(base + cluster.offset, cluster.timestamp);
+        }
+        // the index lists every Cluster, so there's nothing left to scan
+        self.scanned_to = u64::MAX;
+        self.done = true;
+        self
+    }
+
+    // The Clusters found so far, in file order
+    pub fn clusters(&self) -> Vec<IndexCluster> {
+        let base = self.segment_data_offset();
+        self.clusters
+            .iter()
+            .map(|(&offset, &timestamp)| IndexCluster { offset: offset - base, timestamp })
+            .collect()
+    }
+
+    // Find the last Cluster starting at or before `timestamp`, scanning
+    // forward from where earlier seeks stopped only as far as needed
+    pub fn seek(&mut self, timestamp: u64) -> Result<Option<IndexCluster>, Error> {
+        while !self.done && !self.scanned_past(timestamp) {
+            self.scan_next()?;
+        }
+        let base = self.segment_data_offset();
+        Ok(self.clusters
+            .range(..self.scanned_to)
+            .rev()
+            .find(|(_, &t)| t <= timestamp)
+            .map(|(&offset, &timestamp)| IndexCluster { offset: offset - base, timestamp }))
+    }
+
+    // Find the same Cluster as `seek` by bisection: jump into the middle of
+    // the range still to search, resync on the next Cluster ID, read its
+    // Timestamp and halve the range. Clusters found by earlier calls narrow
+    // the starting range. Cluster IDs that happen to appear inside block
+    // data are told apart by checking that the Cluster's size ends on
+    // another top level element, so this suits files with known sizes.
+    pub fn bisect(&mut self, timestamp: u64) -> Result<Option<IndexCluster>, Error> {
+        let mut best = self.clusters.iter().rev().find(|(_, &t)| t <= timestamp).map(|(&o, &t)| (o, t));
+        let mut low = best.map(|(offset, _)| offset + 1).unwrap_or_else(|| self.segment_data_offset());
+        let mut high = match self.clusters.iter().find(|(_, &t)| t > timestamp) {
+            Some((&offset, _)) => offset,
+            None => self.segment_end()?,
+        };
+
+        // everything before `low` is at or before the target, everything
+        // from `high` on is after it
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.next_cluster(middle, high)? {
+                Some((offset, cluster_timestamp)) if cluster_timestamp <= timestamp => {
+                    best = Some((offset, cluster_timestamp));
+                    low = offset + 1;
+                },
+                Some((offset, _)) => high = offset,
+                None => high = middle,
+            }
+        }
+
+        let base = self.segment_data_offset();
+        Ok(best.map(|(offset, timestamp)| IndexCluster { offset: offset - base, timestamp }))
+    }
+
+    // The last Cluster in the file, e.g. to work back from for the last
+    // few seconds
+    pub fn last(&mut self) -> Result<Option<IndexCluster>, Error> {
+        self.bisect(u64::MAX)
+    }
+
+    // For files with neither a Duration in Info nor Cues: resync on the
+    // last Cluster from near the end of the file and read it for the end
+    // of its last block. None if there are no Clusters at all.
+    pub fn estimate_duration(&mut self) -> Result<Option<DurationEstimate>, Error> {
+        let (start, end) = (self.segment_data_offset(), self.segment_end()?);
+        let mut tail = TAIL_LENGTH;
+        let (offset, timestamp) = loop {
+            let from = end.saturating_sub(tail).max(start);
+            let mut last = None;
+            while let Some((offset, timestamp)) = self.next_cluster(last.map_or(from, |(o, _)| o + 1), end)? {
+                last = Some((offset, timestamp));
+            }
+            match last {
+                Some(last) => break last,
+                None if from == start => return Ok(None),
+                None => tail = tail.saturating_mul(2),
+            }
+        };
+
+        self.reader.seek_to(offset)?;
+        let element = self.reader.parse_element()?;
+        let complete = element.is_unknown_size() || element.end_offset() <= self.reader.stream_len()?;
+        let cluster = IndexCluster { offset: offset - start, timestamp };
+        let last_end = match self.read_cluster(&cluster) {
+            Ok(node) if complete => cluster_packets_rev(node).iter()
+                .map(|packet| packet.timestamp.saturating_add(packet.duration.unwrap_or(0)))
+                .max(),
+            _ => None,
+        };
+        Ok(Some(DurationEstimate {
+            duration: last_end.unwrap_or(timestamp).max(timestamp),
+            confident: last_end.is_some(),
+        }))
+    }
+
+    // The Cluster before `cluster`, for walking backwards. Clusters that
+    // record their PrevSize lead straight to the previous one, unless their
+    // Position shows they have moved since it was written. Otherwise the
+    // Clusters found so far or taken from an index are used, scanning
+    // forward from where earlier scans stopped if needed.
+    pub fn prev(&mut self, cluster: &IndexCluster) -> Result<Option<IndexCluster>, Error> {
+        let base = self.segment_data_offset();
+        let offset = base + cluster.offset;
+        let (position, prev_size) = self.back_links(offset)?;
+        let previous = prev_size
+            .filter(|_| position.is_none_or(|position| position == cluster.offset))
+            .and_then(|size| offset.checked_sub(size))
+            .filter(|previous| *previous >= base);
+        if let Some(previous) = previous {
+            if let Some(timestamp) = self.cluster_timestamp(previous)? {
+                self.reader.seek_to(previous)?;
+                let element = self.reader.parse_element()?;
+                if element.is_unknown_size() || element.end_offset() == offset {
+                    self.clusters.insert(previous, timestamp);
+                    return Ok(Some(IndexCluster { offset: previous - base, timestamp }));
+                }
+            }
+        }
+
+        while !self.done && self.scanned_to <= offset {
+            self.scan_next()?;
+        }
+        Ok(self.clusters
+            .range(..offset)
+            .next_back()
+            .map(|(&offset, &timestamp)| IndexCluster { offset: offset - base, timestamp }))
+    }
+
+    // Packets from the end of the file backwards, reading one Cluster at
+    // a time, see `PacketsRev`
+    pub fn packets_rev(&mut self) -> SeekerPacketsRev<'_, T> {
+        SeekerPacketsRev {
+            seeker: self,
+            cluster: None,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    // Fully parse a Cluster returned by `seek` or `clusters`
+    pub fn read_cluster(&mut self, cluster: &IndexCluster) -> Result<ClusterNode, Error> {
+        let offset = self.segment_data_offset() + cluster.offset;
+        self.reader.seek_to(offset)?;
+        Ok(ClusterNode::from(self.reader.build_node_tree()?))
+    }
+
+    fn segment_data_offset(&self) -> u64 {
+        self.reader.segment_element().map(|s| s.data_offset()).unwrap_or(0)
+    }
+
+    fn segment_end(&mut self) -> Result<u64, Error> {
+        let len = self.reader.stream_len()?;
+        Ok(match self.reader.segment_element() {
+            Some(segment) if !segment.is_unknown_size() => segment.end_offset().min(len),
+            _ => len,
+        })
+    }
+
+    fn truncated(&mut self) -> Result<bool, Error> {
+        let len = self.reader.stream_len()?;
+        Ok(self.reader.segment_element().is_some_and(|segment| !segment.is_unknown_size() && segment.end_offset() > len))
+    }
+
+    fn scanned_past(&self, timestamp: u64) -> bool {
+        self.clusters.range(..self.scanned_to).any(|(_, &t)| t > timestamp)
+    }
+
+    // Step over the next top level element, noting it if it's a Cluster
+    fn scan_next(&mut self) -> Result<(), Error> {
+        let offset = self.scanned_to;
+        let limit = self.reader.stream_len()?;
+        self.reader.seek_to(offset)?;
+        if offset >= self.segment_end()? || !self.reader.element_header_available()? {
+            self.done = true;
+            return Ok(());
+        }
+        // an element of unknown size that runs to the end of the stream
+        // is the last one
+        let end = self.reader.complete_element_end(limit)?;
+        if let Some(timestamp) = self.cluster_timestamp(offset)? {
+            self.clusters.insert(offset, timestamp);
+        }
+        match end {
+            Some(end) => self.scanned_to = end,
+            None => {
+                self.scanned_to = limit;
+                self.done = true;
+            },
+        }
+        Ok(())
+    }
+
+    // The first Cluster starting in `from..to`, cached once found
+    fn next_cluster(&mut self, from: u64, to: u64) -> Result<Option<(u64, u64)>, Error> {
+        let pattern = (ID_CLUSTERNODE as u32).to_be_bytes();
+        let mut start = from;
+        while start < to {
+            // overlap the chunks so an ID split between them is still seen
+            let chunk = self.reader.read_raw_at(start, RESYNC_CHUNK + 3)?;
+            let candidates: Vec<u64> = chunk.windows(4)
+                .enumerate()
+                .filter(|(_, window)| *window == pattern)
+                .map(|(i, _)| start + i as u64)
+                .take_while(|offset| *offset < to)
+                .collect();
+            for offset in candidates {
+                // a bad header means the ID was part of some other data,
+                // but a failing source is still an error
+                let timestamp = match self.cluster_timestamp(offset) {
+                    Ok(Some(timestamp)) => timestamp,
+                    Err(Error::Io(e)) => return Err(Error::Io(e)),
+                    _ => continue,
+                };
+                if self.ends_on_boundary(offset)? {
+                    self.clusters.insert(offset, timestamp);
+                    return Ok(Some((offset, timestamp)));
+                }
+            }
+            if (chunk.len() as u64) < RESYNC_CHUNK + 3 {
+                break;
+            }
+            start += RESYNC_CHUNK;
+        }
+        Ok(None)
+    }
+
+    // Whether the Cluster at `offset` is followed by another top level
+    // element or the end of the Segment, as a real one would be
+    fn ends_on_boundary(&mut self, offset: u64) -> Result<bool, Error> {
+        self.reader.seek_to(offset)?;
+        let cluster = self.reader.parse_element()?;
+        if cluster.is_unknown_size() {
+            return Ok(true);
+        }
+        let end = cluster.end_offset();
+        let segment_end = self.segment_end()?;
+        if end >= segment_end {
+            // the last Cluster of a file that was cut off runs past the data
+            return Ok(end == segment_end || self.truncated()?);
+        }
+        self.reader.seek_to(end)?;
+        Ok(self.reader.peek_element_id()?.is_some_and(is_segment_child))
+    }
+
+    // The Position and PrevSize of the Cluster at `offset`, which come
+    // before its blocks
+    fn back_links(&mut self, offset: u64) -> Result<(Option<u64>, Option<u64>), Error> {
+        self.reader.seek_to(offset)?;
+        let cluster = self.reader.parse_element()?;
+        let (mut position, mut prev_size) = (None, None);
+        while cluster.is_unknown_size() || self.reader.position()? < cluster.end_offset() {
+            match self.reader.peek_element_id()? {
+                Some(ID_POSITION) => position = Some(self.reader.parse_element()?.data.into_uint()),
+                Some(ID_PREVSIZE) => prev_size = Some(self.reader.parse_element()?.data.into_uint()),
+                Some(ID_TIMESTAMP) | Some(ID_CRC32) | Some(ID_VOID) => {
+                    self.reader.parse_element()?;
+                },
+                _ => break,
+            }
+        }
+        Ok((position, prev_size))
+    }
+
+    // The Timestamp of the Cluster at `offset`, reading no further than
+    // the Timestamp itself. None if there is no Cluster there, or if its
+    // Timestamp doesn't come before the blocks as it should.
+    fn cluster_timestamp(&mut self, offset: u64) -> Result<Option<u64>, Error> {
+        self.reader.seek_to(offset)?;
+        if self.reader.peek_element_id()? != Some(ID_CLUSTERNODE) {
+            return Ok(None);
+        }
+        let cluster = self.reader.parse_element()?;
+        // a Cluster can't run past the end of the Segment, nor a child past
+        // the end of its Cluster
+        let fits = |end: u64| cluster.is_unknown_size() || end <= cluster.end_offset();
+        let outside = self.reader.segment_element().is_some_and(|segment| {
+            !segment.is_unknown_size() && !cluster.is_unknown_size() && cluster.end_offset() > segment.end_offset()
+        });
+        if outside {
+            return Ok(None);
+        }
+        while cluster.is_unknown_size() || self.reader.position()? < cluster.end_offset() {
+            match self.reader.peek_element_id()? {
+                Some(ID_TIMESTAMP) => {
+                    let element = self.reader.parse_element()?;
+                    return Ok(Some(element.data.into_uint()).filter(|_| fits(element.end_offset())));
+                },
+                Some(ID_CRC32) | Some(ID_VOID) => {
+                    self.reader.parse_element()?;
+                },
+                _ => break,
+            }
+        }
+        Ok(None)
+    }
+}
+
+// What `ClusterSeeker::estimate_duration` makes of a file without a
+// Duration or Cues: how long it is going by its last Cluster, in
+// TimestampScale units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurationEstimate {
+    pub duration: u64,
+    // whether the last Cluster could be read whole, so this is the end of
+    // its last block rather than just where the Cluster starts
+    pub confident: bool,
+}
+
+pub struct SeekerPacketsRev<'a, T: Read + Seek> {
+    seeker: &'a mut ClusterSeeker<T>,
+    // the Cluster `pending` came from
+    cluster: Option<IndexCluster>,
+    pending: Vec<Packet>,
+    started: bool,
+}
+
+impl<T: Read + Seek> SeekerPacketsRev<'_, T> {
+    fn next_cluster(&mut self) -> Result<Option<IndexCluster>, Error> {
+        match self.cluster {
+            Some(cluster) => self.seeker.prev(&cluster),
+            None if !self.started => {
+                self.started = true;
+                self.seeker.last()
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T: Read + Seek> Iterator for SeekerPacketsRev<'_, T> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Result<Packet, Error>> {
+        while self.pending.is_empty() {
+            let cluster = match self.next_cluster() {
+                Ok(cluster) => cluster?,
+                Err(e) => return Some(Err(e)),
+            };
+            self.cluster = Some(cluster);
+            match self.seeker.read_cluster(&cluster) {
+                Ok(node) => self.pending = cluster_packets_rev(node),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.pending.pop().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Error as IOError, ErrorKind, SeekFrom};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn builder() -> WebmBuilder {
+        let mut builder = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).without_cues();
+        for second in 0..10 {
+            builder = builder.cluster(second * 1000)
+                .frame(1, 0, true, &[0; 64])
+                .frame(1, 500, false, &[0; 64]);
+        }
+        builder
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut seeker = ClusterSeeker::new(Cursor::new(builder().build())).unwrap();
+        let cluster = seeker.seek(2500).unwrap().unwrap();
+        assert_eq!(cluster.timestamp, 2000);
+        // only scanned up to the first Cluster after the target
+        assert_eq!(seeker.clusters().len(), 4);
+
+        let node = seeker.read_cluster(&cluster).unwrap();
+        assert_eq!(node.get_timestamp(), 2000);
+        assert_eq!(node.get_simple_blocks().len(), 2);
+
+        // cached Clusters are reused
+        assert_eq!(seeker.seek(1999).unwrap().unwrap().timestamp, 1000);
+        assert_eq!(seeker.clusters().len(), 4);
+
+        assert_eq!(seeker.seek(60_000).unwrap().unwrap().timestamp, 9000);
+        assert_eq!(seeker.clusters().len(), 10);
+        assert_eq!(seeker.seek(0).unwrap().unwrap().offset, seeker.clusters()[0].offset);
+    }
+
+    #[test]
+    fn test_seek_unknown_sizes() {
+        let data = builder().unknown_size_clusters().unknown_size_segment().build();
+        let mut seeker = ClusterSeeker::new(Cursor::new(data)).unwrap();
+        assert_eq!(seeker.seek(7200).unwrap().unwrap().timestamp, 7000);
+        assert_eq!(seeker.seek(99_999).unwrap().unwrap().timestamp, 9000);
+        assert_eq!(seeker.clusters().len(), 10);
+    }
+
+    // A source that can't find its end, like a network stream of unknown
+    // length
+    struct NoEnd(Cursor<Vec<u8>>);
+
+    impl Read for NoEnd {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, IOError> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for NoEnd {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, IOError> {
+            match pos {
+                SeekFrom::End(_) => Err(IOError::new(ErrorKind::Unsupported, "no end")),
+                pos => self.0.seek(pos),
+            }
+        }
+    }
+
+    #[test]
+    fn test_source_errors() {
+        let mut seeker = ClusterSeeker::new(NoEnd(Cursor::new(builder().build()))).unwrap();
+        assert!(matches!(seeker.seek(2500), Err(Error::Io(_))));
+        assert!(matches!(seeker.bisect(2500), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_prev() {
+        let mut seeker = ClusterSeeker::new(Cursor::new(builder().prev_sizes().build())).unwrap();
+        let mut cluster = seeker.last().unwrap().unwrap();
+        assert_eq!(cluster.timestamp, 9000);
+        let mut timestamps = vec![cluster.timestamp];
+        while let Some(previous) = seeker.prev(&cluster).unwrap() {
+            timestamps.push(previous.timestamp);
+            cluster = previous;
+        }
+        assert_eq!(timestamps, (0..10).rev().map(|s| s * 1000).collect::<Vec<u64>>());
+        // PrevSize led the way, so nothing was scanned
+        assert!(!seeker.done);
+
+        // without PrevSize, the file is scanned once
+        let mut seeker = ClusterSeeker::new(Cursor::new(builder().build())).unwrap();
+        let last = seeker.last().unwrap().unwrap();
+        let previous = seeker.prev(&last).unwrap().unwrap();
+        assert_eq!(previous.timestamp, 8000);
+        assert_eq!(seeker.clusters().len(), 10);
+        assert_eq!(seeker.prev(&seeker.clusters()[0]).unwrap(), None);
+
+        // or not at all with an index
+        let data = builder().build();
+        let index = Index::build(&crate::ebml::WebmFile::from_bytes(&data).unwrap());
+        let mut seeker = ClusterSeeker::new(Cursor::new(data)).unwrap().with_index(&index);
+        assert_eq!(seeker.prev(&index.clusters[5]).unwrap(), Some(index.clusters[4]));
+    }
+
+    #[test]
+    fn test_packets_rev() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .prev_sizes()
+            .cluster(0)
+            .frame(1, 0, true, &[0])
+            .frame(1, 80, false, &[1])
+            .frame(1, 40, false, &[2])
+            .frame(2, 40, true, &[3])
+            .cluster(1000)
+            .frame(1, 0, true, &[4])
+            .frame(2, 10, true, &[5])
+            .build();
+        let document = crate::ebml::WebmFile::from_bytes(&data).unwrap();
+        let forwards: Vec<u8> = document.packets().map(|p| p.data[0]).collect();
+        assert_eq!(forwards, vec![0, 1, 2, 3, 4, 5]);
+        let backwards: Vec<u8> = document.packets_rev().map(|p| p.data[0]).collect();
+        assert_eq!(backwards, vec![5, 4, 1, 3, 2, 0]);
+
+        let mut seeker = ClusterSeeker::new(Cursor::new(data)).unwrap();
+        let lazily: Vec<u8> = seeker.packets_rev().map(|p| p.unwrap().data[0]).collect();
+        assert_eq!(lazily, backwards);
+        assert!(!seeker.done);
+    }
+
+    #[test]
+    fn test_estimate_duration() {
+        let data = builder().build();
+        let mut seeker = ClusterSeeker::new(Cursor::new(data.clone())).unwrap();
+        let estimate = seeker.estimate_duration().unwrap().unwrap();
+        assert_eq!(estimate, DurationEstimate { duration: 9500, confident: true });
+
+        // cut off inside the last Cluster
+        let truncated = data[..data.len() - 10].to_vec();
+        let mut seeker = ClusterSeeker::new(Cursor::new(truncated)).unwrap();
+        assert_eq!(seeker.estimate_duration().unwrap(), Some(DurationEstimate { duration: 9000, confident: false }));
+
+        // Cues and Duration in the sample aren't needed
+        let sample = std::fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut seeker = ClusterSeeker::new(Cursor::new(sample)).unwrap();
+        let estimate = seeker.estimate_duration().unwrap().unwrap();
+        assert!(estimate.confident);
+        assert!((32_000..=32_480).contains(&estimate.duration), "{}", estimate.duration);
+
+        let data = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).without_cues().build();
+        assert_eq!(ClusterSeeker::new(Cursor::new(data)).unwrap().estimate_duration().unwrap(), None);
+    }
+
+    #[test]
+    fn test_bisect() {
+        // block data holding things that look like the start of a Cluster
+        let mut builder = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).without_cues();
+        for second in 0..100 {
+            builder = builder.cluster(second * 1000)
+                .frame(1, 0, true, &[0x1f, 0x43, 0xb6, 0x75, 0x00])
+                .frame(1, 500, false, &[0x1f, 0x43, 0xb6, 0x75, 0x84, 0xe7, 0x81, 0x05, 0x00, 0x00, 0x00]);
+        }
+        let data = builder.build();
+        let mut linear = ClusterSeeker::new(Cursor::new(data.clone())).unwrap();
+        let mut seeker = ClusterSeeker::new(Cursor::new(data)).unwrap();
+
+        for target in [0, 999, 42_000, 42_999, 99_000, 1_000_000] {
+            let expected = linear.seek(target).unwrap();
+            assert_eq!(seeker.bisect(target).unwrap(), expected, "{}", target);
+        }
+        // far fewer Clusters were read than a scan would have
+        assert!(seeker.clusters().len() < 40);
+        assert!(seeker.clusters().iter().all(|c| c.timestamp % 1000 == 0));
+
+        let cluster = seeker.bisect(61_234).unwrap().unwrap();
+        assert_eq!(seeker.read_cluster(&cluster).unwrap().get_timestamp(), 61_000);
+    }
+}
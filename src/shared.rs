@@ -0,0 +1,86 @@
+use std::ops::Range;
+use std::sync::Arc;
+use crate::ebml::{Element, WebmFile};
+use crate::error::Error;
+
+// A parsed document together with the bytes it was parsed from, for
+// worker threads that answer queries about a file and serve byte ranges of
+// it, e.g. Clusters to many clients. Clones share both, so handing one to
+// each thread copies nothing.
+//
+//     let shared = SharedWebm::new(fs::read(path)?)?;
+//     let worker = shared.clone();
+//     thread::spawn(move || {
+//         let cluster = &worker.document().root.get_clusters()[0];
+//         send(worker.element_bytes(&cluster.get_element()));
+//     });
+#[derive(Clone)]
+pub struct SharedWebm {
+    document: Arc<WebmFile>,
+    data: Arc<[u8]>,
+}
+
+impl SharedWebm {
+    pub fn new(data: impl Into<Arc<[u8]>>) -> Result<SharedWebm, Error> {
+        let data = data.into();
+        let document = Arc::new(WebmFile::from_bytes(&data)?);
+        Ok(SharedWebm { document, data })
+    }
+
+    pub fn document(&self) -> &WebmFile {
+        &self.document
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // The bytes in `range`, cut short at the end of the data. None if it
+    // starts past the end.
+    pub fn range(&self, range: Range<u64>) -> Option<&[u8]> {
+        let len = self.data.len() as u64;
+        if range.start > len {
+            return None;
+        }
+        Some(&self.data[range.start as usize..range.end.clamp(range.start, len) as usize])
+    }
+
+    // An element as it is in the file, header included. An unknown-sized
+    // one runs to the end of the data.
+    pub fn element_bytes(&self, element: &Element) -> Option<&[u8]> {
+        self.range(element.offset..element.end_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread;
+    use super::*;
+
+    #[test]
+    fn test_shared() {
+        let data = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let shared = SharedWebm::new(data.clone()).unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let cluster = shared.document().root.get_clusters()[0].get_element();
+                    shared.element_bytes(&cluster).unwrap().to_vec()
+                })
+            })
+            .collect();
+        let cluster = shared.document().root.get_clusters()[0].get_element();
+        let expected = &data[cluster.offset as usize..cluster.end_offset() as usize];
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), expected);
+        }
+
+        assert_eq!(shared.range(0..4), Some(&data[..4]));
+        assert_eq!(shared.range(data.len() as u64 - 2..u64::MAX), Some(&data[data.len() - 2..]));
+        assert_eq!(shared.range(data.len() as u64 + 1..u64::MAX), None);
+        assert!(SharedWebm::new(vec![0; 16]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Read, Seek, SeekFrom, Error as IOError};
+use crate::ebml::{WebmFile, SignatureSlotNode};
+
+// The contents of a SignatureSlot in a form that's easy to hand to a
+// crypto library
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    // 1 = RSA, 2 = elliptic
+    pub algorithm: Option<u64>,
+    // 1 = SHA1-160, 2 = MD5
+    pub hash: Option<u64>,
+    pub public_key: Option<Vec<u8>>,
+    pub signature: Option<Vec<u8>>,
+    // IDs of the Segment children covered by the signature, in order
+    pub signed_elements: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureCheck {
+    pub signature: Signature,
+    pub valid: bool,
+}
+
+impl From<&SignatureSlotNode> for Signature {
+    fn from(slot: &SignatureSlotNode) -> Signature {
+        Signature {
+            algorithm: slot.get_algorithm(),
+            hash: slot.get_hash(),
+            public_key: slot.get_public_key(),
+            signature: slot.get_signature(),
+            signed_elements: slot.get_element_lists()
+                .iter()
+                .flat_map(|list| list.get_signed_elements())
+                .collect(),
+        }
+    }
+}
+
+impl Signature {
+    // Raw bytes (headers included) of every Segment child the signature
+    // covers, concatenated in the order the IDs are listed
+    pub fn signed_data<T: Read + Seek>(
+        &self,
+        document: &WebmFile,
+        source: &mut T,
+    ) -> Result<Vec<u8>, IOError> {
+        let children = document.root.get_children();
+        let mut data = Vec::new();
+        for id in self.signed_elements.iter() {
+            for element in children.iter().map(|n| n.get_element()).filter(|e| e.id == *id) {
+                source.seek(SeekFrom::Start(element.offset))?;
+                (&mut *source)
+                    .take(element.end_offset() - element.offset)
+                    .read_to_end(&mut data)?;
+            }
+        }
+        Ok(data)
+    }
+}
+
+// Check every SignatureSlot in the Segment. The crate doesn't do any
+// cryptography itself, `verify` is called with each signature and the bytes
+// it covers and decides whether it's valid.
+pub fn verify_signatures<T, F>(
+    document: &WebmFile,
+    source: &mut T,
+    mut verify: F,
+) -> Result<Vec<SignatureCheck>, IOError>
+where
+    T: Read + Seek,
+    F: FnMut(&Signature, &[u8]) -> bool,
+{
+    let mut checks = Vec::new();
+    for slot in document.root.get_signature_slots() {
+        let signature = Signature::from(&slot);
+        let data = signature.signed_data(document, source)?;
+        let valid = verify(&signature, &data);
+        checks.push(SignatureCheck { signature, valid });
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn signed_document() -> Vec<u8> {
+        let mut data = vec![0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+        data.extend(b"webm");
+        data.extend(&[0x18, 0x53, 0x80, 0x67, 0xa5]);
+        // Info with just a TimestampScale
+        data.extend(&[0x15, 0x49, 0xa9, 0x66, 0x85, 0x2a, 0xd7, 0xb1, 0x81, 0x01]);
+        // SignatureSlot signing the Info element
+        data.extend(&[0x1b, 0x53, 0x86, 0x67, 0x96]);
+        data.extend(&[0x7e, 0x8a, 0x81, 0x01, 0x7e, 0xb5, 0x82, 0xde, 0xad]);
+        data.extend(&[0x7e, 0x5b, 0x89, 0x7e, 0x7b, 0x86, 0x65, 0x32, 0x84, 0x15, 0x49, 0xa9, 0x66]);
+        data
+    }
+
+    #[test]
+    fn test_parse_signature_slot() {
+        let data = signed_document();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let slots = document.root.get_signature_slots();
+        let signature = Signature::from(&slots[0]);
+        assert_eq!(signature.algorithm, Some(1));
+        assert_eq!(signature.hash, None);
+        assert_eq!(signature.signature, Some(vec![0xde, 0xad]));
+        assert_eq!(signature.signed_elements, vec![0x1549a966]);
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let data = signed_document();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let mut source = Cursor::new(&data);
+
+        let checks = verify_signatures(&document, &mut source, |signature, signed| {
+            signature.signature == Some(vec![0xde, 0xad])
+                && signed == [0x15, 0x49, 0xa9, 0x66, 0x85, 0x2a, 0xd7, 0xb1, 0x81, 0x01]
+        }).unwrap();
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].valid);
+
+        let checks = verify_signatures(&document, &mut source, |_, _| false).unwrap();
+        assert!(!checks[0].valid);
+    }
+}
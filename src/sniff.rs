@@ -0,0 +1,109 @@
+use std::io::Read;
+use crate::consts::*;
+use crate::error::Error;
+use crate::vint;
+
+// Content type detection from the first bytes of a stream, for services
+// that need to tell WebM from Matroska without parsing either
+
+// Most bytes read, plenty for an EBML header
+const SNIFF_LENGTH: u64 = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sniff {
+    Webm,
+    Matroska,
+    // an EBML document of another DocType
+    Ebml(String),
+    // no EBML magic number
+    NotEbml,
+}
+
+// Check the magic number and DocType, reading at most a few hundred bytes.
+// A header without a DocType is Matroska, the spec default. Errors are
+// for failed reads and for EBML headers too broken or long to hold one.
+pub fn sniff<R: Read>(reader: R) -> Result<Sniff, Error> {
+    let mut data = Vec::new();
+    reader.take(SNIFF_LENGTH).read_to_end(&mut data)?;
+    if !data.starts_with(&MAGIC_NUMBER) {
+        return Ok(Sniff::NotEbml);
+    }
+
+    let (size, len) = vint::parse(&data[4..]).ok_or_else(|| vint_error(&data, 4))?;
+    let start = 4 + len;
+    let end = start.saturating_add(size.min(SNIFF_LENGTH) as usize);
+    let mut offset = start;
+    let mut doc_type = None;
+    while offset < end.min(data.len()) {
+        let (id, id_len) = vint::read_raw(&data[offset..]).ok_or_else(|| vint_error(&data, offset))?;
+        let (size, size_len) = vint::parse(&data[offset + id_len..])
+            .ok_or_else(|| vint_error(&data, offset + id_len))?;
+        let payload = offset + id_len + size_len;
+        let next = payload.saturating_add(size.min(SNIFF_LENGTH) as usize);
+        if id == ID_DOCTYPE {
+            let value = data.get(payload..next).ok_or(Error::UnexpectedEof { offset: offset as u64 })?;
+            doc_type = Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string());
+            break;
+        }
+        offset = next;
+    }
+    if doc_type.is_none() && end > data.len() {
+        return Err(Error::UnexpectedEof { offset: 0 });
+    }
+
+    Ok(match doc_type.as_deref() {
+        Some("webm") => Sniff::Webm,
+        Some("matroska") | None => Sniff::Matroska,
+        Some(other) => Sniff::Ebml(other.to_string()),
+    })
+}
+
+// Why a vint at `offset` couldn't be read
+fn vint_error(data: &[u8], offset: usize) -> Error {
+    match data.get(offset).map(|&first| vint::length(first)) {
+        Some(None) => Error::InvalidVint { offset: offset as u64 },
+        _ => Error::UnexpectedEof { offset: offset as u64 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use crate::ebml::encode::{write_element, write_string, write_uint};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn header(children: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_element(&mut data, ID_EBMLHEADERNODE, children);
+        data
+    }
+
+    #[test]
+    fn test_sniff() {
+        let f = File::open("./sample/big-buck-bunny_trailer.webm").unwrap();
+        assert_eq!(sniff(f).unwrap(), Sniff::Webm);
+        let data = WebmBuilder::new().doc_type("matroska").build();
+        assert_eq!(sniff(&data[..]).unwrap(), Sniff::Matroska);
+        assert_eq!(sniff(&b"\x00\x00\x00\x18ftypisom"[..]).unwrap(), Sniff::NotEbml);
+        assert_eq!(sniff(&[][..]).unwrap(), Sniff::NotEbml);
+
+        let mut children = Vec::new();
+        write_uint(&mut children, ID_EBMLVERSION, 1);
+        write_string(&mut children, ID_DOCTYPE, "webm\0\0");
+        assert_eq!(sniff(&header(&children)[..]).unwrap(), Sniff::Webm);
+
+        let mut children = Vec::new();
+        write_string(&mut children, ID_DOCTYPE, "mka3d");
+        assert_eq!(sniff(&header(&children)[..]).unwrap(), Sniff::Ebml("mka3d".to_string()));
+
+        // no DocType means the default
+        let mut children = Vec::new();
+        write_uint(&mut children, ID_EBMLVERSION, 1);
+        assert_eq!(sniff(&header(&children)[..]).unwrap(), Sniff::Matroska);
+
+        // cut off before the DocType
+        let data = WebmBuilder::new().build();
+        assert!(matches!(sniff(&data[..8]), Err(Error::UnexpectedEof { .. })));
+    }
+}
@@ -0,0 +1,263 @@
+use crate::block::superframe_frames;
+use crate::consts::*;
+use crate::ebml::Node;
+
+// Temporal layers of VP9 streams, for thinning a scalable (SVC) stream
+// down to a lower frame rate without decoding, see
+// `Packets::max_temporal_layer`. A layer the stream signals itself in a
+// BlockAddition is used as is. Otherwise it's worked out from the frame
+// headers: intra frames are layer 0, and an inter frame is on the highest
+// layer among the reference slots it names, or one above that when it
+// doesn't refresh any of them. This can rank frames differently than the
+// encoder did, but a frame is never on a lower layer than one it may
+// reference, so dropping the layers above some point leaves a stream that
+// still decodes.
+
+// VP9 keeps eight reference frames
+const SLOTS: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct TemporalLayers {
+    // the layer of the block that last refreshed each slot
+    slots: [u8; SLOTS],
+}
+
+impl TemporalLayers {
+    pub fn new() -> TemporalLayers {
+        TemporalLayers::default()
+    }
+
+    // The layer of a block's payload, a single frame or a superframe,
+    // noting it for the slots it refreshes. `signalled` is a layer the
+    // stream gives for the block. Frames that can't be parsed are kept on
+    // layer 0.
+    pub fn block_layer(&mut self, data: &[u8], signalled: Option<u8>) -> u8 {
+        let frames = superframe_frames(data).unwrap_or_else(|| vec![data]);
+        let mut slots = self.slots;
+        let mut layer = 0;
+        let mut refreshed = 0u8;
+        for frame in frames {
+            let (frame_layer, refresh) = match FrameHeader::parse(frame) {
+                Some(FrameHeader::ShowExisting(slot)) => (slots[slot], 0),
+                Some(FrameHeader::Intra { refresh }) => (0, refresh),
+                Some(FrameHeader::Inter { refresh, references }) => {
+                    let base = references.iter().map(|&slot| slots[slot]).max().unwrap_or(0);
+                    let named = references.iter().fold(0u8, |mask, &slot| mask | 1 << slot);
+                    match refresh & named {
+                        0 => (base.saturating_add(1), refresh),
+                        _ => (base, refresh),
+                    }
+                },
+                None => (0, 0),
+            };
+            // later frames of a superframe may reference earlier ones
+            for (slot, value) in slots.iter_mut().enumerate() {
+                if refresh & 1 << slot != 0 {
+                    *value = frame_layer;
+                }
+            }
+            layer = layer.max(frame_layer);
+            refreshed |= refresh;
+        }
+
+        // the block is kept or dropped as a whole
+        let layer = signalled.unwrap_or(layer);
+        for (slot, value) in self.slots.iter_mut().enumerate() {
+            if refreshed & 1 << slot != 0 {
+                *value = layer;
+            }
+        }
+        layer
+    }
+}
+
+// The first byte of the BlockAdditional with this BlockAddID in a
+// BlockGroup, where a stream may give each block's temporal layer
+pub fn signalled_layer(block_group: &Node, add_id: u64) -> Option<u8> {
+    block_group.children().iter()
+        .filter(|child| child.element().id == ID_BLOCKADDITIONSNODE)
+        .flat_map(|additions| additions.children())
+        .filter(|more| more.element().id == ID_BLOCKMORENODE)
+        .find(|more| {
+            let id = more.children().iter().find(|child| child.element().id == ID_BLOCKADDID);
+            id.map_or(1, |id| id.element().data.into_uint()) == add_id
+        })
+        .and_then(|more| more.children().iter().find(|child| child.element().id == ID_BLOCKADDITIONAL))
+        .and_then(|additional| additional.element().data.as_bytes().first().copied())
+}
+
+// What a VP9 uncompressed header says about reference slots
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameHeader {
+    // shows the frame in a slot again without decoding anything
+    ShowExisting(usize),
+    // keyframes, which refresh every slot, and intra-only frames
+    Intra { refresh: u8 },
+    Inter { refresh: u8, references: [usize; 3] },
+}
+
+impl FrameHeader {
+    fn parse(frame: &[u8]) -> Option<FrameHeader> {
+        let mut bits = Bits { data: frame, position: 0 };
+        if bits.read(2)? != 2 {
+            return None;
+        }
+        let profile = bits.read(1)? | bits.read(1)? << 1;
+        if profile == 3 {
+            bits.read(1)?;
+        }
+        if bits.read(1)? == 1 {
+            return Some(FrameHeader::ShowExisting(bits.read(3)? as usize));
+        }
+        let keyframe = bits.read(1)? == 0;
+        let show_frame = bits.read(1)? == 1;
+        let error_resilient = bits.read(1)? == 1;
+        if keyframe {
+            return Some(FrameHeader::Intra { refresh: 0xff });
+        }
+        let intra_only = !show_frame && bits.read(1)? == 1;
+        if !error_resilient {
+            // reset_frame_context
+            bits.read(2)?;
+        }
+        if intra_only {
+            // sync code, then the color config for profiles above 0
+            if bits.read(24)? != 0x498342 {
+                return None;
+            }
+            if profile > 0 {
+                if profile >= 2 {
+                    bits.read(1)?;
+                }
+                let srgb = bits.read(3)? == 7;
+                let extra = match (srgb, profile) {
+                    (false, 1) | (false, 3) => 4,
+                    (false, _) => 1,
+                    (true, 1) | (true, 3) => 1,
+                    (true, _) => 0,
+                };
+                bits.read(extra)?;
+            }
+            return Some(FrameHeader::Intra { refresh: bits.read(8)? as u8 });
+        }
+        let refresh = bits.read(8)? as u8;
+        let mut references = [0; 3];
+        for reference in references.iter_mut() {
+            *reference = bits.read(3)? as usize;
+            // sign bias
+            bits.read(1)?;
+        }
+        Some(FrameHeader::Inter { refresh, references })
+    }
+}
+
+// Most significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.data.get(self.position / 8)?;
+            value = value << 1 | (*byte >> (7 - self.position % 8) & 1) as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::Packet;
+    use crate::ebml::{Element, WebmFile};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    // headers for profile 0 frames, padded out to whole bytes
+    fn keyframe() -> Vec<u8> {
+        vec![0x82, 0x49, 0x83, 0x42, 0x00]
+    }
+
+    fn inter(refresh: u8, references: [u8; 3]) -> Vec<u8> {
+        // frame_marker, profile, show_existing_frame, frame_type,
+        // show_frame, error_resilient_mode, reset_frame_context
+        let mut bits: Vec<u8> = vec![1, 0, 0, 0, 0, 1, 1, 0, 0, 0];
+        bits.extend((0..8).rev().map(|i| refresh >> i & 1));
+        for reference in references.iter() {
+            bits.extend((0..3).rev().map(|i| reference >> i & 1));
+            bits.push(0);
+        }
+        bits.chunks(8)
+            .map(|chunk| chunk.iter().enumerate().fold(0, |byte, (i, bit)| byte | bit << (7 - i)))
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_header() {
+        assert_eq!(FrameHeader::parse(&keyframe()), Some(FrameHeader::Intra { refresh: 0xff }));
+        assert_eq!(
+            FrameHeader::parse(&inter(0x02, [0, 1, 2])),
+            Some(FrameHeader::Inter { refresh: 0x02, references: [0, 1, 2] })
+        );
+        assert_eq!(FrameHeader::parse(&[0x8a]), Some(FrameHeader::ShowExisting(2)));
+        assert_eq!(FrameHeader::parse(&[0x00, 0x00]), None);
+        assert_eq!(FrameHeader::parse(&[0x86]), None);
+    }
+
+    #[test]
+    fn test_layers() {
+        // three layers: 0 refreshes slot 0, 1 reads it and refreshes slot
+        // 1, and 2 reads slot 1 and refreshes nothing
+        let mut layers = TemporalLayers::new();
+        assert_eq!(layers.block_layer(&keyframe(), None), 0);
+        let pattern = [inter(0, [1, 1, 1]), inter(0x02, [0, 0, 0]), inter(0, [1, 1, 1]), inter(0x01, [0, 0, 0])];
+        let found: Vec<u8> = pattern.iter().map(|frame| layers.block_layer(frame, None)).collect();
+        assert_eq!(found, vec![1, 1, 2, 0]);
+
+        // a signalled layer wins, and is what the slots it refreshes get
+        assert_eq!(layers.block_layer(&inter(0x01, [0, 0, 0]), Some(2)), 2);
+        assert_eq!(layers.block_layer(&inter(0x01, [0, 0, 0]), None), 2);
+    }
+
+    #[test]
+    fn test_max_temporal_layer() {
+        let mut builder = WebmBuilder::new().video_track(1, "V_VP9", 64, 64).cluster(0).frame(1, 0, true, &keyframe());
+        for i in 0..8 {
+            let frame = match i % 4 {
+                0 | 2 => inter(0, [1, 1, 1]),
+                1 => inter(0x02, [0, 0, 0]),
+                _ => inter(0x01, [0, 0, 0]),
+            };
+            builder = builder.frame(1, (i + 1) * 10, false, &frame);
+        }
+        let document = WebmFile::from_bytes(&builder.build()).unwrap();
+        let timestamps = |max: u8| -> Vec<u64> {
+            document.packets().max_temporal_layer(1, max).map(|p: Packet| p.timestamp).collect()
+        };
+        assert_eq!(timestamps(2).len(), 9);
+        assert_eq!(timestamps(1), vec![0, 10, 20, 40, 60, 80]);
+        assert_eq!(timestamps(0), vec![0, 40, 80]);
+        assert_eq!(document.packets().max_temporal_layer(2, 0).count(), 9);
+    }
+
+    #[test]
+    fn test_signalled_layer() {
+        let more = |id: Option<u64>, layer: u8| {
+            let mut children = vec![Element::binary(ID_BLOCKADDITIONAL, &[layer, 0xff]).into()];
+            if let Some(id) = id {
+                children.push(Element::uint(ID_BLOCKADDID, id).into());
+            }
+            Element::master(ID_BLOCKMORENODE, children)
+        };
+        let group = Element::master(ID_BLOCKGROUPNODE, vec![
+            Element::binary(ID_BLOCK, &[0x81, 0, 0, 0]).into(),
+            Element::master(ID_BLOCKADDITIONSNODE, vec![more(None, 1), more(Some(4), 2)]),
+        ]);
+        assert_eq!(signalled_layer(&group, 1), Some(1));
+        assert_eq!(signalled_layer(&group, 4), Some(2));
+        assert_eq!(signalled_layer(&group, 5), None);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::consts::*;
+use crate::ebml::encode::{write_element, write_float, write_int, write_string, write_uint};
+use crate::vint;
+
+// Fluent builder for tiny in-memory WebM files, for tests that would
+// otherwise need binary fixtures. Layout is EBML header, Segment, Info,
+// Tracks, the clusters and finally Cues.
+//
+//     let data = WebmBuilder::new()
+//         .video_track(1, "V_VP9", 320, 240)
+//         .cluster(0)
+//         .frame(1, 0, true, &[0; 16])
+//         .build();
+#[derive(Debug, Clone)]
+pub struct WebmBuilder {
+    doc_type: String,
+    timestamp_scale: u64,
+    duration: Option<f64>,
+    tracks: Vec<Vec<u8>>,
+    clusters: Vec<ClusterSpec>,
+    cues: bool,
+    unknown_size_segment: bool,
+    unknown_size_clusters: bool,
+    prev_sizes: bool,
+    bad_crc: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ClusterSpec {
+    timestamp: u64,
+    // block bytes, with DiscardPadding for blocks written as a BlockGroup
+    blocks: Vec<(Vec<u8>, Option<i64>)>,
+    // track of the first keyframe, used for Cues
+    cue_track: Option<u64>,
+}
+
+impl WebmBuilder {
+    pub fn new() -> WebmBuilder {
+        WebmBuilder {
+            doc_type: "webm".to_string(),
+            timestamp_scale: 1_000_000,
+            duration: None,
+            tracks: Vec::new(),
+            clusters: Vec::new(),
+            cues: true,
+            unknown_size_segment: false,
+            unknown_size_clusters: false,
+            prev_sizes: false,
+            bad_crc: false,
+        }
+    }
+
+    pub fn doc_type(mut self, doc_type: &str) -> Self {
+        self.doc_type = doc_type.to_string();
+        self
+    }
+
+    pub fn timestamp_scale(mut self, scale: u64) -> Self {
+        self.timestamp_scale = scale;
+        self
+    }
+
+    pub fn duration(mut self, duration: f64) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn video_track(self, number: u64, codec_id: &str, width: u64, height: u64) -> Self {
+        let mut video = Vec::new();
+        write_uint(&mut video, ID_PIXELWIDTH, width);
+        write_uint(&mut video, ID_PIXELHEIGHT, height);
+        self.track(number, 1, codec_id, ID_VIDEONODE, video)
+    }
+
+    pub fn audio_track(self, number: u64, codec_id: &str, sampling_frequency: f64, channels: u64) -> Self {
+        let mut audio = Vec::new();
+        write_float(&mut audio, ID_SAMPLINGFREQUENCY, sampling_frequency);
+        write_uint(&mut audio, ID_CHANNELS, channels);
+        self.track(number, 2, codec_id, ID_AUDIONODE, audio)
+    }
+
+    fn track(mut self, number: u64, track_type: u64, codec_id: &str, settings_id: u64, settings: Vec<u8>) -> Self {
+        let mut entry = Vec::new();
+        write_uint(&mut entry, ID_TRACKNUMBER, number);
+        write_uint(&mut entry, ID_TRACKUID, number);
+        write_uint(&mut entry, ID_TRACKTYPE, track_type);
+        write_string(&mut entry, ID_CODECID, codec_id);
+        write_element(&mut entry, settings_id, &settings);
+        self.tracks.push(entry);
+        self
+    }
+
+    // Set CodecDelay, in nanoseconds, on the latest track
+    pub fn codec_delay(mut self, delay: u64) -> Self {
+        if let Some(entry) = self.tracks.last_mut() {
+            write_uint(entry, ID_CODECDELAY, delay);
+        }
+        self
+    }
+
+    // Set SeekPreRoll, in nanoseconds, on the latest track
+    pub fn seek_preroll(mut self, preroll: u64) -> Self {
+        if let Some(entry) = self.tracks.last_mut() {
+            write_uint(entry, ID_SEEKPREROLL, preroll);
+        }
+        self
+    }
+
+    // Start a new cluster, frames are added to the latest one
+    pub fn cluster(mut self, timestamp: u64) -> Self {
+        self.clusters.push(ClusterSpec { timestamp, blocks: Vec::new(), cue_track: None });
+        self
+    }
+
+    // Add a SimpleBlock with a timestamp relative to its cluster
+    pub fn frame(mut self, track: u64, timecode: i16, keyframe: bool, data: &[u8]) -> Self {
+        if self.clusters.is_empty() {
+            self = self.cluster(0);
+        }
+        let mut block = vec![0x80 | track as u8];
+        block.extend(&timecode.to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0x00 });
+        block.extend(data);
+
+        let cluster = self.clusters.last_mut().unwrap();
+        if keyframe && cluster.cue_track.is_none() {
+            cluster.cue_track = Some(track);
+        }
+        cluster.blocks.push((block, None));
+        self
+    }
+
+    // Turn the latest frame into a BlockGroup with a DiscardPadding, in
+    // nanoseconds
+    pub fn discard_padding(mut self, padding: i64) -> Self {
+        if let Some((_, discard)) = self.clusters.last_mut().and_then(|c| c.blocks.last_mut()) {
+            *discard = Some(padding);
+        }
+        self
+    }
+
+    pub fn without_cues(mut self) -> Self {
+        self.cues = false;
+        self
+    }
+
+    pub fn unknown_size_segment(mut self) -> Self {
+        self.unknown_size_segment = true;
+        self
+    }
+
+    pub fn unknown_size_clusters(mut self) -> Self {
+        self.unknown_size_clusters = true;
+        self
+    }
+
+    // Give every Cluster its Position and the PrevSize of the one before
+    pub fn prev_sizes(mut self) -> Self {
+        self.prev_sizes = true;
+        self
+    }
+
+    // Start the Info element with a CRC-32 that doesn't match its contents
+    pub fn bad_crc(mut self) -> Self {
+        self.bad_crc = true;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        write_uint(&mut header, ID_EBMLVERSION, 1);
+        write_uint(&mut header, ID_EBMLREADVERSION, 1);
+        write_uint(&mut header, ID_EBMLMAXIDLENGTH, 4);
+        write_uint(&mut header, ID_EBMLMAXSIZELENGTH, 8);
+        write_string(&mut header, ID_DOCTYPE, &self.doc_type);
+        write_uint(&mut header, ID_DOCTYPEVERSION, 4);
+        write_uint(&mut header, ID_DOCTYPEREADVERSION, 2);
+
+        let mut segment = Vec::new();
+        let mut info = Vec::new();
+        if self.bad_crc {
+            write_element(&mut info, ID_CRC32, &[0xde, 0xad, 0xbe, 0xef]);
+        }
+        write_uint(&mut info, ID_TIMESTAMPSCALE, self.timestamp_scale);
+        write_string(&mut info, ID_MUXINGAPP, "libwebm-rs");
+        write_string(&mut info, ID_WRITINGAPP, "libwebm-rs");
+        if let Some(duration) = self.duration {
+            write_float(&mut info, ID_DURATION, duration);
+        }
+        write_element(&mut segment, ID_INFONODE, &info);
+
+        let mut tracks = Vec::new();
+        for entry in &self.tracks {
+            write_element(&mut tracks, ID_TRACKENTRYNODE, entry);
+        }
+        write_element(&mut segment, ID_TRACKSNODE, &tracks);
+
+        let mut cues = Vec::new();
+        let mut prev_size = 0;
+        for spec in &self.clusters {
+            if let Some(track) = spec.cue_track {
+                let mut positions = Vec::new();
+                write_uint(&mut positions, ID_CUETRACK, track);
+                write_uint(&mut positions, ID_CUECLUSTERPOSITION, segment.len() as u64);
+                let mut point = Vec::new();
+                write_uint(&mut point, ID_CUETIME, spec.timestamp);
+                write_element(&mut point, ID_CUETRACKPOSITIONSNODE, &positions);
+                write_element(&mut cues, ID_CUEPOINTNODE, &point);
+            }
+
+            let mut cluster = Vec::new();
+            write_uint(&mut cluster, ID_TIMESTAMP, spec.timestamp);
+            if self.prev_sizes {
+                write_uint(&mut cluster, ID_POSITION, segment.len() as u64);
+                if prev_size > 0 {
+                    write_uint(&mut cluster, ID_PREVSIZE, prev_size);
+                }
+            }
+            for (block, padding) in &spec.blocks {
+                match padding {
+                    Some(padding) => {
+                        let mut group = Vec::new();
+                        write_element(&mut group, ID_BLOCK, block);
+                        write_int(&mut group, ID_DISCARDPADDING, *padding);
+                        write_element(&mut cluster, ID_BLOCKGROUPNODE, &group);
+                    },
+                    None => write_element(&mut cluster, ID_SIMPLEBLOCK, block),
+                }
+            }
+            let start = segment.len();
+            if self.unknown_size_clusters {
+                write_unknown_size(&mut segment, ID_CLUSTERNODE, &cluster);
+            } else {
+                write_element(&mut segment, ID_CLUSTERNODE, &cluster);
+            }
+            prev_size = (segment.len() - start) as u64;
+        }
+        if self.cues && !cues.is_empty() {
+            write_element(&mut segment, ID_CUESNODE, &cues);
+        }
+
+        let mut data = Vec::new();
+        write_element(&mut data, ID_EBMLHEADERNODE, &header);
+        if self.unknown_size_segment {
+            write_unknown_size(&mut data, ID_SEGMENTNODE, &segment);
+        } else {
+            write_element(&mut data, ID_SEGMENTNODE, &segment);
+        }
+        data
+    }
+}
+
+impl Default for WebmBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn write_unknown_size(out: &mut Vec<u8>, id: u64, data: &[u8]) {
+    vint::write_id(out, id);
+    vint::write_unknown_size(out);
+    out.extend(data);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ebml::WebmFile;
+    use super::*;
+
+    fn sample() -> WebmBuilder {
+        WebmBuilder::new()
+            .duration(80.0)
+            .video_track(1, "V_VP9", 320, 240)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .cluster(0)
+            .frame(1, 0, true, &[1; 8])
+            .frame(2, 0, true, &[2; 4])
+            .frame(1, 40, false, &[3; 8])
+            .cluster(80)
+            .frame(1, 0, true, &[4; 8])
+    }
+
+    #[test]
+    fn test_build() {
+        let document = WebmFile::from_bytes(&sample().build()).unwrap();
+        assert_eq!(document.header.get_doc_type(), "webm");
+        assert_eq!(document.root.get_info_nodes()[0].get_duration(), Some(80.0));
+
+        let tracks = document.root.get_tracks()[0].get_track_entries();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].get_codec_id(), "V_VP9");
+        assert_eq!(tracks[1].get_audio_settings().unwrap().get_num_channels(), 2);
+
+        let packets: Vec<_> = document.packets().collect();
+        assert_eq!(packets.len(), 4);
+        assert_eq!(packets[3].timestamp, 80);
+        assert!(!packets[2].keyframe);
+
+        let points = document.root.get_cues()[0].get_cue_points();
+        assert_eq!(points.len(), 2);
+    }
+
+    #[test]
+    fn test_quirks() {
+        let data = sample()
+            .without_cues()
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .bad_crc()
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert!(document.root.get_cues().is_empty());
+        assert!(document.root.get_element().is_unknown_size());
+        assert_eq!(document.root.get_clusters().len(), 2);
+        assert_eq!(document.packets().count(), 4);
+
+        let info = document.root.get_info_nodes()[0].get_children();
+        assert_eq!(info[0].get_element().id, ID_CRC32);
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{Write, Error as IOError};
+use crate::block::{block_of, BlockEntry};
+use crate::consts::*;
+use crate::ebml::WebmFile;
+
+// A table of every block for spreadsheets and dataframes, keeping the
+// WebM details generic tools drop: block kind, lacing, flags and where each
+// block and its Cluster sit in the file.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Delimiter {
+    #[default]
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Delimiter::Comma => ",",
+            Delimiter::Tab => "\t",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineRow {
+    pub track: u64,
+    // in TimestampScale units, and in nanoseconds
+    pub timestamp: u64,
+    pub pts: u64,
+    // BlockDuration, for BlockGroups that have one
+    pub duration: Option<u64>,
+    // payload bytes of every frame in the block
+    pub size: usize,
+    pub frames: usize,
+    pub keyframe: bool,
+    pub invisible: bool,
+    pub discardable: bool,
+    pub block_group: bool,
+    // absolute file offsets of the Cluster and the block element
+    pub cluster_offset: u64,
+    pub block_offset: u64,
+}
+
+const COLUMNS: [&str; 12] = [
+    "track", "timestamp", "pts", "duration", "size", "frames", "keyframe",
+    "invisible", "discardable", "block_group", "cluster_offset", "block_offset",
+];
+
+// One row per SimpleBlock or BlockGroup, in file order
+pub fn timeline(document: &WebmFile) -> Vec<TimelineRow> {
+    let scale = document.root.get_info_nodes()
+        .first()
+        .map(|info| info.get_timestamp_scale())
+        .unwrap_or(1_000_000);
+    let mut rows = Vec::new();
+    for cluster in document.root.get_clusters() {
+        let cluster_timestamp = cluster.get_timestamp();
+        let cluster_offset = cluster.get_element().offset;
+        for node in cluster.get_children() {
+            let BlockEntry { block, keyframe, duration, .. } = match block_of(&node) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let timestamp = block.timestamp(cluster_timestamp);
+            rows.push(TimelineRow {
+                track: block.track,
+                timestamp,
+                pts: timestamp.saturating_mul(scale),
+                duration,
+                size: block.frames.iter().map(Vec::len).sum(),
+                frames: block.frames.len(),
+                keyframe,
+                invisible: block.is_invisible(),
+                discardable: block.is_discardable(),
+                block_group: node.get_element().id == ID_BLOCKGROUPNODE,
+                cluster_offset,
+                block_offset: node.get_element().offset,
+            });
+        }
+    }
+    rows
+}
+
+// The timeline as CSV or TSV with a header row. Missing durations are
+// empty fields and flags are `true`/`false`.
+pub fn write_timeline<W: Write>(document: &WebmFile, delimiter: Delimiter, mut out: W) -> Result<(), IOError> {
+    let separator = delimiter.as_str();
+    writeln!(out, "{}", COLUMNS.join(separator))?;
+    for row in timeline(document) {
+        let fields = [
+            row.track.to_string(),
+            row.timestamp.to_string(),
+            row.pts.to_string(),
+            row.duration.map(|d| d.to_string()).unwrap_or_default(),
+            row.size.to_string(),
+            row.frames.to_string(),
+            row.keyframe.to_string(),
+            row.invisible.to_string(),
+            row.discardable.to_string(),
+            row.block_group.to_string(),
+            row.cluster_offset.to_string(),
+            row.block_offset.to_string(),
+        ];
+        writeln!(out, "{}", fields.join(separator))?;
+    }
+    Ok(())
+}
+
+// The Block in a SimpleBlock or BlockGroup, whether it's a keyframe and
+// its BlockDuration
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    fn document() -> WebmFile {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .cluster(0)
+            .frame(1, 0, true, &[0; 10])
+            .frame(2, 5, true, &[0; 3])
+            .discard_padding(1000)
+            .cluster(1000)
+            .frame(1, 40, false, &[0; 4])
+            .build();
+        WebmFile::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn test_timeline() {
+        let document = document();
+        let rows = timeline(&document);
+        assert_eq!(rows.len(), 3);
+        assert_eq!((rows[0].track, rows[0].size, rows[0].keyframe, rows[0].block_group), (1, 10, true, false));
+        assert!(rows[1].block_group);
+        assert_eq!(rows[2].timestamp, 1040);
+        assert_eq!(rows[2].pts, 1_040_000_000);
+        assert!(!rows[2].keyframe);
+
+        let clusters = document.root.get_clusters();
+        assert_eq!(rows[1].cluster_offset, clusters[0].get_element().offset);
+        assert_eq!(rows[2].cluster_offset, clusters[1].get_element().offset);
+        assert!(rows[0].block_offset > rows[0].cluster_offset);
+    }
+
+    #[test]
+    fn test_write_timeline() {
+        let document = document();
+        let mut csv = Vec::new();
+        write_timeline(&document, Delimiter::Comma, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "track,timestamp,pts,duration,size,frames,keyframe,invisible,discardable,block_group,cluster_offset,block_offset");
+        assert!(lines[3].starts_with("1,1040,1040000000,,4,1,false,false,false,false,"));
+
+        let mut tsv = Vec::new();
+        write_timeline(&document, Delimiter::Tab, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert_eq!(tsv.lines().nth(1).unwrap().split('\t').count(), COLUMNS.len());
+        assert_eq!(tsv.replace('\t', ","), csv);
+    }
+}
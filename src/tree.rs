@@ -0,0 +1,155 @@
+use crate::ebml::{Element, ElementPath, Node, WebmFile};
+
+// A navigable view of a parsed document. Every node gets a `NodeId`
+// numbered in document order, and from any node its parent and siblings
+// can be reached as well as its children, e.g. for a validator to report
+// where a problem is. The ids stay valid as long as the tree they came
+// from; edit the document and build a new tree to renumber.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+struct Entry<'a> {
+    node: &'a Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    // position among the parent's children, or among the top level nodes
+    index: usize,
+}
+
+pub struct DocumentTree<'a> {
+    entries: Vec<Entry<'a>>,
+    // the EBML header and the Segment
+    roots: Vec<NodeId>,
+}
+
+impl<'a> DocumentTree<'a> {
+    pub fn new(document: &'a WebmFile) -> DocumentTree<'a> {
+        let mut tree = DocumentTree { entries: Vec::new(), roots: Vec::new() };
+        for (index, node) in [document.header.as_node(), document.root.as_node()].iter().enumerate() {
+            let id = tree.add(node, None, index);
+            tree.roots.push(id);
+        }
+        tree
+    }
+
+    fn add(&mut self, node: &'a Node, parent: Option<NodeId>, index: usize) -> NodeId {
+        let id = NodeId(self.entries.len());
+        self.entries.push(Entry { node, parent, children: Vec::new(), index });
+        let children = node.children()
+            .iter()
+            .enumerate()
+            .map(|(index, child)| self.add(child, Some(id), index))
+            .collect();
+        self.entries[id.0].children = children;
+        id
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Every id in document order
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.entries.len()).map(NodeId)
+    }
+
+    // These panic for ids beyond the end of this tree
+    pub fn node(&self, id: NodeId) -> &'a Node {
+        self.entries[id.0].node
+    }
+
+    pub fn element(&self, id: NodeId) -> &'a Element {
+        self.entries[id.0].node.element()
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.entries[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.entries[id.0].children
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let index = self.entries[id.0].index;
+        self.siblings(id).get(index + 1).copied()
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let index = self.entries[id.0].index;
+        index.checked_sub(1).and_then(|i| self.siblings(id).get(i).copied())
+    }
+
+    // From the top level node down to this one
+    pub fn ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut ids = vec![id];
+        while let Some(parent) = self.parent(*ids.last().unwrap()) {
+            ids.push(parent);
+        }
+        ids.reverse();
+        ids
+    }
+
+    pub fn path(&self, id: NodeId) -> ElementPath {
+        ElementPath(self.ancestors(id).into_iter().map(|id| self.element(id).id).collect())
+    }
+
+    // The first node in document order with this element ID
+    pub fn find(&self, element_id: u64) -> Option<NodeId> {
+        self.ids().find(|id| self.element(*id).id == element_id)
+    }
+
+    fn siblings(&self, id: NodeId) -> &[NodeId] {
+        match self.parent(id) {
+            Some(parent) => self.children(parent),
+            None => &self.roots,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::*;
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    #[test]
+    fn test_navigation() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, &[0])
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let tree = DocumentTree::new(&document);
+        assert_eq!(tree.len(), document.iter().count());
+        assert_eq!(tree.roots(), &[NodeId(0), tree.find(ID_SEGMENTNODE).unwrap()]);
+        assert_eq!(tree.next_sibling(tree.roots()[0]), Some(tree.roots()[1]));
+
+        // from the first CodecID to the second TrackEntry
+        let codec = tree.find(ID_CODECID).unwrap();
+        let entry = tree.parent(codec).unwrap();
+        assert_eq!(tree.element(entry).id, ID_TRACKENTRYNODE);
+        let second = tree.next_sibling(entry).unwrap();
+        assert_eq!(tree.prev_sibling(second), Some(entry));
+        assert_eq!(tree.next_sibling(second), None);
+        assert_eq!(tree.parent(tree.children(second)[0]), Some(second));
+        assert_eq!(tree.path(codec).to_string(), "SegmentNode/TracksNode/TrackEntryNode/CodecID");
+        assert_eq!(tree.ancestors(codec).len(), 4);
+
+        // ids follow document order
+        let offsets: Vec<u64> = tree.ids().map(|id| tree.element(id).offset).collect();
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(tree.prev_sibling(NodeId(0)), None);
+        assert_eq!(tree.parent(NodeId(0)), None);
+    }
+}
@@ -0,0 +1,737 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Error as FmtError};
+use crate::consts::*;
+use crate::ebml::{ElementKind, ElementPath, ElementValue, Node, WebmFile};
+use crate::block::block_of;
+
+// Checks a parsed document against rules beyond what the parser enforces,
+// collecting every problem rather than stopping at the first
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    // the element at fault, or the master a missing element belongs in
+    pub path: ElementPath,
+    pub offset: u64,
+    pub problem: Problem,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    // an EBML header value a reader can't accept, e.g. a DocType of matroska
+    HeaderValue,
+    // a master without a child with this ID
+    MissingElement(u64),
+    // Info or Tracks after the first Cluster
+    InitAfterMedia,
+    // an unknown size on something other than a Segment or Cluster
+    UnknownSize,
+    // a Cluster's Timestamp after one of its blocks
+    TimestampAfterBlock,
+    // a Cluster whose first block for this video track isn't a keyframe
+    NotKeyframe { track: u64 },
+    // a block earlier than the one before it on the same track
+    OutOfOrder { track: u64 },
+    // an element inside a master the spec doesn't allow it in
+    Misplaced,
+    // a number outside the values the spec allows for the element
+    OutOfRange(f64),
+    // a TrackNumber or TrackUID another TrackEntry already has
+    Duplicate(u64),
+    // a block on a track no TrackEntry declares
+    UnknownTrack(u64),
+    // a block whose absolute timestamp is before zero, after the next
+    // Cluster's or after the end of the Segment. A negative relative
+    // timestamp is fine as long as the absolute one isn't.
+    OutsideCluster { track: u64 },
+    // a block 32768 or more TimestampScale units, the range of the 16 bit
+    // relative timestamp, away from the block before it on the same track,
+    // the mark of a relative timestamp that wrapped
+    TimestampOverflow { track: u64 },
+    // a master whose children take up a different number of bytes than
+    // its size says
+    SizeMismatch { declared: u64, children: u64 },
+    // an element running past the end of the master it's in
+    PastParent,
+    // an element running past the end of the file
+    PastEof,
+    // top level elements taking up more or fewer bytes than the file has
+    FileLength { elements: u64, file: u64 },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Problem::HeaderValue => write!(f, "value not allowed"),
+            Problem::MissingElement(id) => match element_name(*id) {
+                Some(name) => write!(f, "missing {}", name),
+                None => write!(f, "missing {:#x}", id),
+            },
+            Problem::InitAfterMedia => write!(f, "comes after the first Cluster"),
+            Problem::UnknownSize => write!(f, "unknown size"),
+            Problem::TimestampAfterBlock => write!(f, "Timestamp after the first block"),
+            Problem::NotKeyframe { track } => {
+                write!(f, "first block of track {} is not a keyframe", track)
+            },
+            Problem::OutOfOrder { track } => write!(f, "block on track {} goes back in time", track),
+            Problem::Misplaced => write!(f, "not allowed here"),
+            Problem::OutOfRange(value) => write!(f, "{} is out of range", value),
+            Problem::Duplicate(value) => write!(f, "{} is already used", value),
+            Problem::UnknownTrack(track) => write!(f, "track {} is not declared", track),
+            Problem::OutsideCluster { track } => {
+                write!(f, "block on track {} is outside its Cluster's time span", track)
+            },
+            Problem::TimestampOverflow { track } => {
+                write!(f, "block on track {} jumps by the 16 bit relative timestamp range", track)
+            },
+            Problem::SizeMismatch { declared, children } => {
+                write!(f, "size is {} but the children take {} bytes", declared, children)
+            },
+            Problem::PastParent => write!(f, "runs past the end of its parent"),
+            Problem::PastEof => write!(f, "runs past the end of the file"),
+            Problem::FileLength { elements, file } => {
+                write!(f, "top level elements take {} bytes of a {} byte file", elements, file)
+            },
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{} at {}: {}", self.path, self.offset, self.problem)
+    }
+}
+
+// The rules of the W3C WebM Byte Stream Format that Media Source Extensions
+// hold appended data to: a webm header within the limits browsers support,
+// an initialization segment of Info and Tracks ahead of the media, and
+// Clusters that each start with a Timestamp and a keyframe on every video
+// track, with blocks in time order and no unknown sizes below the Cluster
+// level. Audio isn't held to keyframe flags, which plenty of muxers leave
+// off and browsers don't need.
+pub fn byte_stream(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut issue = |path: Vec<u64>, offset: u64, problem: Problem| {
+        issues.push(Issue { path: ElementPath(path), offset, problem });
+    };
+
+    for node in document.header.as_node().children() {
+        let element = node.element();
+        let allowed = match element.id {
+            ID_DOCTYPE => element.data.into_string() == "webm",
+            ID_EBMLVERSION | ID_EBMLREADVERSION => element.data.into_uint() <= 1,
+            ID_EBMLMAXIDLENGTH => element.data.into_uint() <= 4,
+            ID_EBMLMAXSIZELENGTH => element.data.into_uint() <= 8,
+            _ => true,
+        };
+        if !allowed {
+            issue(vec![ID_EBMLHEADERNODE, element.id], element.offset, Problem::HeaderValue);
+        }
+    }
+
+    let segment = document.root.as_node();
+    let segment_offset = segment.element().offset;
+    let mut init = Vec::new();
+    let mut seen_cluster = false;
+    for child in segment.children() {
+        let element = child.element();
+        match element.id {
+            ID_INFONODE | ID_TRACKSNODE if seen_cluster => {
+                issue(vec![ID_SEGMENTNODE, element.id], element.offset, Problem::InitAfterMedia);
+            },
+            ID_INFONODE | ID_TRACKSNODE => init.push(element.id),
+            ID_CLUSTERNODE => seen_cluster = true,
+            _ => (),
+        }
+    }
+    for id in [ID_INFONODE, ID_TRACKSNODE].iter() {
+        if !init.contains(id) {
+            issue(vec![ID_SEGMENTNODE], segment_offset, Problem::MissingElement(*id));
+        }
+    }
+
+    for (path, element) in document.root.as_node().iter().skip(1) {
+        if element.is_unknown_size() && element.id != ID_CLUSTERNODE {
+            issue(path.0, element.offset, Problem::UnknownSize);
+        }
+    }
+
+    let video: Vec<u64> = document.root.get_tracks()
+        .iter()
+        .flat_map(|tracks| tracks.get_track_entries())
+        .filter(|entry| entry.get_track_type() == 1)
+        .map(|entry| entry.get_track_number())
+        .collect();
+    for cluster in document.root.get_clusters() {
+        check_cluster(cluster.as_node(), &video, &mut issue);
+    }
+    issues
+}
+
+// Elements in masters other than the ones NODE_INFOS lists as their
+// parents, like a PixelWidth under Audio or a CuePoint outside of Cues. IDs
+// outside the table aren't checked.
+pub fn nesting(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (path, element) in document.iter() {
+        let ids = path.ids();
+        let parents = match get_node_info(element.id).and_then(|info| info.parents) {
+            Some(parents) => parents,
+            None => continue,
+        };
+        let allowed = match ids.len().checked_sub(2).map(|i| ids[i]) {
+            Some(parent) => parents.contains(&parent),
+            None => parents.is_empty(),
+        };
+        if !allowed {
+            issues.push(Issue { path, offset: element.offset, problem: Problem::Misplaced });
+        }
+    }
+    issues
+}
+
+// Numbers outside VALUE_RANGES, like a TrackType of 0 or a StereoMode of 15
+pub fn values(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (path, element) in document.iter() {
+        let range = match VALUE_RANGES.iter().find(|(id, _)| *id == element.id) {
+            Some((_, range)) => range,
+            None => continue,
+        };
+        let value = match element.decode_value() {
+            ElementValue::UInt(value) => value as f64,
+            ElementValue::Int(value) => value as f64,
+            ElementValue::Float(value) => value,
+            _ => continue,
+        };
+        if !range.contains(&value) {
+            issues.push(Issue { path, offset: element.offset, problem: Problem::OutOfRange(value) });
+        }
+    }
+    issues
+}
+
+// TrackEntries with a zero or repeated TrackNumber or TrackUID, and blocks
+// on a track that none of them declares
+pub fn tracks(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut numbers = Vec::new();
+    let mut uids = Vec::new();
+    let entries = document.root.get_tracks()
+        .iter()
+        .flat_map(|tracks| tracks.get_track_entries())
+        .collect::<Vec<_>>();
+    for child in entries.iter().flat_map(|entry| entry.as_node().children()) {
+        let element = child.element();
+        let seen = match element.id {
+            ID_TRACKNUMBER => &mut numbers,
+            ID_TRACKUID => &mut uids,
+            _ => continue,
+        };
+        let value = element.data.into_uint();
+        let problem = if value == 0 {
+            Problem::OutOfRange(0.0)
+        } else if seen.contains(&value) {
+            Problem::Duplicate(value)
+        } else {
+            seen.push(value);
+            continue;
+        };
+        issues.push(Issue {
+            path: ElementPath(vec![ID_SEGMENTNODE, ID_TRACKSNODE, ID_TRACKENTRYNODE, element.id]),
+            offset: element.offset,
+            problem,
+        });
+    }
+
+    for cluster in document.root.get_clusters() {
+        for child in cluster.as_node().children() {
+            let track = match block_of(child) {
+                Some(entry) => entry.block.track,
+                None => continue,
+            };
+            if !numbers.contains(&track) {
+                issues.push(Issue {
+                    path: ElementPath(vec![ID_SEGMENTNODE, ID_CLUSTERNODE, child.element().id]),
+                    offset: child.element().offset,
+                    problem: Problem::UnknownTrack(track),
+                });
+            }
+        }
+    }
+    issues
+}
+
+// Blocks whose timestamps don't fit their Cluster: before zero, past the
+// next Cluster's or past the Segment's Duration. A block that sits 32768 or
+// more units from the previous one on its track is reported as an overflow
+// instead, as that's what an encoder that let the 16 bit relative timestamp
+// wrap produces.
+pub fn timestamps(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let clusters = document.root.get_clusters();
+    let duration = document.root.get_info_nodes()
+        .first()
+        .and_then(|info| info.get_duration())
+        .map(|duration| duration.ceil() as u64);
+    // the latest block on each track and the Cluster it was in
+    let mut previous: HashMap<u64, (u64, usize)> = HashMap::new();
+    for (index, cluster) in clusters.iter().enumerate() {
+        let start = cluster.get_timestamp();
+        let end = clusters.get(index + 1).map(|next| next.get_timestamp()).or(duration);
+        for child in cluster.as_node().children() {
+            let block = match block_of(child) {
+                Some(entry) => entry.block,
+                None => continue,
+            };
+            let track = block.track;
+            let time = (start as i64).saturating_add(block.timecode as i64);
+            let wrapped = previous.get(&track).is_some_and(|&(last, cluster)| {
+                let delta = time - last as i64;
+                delta.abs() >= 1 << 15 && (delta < 0 || cluster == index)
+            });
+            let problem = if wrapped {
+                Problem::TimestampOverflow { track }
+            } else if time < 0 || end.is_some_and(|end| time > end as i64) {
+                Problem::OutsideCluster { track }
+            } else {
+                previous.insert(track, (time as u64, index));
+                continue;
+            };
+            previous.insert(track, (time.max(0) as u64, index));
+            issues.push(Issue {
+                path: ElementPath(vec![ID_SEGMENTNODE, ID_CLUSTERNODE, child.element().id]),
+                offset: child.element().offset,
+                problem,
+            });
+        }
+    }
+    issues
+}
+
+// Masters missing a child MANDATORY_CHILDREN lists for them, e.g. a
+// TrackEntry without a CodecID, reported once per missing ID
+pub fn mandatory(document: &WebmFile) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for node in [document.header.as_node(), document.root.as_node()].iter() {
+        check_mandatory(node, &mut Vec::new(), &mut issues);
+    }
+    issues
+}
+
+fn check_mandatory(node: &Node, path: &mut Vec<u64>, issues: &mut Vec<Issue>) {
+    let element = node.element();
+    path.push(element.id);
+    let required = MANDATORY_CHILDREN.iter()
+        .find(|(id, _)| *id == element.id)
+        .map(|(_, children)| *children)
+        .unwrap_or(&[]);
+    for id in required {
+        if !node.children().iter().any(|child| child.element().id == *id) {
+            issues.push(Issue {
+                path: ElementPath(path.clone()),
+                offset: element.offset,
+                problem: Problem::MissingElement(*id),
+            });
+        }
+    }
+    for child in node.children() {
+        check_mandatory(child, path, issues);
+    }
+    path.pop();
+}
+
+// The structural audit of a file of `file_len` bytes: masters whose
+// children don't add up to their size, elements running past their parent
+// or the end of the file, and top level elements that don't add up to the
+// file's length. Elements the reader was told to leave out, e.g. with
+// `skip_elements`, count as missing, so audit documents parsed with the
+// default settings.
+pub fn sizes(document: &WebmFile, file_len: u64) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let top = [document.header.as_node(), document.root.as_node()];
+    for node in top.iter() {
+        check_sizes(node, None, file_len, &mut Vec::new(), &mut issues);
+    }
+    let elements = top.iter().map(|node| extent(node) - node.element().offset).sum();
+    if elements != file_len {
+        issues.push(Issue {
+            path: ElementPath(vec![ID_SEGMENTNODE]),
+            offset: document.root.as_node().element().offset,
+            problem: Problem::FileLength { elements, file: file_len },
+        });
+    }
+    issues
+}
+
+fn check_sizes(node: &Node, parent_end: Option<u64>, file_len: u64, path: &mut Vec<u64>, issues: &mut Vec<Issue>) {
+    let element = node.element();
+    path.push(element.id);
+    let end = extent(node);
+    let mut issue = |problem| issues.push(Issue { path: ElementPath(path.clone()), offset: element.offset, problem });
+    if parent_end.is_some_and(|parent_end| end > parent_end) {
+        issue(Problem::PastParent);
+    }
+    if end > file_len {
+        issue(Problem::PastEof);
+    }
+    if element.kind == ElementKind::Master && !element.is_unknown_size() {
+        let children = node.children().iter().map(|child| extent(child) - child.element().offset).sum();
+        if children != element.size {
+            issue(Problem::SizeMismatch { declared: element.size, children });
+        }
+    }
+    let end = Some(end).filter(|_| !element.is_unknown_size());
+    for child in node.children() {
+        check_sizes(child, end, file_len, path, issues);
+    }
+    path.pop();
+}
+
+// Where an element ends, going by its last child if its size is unknown
+fn extent(node: &Node) -> u64 {
+    let element = node.element();
+    match node.children().last() {
+        Some(last) if element.is_unknown_size() => extent(last).max(element.data_offset()),
+        _ if element.is_unknown_size() => element.data_offset(),
+        _ => element.end_offset(),
+    }
+}
+
+fn check_cluster(cluster: &Node, video: &[u64], issue: &mut impl FnMut(Vec<u64>, u64, Problem)) {
+    let cluster_path = vec![ID_SEGMENTNODE, ID_CLUSTERNODE];
+    let mut timestamp = None;
+    let mut seen_block = false;
+    // the latest block on each track so far
+    let mut latest: HashMap<u64, u64> = HashMap::new();
+    for child in cluster.children() {
+        let element = child.element();
+        let path = vec![ID_SEGMENTNODE, ID_CLUSTERNODE, element.id];
+        if element.id == ID_TIMESTAMP {
+            if seen_block {
+                issue(path.clone(), element.offset, Problem::TimestampAfterBlock);
+            }
+            timestamp = Some(element.data.into_uint());
+            continue;
+        }
+        let (block, keyframe) = match block_of(child) {
+            Some(entry) => (entry.block, entry.keyframe),
+            None => continue,
+        };
+        seen_block = true;
+        let track = block.track;
+        let time = block.timestamp(timestamp.unwrap_or(0));
+        match latest.insert(track, time) {
+            None if !keyframe && video.contains(&track) => {
+                issue(path, element.offset, Problem::NotKeyframe { track });
+            },
+            Some(previous) if time < previous => {
+                issue(path, element.offset, Problem::OutOfOrder { track });
+            },
+            _ => (),
+        }
+    }
+    if timestamp.is_none() {
+        issue(cluster_path, cluster.element().offset, Problem::MissingElement(ID_TIMESTAMP));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use crate::ebml::{encode, Element};
+    use crate::testutil::WebmBuilder;
+    use crate::vint;
+    use super::*;
+
+    fn problems(issues: &[Issue]) -> Vec<Problem> {
+        issues.iter().map(|i| i.problem.clone()).collect()
+    }
+
+    #[test]
+    fn test_byte_stream() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        assert_eq!(byte_stream(&WebmFile::from_bytes(&sample).unwrap()), vec![]);
+
+        // what MediaRecorder writes is fine too
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP8", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .frame(1, 0, true, &[1])
+            .frame(2, 0, true, &[2])
+            .frame(1, 33, false, &[3])
+            .build();
+        assert_eq!(byte_stream(&WebmFile::from_bytes(&data).unwrap()), vec![]);
+    }
+
+    #[test]
+    fn test_nesting() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut document = WebmFile::from_bytes(&sample).unwrap();
+        assert_eq!(nesting(&document), vec![]);
+
+        // a PixelWidth in Audio, a CuePoint in a Cluster, and Voids anywhere
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        root.edit_child(tracks, |tracks| {
+            tracks.edit_child(1, |entry| {
+                let audio = entry.children().iter().position(|n| n.element().id == ID_AUDIONODE).unwrap();
+                entry.edit_child(audio, |audio| {
+                    audio.insert_child(0, Element::uint(ID_PIXELWIDTH, 640).into());
+                    audio.insert_child(0, Element::binary(ID_VOID, &[0]).into());
+                });
+            });
+        });
+        let cluster = root.children().iter().position(|n| n.element().id == ID_CLUSTERNODE).unwrap();
+        root.edit_child(cluster, |cluster| {
+            cluster.insert_child(1, Element::master(ID_CUEPOINTNODE, vec![Element::uint(ID_CUETIME, 0).into()]));
+        });
+
+        let issues = nesting(&document);
+        let paths: Vec<String> = issues.iter().map(|i| i.path.to_string()).collect();
+        assert_eq!(paths, vec![
+            "SegmentNode/TracksNode/TrackEntryNode/AudioNode/PixelWidth",
+            "SegmentNode/ClusterNode/CuePointNode",
+        ]);
+        assert!(issues.iter().all(|i| i.problem == Problem::Misplaced));
+    }
+
+    #[test]
+    fn test_mandatory() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let mut document = WebmFile::from_bytes(&sample).unwrap();
+        assert_eq!(mandatory(&document), vec![]);
+
+        // take the CodecID and PixelHeight from the video track
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        root.edit_child(tracks, |tracks| {
+            tracks.edit_child(0, |entry| {
+                let codec = entry.children().iter().position(|n| n.element().id == ID_CODECID).unwrap();
+                entry.remove_child(codec);
+                let video = entry.children().iter().position(|n| n.element().id == ID_VIDEONODE).unwrap();
+                entry.edit_child(video, |video| {
+                    let height = video.children().iter().position(|n| n.element().id == ID_PIXELHEIGHT).unwrap();
+                    video.remove_child(height);
+                });
+            });
+        });
+        let issues = mandatory(&document);
+        let found: Vec<String> = issues.iter()
+            .map(|i| format!("{}: {}", i.path, i.problem))
+            .collect();
+        assert_eq!(found, vec![
+            "SegmentNode/TracksNode/TrackEntryNode: missing CodecID",
+            "SegmentNode/TracksNode/TrackEntryNode/VideoNode: missing PixelHeight",
+        ]);
+    }
+
+    #[test]
+    fn test_values() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, &[1])
+            .build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(values(&document), vec![]);
+
+        let set = |entry: &mut Node, id: u64, element: Element| {
+            let index = entry.children().iter().position(|n| n.element().id == id).unwrap();
+            entry.remove_child(index);
+            entry.insert_child(index, element.into());
+        };
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        root.edit_child(tracks, |tracks| {
+            tracks.edit_child(0, |video| {
+                set(video, ID_TRACKTYPE, Element::uint(ID_TRACKTYPE, 0));
+                video.insert_child(0, Element::master(ID_VIDEONODE, vec![
+                    Element::uint(ID_STEREOMODE, 15).into(),
+                    Element::uint(ID_FLAGINTERLACED, 2).into(),
+                ]));
+            });
+            tracks.edit_child(1, |audio| {
+                let index = audio.children().iter().position(|n| n.element().id == ID_AUDIONODE).unwrap();
+                audio.edit_child(index, |audio| {
+                    set(audio, ID_SAMPLINGFREQUENCY, Element::float(ID_SAMPLINGFREQUENCY, 0.0));
+                });
+            });
+        });
+
+        let issues = values(&document);
+        let found: Vec<String> = issues.iter()
+            .map(|i| format!("{}: {}", i.path, i.problem))
+            .collect();
+        assert_eq!(found, vec![
+            "SegmentNode/TracksNode/TrackEntryNode/VideoNode/StereoMode: 15 is out of range",
+            "SegmentNode/TracksNode/TrackEntryNode/TrackType: 0 is out of range",
+            "SegmentNode/TracksNode/TrackEntryNode/AudioNode/SamplingFrequency: 0 is out of range",
+        ]);
+
+        // offsets point at the elements once written out
+        let mut data = Cursor::new(Vec::new());
+        document.write_to(&mut data).unwrap();
+        let data = data.into_inner();
+        let issues = values(&WebmFile::from_bytes(&data).unwrap());
+        assert_eq!(issues.len(), 3);
+        assert_eq!(data[issues[1].offset as usize], ID_TRACKTYPE as u8);
+    }
+
+    #[test]
+    fn test_tracks() {
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP9", 64, 64)
+            .audio_track(2, "A_OPUS", 48000.0, 2)
+            .frame(1, 0, true, &[1])
+            .frame(2, 0, true, &[2])
+            .frame(3, 0, true, &[3])
+            .build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        let issues = tracks(&document);
+        assert_eq!(problems(&issues), vec![Problem::UnknownTrack(3)]);
+        assert_eq!(issues[0].path.to_string(), "SegmentNode/ClusterNode/SimpleBlock");
+
+        // give the audio track the video track's number and a zero UID
+        let root = document.root.as_node_mut();
+        let index = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        root.edit_child(index, |tracks| {
+            tracks.edit_child(1, |audio| {
+                for (id, value) in [(ID_TRACKNUMBER, 1), (ID_TRACKUID, 0)].iter() {
+                    let index = audio.children().iter().position(|n| n.element().id == *id).unwrap();
+                    audio.remove_child(index);
+                    audio.insert_child(index, Element::uint(*id, *value).into());
+                }
+            });
+        });
+        let problems = problems(&tracks(&document));
+        assert_eq!(problems, vec![
+            Problem::Duplicate(1),
+            Problem::OutOfRange(0.0),
+            Problem::UnknownTrack(2),
+            Problem::UnknownTrack(3),
+        ]);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        assert_eq!(timestamps(&WebmFile::from_bytes(&sample).unwrap()), vec![]);
+
+        // a 40 second Cluster from an encoder that wraps the relative
+        // timestamp, a block before zero and one after the Duration. A
+        // block before its Cluster's Timestamp but after zero is fine.
+        let data = WebmBuilder::new()
+            .duration(50_000.0)
+            .audio_track(1, "A_OPUS", 48000.0, 2)
+            .frame(1, 32_000, true, &[1])
+            .frame(1, (33_000 - 65_536) as i16, true, &[2])
+            .frame(1, -5, true, &[3])
+            .cluster(40_000)
+            .frame(1, -100, true, &[6])
+            .frame(1, 1_000, true, &[4])
+            .frame(1, 10_001, true, &[5])
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let issues = timestamps(&document);
+        assert_eq!(problems(&issues), vec![
+            Problem::TimestampOverflow { track: 1 },
+            Problem::OutsideCluster { track: 1 },
+            Problem::OutsideCluster { track: 1 },
+        ]);
+        let blocks: Vec<u64> = document.root.get_clusters()
+            .iter()
+            .flat_map(|c| c.get_simple_blocks())
+            .map(|b| b.get_element().offset)
+            .collect();
+        let offsets: Vec<u64> = issues.iter().map(|i| i.offset).collect();
+        assert_eq!(offsets, vec![blocks[1], blocks[2], blocks[5]]);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let document = WebmFile::from_bytes(&sample).unwrap();
+        assert_eq!(sizes(&document, sample.len() as u64), vec![]);
+        assert_eq!(problems(&sizes(&document, sample.len() as u64 + 10)), vec![
+            Problem::FileLength { elements: sample.len() as u64, file: sample.len() as u64 + 10 },
+        ]);
+
+        // cut off before the last top level element
+        let last = document.root.as_node().children().last().unwrap().element().offset;
+        let cut = &sample[..last as usize];
+        let issues = sizes(&WebmFile::from_bytes(cut).unwrap(), cut.len() as u64);
+        let (size, missing) = (document.root.get_element().size, sample.len() as u64 - last);
+        assert_eq!(problems(&issues), vec![
+            Problem::PastEof,
+            Problem::SizeMismatch { declared: size, children: size - missing },
+            Problem::FileLength { elements: sample.len() as u64, file: last },
+        ]);
+
+        let data = WebmBuilder::new()
+            .video_track(1, "V_VP8", 64, 64)
+            .unknown_size_segment()
+            .unknown_size_clusters()
+            .frame(1, 0, true, &[1])
+            .build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        assert_eq!(sizes(&document, data.len() as u64), vec![]);
+
+        // an Info two bytes short of its last child
+        let document = WebmFile::from_bytes(&WebmBuilder::new().build()).unwrap();
+        let children: Vec<u8> = document.root.get_info_nodes()[0].as_node().children().iter()
+            .flat_map(|child| child.to_bytes())
+            .collect();
+        let len = children.len() as u64;
+        let mut info = Vec::new();
+        encode::write_element_id(&mut info, ID_INFONODE);
+        vint::write(&mut info, len - 2);
+        info.extend(children);
+        let mut data = document.header.as_node().to_bytes();
+        encode::write_element(&mut data, ID_SEGMENTNODE, &info);
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let found: Vec<String> = sizes(&document, data.len() as u64).iter()
+            .map(|i| format!("{}: {}", i.path, i.problem))
+            .collect();
+        assert_eq!(found, vec![
+            format!("SegmentNode: size is {} but the children take {} bytes", len + 5, len + 3),
+            format!("SegmentNode/InfoNode: size is {} but the children take {} bytes", len - 2, len),
+            "SegmentNode/InfoNode/WritingApp: runs past the end of its parent".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_byte_stream_problems() {
+        let data = WebmBuilder::new()
+            .doc_type("matroska")
+            .video_track(1, "V_VP8", 64, 64)
+            .frame(1, 0, false, &[1])
+            .frame(1, 33, true, &[2])
+            .frame(1, 10, false, &[3])
+            .build();
+        let mut document = WebmFile::from_bytes(&data).unwrap();
+        let issues = byte_stream(&document);
+        assert_eq!(problems(&issues), vec![
+            Problem::HeaderValue,
+            Problem::NotKeyframe { track: 1 },
+            Problem::OutOfOrder { track: 1 },
+        ]);
+        assert_eq!(issues[0].to_string(), format!("EBMLHeaderNode/DocType at {}: value not allowed", issues[0].offset));
+
+        // move the Tracks after the Cluster and drop the Cluster's Timestamp
+        let root = document.root.as_node_mut();
+        let tracks = root.children().iter().position(|n| n.element().id == ID_TRACKSNODE).unwrap();
+        let tracks = root.remove_child(tracks).unwrap();
+        root.insert_child(usize::MAX, tracks);
+        let cluster = root.children().iter().position(|n| n.element().id == ID_CLUSTERNODE).unwrap();
+        root.edit_child(cluster, |cluster| {
+            cluster.remove_child(0);
+            cluster.insert_child(1, Element::uint(ID_TIMESTAMP, 0).into());
+        });
+        let problems = problems(&byte_stream(&document));
+        assert!(problems.contains(&Problem::InitAfterMedia));
+        assert!(problems.contains(&Problem::TimestampAfterBlock));
+    }
+}
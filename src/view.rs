@@ -0,0 +1,190 @@
+use crate::ebml::Node;
+use crate::error::Error;
+#[cfg(feature = "derive")]
+pub use libwebm_rs_derive::FromEbml;
+
+// Typed views of masters defined by applications, usually through
+// `#[derive(FromEbml)]` with the `derive` feature rather than by hand:
+//
+//     #[derive(FromEbml)]
+//     struct Track {
+//         #[ebml(id = ID_TRACKNUMBER)]
+//         number: u64,
+//         #[ebml(id = ID_FLAGDEFAULT, default = true)]
+//         default: bool,
+//         #[ebml(id = ID_VIDEONODE)]
+//         video: Option<Video>,
+//     }
+//
+//     let track = Track::from_node(entry.as_node())?;
+//
+// Fields are read from the first child with their ID. Values are converted
+// strictly, so an integer that's too long gives `Error::InvalidElement`
+// rather than a truncated number.
+pub trait FromEbml: Sized {
+    fn from_node(node: &Node) -> Result<Self, Error>;
+}
+
+// The first child with the ID, which must be there
+pub fn required<T: FromEbml>(node: &Node, id: u64) -> Result<T, Error> {
+    optional(node, id)?.ok_or(Error::MissingElement { id, offset: node.element().offset })
+}
+
+pub fn optional<T: FromEbml>(node: &Node, id: u64) -> Result<Option<T>, Error> {
+    node.children().iter()
+        .find(|child| child.element().id == id)
+        .map(T::from_node)
+        .transpose()
+}
+
+// Every child with the ID, in order
+pub fn repeated<T: FromEbml>(node: &Node, id: u64) -> Result<Vec<T>, Error> {
+    node.children().iter()
+        .filter(|child| child.element().id == id)
+        .map(T::from_node)
+        .collect()
+}
+
+fn invalid(node: &Node) -> Error {
+    let element = node.element();
+    Error::InvalidElement { id: element.id, offset: element.offset }
+}
+
+impl FromEbml for u64 {
+    fn from_node(node: &Node) -> Result<u64, Error> {
+        node.element().data.try_into_uint().map_err(|_| invalid(node))
+    }
+}
+
+impl FromEbml for i64 {
+    fn from_node(node: &Node) -> Result<i64, Error> {
+        node.element().data.try_into_int().map_err(|_| invalid(node))
+    }
+}
+
+impl FromEbml for f64 {
+    fn from_node(node: &Node) -> Result<f64, Error> {
+        node.element().data.try_into_float().map_err(|_| invalid(node))
+    }
+}
+
+// Flags like FlagDefault, true for anything but zero
+impl FromEbml for bool {
+    fn from_node(node: &Node) -> Result<bool, Error> {
+        u64::from_node(node).map(|value| value != 0)
+    }
+}
+
+impl FromEbml for String {
+    fn from_node(node: &Node) -> Result<String, Error> {
+        node.element().data.try_into_string().map_err(|_| invalid(node))
+    }
+}
+
+impl FromEbml for Vec<u8> {
+    fn from_node(node: &Node) -> Result<Vec<u8>, Error> {
+        Ok(node.element().data.into_vec())
+    }
+}
+
+// The child itself, for parts a view doesn't map
+impl FromEbml for Node {
+    fn from_node(node: &Node) -> Result<Node, Error> {
+        Ok(node.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use libwebm_rs_derive::FromEbml;
+    use crate::consts::*;
+    use crate::ebml::{Element, WebmFile};
+    use crate::testutil::WebmBuilder;
+    use super::*;
+
+    #[derive(Debug, PartialEq, FromEbml)]
+    struct Video {
+        #[ebml(id = ID_PIXELWIDTH)]
+        width: u64,
+        #[ebml(id = ID_PIXELHEIGHT)]
+        height: u64,
+    }
+
+    #[derive(Debug, PartialEq, FromEbml)]
+    struct Track {
+        #[ebml(id = ID_TRACKNUMBER)]
+        number: u64,
+        #[ebml(id = ID_CODECID)]
+        codec: String,
+        #[ebml(id = ID_LANGUAGE, default = "eng")]
+        language: String,
+        #[ebml(id = ID_FLAGDEFAULT, default = true)]
+        default: bool,
+        #[ebml(id = ID_CODECDELAY, default)]
+        codec_delay: u64,
+        #[ebml(id = ID_NAME)]
+        name: Option<String>,
+        #[ebml(id = ID_VIDEONODE)]
+        video: Option<Video>,
+        #[ebml(id = ID_CODECPRIVATE)]
+        private: Option<Vec<u8>>,
+        #[ebml(skip)]
+        note: String,
+    }
+
+    #[derive(Debug, FromEbml)]
+    struct Tracks {
+        #[ebml(id = ID_TRACKENTRYNODE)]
+        entries: Vec<Track>,
+    }
+
+    #[test]
+    fn test_derive() {
+        let sample = fs::read("./sample/big-buck-bunny_trailer.webm").unwrap();
+        let document = WebmFile::from_bytes(&sample).unwrap();
+        let tracks = &document.root.get_tracks()[0];
+        let tracks = Tracks::from_node(tracks.as_node()).unwrap();
+        assert_eq!(tracks.entries.len(), 2);
+        assert_eq!(tracks.entries[0], Track {
+            number: 1,
+            codec: "V_VP8".to_string(),
+            language: "eng".to_string(),
+            default: true,
+            codec_delay: 0,
+            name: None,
+            video: Some(Video { width: 640, height: 360 }),
+            private: None,
+            note: String::new(),
+        });
+        assert_eq!(tracks.entries[1].codec, "A_VORBIS");
+        assert_eq!(tracks.entries[1].video, None);
+        assert!(tracks.entries[1].private.as_ref().is_some_and(|p| !p.is_empty()));
+
+        // defaults and missing children
+        let data = WebmBuilder::new().video_track(1, "V_VP8", 64, 48).build();
+        let document = WebmFile::from_bytes(&data).unwrap();
+        let entry = document.root.get_tracks()[0].get_children().remove(0);
+        let track = Track::from_node(&entry).unwrap();
+        assert_eq!((track.language.as_str(), track.default), ("eng", true));
+        assert_eq!(track.video, Some(Video { width: 64, height: 48 }));
+
+        let mut entry = entry;
+        entry.remove_child(0);
+        assert!(matches!(
+            Track::from_node(&entry),
+            Err(Error::MissingElement { id: ID_TRACKNUMBER, .. })
+        ));
+    }
+
+    #[test]
+    fn test_strict_values() {
+        let node = Node::from(Element::binary(ID_TRACKNUMBER, &[1; 9]));
+        assert!(matches!(u64::from_node(&node), Err(Error::InvalidElement { .. })));
+        let node = Node::from(Element::binary(ID_NAME, &[0xff, 0xfe]));
+        assert!(String::from_node(&node).is_err());
+        assert_eq!(Vec::<u8>::from_node(&node).unwrap(), vec![0xff, 0xfe]);
+        let node = Node::from(Element::uint(ID_FLAGDEFAULT, 0));
+        assert!(!bool::from_node(&node).unwrap());
+    }
+}
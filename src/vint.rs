@@ -0,0 +1,218 @@
+use std::io::Read;
+use crate::consts::UNKNOWN_SIZE;
+
+// EBML variable length integers. The number of leading zero bits in the
+// first byte gives the length, followed by a marker bit and the value bits.
+// A value with every bit set is reserved to mean "unknown" in sizes.
+
+// Largest value that can be encoded, all ones in 8 bytes being reserved
+pub const MAX_VALUE: u64 = (1 << 56) - 2;
+
+// Number of bytes in a vint from its first byte. None for a zero byte,
+// which has no length marker.
+pub fn length(first: u8) -> Option<usize> {
+    match first.leading_zeros() {
+        8 => None,
+        n => Some(n as usize + 1),
+    }
+}
+
+// Read a whole vint in at most two reads and return it as a big-endian
+// integer (marker bit included) along with its length in bytes. None if the
+// stream ends first or the vint is longer than 8 bytes. Element IDs are
+// stored this way.
+pub fn read_raw(mut r: impl Read) -> Option<(u64, usize)> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf[..1]).ok()?;
+    let len = length(buf[0])?;
+    r.read_exact(&mut buf[1..len]).ok()?;
+
+    Some((u64::from_be_bytes(buf) >> (64 - 8 * len), len))
+}
+
+// Read a vint's value. The reserved all ones value is returned as is, use
+// `read_size` where it means an unknown size.
+pub fn read(r: impl Read) -> Option<u64> {
+    let (raw, len) = read_raw(r)?;
+    Some(value(raw, len))
+}
+
+// Read an element size, UNKNOWN_SIZE if every value bit is set
+pub fn read_size(r: impl Read) -> Option<u64> {
+    let (raw, len) = read_raw(r)?;
+    Some(size(raw, len))
+}
+
+// Read an element ID, which keeps its length marker
+pub fn read_id(r: impl Read) -> Option<u64> {
+    read_raw(r).map(|(id, _)| id)
+}
+
+// Decode a vint from the start of a buffer, returning the value and the
+// number of bytes it took up. None if the buffer is too short.
+pub fn parse(bytes: &[u8]) -> Option<(u64, usize)> {
+    let len = length(*bytes.first()?)?;
+    let value = read(bytes.get(..len)?)?;
+    Some((value, len))
+}
+
+// Drop the length marker from a raw vint
+pub fn value(raw: u64, len: usize) -> u64 {
+    raw & (u64::MAX >> (64 - 7 * len))
+}
+
+// Value of a raw element size, with the reserved value mapped to UNKNOWN_SIZE
+pub fn size(raw: u64, len: usize) -> u64 {
+    let value = value(raw, len);
+    if value == u64::MAX >> (64 - 7 * len) {
+        UNKNOWN_SIZE
+    } else {
+        value
+    }
+}
+
+// Shortest length that holds a value without it reading as reserved, None
+// above MAX_VALUE
+pub fn size_of(value: u64) -> Option<usize> {
+    (1..=8).find(|&n| value < (1 << (7 * n)) - 1)
+}
+
+// Append a value using the shortest encoding, returning its length
+pub fn write(out: &mut Vec<u8>, value: u64) -> Option<usize> {
+    let len = size_of(value)?;
+    write_with_length(out, value, len)?;
+    Some(len)
+}
+
+// Append a value padded out to `len` bytes, for sizes that are patched in
+// place later. None if it doesn't fit.
+pub fn write_with_length(out: &mut Vec<u8>, value: u64, len: usize) -> Option<()> {
+    if !(1..=8).contains(&len) || value >= (1 << (7 * len)) - 1 {
+        return None;
+    }
+    let marked = value | (1 << (7 * len));
+    out.extend(&marked.to_be_bytes()[8 - len..]);
+    Some(())
+}
+
+// Append the 8 byte unknown size
+pub fn write_unknown_size(out: &mut Vec<u8>) {
+    out.extend(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+}
+
+// Number of bytes in an element ID, None if its length marker doesn't match
+// its length
+pub fn id_length(id: u64) -> Option<usize> {
+    if id == 0 {
+        return None;
+    }
+    let len = 8 - id.leading_zeros() as usize / 8;
+    let first = (id >> (8 * (len - 1).min(7))) as u8;
+    match length(first) {
+        Some(marked) if marked == len => Some(len),
+        _ => None,
+    }
+}
+
+// Append an element ID, which is stored with its length marker
+pub fn write_id(out: &mut Vec<u8>, id: u64) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    out.extend(&bytes[skip..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length() {
+        assert_eq!(length(0x81), Some(1));
+        assert_eq!(length(0x0e), Some(5));
+        assert_eq!(length(0x01), Some(8));
+        assert_eq!(length(0x00), None);
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(read(&[0x81][..]), Some(1));
+        assert_eq!(read(&[0x40, 0x02][..]), Some(2));
+        assert_eq!(read(&[0x1a, 0x45, 0xdf, 0xa3][..]), Some(0xa45dfa3));
+        assert_eq!(read(&[0x01, 0, 0, 0, 0, 0x21, 0x09, 0x87][..]), Some(0x210987));
+        assert_eq!(read(&[0xff][..]), Some(0x7f));
+        // truncated, and no length marker
+        assert_eq!(read(&[0x40][..]), None);
+        assert_eq!(read(&[0x00, 0x81][..]), None);
+        assert_eq!(parse(&[0x40, 0x02, 0xff]), Some((2, 2)));
+        assert_eq!(parse(&[]), None);
+    }
+
+    #[test]
+    fn test_read_size() {
+        assert_eq!(read_size(&[0x82][..]), Some(2));
+        assert_eq!(read_size(&[0x7f, 0xfe][..]), Some(0x3ffe));
+        // all ones is reserved at every length
+        for len in 1..=8 {
+            let mut data = vec![0xff; len];
+            data[0] = 0xff >> (len - 1);
+            assert_eq!(read_size(&data[..]), Some(UNKNOWN_SIZE));
+        }
+    }
+
+    #[test]
+    fn test_read_id() {
+        // IDs are read raw, keeping the length marker
+        assert_eq!(read_raw(&[0xec][..]), Some((0xec, 1)));
+        assert_eq!(read_raw(&[0x42, 0x86][..]), Some((0x4286, 2)));
+        assert_eq!(read_raw(&[0x1a, 0x45, 0xdf, 0xa3, 0x9f][..]), Some((0x1a45dfa3, 4)));
+        assert_eq!(read_id(&[0x00, 0x81][..]), None);
+    }
+
+    #[test]
+    fn test_write() {
+        for len in 1..=8 {
+            // largest value at each length, and the reserved one after it
+            let max = (1u64 << (7 * len)) - 2;
+            assert_eq!(size_of(max), Some(len));
+            let mut out = Vec::new();
+            assert_eq!(write(&mut out, max), Some(len));
+            assert_eq!(parse(&out), Some((max, len)));
+            assert_eq!(read_size(&out[..]), Some(max));
+
+            if len < 8 {
+                assert_eq!(size_of(max + 1), Some(len + 1));
+            }
+        }
+        assert_eq!(size_of(0), Some(1));
+        assert_eq!(size_of(MAX_VALUE), Some(8));
+        assert_eq!(size_of(MAX_VALUE + 1), None);
+        assert_eq!(write(&mut Vec::new(), MAX_VALUE + 1), None);
+    }
+
+    #[test]
+    fn test_write_with_length() {
+        let mut out = Vec::new();
+        write_with_length(&mut out, 5, 4).unwrap();
+        assert_eq!(out, vec![0x10, 0x00, 0x00, 0x05]);
+        assert_eq!(write_with_length(&mut out, 0x7f, 1), None);
+        assert_eq!(write_with_length(&mut out, 0, 9), None);
+
+        let mut unknown = Vec::new();
+        write_unknown_size(&mut unknown);
+        assert_eq!(read_size(&unknown[..]), Some(UNKNOWN_SIZE));
+    }
+
+    #[test]
+    fn test_ids() {
+        for id in [0xec, 0x4286, 0x2ad7b1, 0x1a45dfa3] {
+            let mut out = Vec::new();
+            write_id(&mut out, id);
+            assert_eq!(Some(out.len()), id_length(id));
+            assert_eq!(read_id(&out[..]), Some(id));
+        }
+        // length markers that don't match the number of bytes
+        assert_eq!(id_length(0x42), None);
+        assert_eq!(id_length(0x8086), None);
+        assert_eq!(id_length(0), None);
+    }
+}